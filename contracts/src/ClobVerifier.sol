@@ -19,6 +19,7 @@ contract ClobVerifier {
         bytes32 domainSeparator;
         uint64 batchSeq;
         uint64 batchTimestamp;
+        uint64 batchDeadline;
         bytes32 daCommitment;
         bytes32 tradesRoot;
         bytes32 feesRoot;
@@ -40,6 +41,8 @@ contract ClobVerifier {
     ISP1Verifier public immutable verifier;
     bytes32 public immutable programVKey;
     mapping(bytes32 => MarketState) public markets;
+    // `serialize(Rules)` of the last batch proven with committed rules, per market.
+    mapping(bytes32 => bytes) public marketRules;
 
     constructor(address verifier_, bytes32 programVKey_) {
         verifier = ISP1Verifier(verifier_);
@@ -47,11 +50,24 @@ contract ClobVerifier {
     }
 
     function verifyAndUpdate(PublicInputs calldata inputs, bytes calldata proof) external {
+        _verifyAndUpdate(inputs, encodePublicInputs(inputs), proof);
+    }
+
+    // For proofs made with `--commit-rules`: the guest commits the rules bytes after the public
+    // inputs, so they are appended before verifying and kept for settlement to read.
+    function verifyAndUpdateWithRules(PublicInputs calldata inputs, bytes calldata rulesBytes, bytes calldata proof)
+        external
+    {
+        require(keccak256(rulesBytes) == inputs.rulesHash, "rulesHash mismatch");
+        _verifyAndUpdate(inputs, bytes.concat(encodePublicInputs(inputs), rulesBytes), proof);
+        marketRules[inputs.domainSeparator] = rulesBytes;
+    }
+
+    function _verifyAndUpdate(PublicInputs calldata inputs, bytes memory publicValues, bytes calldata proof) internal {
         MarketState storage state = markets[inputs.domainSeparator];
         require(inputs.batchSeq == state.batchSeq + 1, "batchSeq mismatch");
         require(inputs.prevRoot == state.root, "prevRoot mismatch");
 
-        bytes memory publicValues = encodePublicInputs(inputs);
         verifier.verifyProof(programVKey, publicValues, proof);
 
         state.root = inputs.newRoot;
@@ -80,6 +96,7 @@ contract ClobVerifier {
             inputs.domainSeparator,
             _u64be(inputs.batchSeq),
             _u64be(inputs.batchTimestamp),
+            _u64be(inputs.batchDeadline),
             inputs.daCommitment,
             inputs.tradesRoot,
             inputs.feesRoot
@@ -22,6 +22,19 @@ contract MockVerifier is ISP1Verifier {
     }
 }
 
+// Accepts only the public values it was told to expect.
+contract ExpectingVerifier is ISP1Verifier {
+    bytes32 public expectedHash;
+
+    function expect(bytes memory publicValues) external {
+        expectedHash = keccak256(publicValues);
+    }
+
+    function verifyProof(bytes32, bytes calldata publicValues, bytes calldata) external view override {
+        require(keccak256(publicValues) == expectedHash, "public values mismatch");
+    }
+}
+
 contract ClobVerifierTest is Test {
     ClobVerifier verifier;
     MockVerifier mock;
@@ -40,6 +53,7 @@ contract ClobVerifierTest is Test {
             domainSeparator: bytes32(uint256(5)),
             batchSeq: 1,
             batchTimestamp: 1234,
+            batchDeadline: 0,
             daCommitment: bytes32(uint256(6)),
             tradesRoot: bytes32(uint256(7)),
             feesRoot: bytes32(uint256(8))
@@ -59,6 +73,7 @@ contract ClobVerifierTest is Test {
             domainSeparator: bytes32(uint256(5)),
             batchSeq: 2,
             batchTimestamp: 1234,
+            batchDeadline: 0,
             daCommitment: bytes32(uint256(6)),
             tradesRoot: bytes32(uint256(7)),
             feesRoot: bytes32(uint256(8))
@@ -76,6 +91,7 @@ contract ClobVerifierTest is Test {
             domainSeparator: bytes32(uint256(5)),
             batchSeq: 1,
             batchTimestamp: 1234,
+            batchDeadline: 0,
             daCommitment: bytes32(uint256(6)),
             tradesRoot: bytes32(uint256(7)),
             feesRoot: bytes32(uint256(8))
@@ -83,4 +99,37 @@ contract ClobVerifierTest is Test {
         vm.expectRevert("prevRoot mismatch");
         verifier.verifyAndUpdate(inputs, hex"deadbeef");
     }
+
+    function testVerifyAndUpdateWithRulesAppendsRules() public {
+        ExpectingVerifier expecting = new ExpectingVerifier();
+        ClobVerifier rulesVerifier = new ClobVerifier(address(expecting), bytes32(uint256(1)));
+        bytes memory rulesBytes = hex"0102030405";
+        ClobVerifier.PublicInputs memory inputs = ClobVerifier.PublicInputs({
+            prevRoot: bytes32(0),
+            newRoot: bytes32(uint256(2)),
+            batchDigest: bytes32(uint256(3)),
+            rulesHash: keccak256(rulesBytes),
+            domainSeparator: bytes32(uint256(5)),
+            batchSeq: 1,
+            batchTimestamp: 1234,
+            batchDeadline: 0,
+            daCommitment: bytes32(uint256(6)),
+            tradesRoot: bytes32(uint256(7)),
+            feesRoot: bytes32(uint256(8))
+        });
+        expecting.expect(bytes.concat(rulesVerifier.encodePublicInputs(inputs), rulesBytes));
+
+        // Without the appended rules the committed stream does not match.
+        vm.expectRevert("public values mismatch");
+        rulesVerifier.verifyAndUpdate(inputs, hex"deadbeef");
+
+        vm.expectRevert("rulesHash mismatch");
+        rulesVerifier.verifyAndUpdateWithRules(inputs, hex"010203", hex"deadbeef");
+
+        rulesVerifier.verifyAndUpdateWithRules(inputs, rulesBytes, hex"deadbeef");
+        (bytes32 root, uint64 seq) = rulesVerifier.markets(inputs.domainSeparator);
+        assertEq(root, inputs.newRoot);
+        assertEq(seq, 1);
+        assertEq(rulesVerifier.marketRules(inputs.domainSeparator), rulesBytes);
+    }
 }
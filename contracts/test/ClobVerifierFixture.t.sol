@@ -24,6 +24,7 @@ contract ClobVerifierFixtureTest is Test {
         bytes32 daCommitment = json.readBytes32(".da_commitment");
         uint64 batchSeq = uint64(json.readUint(".batch_seq"));
         uint64 batchTimestamp = uint64(json.readUint(".batch_timestamp"));
+        uint64 batchDeadline = uint64(json.readUint(".batch_deadline"));
         bytes memory publicValues = json.readBytes(".public_values");
 
         ClobVerifier verifier = new ClobVerifier(address(new FixtureVerifier()), bytes32(uint256(1)));
@@ -35,6 +36,7 @@ contract ClobVerifierFixtureTest is Test {
             domainSeparator: domainSeparator,
             batchSeq: batchSeq,
             batchTimestamp: batchTimestamp,
+            batchDeadline: batchDeadline,
             daCommitment: daCommitment,
             tradesRoot: tradesRoot,
             feesRoot: feesRoot
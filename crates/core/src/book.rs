@@ -0,0 +1,272 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use hashbrown::HashSet;
+
+use crate::constants::{NONE_ORDER_ID, NONE_TICK};
+use crate::errors::CoreError;
+use crate::hash::Keccak256;
+use crate::input::Rules;
+use crate::merkle::SparseMerkleTree;
+use crate::state::{get_market_best, get_order, get_order_node, get_tick_node, StateAccess};
+use crate::types::{MarketBest, MarketId, Order, OrderId, OrderIdScope, OrderStatus, Side, U256};
+use crate::verify::price_from_tick;
+
+#[derive(Debug)]
+pub enum BookError {
+    State(CoreError),
+    BestNotExtreme { side: Side, tick: i32, prev_tick: i32 },
+    EmptyTick { side: Side, tick: i32 },
+    UnsortedTicks { side: Side, tick: i32, next_tick: i32 },
+    AsymmetricTickLink { side: Side, tick: i32, next_tick: i32 },
+    BrokenOrderLink { side: Side, tick: i32, order_id: OrderId },
+    OrderCycle { side: Side, tick: i32, order_id: OrderId },
+    TailMismatch { side: Side, tick: i32 },
+    OrderMismatch { side: Side, tick: i32, order_id: OrderId },
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::State(err) => write!(f, "{err}"),
+            BookError::BestNotExtreme { side, tick, prev_tick } => {
+                write!(f, "{side:?} best tick {tick} has prev tick {prev_tick}")
+            }
+            BookError::EmptyTick { side, tick } => write!(f, "{side:?} tick {tick} is linked but empty"),
+            BookError::UnsortedTicks { side, tick, next_tick } => {
+                write!(f, "{side:?} tick {tick} links to out-of-order next tick {next_tick}")
+            }
+            BookError::AsymmetricTickLink { side, tick, next_tick } => {
+                write!(f, "{side:?} tick {next_tick} does not link back to {tick}")
+            }
+            BookError::BrokenOrderLink { side, tick, order_id } => {
+                write!(f, "{side:?} tick {tick} order 0x{} has a broken link", Hex(order_id.as_bytes()))
+            }
+            BookError::OrderCycle { side, tick, order_id } => {
+                write!(f, "{side:?} tick {tick} order list cycles at 0x{}", Hex(order_id.as_bytes()))
+            }
+            BookError::TailMismatch { side, tick } => write!(f, "{side:?} tick {tick} tail does not match last order"),
+            BookError::OrderMismatch { side, tick, order_id } => {
+                write!(f, "{side:?} tick {tick} order 0x{} is missing, closed, or misplaced", Hex(order_id.as_bytes()))
+            }
+        }
+    }
+}
+
+impl From<CoreError> for BookError {
+    fn from(err: CoreError) -> Self {
+        BookError::State(err)
+    }
+}
+
+struct Hex<'a>(&'a [u8; 32]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+struct TreeReader<'a> {
+    tree: &'a SparseMerkleTree,
+}
+
+impl StateAccess for TreeReader<'_> {
+    type Hasher = Keccak256;
+    type Checkpoint = ();
+
+    fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    fn read_value(&mut self, key: [u8; 32]) -> Result<Option<Vec<u8>>, CoreError> {
+        Ok(self.tree.get(key))
+    }
+
+    fn write_value(&mut self, _key: [u8; 32], _value: Option<Vec<u8>>) -> Result<(), CoreError> {
+        Err(CoreError::State("tree reader is read-only"))
+    }
+
+    fn checkpoint(&self) {}
+
+    fn rollback(&mut self, _checkpoint: ()) -> Result<(), CoreError> {
+        Ok(())
+    }
+}
+
+pub fn verify_book_links(
+    tree: &SparseMerkleTree,
+    market_id: &MarketId,
+    order_id_scope: OrderIdScope,
+) -> Result<(), BookError> {
+    let mut reader = TreeReader { tree };
+    let scope = order_id_scope.market(market_id);
+    let best = get_market_best(&mut reader, market_id)?;
+    verify_side(&mut reader, market_id, scope, Side::Buy, best.best_bid)?;
+    verify_side(&mut reader, market_id, scope, Side::Sell, best.best_ask)?;
+    Ok(())
+}
+
+pub fn queue_position(
+    tree: &SparseMerkleTree,
+    market_id: &MarketId,
+    order_id_scope: OrderIdScope,
+    order_id: &OrderId,
+) -> Option<u32> {
+    let mut reader = TreeReader { tree };
+    let scope = order_id_scope.market(market_id);
+    let order = get_order(&mut reader, scope, order_id).ok()??;
+    if order.status != OrderStatus::Open {
+        return None;
+    }
+    let mut seen = HashSet::new();
+    let mut position = 0u32;
+    let mut current = *order_id;
+    loop {
+        if !seen.insert(current) {
+            return None;
+        }
+        let prev_id = get_order_node(&mut reader, scope, &current).ok()?.prev_order_id;
+        if prev_id == NONE_ORDER_ID {
+            return Some(position);
+        }
+        position += 1;
+        current = prev_id;
+    }
+}
+
+/// `(best_bid, best_ask, mid, spread)` prices from the market's best ticks, with the mid rounded
+/// down. `None` when either side is empty or the best ticks are unreadable or crossed.
+pub fn book_quote(tree: &SparseMerkleTree, market_id: &MarketId, rules: &Rules) -> Option<(U256, U256, U256, U256)> {
+    let mut reader = TreeReader { tree };
+    let best = get_market_best(&mut reader, market_id).ok()?;
+    if best.best_bid == NONE_TICK || best.best_ask == NONE_TICK {
+        return None;
+    }
+    let bid = price_from_tick(best.best_bid, rules.tick_size).ok()?;
+    let ask = price_from_tick(best.best_ask, rules.tick_size).ok()?;
+    let spread = ask.checked_sub(bid)?;
+    let mid = bid + spread / 2;
+    Some((bid, ask, mid, spread))
+}
+
+/// Rebuilds `MarketBest` from the book itself, ignoring the stored one: every open order names a
+/// tick, and the populated ticks among those give the extremes. The host can write the result back
+/// to repair a stale best.
+pub fn recompute_market_best(tree: &SparseMerkleTree, market_id: &MarketId) -> MarketBest {
+    let mut reader = TreeReader { tree };
+    let mut best = MarketBest {
+        best_bid: NONE_TICK,
+        best_ask: NONE_TICK,
+    };
+    let open_ticks: HashSet<(Side, i32)> = tree
+        .values()
+        .filter_map(|(_, bytes)| Order::decode(bytes).ok())
+        .filter(|order| order.status == OrderStatus::Open)
+        .map(|order| (order.side, order.tick))
+        .collect();
+    for (side, tick) in open_ticks {
+        let populated = matches!(
+            get_tick_node(&mut reader, market_id, side.as_u8(), tick),
+            Ok(node) if node.head_order_id != NONE_ORDER_ID
+        );
+        if !populated {
+            continue;
+        }
+        match side {
+            Side::Buy if best.best_bid == NONE_TICK || tick > best.best_bid => best.best_bid = tick,
+            Side::Sell if best.best_ask == NONE_TICK || tick < best.best_ask => best.best_ask = tick,
+            _ => {}
+        }
+    }
+    best
+}
+
+fn verify_side(
+    reader: &mut TreeReader,
+    market_id: &MarketId,
+    scope: Option<&MarketId>,
+    side: Side,
+    best_tick: i32,
+) -> Result<(), BookError> {
+    if best_tick == NONE_TICK {
+        return Ok(());
+    }
+    let mut tick = best_tick;
+    let mut prev_tick = NONE_TICK;
+    loop {
+        let node = get_tick_node(reader, market_id, side.as_u8(), tick)?;
+        if node.prev_tick != prev_tick {
+            if prev_tick == NONE_TICK {
+                return Err(BookError::BestNotExtreme {
+                    side,
+                    tick,
+                    prev_tick: node.prev_tick,
+                });
+            }
+            return Err(BookError::AsymmetricTickLink {
+                side,
+                tick: prev_tick,
+                next_tick: tick,
+            });
+        }
+        if node.head_order_id == NONE_ORDER_ID {
+            return Err(BookError::EmptyTick { side, tick });
+        }
+        verify_orders(reader, market_id, scope, side, tick, &node.head_order_id, &node.tail_order_id)?;
+
+        let next_tick = node.next_tick;
+        if next_tick == NONE_TICK {
+            return Ok(());
+        }
+        let sorted = match side {
+            Side::Buy => next_tick < tick,
+            Side::Sell => next_tick > tick,
+        };
+        if !sorted {
+            return Err(BookError::UnsortedTicks { side, tick, next_tick });
+        }
+        prev_tick = tick;
+        tick = next_tick;
+    }
+}
+
+fn verify_orders(
+    reader: &mut TreeReader,
+    market_id: &MarketId,
+    scope: Option<&MarketId>,
+    side: Side,
+    tick: i32,
+    head: &OrderId,
+    tail: &OrderId,
+) -> Result<(), BookError> {
+    let mut seen = HashSet::new();
+    let mut prev_id = NONE_ORDER_ID;
+    let mut order_id = *head;
+    while order_id != NONE_ORDER_ID {
+        if !seen.insert(order_id) {
+            return Err(BookError::OrderCycle { side, tick, order_id });
+        }
+        let order = get_order(reader, scope, &order_id)?;
+        let placed = matches!(
+            order,
+            Some(ref o) if o.status == OrderStatus::Open && o.market_id == *market_id && o.side == side && o.tick == tick
+        );
+        if !placed {
+            return Err(BookError::OrderMismatch { side, tick, order_id });
+        }
+        let node = get_order_node(reader, scope, &order_id)?;
+        if node.prev_order_id != prev_id {
+            return Err(BookError::BrokenOrderLink { side, tick, order_id });
+        }
+        prev_id = order_id;
+        order_id = node.next_order_id;
+    }
+    if &prev_id != tail {
+        return Err(BookError::TailMismatch { side, tick });
+    }
+    Ok(())
+}
@@ -1,7 +1,47 @@
+use crate::types::OrderId;
+
 pub const ZERO32: [u8; 32] = [0u8; 32];
-pub const NONE_ORDER_ID: [u8; 32] = [0u8; 32];
+pub const NONE_ORDER_ID: OrderId = OrderId::ZERO;
 pub const NONE_TICK: i32 = i32::MIN;
 
+// Tick indexes span `MIN_TICK..=MAX_TICK`; `i32::MIN` is reserved for `NONE_TICK`.
+pub const MIN_TICK: i32 = i32::MIN + 1;
+pub const MAX_TICK: i32 = i32::MAX;
+
+// Fixed-point scale for prices; the only `priceScale` the engine accepts.
+pub const PRICE_SCALE: u128 = 1_000_000_000_000_000_000;
+
+// Largest state record is the 72-byte tick node; leave headroom for future records.
+pub const MAX_VALUE_LEN: usize = 128;
+
+// Bundles claiming more proofs are rejected before the proof vector is allocated. Each proof is at
+// least 8 KiB on the wire, so this admits bundles far beyond any batch `maxFillsPerBatch` allows.
+pub const MAX_PROOFS_PER_BUNDLE: usize = 1 << 16;
+
+// Likewise for messages, checked before the message vector is allocated. Each signed message is
+// over 100 bytes on the wire, and a batch this long would exhaust its proofs long before its end.
+pub const MAX_MESSAGES_PER_BUNDLE: usize = 1 << 16;
+
+// Root of the tree with no leaves (`empty_hash(0)` under keccak256): the genesis `prevRoot` of a
+// market that starts with no balances or orders.
+pub const EMPTY_ROOT: [u8; 32] = [
+    0xca, 0x35, 0xb6, 0x0c, 0x4c, 0xbb, 0x11, 0xbc,
+    0x17, 0xb9, 0x02, 0x98, 0x9f, 0x14, 0xc5, 0x1d,
+    0x76, 0x4d, 0xfb, 0x86, 0x5e, 0x9a, 0xde, 0x93,
+    0xd5, 0x7d, 0xf3, 0xde, 0xf6, 0x2a, 0x1e, 0x05,
+];
+
+// Record size of each namespace's leaf value; the typed getters reject any other length.
+pub const BALANCE_LEN: usize = 64;
+pub const NONCE_LEN: usize = 8;
+pub const ORDER_LEN: usize = 94;
+pub const ORDER_NODE_LEN: usize = 64;
+pub const TICK_NODE_LEN: usize = 72;
+pub const MARKET_BEST_LEN: usize = 8;
+pub const FEE_VAULT_LEN: usize = 32;
+pub const TRADE_SEQ_LEN: usize = 8;
+pub const OPEN_COUNT_LEN: usize = 4;
+
 pub const NS_BAL: [u8; 32] = *b"NS_BAL__________________________";
 pub const NS_NONCE: [u8; 32] = *b"NS_NONCE________________________";
 pub const NS_ORDER: [u8; 32] = *b"NS_ORDER________________________";
@@ -9,6 +49,9 @@ pub const NS_ORDERNODE: [u8; 32] = *b"NS_ORDERNODE____________________";
 pub const NS_TICKNODE: [u8; 32] = *b"NS_TICKNODE_____________________";
 pub const NS_MARKETBEST: [u8; 32] = *b"NS_MARKETBEST___________________";
 pub const NS_FEEVAULT: [u8; 32] = *b"NS_FEEVAULT_____________________";
+pub const NS_TRADESEQ: [u8; 32] = *b"NS_TRADESEQ_____________________";
+pub const NS_OPENCOUNT: [u8; 32] = *b"NS_OPENCOUNT____________________";
 
 pub const DOMAIN_TAG: &[u8] = b"NUMO_SPOT_CLOB_V1";
 pub const BATCH_TAG: &[u8] = b"BATCH_V1";
+pub const SIGNING_TAG: &[u8] = b"SIGN_BATCH_V1";
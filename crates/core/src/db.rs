@@ -0,0 +1,208 @@
+// State over an external key-value store, for sequencers whose tree outgrows memory. Leaves and
+// occupied node hashes live in the store, so a write rehashes only its own path and the root is
+// one read away after a restart.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::errors::CoreError;
+use crate::hash::{Hasher, Keccak256};
+use crate::merkle::{empty_hash_ladder, extend_prefix, get_bit, leaf_hash_with, node_hash_with, prefix_with_len, Proof};
+use crate::state::StateAccess;
+
+const VALUE_PREFIX: u8 = b'v';
+const NODE_PREFIX: u8 = b'n';
+
+/// Byte-keyed storage backing a `DbState`. Implement it over sled, RocksDB or any other store;
+/// backend failures map to `CoreError::State`.
+pub trait KvStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, CoreError>;
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), CoreError>;
+    fn delete(&mut self, key: &[u8]) -> Result<(), CoreError>;
+}
+
+impl KvStore for BTreeMap<Vec<u8>, Vec<u8>> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, CoreError> {
+        Ok(BTreeMap::get(self, key).cloned())
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), CoreError> {
+        self.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), CoreError> {
+        self.remove(key);
+        Ok(())
+    }
+}
+
+/// A `StateAccess` whose leaves and node hashes are kept in a `KvStore`, with the same hashing and
+/// roots as `SparseMerkleTree`. As there, only nodes with a present leaf beneath them are stored.
+pub struct DbState<K: KvStore, H: Hasher = Keccak256> {
+    store: K,
+    root: [u8; 32],
+    empty_hashes: Vec<[u8; 32]>,
+    journal: Vec<([u8; 32], Option<Vec<u8>>)>,
+    hasher: PhantomData<H>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DbCheckpoint {
+    root: [u8; 32],
+    journal: usize,
+}
+
+impl<K: KvStore> DbState<K> {
+    pub fn open(store: K) -> Result<Self, CoreError> {
+        Self::open_with_hasher(store)
+    }
+}
+
+impl<K: KvStore, H: Hasher> DbState<K, H> {
+    /// Opens a store written by an earlier `DbState`, or an empty one for a fresh tree.
+    pub fn open_with_hasher(store: K) -> Result<Self, CoreError> {
+        let mut state = Self {
+            store,
+            root: [0u8; 32],
+            empty_hashes: empty_hash_ladder::<H>(),
+            journal: Vec::new(),
+            hasher: PhantomData,
+        };
+        state.root = state.node_hash([0u8; 32], 0)?;
+        Ok(state)
+    }
+
+    pub fn store(&self) -> &K {
+        &self.store
+    }
+
+    pub fn into_store(self) -> K {
+        self.store
+    }
+
+    pub fn get(&self, key: [u8; 32]) -> Result<Option<Vec<u8>>, CoreError> {
+        self.store.get(&value_key(&key))
+    }
+
+    /// Writes without journaling, for seeding genesis state outside a batch.
+    pub fn update(&mut self, key: [u8; 32], value: Option<Vec<u8>>) -> Result<(), CoreError> {
+        match &value {
+            Some(bytes) => self.store.put(&value_key(&key), bytes)?,
+            None => self.store.delete(&value_key(&key))?,
+        }
+        let leaf = value.as_ref().map(|bytes| leaf_hash_with::<H>(&key, bytes));
+        self.set_node(key, 256, leaf)?;
+        for depth in (0..256u16).rev() {
+            let prefix = prefix_with_len(&key, depth);
+            let left = self.stored_node(extend_prefix(&prefix, depth, 0), depth + 1)?;
+            let right = self.stored_node(extend_prefix(&prefix, depth, 1), depth + 1)?;
+            let hash = match (left, right) {
+                (None, None) => None,
+                (left, right) => {
+                    let empty = self.empty_hashes[depth as usize + 1];
+                    Some(node_hash_with::<H>(&left.unwrap_or(empty), &right.unwrap_or(empty)))
+                }
+            };
+            self.set_node(prefix, depth, hash)?;
+        }
+        self.root = self.node_hash([0u8; 32], 0)?;
+        Ok(())
+    }
+
+    pub fn prove(&self, key: [u8; 32]) -> Result<Proof, CoreError> {
+        let mut siblings = Vec::with_capacity(256);
+        for depth in 0..256u16 {
+            let bit = get_bit(&key, depth);
+            let prefix = prefix_with_len(&key, depth);
+            siblings.push(self.node_hash(extend_prefix(&prefix, depth, bit ^ 1), depth + 1)?);
+        }
+        let (value, present) = match self.get(key)? {
+            Some(bytes) => (bytes, true),
+            None => (Vec::new(), false),
+        };
+        Ok(Proof::new(key, value, present, siblings))
+    }
+
+    /// Drops the rollback journal once a batch is final; checkpoints taken before this are stale.
+    pub fn commit(&mut self) {
+        self.journal.clear();
+    }
+
+    fn node_hash(&self, prefix: [u8; 32], depth: u16) -> Result<[u8; 32], CoreError> {
+        Ok(self.stored_node(prefix, depth)?.unwrap_or(self.empty_hashes[depth as usize]))
+    }
+
+    fn stored_node(&self, prefix: [u8; 32], depth: u16) -> Result<Option<[u8; 32]>, CoreError> {
+        match self.store.get(&node_key(&prefix, depth))? {
+            Some(bytes) => bytes
+                .try_into()
+                .map(Some)
+                .map_err(|_| CoreError::State("invalid stored node hash")),
+            None => Ok(None),
+        }
+    }
+
+    fn set_node(&mut self, prefix: [u8; 32], depth: u16, hash: Option<[u8; 32]>) -> Result<(), CoreError> {
+        match hash {
+            Some(hash) => self.store.put(&node_key(&prefix, depth), &hash),
+            None => self.store.delete(&node_key(&prefix, depth)),
+        }
+    }
+}
+
+impl<K: KvStore, H: Hasher> StateAccess for DbState<K, H> {
+    type Hasher = H;
+    type Checkpoint = DbCheckpoint;
+
+    fn root(&self) -> [u8; 32] {
+        self.root
+    }
+
+    fn read_value(&mut self, key: [u8; 32]) -> Result<Option<Vec<u8>>, CoreError> {
+        self.get(key)
+    }
+
+    fn write_value(&mut self, key: [u8; 32], value: Option<Vec<u8>>) -> Result<(), CoreError> {
+        let previous = self.get(key)?;
+        self.journal.push((key, previous));
+        self.update(key, value)
+    }
+
+    fn checkpoint(&self) -> DbCheckpoint {
+        DbCheckpoint {
+            root: self.root,
+            journal: self.journal.len(),
+        }
+    }
+
+    fn rollback(&mut self, checkpoint: DbCheckpoint) -> Result<(), CoreError> {
+        if checkpoint.journal > self.journal.len() {
+            return Err(CoreError::State("stale checkpoint"));
+        }
+        let undo: Vec<_> = self.journal.drain(checkpoint.journal..).rev().collect();
+        for (key, previous) in undo {
+            self.update(key, previous)?;
+        }
+        if self.root != checkpoint.root {
+            return Err(CoreError::State("rollback root mismatch"));
+        }
+        Ok(())
+    }
+}
+
+fn value_key(key: &[u8; 32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + 32);
+    out.push(VALUE_PREFIX);
+    out.extend_from_slice(key);
+    out
+}
+
+fn node_key(prefix: &[u8; 32], depth: u16) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + 2 + 32);
+    out.push(NODE_PREFIX);
+    out.extend_from_slice(&depth.to_be_bytes());
+    out.extend_from_slice(prefix);
+    out
+}
@@ -1,7 +1,7 @@
 use alloc::vec::Vec;
 
 use crate::errors::CoreError;
-use crate::types::U256;
+use crate::types::{Address, U256};
 
 pub struct Reader<'a> {
     bytes: &'a [u8],
@@ -18,11 +18,12 @@ impl<'a> Reader<'a> {
     }
 
     pub fn read_exact(&mut self, len: usize) -> Result<&'a [u8], CoreError> {
-        if self.offset + len > self.bytes.len() {
+        let end = self.offset.checked_add(len).ok_or(CoreError::Decode("length overflow"))?;
+        if end > self.bytes.len() {
             return Err(CoreError::Decode("unexpected EOF"));
         }
-        let out = &self.bytes[self.offset..self.offset + len];
-        self.offset += len;
+        let out = &self.bytes[self.offset..end];
+        self.offset = end;
         Ok(out)
     }
 
@@ -50,9 +51,9 @@ impl<'a> Reader<'a> {
         Ok(bytes.try_into().unwrap())
     }
 
-    pub fn read_addr(&mut self) -> Result<[u8; 20], CoreError> {
+    pub fn read_addr(&mut self) -> Result<Address, CoreError> {
         let bytes = self.read_exact(20)?;
-        Ok(bytes.try_into().unwrap())
+        Ok(Address(bytes.try_into().unwrap()))
     }
 
     pub fn read_u256(&mut self) -> Result<U256, CoreError> {
@@ -66,6 +67,15 @@ impl<'a> Reader<'a> {
         Ok(bytes.to_vec())
     }
 
+    pub fn read_bytes_max(&mut self, max_len: usize) -> Result<Vec<u8>, CoreError> {
+        let len = self.read_u32()? as usize;
+        if len > max_len {
+            return Err(CoreError::Decode("value too large"));
+        }
+        let bytes = self.read_exact(len)?;
+        Ok(bytes.to_vec())
+    }
+
     pub fn expect_finished(&self) -> Result<(), CoreError> {
         if self.offset != self.bytes.len() {
             return Err(CoreError::Decode("trailing bytes"));
@@ -107,8 +117,8 @@ impl Writer {
         self.bytes.extend_from_slice(value);
     }
 
-    pub fn write_addr(&mut self, value: &[u8; 20]) {
-        self.bytes.extend_from_slice(value);
+    pub fn write_addr(&mut self, value: &Address) {
+        self.bytes.extend_from_slice(value.as_bytes());
     }
 
     pub fn write_u256(&mut self, value: &U256) {
@@ -2,395 +2,1174 @@ use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 
 use crate::constants::{NONE_ORDER_ID, NONE_TICK};
+use crate::encoding::{Reader, Writer};
 use crate::errors::CoreError;
 use crate::input::{Message, Rules, SignedMessage};
-use crate::math::{mul_div_down, mul_div_up};
+use crate::math::{mul_div_down, mul_div_up, sum_u256, to_u256, to_u512};
 use crate::state::{
-    get_balance, get_fee_vault, get_market_best, get_nonce, get_order, get_order_node, get_tick_node,
-    set_balance, set_fee_vault, set_market_best, set_nonce, set_order, set_order_node, set_tick_node,
-    StateAccess,
+    get_balance, get_fee_vault, get_market_best, get_nonce, get_open_count, get_order, get_order_node,
+    get_tick_node, get_trade_seq, set_balance, set_fee_vault, set_market_best, set_nonce, set_open_count,
+    set_order, set_order_node, set_tick_node, set_trade_seq, StateAccess,
+};
+use crate::types::{Address, AllocationMode, AssetId, Balance, FailureMode, FeeTotal, MarketBest, MarketId, Order, OrderId, OrderNode, OrderStatus, Side, TickNode, TimeInForce, TradeRecord, U256, U512};
+use crate::verify::{
+    check_lot_size, check_unique_signatures, derive_order_id_with, recover_signers_with, signing_separator_with,
+    price_from_tick,
 };
-use crate::types::{Balance, FeeTotal, MarketBest, Order, OrderNode, OrderStatus, Side, TickNode, TimeInForce, TradeRecord, U256};
-use crate::verify::{check_lot_size, verify_signature, price_from_tick};
 
 pub struct BatchOutput {
     pub trades: Vec<TradeRecord>,
     pub fee_totals: Vec<FeeTotal>,
+    pub outcomes: Vec<MessageOutcome>,
+}
+
+#[derive(Debug)]
+pub enum MessageOutcome {
+    // `avg_price` is the volume-weighted price of the message's fills in `price_scale` units, or
+    // zero when it filled nothing.
+    Applied { avg_price: U256 },
+    Failed(CoreError),
+}
+
+impl BatchOutput {
+    pub fn encode_trades_feed(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.write_u32(self.trades.len() as u32);
+        for trade in &self.trades {
+            w.write_raw(&trade.encode());
+        }
+        w.into_bytes()
+    }
+
+    pub fn decode_trades_feed(bytes: &[u8]) -> Result<Vec<TradeRecord>, CoreError> {
+        let mut r = Reader::new(bytes);
+        let trades = Self::read_trades_feed(&mut r)?;
+        r.expect_finished()?;
+        Ok(trades)
+    }
+
+    fn read_trades_feed(r: &mut Reader) -> Result<Vec<TradeRecord>, CoreError> {
+        let count = r.read_u32()? as usize;
+        let mut trades = Vec::new();
+        for _ in 0..count {
+            trades.push(TradeRecord::decode(r)?);
+        }
+        Ok(trades)
+    }
+
+    /// The trades feed followed by `U32 feeCount` and the fee totals. Outcomes are not encoded, as
+    /// a failed message's error has no wire form: `decode` leaves them empty, so the applied
+    /// messages' `avg_price` values are lost on a round trip.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.write_raw(&self.encode_trades_feed());
+        w.write_u32(self.fee_totals.len() as u32);
+        for fee in &self.fee_totals {
+            w.write_raw(&fee.encode());
+        }
+        w.into_bytes()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, CoreError> {
+        let mut r = Reader::new(bytes);
+        let trades = Self::read_trades_feed(&mut r)?;
+        let count = r.read_u32()? as usize;
+        let mut fee_totals = Vec::new();
+        for _ in 0..count {
+            fee_totals.push(FeeTotal::decode(&mut r)?);
+        }
+        r.expect_finished()?;
+        Ok(Self {
+            trades,
+            fee_totals,
+            outcomes: Vec::new(),
+        })
+    }
+
+    /// `(makerTick, totalBase, totalQuote)` per tick that traded, in ascending tick order.
+    pub fn aggregate_by_tick(&self) -> Result<Vec<(i32, U256, U256)>, CoreError> {
+        let mut levels: BTreeMap<i32, (U256, U256)> = BTreeMap::new();
+        for trade in &self.trades {
+            let level = levels.entry(trade.maker_tick).or_insert((U256::zero(), U256::zero()));
+            level.0 = level.0.checked_add(trade.qty_base).ok_or(CoreError::Math("tick base overflow"))?;
+            level.1 = level.1.checked_add(trade.quote_amt).ok_or(CoreError::Math("tick quote overflow"))?;
+        }
+        Ok(levels.into_iter().map(|(tick, (base, quote))| (tick, base, quote)).collect())
+    }
 }
 
 pub fn apply_batch<S: StateAccess>(
     state: &mut S,
-    market_id: [u8; 32],
+    market_id: MarketId,
+    rules: &Rules,
+    domain_sep: [u8; 32],
+    messages: &[SignedMessage],
+) -> Result<BatchOutput, CoreError> {
+    run_batch(state, market_id, rules, domain_sep, None, messages, None)
+}
+
+// Catches a host whose tree has drifted from the `prevRoot` it is about to commit to.
+pub fn apply_batch_at_root<S: StateAccess>(
+    state: &mut S,
+    prev_root: [u8; 32],
+    market_id: MarketId,
     rules: &Rules,
     domain_sep: [u8; 32],
+    batch_seq: u64,
     messages: &[SignedMessage],
+) -> Result<BatchOutput, CoreError> {
+    check_prev_root(state, prev_root)?;
+    run_batch(state, market_id, rules, domain_sep, Some(batch_seq), messages, None)
+}
+
+/// Messages paired with signers the caller already recovered for them, as `recover_addresses`
+/// returns them over each `msgHash` under the batch's signing separator. A host that recovered
+/// signers in parallel passes them here instead of recovering each one again; the guest always
+/// recovers its own.
+pub struct RecoveredBatch<'a> {
+    messages: &'a [SignedMessage],
+    signers: Vec<Result<Address, CoreError>>,
+}
+
+impl<'a> RecoveredBatch<'a> {
+    pub fn new(messages: &'a [SignedMessage], signers: Vec<Result<Address, CoreError>>) -> Result<Self, CoreError> {
+        if signers.len() != messages.len() {
+            return Err(CoreError::Invalid("signer count mismatch"));
+        }
+        Ok(Self { messages, signers })
+    }
+}
+
+pub fn apply_recovered_batch_at_root<S: StateAccess>(
+    state: &mut S,
+    prev_root: [u8; 32],
+    market_id: MarketId,
+    rules: &Rules,
+    domain_sep: [u8; 32],
+    batch_seq: u64,
+    batch: RecoveredBatch,
+) -> Result<BatchOutput, CoreError> {
+    check_prev_root(state, prev_root)?;
+    run_batch(state, market_id, rules, domain_sep, Some(batch_seq), batch.messages, Some(batch.signers))
+}
+
+fn check_prev_root<S: StateAccess>(state: &S, prev_root: [u8; 32]) -> Result<(), CoreError> {
+    if state.root() != prev_root {
+        return Err(CoreError::State("prev root mismatch"));
+    }
+    Ok(())
+}
+
+// `batch_seq` is only known to callers that commit to a batch; rules that bind signatures to it
+// cannot be applied without one. Under `FailureMode::Skip` a failing message is rolled back and
+// recorded instead of aborting the batch. Signers not supplied by the caller are recovered here.
+fn run_batch<S: StateAccess>(
+    state: &mut S,
+    market_id: MarketId,
+    rules: &Rules,
+    domain_sep: [u8; 32],
+    batch_seq: Option<u64>,
+    messages: &[SignedMessage],
+    signers: Option<Vec<Result<Address, CoreError>>>,
 ) -> Result<BatchOutput, CoreError> {
     if messages.len() > rules.max_orders_per_batch as usize {
         return Err(CoreError::Invalid("maxOrdersPerBatch exceeded"));
     }
-    if rules.price_scale != U256::from(1_000_000_000_000_000_000u128) {
-        return Err(CoreError::Invalid("priceScale must be 1e18"));
+    rules.validate()?;
+    check_unique_signatures(messages)?;
+    let signing_sep = match batch_seq {
+        Some(seq) => signing_separator_with::<S::Hasher>(&domain_sep, rules, seq),
+        None if rules.bind_batch_seq => return Err(CoreError::Invalid("batch seq required for bound signatures")),
+        None => domain_sep,
+    };
+    let signers = match signers {
+        Some(signers) => signers,
+        None => recover_signers_with::<S::Hasher>(&signing_sep, messages),
+    };
+
+    let mut progress = BatchProgress {
+        trades: Vec::new(),
+        fee_totals: BTreeMap::new(),
+        fills: 0,
+    };
+
+    let mut outcomes = Vec::with_capacity(messages.len());
+    for (signed, signer) in messages.iter().zip(signers) {
+        let checkpoint = state.checkpoint();
+        let trades_len = progress.trades.len();
+        let fills = progress.fills;
+        let fee_totals = progress.fee_totals.clone();
+        match apply_message(state, market_id, rules, domain_sep, signed, signer, &mut progress) {
+            Ok(()) => outcomes.push(MessageOutcome::Applied {
+                avg_price: average_price(&progress.trades[trades_len..], rules)?,
+            }),
+            Err(err) => {
+                state.rollback(checkpoint)?;
+                if rules.failure_mode == FailureMode::Abort {
+                    return Err(err);
+                }
+                progress.trades.truncate(trades_len);
+                progress.fills = fills;
+                progress.fee_totals = fee_totals;
+                outcomes.push(MessageOutcome::Failed(err));
+            }
+        }
     }
-    if rules.maker_fee_bps != 0 {
-        return Err(CoreError::Invalid("makerFeeBps must be zero"));
+
+    let mut fee_totals_vec = Vec::with_capacity(progress.fee_totals.len());
+    for (asset, total) in progress.fee_totals {
+        fee_totals_vec.push(FeeTotal {
+            asset_id: asset,
+            total_fee: to_u256(total).map_err(|_| CoreError::Math("fee total overflow"))?,
+        });
     }
 
-    let mut trades = Vec::new();
-    let mut fee_totals: BTreeMap<[u8; 32], U256> = BTreeMap::new();
+    Ok(BatchOutput {
+        trades: progress.trades,
+        fee_totals: fee_totals_vec,
+        outcomes,
+    })
+}
 
-    for signed in messages {
-        let message = &signed.message;
-        let trader = match message {
-            Message::Place { trader, .. } => trader,
-            Message::Cancel { trader, .. } => trader,
-        };
-        verify_signature(&domain_sep, message, &signed.signature, trader)?;
-        let nonce_value = match message {
-            Message::Place { nonce, .. } => *nonce,
-            Message::Cancel { nonce, .. } => *nonce,
-        };
-        let current_nonce = get_nonce(state, trader)?;
-        if nonce_value != current_nonce + 1 {
-            return Err(CoreError::Invalid("nonce mismatch"));
+// Total quote over total base, rounded down, so partial fills across ticks report one execution price.
+fn average_price(trades: &[TradeRecord], rules: &Rules) -> Result<U256, CoreError> {
+    let base = sum_u256(trades.iter().map(|trade| trade.qty_base))?;
+    if base.is_zero() {
+        return Ok(U256::zero());
+    }
+    let quote = sum_u256(trades.iter().map(|trade| trade.quote_amt))?;
+    mul_div_down(quote, rules.price_scale, base)
+}
+
+struct BatchProgress {
+    trades: Vec<TradeRecord>,
+    // Accumulated wide and narrowed once at the end of the batch.
+    fee_totals: BTreeMap<AssetId, U512>,
+    fills: u32,
+}
+
+// Tick-node and order-node reads made while processing one Place.
+struct BookSteps {
+    taken: u32,
+    max: u32,
+}
+
+impl BookSteps {
+    fn new(max: u32) -> Self {
+        Self { taken: 0, max }
+    }
+
+    fn step(&mut self) -> Result<(), CoreError> {
+        if self.max != 0 && self.taken >= self.max {
+            return Err(CoreError::Invalid("book steps exceeded"));
         }
-        set_nonce(state, trader, nonce_value)?;
+        self.taken += 1;
+        Ok(())
+    }
+}
 
-        match message {
-            Message::Place {
-                trader,
-                order_id,
-                side,
-                tif,
-                tick_index,
-                qty_base,
-                prev_tick_hint,
-                next_tick_hint,
-                ..
-            } => {
-                if get_order(state, order_id)?.is_some() {
-                    return Err(CoreError::Invalid("order id already exists"));
+fn apply_message<S: StateAccess>(
+    state: &mut S,
+    market_id: MarketId,
+    rules: &Rules,
+    domain_sep: [u8; 32],
+    signed: &SignedMessage,
+    signer: Result<Address, CoreError>,
+    progress: &mut BatchProgress,
+) -> Result<(), CoreError> {
+    let message = &signed.message;
+    let scope = rules.order_scope(&market_id);
+    let trader = match message {
+        Message::Place { trader, .. } => trader,
+        Message::Cancel { trader, .. } => trader,
+        Message::CollectFees { operator, .. } => operator,
+        Message::ForceCancel { operator, .. } => operator,
+        Message::FillOrder { trader, .. } => trader,
+    };
+    // Must precede every state access: a forged signer may not read the tree or consume a proof.
+    if signer? != *trader {
+        return Err(CoreError::Signature("signer mismatch"));
+    }
+    let nonce_value = match message {
+        Message::Place { nonce, .. } => *nonce,
+        Message::Cancel { nonce, .. } => *nonce,
+        Message::CollectFees { nonce, .. } => *nonce,
+        Message::ForceCancel { nonce, .. } => *nonce,
+        Message::FillOrder { nonce, .. } => *nonce,
+    };
+    let current_nonce = get_nonce(state, trader)?;
+    if nonce_value != current_nonce + 1 {
+        return Err(CoreError::Invalid("nonce mismatch"));
+    }
+    set_nonce(state, trader, nonce_value)?;
+
+    match message {
+        Message::Place {
+            trader,
+            nonce,
+            order_id,
+            side,
+            tif,
+            tick_index,
+            qty_base,
+            prev_tick_hint,
+            next_tick_hint,
+            reduce_only,
+            quote_budget,
+        } => {
+            // Only the sentinel may be `i32::MIN`; a real order there would alias an empty side.
+            if *tick_index == NONE_TICK {
+                return Err(CoreError::Invalid("reserved tick"));
+            }
+            if *tick_index % rules.tick_spacing != 0 {
+                return Err(CoreError::Invalid("tick not aligned"));
+            }
+            if *quote_budget {
+                if *side != Side::Buy {
+                    return Err(CoreError::Invalid("quote budget requires buy"));
                 }
-                if qty_base.is_zero() {
-                    return Err(CoreError::Invalid("qtyBase zero"));
+                if *reduce_only {
+                    return Err(CoreError::Invalid("quote budget cannot be reduce-only"));
                 }
+            }
+            // Reduce-only and quote-budget orders never rest.
+            let tif = if *reduce_only || *quote_budget { &TimeInForce::Ioc } else { tif };
+            if rules.enforce_derived_order_id
+                && *order_id != derive_order_id_with::<S::Hasher>(&domain_sep, trader, *nonce)
+            {
+                return Err(CoreError::Invalid("order id not derived"));
+            }
+            if get_order(state, scope, order_id)?.is_some() {
+                return Err(CoreError::Invalid("order id already exists"));
+            }
+            if qty_base.is_zero() {
+                return Err(CoreError::Invalid("qtyBase zero"));
+            }
+            // A quote budget is sized in quote units, so lot size only applies to the fills.
+            if !*quote_budget {
                 check_lot_size(*qty_base, rules.lot_size)?;
-                let price = price_from_tick(*tick_index, rules.tick_size)?;
-                let mut remaining = *qty_base;
-                let limit_price = price;
+            }
+            // Reject out-of-range sizes here so they cannot overflow balance arithmetic later.
+            if *qty_base > rules.max_balance {
+                return Err(CoreError::Invalid("qtyBase exceeds maxBalance"));
+            }
+            let price = price_from_tick(*tick_index, rules.tick_size)?;
+            let qty_base = &if *reduce_only {
+                reduce_only_qty(state, trader, *side, *qty_base, price, rules)?
+            } else {
+                *qty_base
+            };
+            if *quote_budget {
+                if *qty_base < rules.min_notional {
+                    return Err(CoreError::Invalid("below min notional"));
+                }
+            } else {
+                if price.checked_mul(*qty_base).is_none() {
+                    return Err(CoreError::Invalid("price * qtyBase overflows"));
+                }
+                if mul_div_down(price, *qty_base, rules.price_scale)? < rules.min_notional {
+                    return Err(CoreError::Invalid("below min notional"));
+                }
+            }
+            let mut remaining = *qty_base;
+            let limit_price = price;
 
-                let mut balance_quote = get_balance(state, trader, &rules.quote_asset_id)?;
-                let mut balance_base = get_balance(state, trader, &rules.base_asset_id)?;
+            let mut balance_quote = get_balance(state, trader, &rules.quote_asset_id)?;
+            let mut balance_base = get_balance(state, trader, &rules.base_asset_id)?;
 
-                match side {
-                    Side::Buy => {
-                        let lock_quote = mul_div_up(price, *qty_base, rules.price_scale)?;
-                        if balance_quote.available < lock_quote {
-                            return Err(CoreError::Invalid("insufficient quote balance"));
-                        }
-                        balance_quote.available -= lock_quote;
-                        balance_quote.locked += lock_quote;
-                        set_balance(state, trader, &rules.quote_asset_id, &balance_quote)?;
+            let order_locked = match side {
+                Side::Buy => {
+                    let lock_quote = if *quote_budget {
+                        *qty_base
+                    } else {
+                        mul_div_up(price, *qty_base, rules.price_scale)?
+                    };
+                    if balance_quote.available < lock_quote {
+                        return Err(CoreError::Invalid("insufficient quote balance"));
                     }
-                    Side::Sell => {
-                        if balance_base.available < *qty_base {
-                            return Err(CoreError::Invalid("insufficient base balance"));
-                        }
-                        balance_base.available -= *qty_base;
-                        balance_base.locked += *qty_base;
-                        set_balance(state, trader, &rules.base_asset_id, &balance_base)?;
+                    balance_quote.available -= lock_quote;
+                    balance_quote.locked += lock_quote;
+                    set_balance(state, trader, &rules.quote_asset_id, &balance_quote)?;
+                    lock_quote
+                }
+                Side::Sell => {
+                    if balance_base.available < *qty_base {
+                        return Err(CoreError::Invalid("insufficient base balance"));
                     }
+                    balance_base.available -= *qty_base;
+                    balance_base.locked += *qty_base;
+                    set_balance(state, trader, &rules.base_asset_id, &balance_base)?;
+                    *qty_base
                 }
+            };
+            let mut taker = Taker {
+                trader,
+                order_id,
+                side: *side,
+                locked: order_locked,
+                notional: U256::zero(),
+            };
 
-                let mut best = get_market_best(state, &market_id)?;
-                let mut matches = 0u32;
+            let mut best = get_market_best(state, &market_id)?;
+            check_price_band(&best, *side, limit_price, rules)?;
+            let mut matches = 0u32;
+            let mut steps = BookSteps::new(rules.max_book_steps_per_order);
 
-                loop {
-                    let current_tick = match side {
-                        Side::Buy => best.best_ask,
-                        Side::Sell => best.best_bid,
-                    };
-                    if current_tick == NONE_TICK {
-                        break;
-                    }
-                    let tick_price = price_from_tick(current_tick, rules.tick_size)?;
-                    let price_ok = match side {
-                        Side::Buy => tick_price <= limit_price,
-                        Side::Sell => tick_price >= limit_price,
-                    };
-                    if !price_ok || remaining.is_zero() {
+            loop {
+                let current_tick = match side {
+                    Side::Buy => best.best_ask,
+                    Side::Sell => best.best_bid,
+                };
+                if current_tick == NONE_TICK {
+                    break;
+                }
+                let tick_price = price_from_tick(current_tick, rules.tick_size)?;
+                let price_ok = match side {
+                    Side::Buy => tick_price <= limit_price,
+                    Side::Sell => tick_price >= limit_price,
+                };
+                if !price_ok || remaining.is_zero() {
+                    break;
+                }
+                // A quote budget buys whatever base the collateral still locked covers at this tick.
+                if *quote_budget {
+                    remaining = budget_qty(taker.locked, tick_price, rules)?;
+                    if remaining.is_zero() {
                         break;
                     }
+                }
 
-                    let mut tick_node = get_tick_node(state, &market_id, side.opposite().as_u8(), current_tick)?;
-                    while tick_node.head_order_id != NONE_ORDER_ID && !remaining.is_zero() {
-                        if matches >= rules.max_matches_per_order {
-                            return Err(CoreError::Invalid("maxMatchesPerOrder exceeded"));
-                        }
-                        matches += 1;
-                        let maker_order_id = tick_node.head_order_id;
-                        let mut maker_order = get_order(state, &maker_order_id)?
-                            .ok_or(CoreError::Invalid("maker order missing"))?;
-                        if maker_order.status != OrderStatus::Open {
-                            return Err(CoreError::Invalid("maker order not open"));
-                        }
-                        if maker_order.side == *side {
-                            return Err(CoreError::Invalid("maker side mismatch"));
-                        }
-                        let fill_qty = if remaining < maker_order.qty_remaining {
-                            remaining
-                        } else {
-                            maker_order.qty_remaining
-                        };
-                        let quote_amt = mul_div_down(tick_price, fill_qty, rules.price_scale)?;
-                        let fee = mul_div_up(quote_amt, U256::from(rules.taker_fee_bps), U256::from(10_000u64))?;
-
-                        match side {
-                            Side::Buy => {
-                                let mut taker_quote = get_balance(state, trader, &rules.quote_asset_id)?;
-                                let mut taker_base = get_balance(state, trader, &rules.base_asset_id)?;
-                                let mut maker_base = get_balance(state, &maker_order.owner, &rules.base_asset_id)?;
-                                let mut maker_quote = get_balance(state, &maker_order.owner, &rules.quote_asset_id)?;
-
-                                let spend = quote_amt + fee;
-                                if taker_quote.locked < spend {
-                                    return Err(CoreError::Invalid("taker locked quote insufficient"));
-                                }
-                                if maker_base.locked < fill_qty {
-                                    return Err(CoreError::Invalid("maker locked base insufficient"));
-                                }
-
-                                taker_quote.locked -= spend;
-                                taker_base.available += fill_qty;
-                                maker_base.locked -= fill_qty;
-                                maker_quote.available += quote_amt;
-
-                                ensure_balance_limit(&taker_quote, rules.max_balance)?;
-                                ensure_balance_limit(&taker_base, rules.max_balance)?;
-                                ensure_balance_limit(&maker_base, rules.max_balance)?;
-                                ensure_balance_limit(&maker_quote, rules.max_balance)?;
-
-                                set_balance(state, trader, &rules.quote_asset_id, &taker_quote)?;
-                                set_balance(state, trader, &rules.base_asset_id, &taker_base)?;
-                                set_balance(state, &maker_order.owner, &rules.base_asset_id, &maker_base)?;
-                                set_balance(state, &maker_order.owner, &rules.quote_asset_id, &maker_quote)?;
+                steps.step()?;
+                let mut tick_node = get_tick_node(state, &market_id, side.opposite().as_u8(), current_tick)?;
+                // The best tick must be populated and have nothing better linked ahead of it.
+                if tick_node.head_order_id == NONE_ORDER_ID || tick_node.prev_tick != NONE_TICK {
+                    return Err(CoreError::State("stale market best"));
+                }
+                let allocations = match rules.allocation_mode {
+                    AllocationMode::Fifo => Vec::new(),
+                    AllocationMode::ProRata => {
+                        let window = rules.max_matches_per_order - matches;
+                        pro_rata_allocations(state, scope, &tick_node, remaining, window, rules, &mut steps)?
+                    }
+                };
+                let mut next_allocation = 0;
+                loop {
+                    let (maker_order_id, allocated) = match rules.allocation_mode {
+                        AllocationMode::Fifo => {
+                            if tick_node.head_order_id == NONE_ORDER_ID || remaining.is_zero() {
+                                break;
                             }
-                            Side::Sell => {
-                                let mut taker_base = get_balance(state, trader, &rules.base_asset_id)?;
-                                let mut taker_quote = get_balance(state, trader, &rules.quote_asset_id)?;
-                                let mut maker_base = get_balance(state, &maker_order.owner, &rules.base_asset_id)?;
-                                let mut maker_quote = get_balance(state, &maker_order.owner, &rules.quote_asset_id)?;
-
-                                if taker_base.locked < fill_qty {
-                                    return Err(CoreError::Invalid("taker locked base insufficient"));
-                                }
-                                if maker_quote.locked < quote_amt {
-                                    return Err(CoreError::Invalid("maker locked quote insufficient"));
-                                }
-
-                                taker_base.locked -= fill_qty;
-                                let receive = quote_amt.checked_sub(fee).ok_or(CoreError::Math("fee exceeds quote"))?;
-                                taker_quote.available += receive;
-                                maker_quote.locked -= quote_amt;
-                                maker_base.available += fill_qty;
-
-                                ensure_balance_limit(&taker_base, rules.max_balance)?;
-                                ensure_balance_limit(&taker_quote, rules.max_balance)?;
-                                ensure_balance_limit(&maker_base, rules.max_balance)?;
-                                ensure_balance_limit(&maker_quote, rules.max_balance)?;
-
-                                set_balance(state, trader, &rules.base_asset_id, &taker_base)?;
-                                set_balance(state, trader, &rules.quote_asset_id, &taker_quote)?;
-                                set_balance(state, &maker_order.owner, &rules.base_asset_id, &maker_base)?;
-                                set_balance(state, &maker_order.owner, &rules.quote_asset_id, &maker_quote)?;
+                            (tick_node.head_order_id, None)
+                        }
+                        AllocationMode::ProRata => match allocations.get(next_allocation) {
+                            Some((id, qty)) => {
+                                next_allocation += 1;
+                                (*id, Some(*qty))
                             }
+                            None => break,
+                        },
+                    };
+                    if matches >= rules.max_matches_per_order {
+                        return Err(CoreError::Invalid("maxMatchesPerOrder exceeded"));
+                    }
+                    matches += 1;
+                    if progress.fills >= rules.max_fills_per_batch {
+                        return Err(CoreError::Invalid("maxFillsPerBatch exceeded"));
+                    }
+                    progress.fills += 1;
+                    let mut maker_order = get_order(state, scope, &maker_order_id)?
+                        .ok_or(CoreError::Invalid("maker order missing"))?;
+                    if maker_order.status != OrderStatus::Open {
+                        return Err(CoreError::Invalid("maker order not open"));
+                    }
+                    if maker_order.qty_remaining.is_zero() {
+                        return Err(CoreError::State("zero-qty open maker"));
+                    }
+                    if maker_order.side == *side {
+                        return Err(CoreError::Invalid("maker side mismatch"));
+                    }
+                    let fill_qty = match allocated {
+                        Some(qty) => qty,
+                        None if remaining < maker_order.qty_remaining => remaining,
+                        None => maker_order.qty_remaining,
+                    };
+                    // Per-fill rounding can cost more than the tick-level estimate; never overspend.
+                    let fill_qty = if *quote_budget {
+                        let affordable = budget_qty(taker.locked, tick_price, rules)?;
+                        if affordable.is_zero() {
+                            break;
                         }
+                        fill_qty.min(affordable)
+                    } else {
+                        fill_qty
+                    };
+                    settle_fill(
+                        state,
+                        &market_id,
+                        rules,
+                        &mut taker,
+                        &Fill {
+                            maker_order_id,
+                            qty: fill_qty,
+                            price: tick_price,
+                        },
+                        &mut maker_order,
+                        progress,
+                    )?;
 
-                        let fee_asset = rules.quote_asset_id;
-                        let entry = fee_totals.entry(fee_asset).or_insert_with(U256::zero);
-                        *entry += fee;
-                        let mut fee_vault = get_fee_vault(state, &fee_asset)?;
-                        fee_vault.total += fee;
-                        set_fee_vault(state, &fee_asset, &fee_vault)?;
-
-                        maker_order.qty_remaining -= fill_qty;
-                        if maker_order.qty_remaining.is_zero() {
-                            maker_order.status = OrderStatus::Filled;
-                        }
-                        set_order(state, &maker_order_id, &maker_order)?;
+                    remaining -= fill_qty;
 
-                        trades.push(TradeRecord {
-                            market_id,
-                            maker_order_id,
-                            taker_order_id: *order_id,
-                            maker: maker_order.owner,
-                            taker: *trader,
-                            side_taker: *side,
-                            maker_tick: maker_order.tick,
-                            qty_base: fill_qty,
-                            quote_amt,
-                            taker_fee_quote: fee,
-                        });
-
-                        remaining -= fill_qty;
-
-                        if maker_order.status == OrderStatus::Filled {
-                            let maker_node = get_order_node(state, &maker_order_id)?;
-                            let next_id = maker_node.next_order_id;
+                    if maker_order.status == OrderStatus::Filled {
+                        steps.step()?;
+                        let maker_node = get_order_node(state, scope, &maker_order_id)?;
+                        let prev_id = maker_node.prev_order_id;
+                        let next_id = maker_node.next_order_id;
+                        if prev_id == NONE_ORDER_ID {
                             tick_node.head_order_id = next_id;
-                            if next_id == NONE_ORDER_ID {
-                                tick_node.tail_order_id = NONE_ORDER_ID;
-                            } else {
-                                let mut next_node = get_order_node(state, &next_id)?;
-                                next_node.prev_order_id = NONE_ORDER_ID;
-                                set_order_node(state, &next_id, &next_node)?;
-                            }
-                            set_order_node(state, &maker_order_id, &OrderNode {
-                                prev_order_id: NONE_ORDER_ID,
-                                next_order_id: NONE_ORDER_ID,
-                            })?;
+                        } else {
+                            steps.step()?;
+                            let mut prev_node = get_order_node(state, scope, &prev_id)?;
+                            prev_node.next_order_id = next_id;
+                            set_order_node(state, scope, &prev_id, &prev_node)?;
                         }
+                        if next_id == NONE_ORDER_ID {
+                            tick_node.tail_order_id = prev_id;
+                        } else {
+                            steps.step()?;
+                            let mut next_node = get_order_node(state, scope, &next_id)?;
+                            next_node.prev_order_id = prev_id;
+                            set_order_node(state, scope, &next_id, &next_node)?;
+                        }
+                        set_order_node(state, scope, &maker_order_id, &OrderNode {
+                            prev_order_id: NONE_ORDER_ID,
+                            next_order_id: NONE_ORDER_ID,
+                        })?;
                     }
+                }
 
-                    if tick_node.head_order_id == NONE_ORDER_ID {
-                        let prev_tick = tick_node.prev_tick;
-                        let next_tick = tick_node.next_tick;
-                        if prev_tick != NONE_TICK {
-                            let mut prev_node = get_tick_node(state, &market_id, side.opposite().as_u8(), prev_tick)?;
-                            prev_node.next_tick = next_tick;
-                            set_tick_node(state, &market_id, side.opposite().as_u8(), prev_tick, &prev_node)?;
-                        }
-                        if next_tick != NONE_TICK {
-                            let mut next_node = get_tick_node(state, &market_id, side.opposite().as_u8(), next_tick)?;
-                            next_node.prev_tick = prev_tick;
-                            set_tick_node(state, &market_id, side.opposite().as_u8(), next_tick, &next_node)?;
-                        }
-                        match side {
-                            Side::Buy => {
-                                if best.best_ask == current_tick {
-                                    best.best_ask = next_tick;
-                                }
+                if tick_node.head_order_id == NONE_ORDER_ID {
+                    let prev_tick = tick_node.prev_tick;
+                    let next_tick = tick_node.next_tick;
+                    if prev_tick != NONE_TICK {
+                        steps.step()?;
+                        let mut prev_node = get_tick_node(state, &market_id, side.opposite().as_u8(), prev_tick)?;
+                        prev_node.next_tick = next_tick;
+                        set_tick_node(state, &market_id, side.opposite().as_u8(), prev_tick, &prev_node)?;
+                    }
+                    if next_tick != NONE_TICK {
+                        steps.step()?;
+                        let mut next_node = get_tick_node(state, &market_id, side.opposite().as_u8(), next_tick)?;
+                        next_node.prev_tick = prev_tick;
+                        set_tick_node(state, &market_id, side.opposite().as_u8(), next_tick, &next_node)?;
+                    }
+                    match side {
+                        Side::Buy => {
+                            if best.best_ask == current_tick {
+                                best.best_ask = next_tick;
                             }
-                            Side::Sell => {
-                                if best.best_bid == current_tick {
-                                    best.best_bid = next_tick;
-                                }
+                        }
+                        Side::Sell => {
+                            if best.best_bid == current_tick {
+                                best.best_bid = next_tick;
                             }
                         }
-                        set_tick_node(
-                            state,
-                            &market_id,
-                            side.opposite().as_u8(),
-                            current_tick,
-                            &TickNode {
-                                prev_tick: NONE_TICK,
-                                next_tick: NONE_TICK,
-                                head_order_id: NONE_ORDER_ID,
-                                tail_order_id: NONE_ORDER_ID,
-                            },
-                        )?;
-                        set_market_best(state, &market_id, &best)?;
-                    } else {
-                        set_tick_node(state, &market_id, side.opposite().as_u8(), current_tick, &tick_node)?;
                     }
+                    set_tick_node(
+                        state,
+                        &market_id,
+                        side.opposite().as_u8(),
+                        current_tick,
+                        &TickNode {
+                            prev_tick: NONE_TICK,
+                            next_tick: NONE_TICK,
+                            head_order_id: NONE_ORDER_ID,
+                            tail_order_id: NONE_ORDER_ID,
+                        },
+                    )?;
+                    set_market_best(state, &market_id, &best)?;
+                } else {
+                    set_tick_node(state, &market_id, side.opposite().as_u8(), current_tick, &tick_node)?;
+                }
 
-                    if remaining.is_zero() {
-                        break;
-                    }
+                if remaining.is_zero() {
+                    break;
                 }
+            }
 
-                match tif {
-                    TimeInForce::Ioc => {
-                        if !remaining.is_zero() {
-                            release_remaining(
-                                state,
-                                trader,
-                                *side,
-                                remaining,
-                                price,
-                                rules,
-                            )?;
-                        }
+            // Only a resting GTC remainder keeps collateral locked; anything left over from
+            // price improvement or rounding goes back to available.
+            let reserve = if *tif == TimeInForce::Gtc && !remaining.is_zero() {
+                match side {
+                    Side::Buy => mul_div_up(price, remaining, rules.price_scale)?,
+                    Side::Sell => remaining,
+                }
+            } else {
+                U256::zero()
+            };
+            let surplus = taker.locked
+                .checked_sub(reserve)
+                .ok_or(CoreError::Invalid("order collateral insufficient"))?;
+            if !surplus.is_zero() {
+                let (asset, insufficient) = match side {
+                    Side::Buy => (&rules.quote_asset_id, "locked quote insufficient"),
+                    Side::Sell => (&rules.base_asset_id, "locked base insufficient"),
+                };
+                release_locked(state, trader, asset, surplus, rules, insufficient)?;
+            }
+
+            match tif {
+                TimeInForce::Ioc => {
+                    set_order(
+                        state,
+                        scope,
+                        order_id,
+                        &Order {
+                            owner: *trader,
+                            market_id,
+                            side: *side,
+                            tick: *tick_index,
+                            qty_remaining: U256::zero(),
+                            tif: *tif,
+                            status: if remaining.is_zero() {
+                                OrderStatus::Filled
+                            } else {
+                                OrderStatus::Canceled
+                            },
+                        },
+                    )?;
+                }
+                TimeInForce::Gtc => {
+                    if remaining.is_zero() {
                         set_order(
                             state,
+                            scope,
                             order_id,
                             &Order {
                                 owner: *trader,
+                                market_id,
                                 side: *side,
                                 tick: *tick_index,
                                 qty_remaining: U256::zero(),
                                 tif: *tif,
-                                status: if remaining.is_zero() {
-                                    OrderStatus::Filled
-                                } else {
-                                    OrderStatus::Canceled
-                                },
+                                status: OrderStatus::Filled,
                             },
                         )?;
-                    }
-                    TimeInForce::Gtc => {
-                        if remaining.is_zero() {
-                            set_order(
-                                state,
-                                order_id,
-                                &Order {
-                                    owner: *trader,
-                                    side: *side,
-                                    tick: *tick_index,
-                                    qty_remaining: U256::zero(),
-                                    tif: *tif,
-                                    status: OrderStatus::Filled,
-                                },
-                            )?;
-                        } else {
-                            place_resting(
-                                state,
-                                &market_id,
-                                order_id,
-                                trader,
-                                *side,
-                                *tick_index,
-                                remaining,
-                                *tif,
-                                *prev_tick_hint,
-                                *next_tick_hint,
-                                &mut best,
-                            )?;
-                        }
+                    } else {
+                        place_resting(
+                            state,
+                            &market_id,
+                            scope,
+                            order_id,
+                            trader,
+                            *side,
+                            *tick_index,
+                            remaining,
+                            *tif,
+                            *prev_tick_hint,
+                            *next_tick_hint,
+                            &mut best,
+                            &mut steps,
+                        )?;
+                        open_order_added(state, trader, rules)?;
                     }
                 }
             }
-            Message::Cancel { trader, order_id, .. } => {
-                let mut order = get_order(state, order_id)?.ok_or(CoreError::Invalid("order missing"))?;
-                if &order.owner != trader {
-                    return Err(CoreError::Invalid("cancel owner mismatch"));
-                }
-                if order.status != OrderStatus::Open {
-                    return Err(CoreError::Invalid("order not open"));
-                }
-                let price = price_from_tick(order.tick, rules.tick_size)?;
-                release_remaining(state, trader, order.side, order.qty_remaining, price, rules)?;
-                order.qty_remaining = U256::zero();
-                order.status = OrderStatus::Canceled;
-                set_order(state, order_id, &order)?;
-                remove_from_book(state, &market_id, order.side, order.tick, order_id)?;
+        }
+        Message::Cancel { trader, order_id, .. } => {
+            let order = get_order(state, scope, order_id)?.ok_or(CoreError::Invalid("order missing"))?;
+            if &order.owner != trader {
+                return Err(CoreError::Invalid("cancel owner mismatch"));
             }
+            cancel_order(state, &market_id, order_id, order, rules)?;
         }
-    }
+        Message::ForceCancel { operator, order_id, .. } => {
+            if rules.admin.is_zero() || operator != &rules.admin {
+                return Err(CoreError::Invalid("admin mismatch"));
+            }
+            let order = get_order(state, scope, order_id)?.ok_or(CoreError::Invalid("order missing"))?;
+            cancel_order(state, &market_id, order_id, order, rules)?;
+        }
+        Message::CollectFees {
+            operator,
+            asset,
+            amount,
+            to,
+            ..
+        } => {
+            if rules.fee_collector.is_zero() || operator != &rules.fee_collector {
+                return Err(CoreError::Invalid("fee collector mismatch"));
+            }
+            if amount.is_zero() {
+                return Err(CoreError::Invalid("amount zero"));
+            }
+            let mut vault = get_fee_vault(state, asset)?;
+            if *amount > vault.total {
+                return Err(CoreError::Invalid("fee vault insufficient"));
+            }
+            vault.total -= *amount;
+            set_fee_vault(state, asset, &vault)?;
+            let mut bal = get_balance(state, to, asset)?;
+            bal.available = bal
+                .available
+                .checked_add(*amount)
+                .ok_or(CoreError::Math("balance overflow"))?;
+            ensure_balance_limit(&bal, rules.max_balance)?;
+            set_balance(state, to, asset, &bal)?;
+        }
+        Message::FillOrder {
+            trader,
+            nonce,
+            order_id,
+            maker_order_id,
+            max_qty,
+        } => {
+            if rules.enforce_derived_order_id
+                && *order_id != derive_order_id_with::<S::Hasher>(&domain_sep, trader, *nonce)
+            {
+                return Err(CoreError::Invalid("order id not derived"));
+            }
+            if get_order(state, scope, order_id)?.is_some() {
+                return Err(CoreError::Invalid("order id already exists"));
+            }
+            if max_qty.is_zero() {
+                return Err(CoreError::Invalid("maxQty zero"));
+            }
+            check_lot_size(*max_qty, rules.lot_size)?;
+            if *max_qty > rules.max_balance {
+                return Err(CoreError::Invalid("maxQty exceeds maxBalance"));
+            }
+            let mut maker_order = get_order(state, scope, maker_order_id)?
+                .ok_or(CoreError::Invalid("maker order missing"))?;
+            check_order_market(&maker_order, &market_id)?;
+            if maker_order.status != OrderStatus::Open {
+                return Err(CoreError::Invalid("maker order not open"));
+            }
+            if maker_order.qty_remaining.is_zero() {
+                return Err(CoreError::State("zero-qty open maker"));
+            }
+            // The taker crosses whichever side the named order rests on, at the maker's price.
+            let side = maker_order.side.opposite();
+            let price = price_from_tick(maker_order.tick, rules.tick_size)?;
+            // The book is only consulted when a band needs the best price as its reference.
+            if rules.max_price_deviation_bps != 0 {
+                check_price_band(&get_market_best(state, &market_id)?, side, price, rules)?;
+            }
+            let fill_qty = (*max_qty).min(maker_order.qty_remaining);
+            if mul_div_down(price, fill_qty, rules.price_scale)? < rules.min_notional {
+                return Err(CoreError::Invalid("below min notional"));
+            }
+            if progress.fills >= rules.max_fills_per_batch {
+                return Err(CoreError::Invalid("maxFillsPerBatch exceeded"));
+            }
+            progress.fills += 1;
 
-    let mut fee_totals_vec = Vec::with_capacity(fee_totals.len());
-    for (asset, total) in fee_totals {
-        fee_totals_vec.push(FeeTotal {
-            asset_id: asset,
-            total_fee: total,
-        });
+            // Lock exactly what the fill spends so settlement leaves no collateral behind.
+            let terms = fill_terms(side, fill_qty, price, U256::zero(), rules)?;
+            let (asset, lock, insufficient) = match side {
+                Side::Buy if terms.fee_in_base => (&rules.quote_asset_id, terms.quote_amt, "insufficient quote balance"),
+                Side::Buy => (
+                    &rules.quote_asset_id,
+                    terms
+                        .quote_amt
+                        .checked_add(terms.fee)
+                        .ok_or(CoreError::Math("fill cost overflow"))?,
+                    "insufficient quote balance",
+                ),
+                Side::Sell => (&rules.base_asset_id, fill_qty, "insufficient base balance"),
+            };
+            let mut balance = get_balance(state, trader, asset)?;
+            if balance.available < lock {
+                return Err(CoreError::Invalid(insufficient));
+            }
+            balance.available -= lock;
+            balance.locked += lock;
+            set_balance(state, trader, asset, &balance)?;
+
+            let mut taker = Taker {
+                trader,
+                order_id,
+                side,
+                locked: lock,
+                notional: U256::zero(),
+            };
+            settle_fill(
+                state,
+                &market_id,
+                rules,
+                &mut taker,
+                &Fill {
+                    maker_order_id: *maker_order_id,
+                    qty: fill_qty,
+                    price,
+                },
+                &mut maker_order,
+                progress,
+            )?;
+            if maker_order.status == OrderStatus::Filled {
+                remove_from_book(state, &market_id, scope, maker_order.side, maker_order.tick, maker_order_id)?;
+            }
+            set_order(
+                state,
+                scope,
+                order_id,
+                &Order {
+                    owner: *trader,
+                    market_id,
+                    side,
+                    tick: maker_order.tick,
+                    qty_remaining: U256::zero(),
+                    tif: TimeInForce::Ioc,
+                    status: OrderStatus::Filled,
+                },
+            )?;
+        }
     }
+    Ok(())
+}
 
-    Ok(BatchOutput {
-        trades,
-        fee_totals: fee_totals_vec,
+// The resting order and size of one match, priced at the maker's tick.
+struct Fill {
+    maker_order_id: OrderId,
+    qty: U256,
+    price: U256,
+}
+
+// The incoming side of a match; `locked` is the collateral the order still holds.
+struct Taker<'a> {
+    trader: &'a Address,
+    order_id: &'a OrderId,
+    side: Side,
+    locked: U256,
+    // Quote filled so far, which decides the fee tier of the next fill.
+    notional: U256,
+}
+
+struct FillTerms {
+    quote_amt: U256,
+    fee: U256,
+    fee_asset: AssetId,
+    fee_in_base: bool,
+}
+
+// The taker fee comes out of quote unless the market charges it in the asset the taker
+// receives, which makes a buy pay in base. `notional` is the quote the order has already filled.
+fn fill_terms(side: Side, qty: U256, price: U256, notional: U256, rules: &Rules) -> Result<FillTerms, CoreError> {
+    let quote_amt = mul_div_down(price, qty, rules.price_scale)?;
+    let fee_in_base = rules.fee_in_received_asset && side == Side::Buy;
+    let fee_asset = if fee_in_base { rules.base_asset_id } else { rules.quote_asset_id };
+    let fee_basis = if fee_in_base { qty } else { quote_amt };
+    let fee = taker_fee(fee_basis, quote_amt, notional, rules)?;
+    Ok(FillTerms {
+        quote_amt,
+        fee,
+        fee_asset,
+        fee_in_base,
     })
 }
 
+// A fill straddling the tier boundary splits its fee basis in proportion to the quote inside the
+// tier and rounds the blended fee up once.
+fn taker_fee(basis: U256, quote_amt: U256, notional: U256, rules: &Rules) -> Result<U256, CoreError> {
+    let denom = U256::from(10_000u64);
+    let tier_left = rules.taker_fee_tier_notional.saturating_sub(notional);
+    if rules.taker_fee_tier_notional.is_zero() || tier_left >= quote_amt {
+        return mul_div_up(basis, U256::from(rules.taker_fee_bps), denom);
+    }
+    if tier_left.is_zero() {
+        return mul_div_up(basis, U256::from(rules.taker_fee_bps_tier2), denom);
+    }
+    let inside = mul_div_down(basis, tier_left, quote_amt)?;
+    let weighted = inside
+        .checked_mul(U256::from(rules.taker_fee_bps))
+        .and_then(|w| w.checked_add((basis - inside).checked_mul(U256::from(rules.taker_fee_bps_tier2))?))
+        .ok_or(CoreError::Math("fee overflow"))?;
+    mul_div_up(weighted, U256::one(), denom)
+}
+
+// Moves one fill's base, quote and fee between taker and maker out of the taker's order
+// collateral, updates the maker order and records the trade. Unlinking a filled maker is left to
+// the caller.
+fn settle_fill<S: StateAccess>(
+    state: &mut S,
+    market_id: &MarketId,
+    rules: &Rules,
+    taker: &mut Taker,
+    fill: &Fill,
+    maker_order: &mut Order,
+    progress: &mut BatchProgress,
+) -> Result<(), CoreError> {
+    if fill.qty.is_zero() {
+        return Err(CoreError::State("zero-qty fill"));
+    }
+    let scope = rules.order_scope(market_id);
+    let FillTerms {
+        quote_amt,
+        fee,
+        fee_asset,
+        fee_in_base,
+    } = fill_terms(taker.side, fill.qty, fill.price, taker.notional, rules)?;
+    taker.notional = taker
+        .notional
+        .checked_add(quote_amt)
+        .ok_or(CoreError::Math("taker notional overflow"))?;
+    let trader = taker.trader;
+    let fill_qty = fill.qty;
+
+    match taker.side {
+        Side::Buy => {
+            let mut taker_quote = get_balance(state, trader, &rules.quote_asset_id)?;
+            let mut taker_base = get_balance(state, trader, &rules.base_asset_id)?;
+            let mut maker_base = get_balance(state, &maker_order.owner, &rules.base_asset_id)?;
+            let mut maker_quote = get_balance(state, &maker_order.owner, &rules.quote_asset_id)?;
+
+            let (spend, receive) = if fee_in_base {
+                let receive =
+                    fill_qty.checked_sub(fee).ok_or(CoreError::Math("fee exceeds base"))?;
+                (quote_amt, receive)
+            } else {
+                (quote_amt + fee, fill_qty)
+            };
+            if taker_quote.locked < spend {
+                return Err(CoreError::Invalid("taker locked quote insufficient"));
+            }
+            taker.locked = taker.locked
+                .checked_sub(spend)
+                .ok_or(CoreError::Invalid("taker locked quote insufficient"))?;
+            if maker_base.locked < fill_qty {
+                return Err(CoreError::Invalid("maker locked base insufficient"));
+            }
+
+            taker_quote.locked -= spend;
+            taker_base.available += receive;
+            maker_base.locked -= fill_qty;
+            maker_quote.available += quote_amt;
+
+            ensure_balance_limit(&taker_quote, rules.max_balance)?;
+            ensure_balance_limit(&taker_base, rules.max_balance)?;
+            ensure_balance_limit(&maker_base, rules.max_balance)?;
+            ensure_balance_limit(&maker_quote, rules.max_balance)?;
+
+            set_balance(state, trader, &rules.quote_asset_id, &taker_quote)?;
+            set_balance(state, trader, &rules.base_asset_id, &taker_base)?;
+            set_balance(state, &maker_order.owner, &rules.base_asset_id, &maker_base)?;
+            set_balance(state, &maker_order.owner, &rules.quote_asset_id, &maker_quote)?;
+        }
+        Side::Sell => {
+            let mut taker_base = get_balance(state, trader, &rules.base_asset_id)?;
+            let mut taker_quote = get_balance(state, trader, &rules.quote_asset_id)?;
+            let mut maker_base = get_balance(state, &maker_order.owner, &rules.base_asset_id)?;
+            let mut maker_quote = get_balance(state, &maker_order.owner, &rules.quote_asset_id)?;
+
+            if taker_base.locked < fill_qty {
+                return Err(CoreError::Invalid("taker locked base insufficient"));
+            }
+            if maker_quote.locked < quote_amt {
+                return Err(CoreError::Invalid("maker locked quote insufficient"));
+            }
+
+            taker_base.locked -= fill_qty;
+            taker.locked -= fill_qty;
+            let receive = quote_amt.checked_sub(fee).ok_or(CoreError::Math("fee exceeds quote"))?;
+            taker_quote.available += receive;
+            maker_quote.locked -= quote_amt;
+            maker_base.available += fill_qty;
+
+            ensure_balance_limit(&taker_base, rules.max_balance)?;
+            ensure_balance_limit(&taker_quote, rules.max_balance)?;
+            ensure_balance_limit(&maker_base, rules.max_balance)?;
+            ensure_balance_limit(&maker_quote, rules.max_balance)?;
+
+            set_balance(state, trader, &rules.base_asset_id, &taker_base)?;
+            set_balance(state, trader, &rules.quote_asset_id, &taker_quote)?;
+            set_balance(state, &maker_order.owner, &rules.base_asset_id, &maker_base)?;
+            set_balance(state, &maker_order.owner, &rules.quote_asset_id, &maker_quote)?;
+        }
+    }
+
+    let entry = progress.fee_totals.entry(fee_asset).or_insert_with(U512::zero);
+    *entry += to_u512(fee);
+    let mut fee_vault = get_fee_vault(state, &fee_asset)?;
+    fee_vault.total = fee_vault
+        .total
+        .checked_add(fee)
+        .ok_or(CoreError::Math("fee vault overflow"))?;
+    set_fee_vault(state, &fee_asset, &fee_vault)?;
+
+    maker_order.qty_remaining -= fill_qty;
+    if maker_order.qty_remaining.is_zero() {
+        maker_order.status = OrderStatus::Filled;
+        open_order_removed(state, &maker_order.owner, rules)?;
+    }
+    set_order(state, scope, &fill.maker_order_id, maker_order)?;
+
+    let trade_seq = get_trade_seq(state, market_id)?
+        .checked_add(1)
+        .ok_or(CoreError::Math("trade seq overflow"))?;
+    set_trade_seq(state, market_id, trade_seq)?;
+    progress.trades.push(TradeRecord {
+        market_id: *market_id,
+        trade_seq,
+        maker_order_id: fill.maker_order_id,
+        taker_order_id: *taker.order_id,
+        maker: maker_order.owner,
+        taker: *trader,
+        side_taker: taker.side,
+        maker_tick: maker_order.tick,
+        qty_base: fill_qty,
+        quote_amt,
+        taker_fee: fee,
+    });
+    Ok(())
+}
+
+// Splits `remaining` across the first `window` makers of the level in proportion to each one's
+// size, working in lots so every fill stays a lot multiple. Rounding dust goes to the largest
+// makers first (queue order breaks ties), each capped at what it has left. Makers allocated
+// nothing are omitted. Makers past the window wait for the next pass over the level, so a
+// crowded level bounds the work instead of rejecting the taker.
+fn pro_rata_allocations<S: StateAccess>(
+    state: &mut S,
+    scope: Option<&MarketId>,
+    tick_node: &TickNode,
+    remaining: U256,
+    window: u32,
+    rules: &Rules,
+    steps: &mut BookSteps,
+) -> Result<Vec<(OrderId, U256)>, CoreError> {
+    // The taker still has size but no matches left to spend on it.
+    if window == 0 {
+        return Err(CoreError::Invalid("maxMatchesPerOrder exceeded"));
+    }
+    let mut makers = Vec::new();
+    let mut level_lots = U256::zero();
+    let mut order_id = tick_node.head_order_id;
+    while order_id != NONE_ORDER_ID && makers.len() < window as usize {
+        let order = get_order(state, scope, &order_id)?.ok_or(CoreError::Invalid("maker order missing"))?;
+        // Such a maker would be allocated nothing and never leave the book.
+        if order.qty_remaining.is_zero() {
+            return Err(CoreError::State("zero-qty open maker"));
+        }
+        let lots = order.qty_remaining / rules.lot_size;
+        level_lots = level_lots
+            .checked_add(lots)
+            .ok_or(CoreError::Math("level size overflow"))?;
+        makers.push((order_id, lots));
+        steps.step()?;
+        order_id = get_order_node(state, scope, &order_id)?.next_order_id;
+    }
+
+    let take_lots = core::cmp::min(remaining / rules.lot_size, level_lots);
+    let mut shares = Vec::with_capacity(makers.len());
+    let mut allocated = U256::zero();
+    for (_, lots) in &makers {
+        let share = if take_lots == level_lots {
+            *lots
+        } else {
+            mul_div_down(take_lots, *lots, level_lots)?
+        };
+        allocated += share;
+        shares.push(share);
+    }
+
+    let mut by_size: Vec<usize> = (0..makers.len()).collect();
+    by_size.sort_by(|a, b| makers[*b].1.cmp(&makers[*a].1));
+    let mut dust = take_lots - allocated;
+    for idx in by_size {
+        if dust.is_zero() {
+            break;
+        }
+        let room = makers[idx].1 - shares[idx];
+        let extra = core::cmp::min(room, dust);
+        shares[idx] += extra;
+        dust -= extra;
+    }
+
+    Ok(makers
+        .iter()
+        .zip(shares)
+        .filter(|(_, share)| !share.is_zero())
+        .map(|((id, _), share)| (*id, share * rules.lot_size))
+        .collect())
+}
+
+// Largest lot multiple of base a buy can take at `price` while spending at most `budget` quote,
+// with the taker fee charged as the fill path charges it.
+fn budget_qty(budget: U256, price: U256, rules: &Rules) -> Result<U256, CoreError> {
+    let denom = U256::from(10_000u64);
+    let fee_bps = if rules.fee_in_received_asset {
+        U256::zero()
+    } else {
+        U256::from(rules.taker_fee_bps)
+    };
+    let before_fee = mul_div_down(budget, denom, denom + fee_bps)?;
+    let mut qty = mul_div_down(before_fee, rules.price_scale, price)?;
+    qty -= qty % rules.lot_size;
+    while !qty.is_zero() {
+        let quote_amt = mul_div_down(price, qty, rules.price_scale)?;
+        if quote_amt + mul_div_up(quote_amt, fee_bps, denom)? <= budget {
+            break;
+        }
+        qty -= rules.lot_size;
+    }
+    Ok(qty)
+}
+
+// Caps a reduce-only order to the trader's exposure on the other side of the book: base locked in
+// resting asks for a buy, and quote locked in resting bids, valued at the limit, for a sell.
+fn reduce_only_qty<S: StateAccess>(
+    state: &mut S,
+    trader: &Address,
+    side: Side,
+    qty_base: U256,
+    price: U256,
+    rules: &Rules,
+) -> Result<U256, CoreError> {
+    let reducible = match side {
+        Side::Buy => get_balance(state, trader, &rules.base_asset_id)?.locked,
+        Side::Sell => {
+            let locked_quote = get_balance(state, trader, &rules.quote_asset_id)?.locked;
+            mul_div_down(locked_quote, rules.price_scale, price)?
+        }
+    };
+    let capped = qty_base.min(reducible);
+    let capped = capped - capped % rules.lot_size;
+    if capped.is_zero() {
+        return Err(CoreError::Invalid("reduce-only would increase exposure"));
+    }
+    Ok(capped)
+}
+
+// Rejects a crossing order whose limit is more than `max_price_deviation_bps` through the best
+// opposite price. Orders that would only rest are not checked.
+fn check_price_band(best: &MarketBest, side: Side, limit_price: U256, rules: &Rules) -> Result<(), CoreError> {
+    if rules.max_price_deviation_bps == 0 {
+        return Ok(());
+    }
+    let reference_tick = match side {
+        Side::Buy => best.best_ask,
+        Side::Sell => best.best_bid,
+    };
+    if reference_tick == NONE_TICK {
+        return Ok(());
+    }
+    let reference = price_from_tick(reference_tick, rules.tick_size)?;
+    let band = U256::from(rules.max_price_deviation_bps);
+    let denom = U256::from(10_000u64);
+    let outside = match side {
+        Side::Buy => {
+            limit_price >= reference && limit_price > mul_div_down(reference, denom + band, denom)?
+        }
+        Side::Sell => {
+            let floor = denom.saturating_sub(band);
+            limit_price <= reference && limit_price < mul_div_up(reference, floor, denom)?
+        }
+    };
+    if outside {
+        return Err(CoreError::Invalid("price outside band"));
+    }
+    Ok(())
+}
+
 fn ensure_balance_limit(balance: &Balance, max_balance: U256) -> Result<(), CoreError> {
     if balance.available > max_balance || balance.locked > max_balance {
         return Err(CoreError::Invalid("balance exceeds maxBalance"));
@@ -398,9 +1177,89 @@ fn ensure_balance_limit(balance: &Balance, max_balance: U256) -> Result<(), Core
     Ok(())
 }
 
+// Releases the remainder's collateral to the order's owner, whoever requested the cancel.
+fn cancel_order<S: StateAccess>(
+    state: &mut S,
+    market_id: &MarketId,
+    order_id: &OrderId,
+    mut order: Order,
+    rules: &Rules,
+) -> Result<(), CoreError> {
+    check_order_market(&order, market_id)?;
+    if order.status != OrderStatus::Open {
+        return Err(CoreError::Invalid("order not open"));
+    }
+    let scope = rules.order_scope(market_id);
+    check_queued(state, market_id, order.side, order.tick, order_id, rules)?;
+    let price = price_from_tick(order.tick, rules.tick_size)?;
+    release_remaining(state, &order.owner, order.side, order.qty_remaining, price, rules)?;
+    order.qty_remaining = U256::zero();
+    order.status = OrderStatus::Canceled;
+    set_order(state, scope, order_id, &order)?;
+    open_order_removed(state, &order.owner, rules)?;
+    remove_from_book(state, market_id, scope, order.side, order.tick, order_id)
+}
+
+// Under a global order id scope the record key no longer names the market, so the record must.
+fn check_order_market(order: &Order, market_id: &MarketId) -> Result<(), CoreError> {
+    if order.market_id != *market_id {
+        return Err(CoreError::Invalid("order in another market"));
+    }
+    Ok(())
+}
+
+// Checks the order's own links against its stored side and tick, so a corrupted order record cannot
+// unlink nodes from some other level: its predecessor must point back at it, and an order at either
+// end of its queue must be that end of the level. At most three leaves are read at any queue depth.
+fn check_queued<S: StateAccess>(
+    state: &mut S,
+    market_id: &MarketId,
+    side: Side,
+    tick: i32,
+    order_id: &OrderId,
+    rules: &Rules,
+) -> Result<(), CoreError> {
+    let scope = rules.order_scope(market_id);
+    let node = get_order_node(state, scope, order_id)?;
+    let mut linked = true;
+    if node.prev_order_id != NONE_ORDER_ID {
+        linked &= get_order_node(state, scope, &node.prev_order_id)?.next_order_id == *order_id;
+    }
+    if node.prev_order_id == NONE_ORDER_ID || node.next_order_id == NONE_ORDER_ID {
+        let level = get_tick_node(state, market_id, side.as_u8(), tick)?;
+        linked &= node.prev_order_id != NONE_ORDER_ID || level.head_order_id == *order_id;
+        linked &= node.next_order_id != NONE_ORDER_ID || level.tail_order_id == *order_id;
+    }
+    if !linked {
+        return Err(CoreError::State("order not queued at its tick"));
+    }
+    Ok(())
+}
+
+// Resting orders per owner are only counted while `max_open_orders_per_trader` is set.
+fn open_order_added<S: StateAccess>(state: &mut S, owner: &Address, rules: &Rules) -> Result<(), CoreError> {
+    if rules.max_open_orders_per_trader == 0 {
+        return Ok(());
+    }
+    let count = get_open_count(state, owner)?;
+    if count >= rules.max_open_orders_per_trader {
+        return Err(CoreError::Invalid("max open orders exceeded"));
+    }
+    set_open_count(state, owner, count + 1)
+}
+
+// Saturates: an order that rested before the cap was enabled was never counted.
+fn open_order_removed<S: StateAccess>(state: &mut S, owner: &Address, rules: &Rules) -> Result<(), CoreError> {
+    if rules.max_open_orders_per_trader == 0 {
+        return Ok(());
+    }
+    let count = get_open_count(state, owner)?;
+    set_open_count(state, owner, count.saturating_sub(1))
+}
+
 fn release_remaining<S: StateAccess>(
     state: &mut S,
-    trader: &[u8; 20],
+    trader: &Address,
     side: Side,
     remaining: U256,
     price: U256,
@@ -409,34 +1268,36 @@ fn release_remaining<S: StateAccess>(
     match side {
         Side::Buy => {
             let release = mul_div_up(price, remaining, rules.price_scale)?;
-            let mut bal = get_balance(state, trader, &rules.quote_asset_id)?;
-            if bal.locked < release {
-                return Err(CoreError::Invalid("locked quote insufficient"));
-            }
-            bal.locked -= release;
-            bal.available += release;
-            ensure_balance_limit(&bal, rules.max_balance)?;
-            set_balance(state, trader, &rules.quote_asset_id, &bal)?;
-        }
-        Side::Sell => {
-            let mut bal = get_balance(state, trader, &rules.base_asset_id)?;
-            if bal.locked < remaining {
-                return Err(CoreError::Invalid("locked base insufficient"));
-            }
-            bal.locked -= remaining;
-            bal.available += remaining;
-            ensure_balance_limit(&bal, rules.max_balance)?;
-            set_balance(state, trader, &rules.base_asset_id, &bal)?;
+            release_locked(state, trader, &rules.quote_asset_id, release, rules, "locked quote insufficient")
         }
+        Side::Sell => release_locked(state, trader, &rules.base_asset_id, remaining, rules, "locked base insufficient"),
     }
-    Ok(())
+}
+
+fn release_locked<S: StateAccess>(
+    state: &mut S,
+    trader: &Address,
+    asset: &AssetId,
+    amount: U256,
+    rules: &Rules,
+    insufficient: &'static str,
+) -> Result<(), CoreError> {
+    let mut bal = get_balance(state, trader, asset)?;
+    if bal.locked < amount {
+        return Err(CoreError::Invalid(insufficient));
+    }
+    bal.locked -= amount;
+    bal.available += amount;
+    ensure_balance_limit(&bal, rules.max_balance)?;
+    set_balance(state, trader, asset, &bal)
 }
 
 fn place_resting<S: StateAccess>(
     state: &mut S,
-    market_id: &[u8; 32],
-    order_id: &[u8; 32],
-    trader: &[u8; 20],
+    market_id: &MarketId,
+    scope: Option<&MarketId>,
+    order_id: &OrderId,
+    trader: &Address,
     side: Side,
     tick: i32,
     qty_remaining: U256,
@@ -444,7 +1305,9 @@ fn place_resting<S: StateAccess>(
     prev_tick_hint: i32,
     next_tick_hint: i32,
     best: &mut MarketBest,
+    steps: &mut BookSteps,
 ) -> Result<(), CoreError> {
+    steps.step()?;
     let mut tick_node = get_tick_node(state, market_id, side.as_u8(), tick)?;
     let active = tick_node.head_order_id != NONE_ORDER_ID;
     let old_tail = if active {
@@ -454,6 +1317,12 @@ fn place_resting<S: StateAccess>(
     };
 
     if !active {
+        // verify_tick_hints reads each non-sentinel hint's node once.
+        for hint in [prev_tick_hint, next_tick_hint] {
+            if hint != NONE_TICK {
+                steps.step()?;
+            }
+        }
         verify_tick_hints(state, market_id, side, tick, prev_tick_hint, next_tick_hint, best)?;
         tick_node.prev_tick = prev_tick_hint;
         tick_node.next_tick = next_tick_hint;
@@ -461,11 +1330,13 @@ fn place_resting<S: StateAccess>(
         tick_node.tail_order_id = *order_id;
 
         if prev_tick_hint != NONE_TICK {
+            steps.step()?;
             let mut prev_node = get_tick_node(state, market_id, side.as_u8(), prev_tick_hint)?;
             prev_node.next_tick = tick;
             set_tick_node(state, market_id, side.as_u8(), prev_tick_hint, &prev_node)?;
         }
         if next_tick_hint != NONE_TICK {
+            steps.step()?;
             let mut next_node = get_tick_node(state, market_id, side.as_u8(), next_tick_hint)?;
             next_node.prev_tick = tick;
             set_tick_node(state, market_id, side.as_u8(), next_tick_hint, &next_node)?;
@@ -484,11 +1355,17 @@ fn place_resting<S: StateAccess>(
         }
         set_market_best(state, market_id, best)?;
     } else {
+        if (prev_tick_hint != NONE_TICK && prev_tick_hint != tick_node.prev_tick)
+            || (next_tick_hint != NONE_TICK && next_tick_hint != tick_node.next_tick)
+        {
+            return Err(CoreError::Invalid("hints inconsistent with active tick"));
+        }
         let tail_id = tick_node.tail_order_id;
         if tail_id != NONE_ORDER_ID {
-            let mut tail_node = get_order_node(state, &tail_id)?;
+            steps.step()?;
+            let mut tail_node = get_order_node(state, scope, &tail_id)?;
             tail_node.next_order_id = *order_id;
-            set_order_node(state, &tail_id, &tail_node)?;
+            set_order_node(state, scope, &tail_id, &tail_node)?;
         }
         tick_node.tail_order_id = *order_id;
     }
@@ -496,9 +1373,11 @@ fn place_resting<S: StateAccess>(
     set_tick_node(state, market_id, side.as_u8(), tick, &tick_node)?;
     set_order(
         state,
+        scope,
         order_id,
         &Order {
             owner: *trader,
+            market_id: *market_id,
             side,
             tick,
             qty_remaining,
@@ -508,6 +1387,7 @@ fn place_resting<S: StateAccess>(
     )?;
     set_order_node(
         state,
+        scope,
         order_id,
         &OrderNode {
             prev_order_id: old_tail,
@@ -520,7 +1400,7 @@ fn place_resting<S: StateAccess>(
 
 fn verify_tick_hints<S: StateAccess>(
     state: &mut S,
-    market_id: &[u8; 32],
+    market_id: &MarketId,
     side: Side,
     tick: i32,
     prev_tick: i32,
@@ -569,33 +1449,35 @@ fn verify_tick_hints<S: StateAccess>(
 
 fn remove_from_book<S: StateAccess>(
     state: &mut S,
-    market_id: &[u8; 32],
+    market_id: &MarketId,
+    scope: Option<&MarketId>,
     side: Side,
     tick: i32,
-    order_id: &[u8; 32],
+    order_id: &OrderId,
 ) -> Result<(), CoreError> {
     let mut tick_node = get_tick_node(state, market_id, side.as_u8(), tick)?;
-    let order_node = get_order_node(state, order_id)?;
+    let order_node = get_order_node(state, scope, order_id)?;
     let prev_id = order_node.prev_order_id;
     let next_id = order_node.next_order_id;
 
     if prev_id != NONE_ORDER_ID {
-        let mut prev_node = get_order_node(state, &prev_id)?;
+        let mut prev_node = get_order_node(state, scope, &prev_id)?;
         prev_node.next_order_id = next_id;
-        set_order_node(state, &prev_id, &prev_node)?;
+        set_order_node(state, scope, &prev_id, &prev_node)?;
     } else {
         tick_node.head_order_id = next_id;
     }
     if next_id != NONE_ORDER_ID {
-        let mut next_node = get_order_node(state, &next_id)?;
+        let mut next_node = get_order_node(state, scope, &next_id)?;
         next_node.prev_order_id = prev_id;
-        set_order_node(state, &next_id, &next_node)?;
+        set_order_node(state, scope, &next_id, &next_node)?;
     } else {
         tick_node.tail_order_id = prev_id;
     }
 
     set_order_node(
         state,
+        scope,
         order_id,
         &OrderNode {
             prev_order_id: NONE_ORDER_ID,
@@ -7,6 +7,9 @@ pub enum CoreError {
     Math(&'static str),
     Signature(&'static str),
     State(&'static str),
+    Proof { expected: usize, got: usize },
+    // Execution asked for proof `index` (zero-based) for `key` after the supplied proofs ran out.
+    MissingProof { index: usize, key: [u8; 32] },
 }
 
 impl fmt::Display for CoreError {
@@ -17,6 +20,16 @@ impl fmt::Display for CoreError {
             CoreError::Math(msg) => write!(f, "math error: {msg}"),
             CoreError::Signature(msg) => write!(f, "signature error: {msg}"),
             CoreError::State(msg) => write!(f, "state error: {msg}"),
+            CoreError::Proof { expected, got } => {
+                write!(f, "proof count mismatch: expected {expected}, got {got}")
+            }
+            CoreError::MissingProof { index, key } => {
+                write!(f, "proof count mismatch: ran out at proof {index} for key 0x")?;
+                for byte in key {
+                    write!(f, "{byte:02x}")?;
+                }
+                Ok(())
+            }
         }
     }
 }
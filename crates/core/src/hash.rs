@@ -1,4 +1,20 @@
-use tiny_keccak::{Hasher, Keccak};
+use k256::sha2::{Digest as _, Sha256};
+use tiny_keccak::{Hasher as _, Keccak};
+
+use crate::errors::CoreError;
+
+pub trait Hasher {
+    fn hash(data: &[u8]) -> [u8; 32];
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Keccak256;
+
+impl Hasher for Keccak256 {
+    fn hash(data: &[u8]) -> [u8; 32] {
+        keccak256(data)
+    }
+}
 
 pub fn keccak256(data: &[u8]) -> [u8; 32] {
     let mut hasher = Keccak::v256();
@@ -7,3 +23,40 @@ pub fn keccak256(data: &[u8]) -> [u8; 32] {
     hasher.finalize(&mut out);
     out
 }
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Hash for side-channel digests that are never committed, such as the touched-keys digest.
+/// Consensus hashing is always keccak256.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DigestKind {
+    #[default]
+    Keccak256,
+    Sha256,
+}
+
+impl DigestKind {
+    pub fn from_u8(value: u8) -> Result<Self, CoreError> {
+        match value {
+            0 => Ok(DigestKind::Keccak256),
+            1 => Ok(DigestKind::Sha256),
+            _ => Err(CoreError::Decode("invalid digest kind")),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            DigestKind::Keccak256 => 0,
+            DigestKind::Sha256 => 1,
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> [u8; 32] {
+        match self {
+            DigestKind::Keccak256 => keccak256(data),
+            DigestKind::Sha256 => sha256(data),
+        }
+    }
+}
@@ -1,14 +1,18 @@
 use alloc::vec::Vec;
 
+use crate::constants::{MAX_MESSAGES_PER_BUNDLE, MAX_PROOFS_PER_BUNDLE, MAX_VALUE_LEN, PRICE_SCALE};
 use crate::encoding::{Reader, Writer};
 use crate::errors::CoreError;
+use crate::hash::DigestKind;
 use crate::merkle::Proof;
-use crate::types::{Side, TimeInForce, U256};
+use crate::types::{
+    Address, AllocationMode, AssetId, FailureMode, MarketId, OrderId, OrderIdScope, Side, TimeInForce, U256,
+};
 
 #[derive(Clone, Debug)]
 pub struct Rules {
-    pub base_asset_id: [u8; 32],
-    pub quote_asset_id: [u8; 32],
+    pub base_asset_id: AssetId,
+    pub quote_asset_id: AssetId,
     pub price_scale: U256,
     pub tick_size: U256,
     pub lot_size: U256,
@@ -16,14 +20,81 @@ pub struct Rules {
     pub maker_fee_bps: u32,
     pub max_orders_per_batch: u32,
     pub max_matches_per_order: u32,
+    pub max_fills_per_batch: u32,
+    pub allocation_mode: AllocationMode,
+    pub min_notional: U256,
     pub max_balance: U256,
+    pub fee_collector: Address,
+    pub fee_in_received_asset: bool,
+    pub enforce_derived_order_id: bool,
+    pub admin: Address,
+    pub max_price_deviation_bps: u32,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    // When false the guest commits zero for `trades_root`/`fees_root` and skips hashing the records.
+    pub commit_trade_roots: bool,
+    pub max_open_orders_per_trader: u32,
+    // Tick-node and order-node reads one Place may make; 0 disables the bound.
+    pub max_book_steps_per_order: u32,
+    // Placed ticks must be multiples of this; price per tick is still `tick_size`.
+    pub tick_spacing: i32,
+    // Quote notional per order charged at `taker_fee_bps`; fills beyond it pay `taker_fee_bps_tier2`.
+    // Zero disables tiering.
+    pub taker_fee_tier_notional: U256,
+    pub taker_fee_bps_tier2: u32,
+    // Global ids let an order in one market block the same id in every other market sharing the
+    // tree; per-market ids keep markets independent.
+    pub order_id_scope: OrderIdScope,
+    // Signatures commit to the batch sequence number, so a message only verifies in the batch it was
+    // signed for and cannot be replayed into a later one.
+    pub bind_batch_seq: bool,
+    // Committed so the guest and every host apply a failing message the same way.
+    pub failure_mode: FailureMode,
 }
 
 impl Rules {
+    /// Checks the invariants the engine relies on; `apply_batch` rejects rules that fail here.
+    pub fn validate(&self) -> Result<(), CoreError> {
+        if self.price_scale != U256::from(PRICE_SCALE) {
+            return Err(CoreError::Invalid("priceScale must be 1e18"));
+        }
+        if self.maker_fee_bps != 0 {
+            return Err(CoreError::Invalid("makerFeeBps must be zero"));
+        }
+        if self.taker_fee_bps > 10_000 {
+            return Err(CoreError::Invalid("takerFeeBps exceeds 10000"));
+        }
+        if self.tick_size.is_zero() {
+            return Err(CoreError::Invalid("tick size zero"));
+        }
+        if self.lot_size.is_zero() {
+            return Err(CoreError::Invalid("lot size zero"));
+        }
+        if self.tick_spacing <= 0 {
+            return Err(CoreError::Invalid("tick spacing not positive"));
+        }
+        // Quote-budget sizing assumes `taker_fee_bps` is the highest rate a fill can pay.
+        if !self.taker_fee_tier_notional.is_zero() && self.taker_fee_bps_tier2 > self.taker_fee_bps {
+            return Err(CoreError::Invalid("takerFeeBpsTier2 exceeds takerFeeBps"));
+        }
+        if self.max_balance.is_zero() {
+            return Err(CoreError::Invalid("max balance zero"));
+        }
+        if self.base_decimals > 77 || self.quote_decimals > 77 {
+            return Err(CoreError::Invalid("decimals exceed 77"));
+        }
+        Ok(())
+    }
+
+    /// The market order keys are scoped to, or `None` for a global order-id namespace.
+    pub fn order_scope<'a>(&self, market_id: &'a MarketId) -> Option<&'a MarketId> {
+        self.order_id_scope.market(market_id)
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         let mut w = Writer::new();
-        w.write_b32(&self.base_asset_id);
-        w.write_b32(&self.quote_asset_id);
+        w.write_b32(self.base_asset_id.as_bytes());
+        w.write_b32(self.quote_asset_id.as_bytes());
         w.write_u256(&self.price_scale);
         w.write_u256(&self.tick_size);
         w.write_u256(&self.lot_size);
@@ -31,14 +102,33 @@ impl Rules {
         w.write_u32(self.maker_fee_bps);
         w.write_u32(self.max_orders_per_batch);
         w.write_u32(self.max_matches_per_order);
+        w.write_u32(self.max_fills_per_batch);
+        w.write_u8(self.allocation_mode.as_u8());
+        w.write_u256(&self.min_notional);
         w.write_u256(&self.max_balance);
+        w.write_addr(&self.fee_collector);
+        w.write_u8(self.fee_in_received_asset as u8);
+        w.write_u8(self.enforce_derived_order_id as u8);
+        w.write_addr(&self.admin);
+        w.write_u32(self.max_price_deviation_bps);
+        w.write_u8(self.base_decimals);
+        w.write_u8(self.quote_decimals);
+        w.write_u8(self.commit_trade_roots as u8);
+        w.write_u32(self.max_open_orders_per_trader);
+        w.write_u32(self.max_book_steps_per_order);
+        w.write_i32(self.tick_spacing);
+        w.write_u256(&self.taker_fee_tier_notional);
+        w.write_u32(self.taker_fee_bps_tier2);
+        w.write_u8(self.order_id_scope.as_u8());
+        w.write_u8(self.bind_batch_seq as u8);
+        w.write_u8(self.failure_mode.as_u8());
         w.into_bytes()
     }
 
     pub fn decode(reader: &mut Reader) -> Result<Self, CoreError> {
         Ok(Self {
-            base_asset_id: reader.read_b32()?,
-            quote_asset_id: reader.read_b32()?,
+            base_asset_id: AssetId(reader.read_b32()?),
+            quote_asset_id: AssetId(reader.read_b32()?),
             price_scale: reader.read_u256()?,
             tick_size: reader.read_u256()?,
             lot_size: reader.read_u256()?,
@@ -46,11 +136,218 @@ impl Rules {
             maker_fee_bps: reader.read_u32()?,
             max_orders_per_batch: reader.read_u32()?,
             max_matches_per_order: reader.read_u32()?,
+            max_fills_per_batch: reader.read_u32()?,
+            allocation_mode: AllocationMode::from_u8(reader.read_u8()?)?,
+            min_notional: reader.read_u256()?,
             max_balance: reader.read_u256()?,
+            fee_collector: reader.read_addr()?,
+            fee_in_received_asset: decode_flag(reader.read_u8()?, "invalid fee asset flag")?,
+            enforce_derived_order_id: decode_flag(reader.read_u8()?, "invalid order id flag")?,
+            admin: reader.read_addr()?,
+            max_price_deviation_bps: reader.read_u32()?,
+            base_decimals: reader.read_u8()?,
+            quote_decimals: reader.read_u8()?,
+            commit_trade_roots: decode_flag(reader.read_u8()?, "invalid trade roots flag")?,
+            max_open_orders_per_trader: reader.read_u32()?,
+            max_book_steps_per_order: reader.read_u32()?,
+            tick_spacing: reader.read_i32()?,
+            taker_fee_tier_notional: reader.read_u256()?,
+            taker_fee_bps_tier2: reader.read_u32()?,
+            order_id_scope: OrderIdScope::from_u8(reader.read_u8()?)?,
+            bind_batch_seq: decode_flag(reader.read_u8()?, "invalid bind batch seq flag")?,
+            failure_mode: FailureMode::from_u8(reader.read_u8()?)?,
         })
     }
 }
 
+fn decode_flag(value: u8, err: &'static str) -> Result<bool, CoreError> {
+    match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(CoreError::Decode(err)),
+    }
+}
+
+/// Builds `Rules` from the spec defaults, validating the result in `build`.
+#[derive(Clone, Debug)]
+pub struct RulesBuilder {
+    rules: Rules,
+}
+
+impl RulesBuilder {
+    pub fn new(base_asset_id: AssetId, quote_asset_id: AssetId) -> Self {
+        Self {
+            rules: Rules {
+                base_asset_id,
+                quote_asset_id,
+                price_scale: U256::from(PRICE_SCALE),
+                tick_size: U256::from(PRICE_SCALE),
+                lot_size: U256::one(),
+                taker_fee_bps: 0,
+                maker_fee_bps: 0,
+                max_orders_per_batch: 128,
+                max_matches_per_order: 64,
+                max_fills_per_batch: 1024,
+                allocation_mode: AllocationMode::Fifo,
+                min_notional: U256::zero(),
+                max_balance: U256::MAX,
+                fee_collector: Address::ZERO,
+                fee_in_received_asset: false,
+                enforce_derived_order_id: false,
+                admin: Address::ZERO,
+                max_price_deviation_bps: 0,
+                base_decimals: 18,
+                quote_decimals: 18,
+                commit_trade_roots: true,
+                max_open_orders_per_trader: 0,
+                max_book_steps_per_order: 0,
+                tick_spacing: 1,
+                taker_fee_tier_notional: U256::zero(),
+                taker_fee_bps_tier2: 0,
+                order_id_scope: OrderIdScope::Market,
+                bind_batch_seq: false,
+                failure_mode: FailureMode::Abort,
+            },
+        }
+    }
+
+    pub fn price_scale(mut self, price_scale: U256) -> Self {
+        self.rules.price_scale = price_scale;
+        self
+    }
+
+    pub fn tick_size(mut self, tick_size: U256) -> Self {
+        self.rules.tick_size = tick_size;
+        self
+    }
+
+    pub fn lot_size(mut self, lot_size: U256) -> Self {
+        self.rules.lot_size = lot_size;
+        self
+    }
+
+    pub fn taker_fee_bps(mut self, taker_fee_bps: u32) -> Self {
+        self.rules.taker_fee_bps = taker_fee_bps;
+        self
+    }
+
+    pub fn maker_fee_bps(mut self, maker_fee_bps: u32) -> Self {
+        self.rules.maker_fee_bps = maker_fee_bps;
+        self
+    }
+
+    pub fn max_orders_per_batch(mut self, max_orders_per_batch: u32) -> Self {
+        self.rules.max_orders_per_batch = max_orders_per_batch;
+        self
+    }
+
+    pub fn max_matches_per_order(mut self, max_matches_per_order: u32) -> Self {
+        self.rules.max_matches_per_order = max_matches_per_order;
+        self
+    }
+
+    pub fn max_fills_per_batch(mut self, max_fills_per_batch: u32) -> Self {
+        self.rules.max_fills_per_batch = max_fills_per_batch;
+        self
+    }
+
+    pub fn allocation_mode(mut self, allocation_mode: AllocationMode) -> Self {
+        self.rules.allocation_mode = allocation_mode;
+        self
+    }
+
+    pub fn min_notional(mut self, min_notional: U256) -> Self {
+        self.rules.min_notional = min_notional;
+        self
+    }
+
+    pub fn max_balance(mut self, max_balance: U256) -> Self {
+        self.rules.max_balance = max_balance;
+        self
+    }
+
+    pub fn fee_collector(mut self, fee_collector: Address) -> Self {
+        self.rules.fee_collector = fee_collector;
+        self
+    }
+
+    pub fn fee_in_received_asset(mut self, fee_in_received_asset: bool) -> Self {
+        self.rules.fee_in_received_asset = fee_in_received_asset;
+        self
+    }
+
+    pub fn enforce_derived_order_id(mut self, enforce_derived_order_id: bool) -> Self {
+        self.rules.enforce_derived_order_id = enforce_derived_order_id;
+        self
+    }
+
+    pub fn admin(mut self, admin: Address) -> Self {
+        self.rules.admin = admin;
+        self
+    }
+
+    pub fn max_price_deviation_bps(mut self, max_price_deviation_bps: u32) -> Self {
+        self.rules.max_price_deviation_bps = max_price_deviation_bps;
+        self
+    }
+
+    pub fn base_decimals(mut self, base_decimals: u8) -> Self {
+        self.rules.base_decimals = base_decimals;
+        self
+    }
+
+    pub fn quote_decimals(mut self, quote_decimals: u8) -> Self {
+        self.rules.quote_decimals = quote_decimals;
+        self
+    }
+
+    pub fn commit_trade_roots(mut self, commit_trade_roots: bool) -> Self {
+        self.rules.commit_trade_roots = commit_trade_roots;
+        self
+    }
+
+    pub fn max_open_orders_per_trader(mut self, max_open_orders_per_trader: u32) -> Self {
+        self.rules.max_open_orders_per_trader = max_open_orders_per_trader;
+        self
+    }
+
+    pub fn max_book_steps_per_order(mut self, max_book_steps_per_order: u32) -> Self {
+        self.rules.max_book_steps_per_order = max_book_steps_per_order;
+        self
+    }
+
+    pub fn tick_spacing(mut self, tick_spacing: i32) -> Self {
+        self.rules.tick_spacing = tick_spacing;
+        self
+    }
+
+    pub fn taker_fee_tier(mut self, tier_notional: U256, tier2_bps: u32) -> Self {
+        self.rules.taker_fee_tier_notional = tier_notional;
+        self.rules.taker_fee_bps_tier2 = tier2_bps;
+        self
+    }
+
+    pub fn order_id_scope(mut self, order_id_scope: OrderIdScope) -> Self {
+        self.rules.order_id_scope = order_id_scope;
+        self
+    }
+
+    pub fn bind_batch_seq(mut self, enabled: bool) -> Self {
+        self.rules.bind_batch_seq = enabled;
+        self
+    }
+
+    pub fn failure_mode(mut self, failure_mode: FailureMode) -> Self {
+        self.rules.failure_mode = failure_mode;
+        self
+    }
+
+    pub fn build(self) -> Result<Rules, CoreError> {
+        self.rules.validate()?;
+        Ok(self.rules)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PublicInputsPartial {
     pub prev_root: [u8; 32],
@@ -59,6 +356,8 @@ pub struct PublicInputsPartial {
     pub domain_separator: [u8; 32],
     pub batch_seq: u64,
     pub batch_timestamp: u64,
+    // Latest `batch_timestamp` the guest accepts for this batch; zero disables the check.
+    pub batch_deadline: u64,
     pub da_commitment: [u8; 32],
 }
 
@@ -71,6 +370,7 @@ impl PublicInputsPartial {
         w.write_b32(&self.domain_separator);
         w.write_u64(self.batch_seq);
         w.write_u64(self.batch_timestamp);
+        w.write_u64(self.batch_deadline);
         w.write_b32(&self.da_commitment);
         w.into_bytes()
     }
@@ -83,12 +383,13 @@ impl PublicInputsPartial {
             domain_separator: reader.read_b32()?,
             batch_seq: reader.read_u64()?,
             batch_timestamp: reader.read_u64()?,
+            batch_deadline: reader.read_u64()?,
             da_commitment: reader.read_b32()?,
         })
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PublicInputs {
     pub prev_root: [u8; 32],
     pub new_root: [u8; 32],
@@ -97,6 +398,7 @@ pub struct PublicInputs {
     pub domain_separator: [u8; 32],
     pub batch_seq: u64,
     pub batch_timestamp: u64,
+    pub batch_deadline: u64,
     pub da_commitment: [u8; 32],
     pub trades_root: [u8; 32],
     pub fees_root: [u8; 32],
@@ -112,6 +414,7 @@ impl PublicInputs {
         w.write_b32(&self.domain_separator);
         w.write_u64(self.batch_seq);
         w.write_u64(self.batch_timestamp);
+        w.write_u64(self.batch_deadline);
         w.write_b32(&self.da_commitment);
         w.write_b32(&self.trades_root);
         w.write_b32(&self.fees_root);
@@ -127,32 +430,113 @@ pub struct MessageSignature {
 }
 
 impl MessageSignature {
-    pub fn encode(&self) -> [u8; 65] {
+    pub fn y_parity(&self) -> Result<u8, CoreError> {
+        match self.v {
+            0 | 1 => Ok(self.v),
+            27 | 28 => Ok(self.v - 27),
+            _ => Err(CoreError::Signature("invalid v")),
+        }
+    }
+
+    // The wire form carries `v` as the 0/1 parity, so each signature has one byte encoding; a `v`
+    // with no parity has none and is rejected rather than written through.
+    pub fn encode(&self) -> Result<[u8; 65], CoreError> {
         let mut out = [0u8; 65];
         out[..32].copy_from_slice(&self.r);
         out[32..64].copy_from_slice(&self.s);
-        out[64] = self.v;
-        out
+        out[64] = self.y_parity()?;
+        Ok(out)
+    }
+
+    // EIP-2098: the recovery parity is packed into the top bit of `s`. Only host JSON accepts this
+    // form; `SignedMessage` and `GuestInput` always carry the 65-byte one.
+    pub fn encode_compact(&self) -> Result<[u8; 64], CoreError> {
+        let y_parity = self.y_parity()?;
+        if self.s[0] & 0x80 != 0 {
+            return Err(CoreError::Signature("high s"));
+        }
+        let mut out = [0u8; 64];
+        out[..32].copy_from_slice(&self.r);
+        out[32..].copy_from_slice(&self.s);
+        out[32] |= y_parity << 7;
+        Ok(out)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, CoreError> {
+        if bytes.len() == 65 && bytes[64] > 1 {
+            return Err(CoreError::Decode("non-canonical v"));
+        }
+        Self::decode_relaxed(bytes)
+    }
+
+    // Also accepts the legacy `v` of 27/28 in the 65-byte form, normalized to 0/1.
+    pub fn decode_relaxed(bytes: &[u8]) -> Result<Self, CoreError> {
+        match bytes.len() {
+            65 => {
+                let sig = Self {
+                    r: bytes[..32].try_into().unwrap(),
+                    s: bytes[32..64].try_into().unwrap(),
+                    v: bytes[64],
+                };
+                Ok(Self {
+                    v: sig.y_parity()?,
+                    ..sig
+                })
+            }
+            64 => {
+                let mut s: [u8; 32] = bytes[32..].try_into().unwrap();
+                let y_parity = s[0] >> 7;
+                s[0] &= 0x7f;
+                Ok(Self {
+                    r: bytes[..32].try_into().unwrap(),
+                    s,
+                    v: y_parity,
+                })
+            }
+            _ => Err(CoreError::Decode("invalid signature length")),
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub enum Message {
     Place {
-        trader: [u8; 20],
+        trader: Address,
         nonce: u64,
-        order_id: [u8; 32],
+        order_id: OrderId,
         side: Side,
         tif: TimeInForce,
         tick_index: i32,
         qty_base: U256,
         prev_tick_hint: i32,
         next_tick_hint: i32,
+        reduce_only: bool,
+        quote_budget: bool,
     },
     Cancel {
-        trader: [u8; 20],
+        trader: Address,
+        nonce: u64,
+        order_id: OrderId,
+    },
+    CollectFees {
+        operator: Address,
+        nonce: u64,
+        asset: AssetId,
+        amount: U256,
+        to: Address,
+    },
+    ForceCancel {
+        operator: Address,
         nonce: u64,
-        order_id: [u8; 32],
+        order_id: OrderId,
+    },
+    // Takes up to `max_qty` from one named resting order at its price, without walking the book.
+    FillOrder {
+        trader: Address,
+        nonce: u64,
+        order_id: OrderId,
+        maker_order_id: OrderId,
+        max_qty: U256,
     },
 }
 
@@ -161,6 +545,9 @@ impl Message {
         match self {
             Message::Place { .. } => 0x01,
             Message::Cancel { .. } => 0x02,
+            Message::CollectFees { .. } => 0x03,
+            Message::ForceCancel { .. } => 0x04,
+            Message::FillOrder { .. } => 0x05,
         }
     }
 
@@ -176,22 +563,61 @@ impl Message {
                 tif,
                 tick_index,
                 qty_base,
+                reduce_only,
+                quote_budget,
                 ..
             } => {
                 w.write_addr(trader);
                 w.write_u64(*nonce);
-                w.write_b32(order_id);
+                w.write_b32(order_id.as_bytes());
                 w.write_u8(side.as_u8());
                 w.write_u32(tif.as_u32());
                 w.write_i32(*tick_index);
                 w.write_u256(qty_base);
+                w.write_u8(*reduce_only as u8);
+                w.write_u8(*quote_budget as u8);
             }
             Message::Cancel {
                 trader, nonce, order_id, ..
             } => {
                 w.write_addr(trader);
                 w.write_u64(*nonce);
-                w.write_b32(order_id);
+                w.write_b32(order_id.as_bytes());
+            }
+            Message::CollectFees {
+                operator,
+                nonce,
+                asset,
+                amount,
+                to,
+            } => {
+                w.write_addr(operator);
+                w.write_u64(*nonce);
+                w.write_b32(asset.as_bytes());
+                w.write_u256(amount);
+                w.write_addr(to);
+            }
+            Message::ForceCancel {
+                operator,
+                nonce,
+                order_id,
+            } => {
+                w.write_addr(operator);
+                w.write_u64(*nonce);
+                w.write_b32(order_id.as_bytes());
+            }
+            Message::FillOrder {
+                trader,
+                nonce,
+                order_id,
+                maker_order_id,
+                max_qty,
+            } => {
+                w.write_addr(trader);
+                w.write_u64(*nonce);
+                w.write_b32(order_id.as_bytes());
+                w.write_b32(maker_order_id.as_bytes());
+                w.write_u256(max_qty);
             }
         }
         w.into_bytes()
@@ -204,138 +630,254 @@ pub struct SignedMessage {
     pub signature: MessageSignature,
 }
 
+impl SignedMessage {
+    // Wire form inside `GuestInput`: type byte, signed fields, signature, then any unsigned hints.
+    pub fn encode(&self) -> Result<Vec<u8>, CoreError> {
+        let sig = self.signature.encode()?;
+        let mut w = Writer::new();
+        match &self.message {
+            Message::Place {
+                trader,
+                nonce,
+                order_id,
+                side,
+                tif,
+                tick_index,
+                qty_base,
+                prev_tick_hint,
+                next_tick_hint,
+                reduce_only,
+                quote_budget,
+            } => {
+                w.write_u8(0x01);
+                w.write_addr(trader);
+                w.write_u64(*nonce);
+                w.write_b32(order_id.as_bytes());
+                w.write_u8(side.as_u8());
+                w.write_u32(tif.as_u32());
+                w.write_i32(*tick_index);
+                w.write_u256(qty_base);
+                w.write_u8(*reduce_only as u8);
+                w.write_u8(*quote_budget as u8);
+                w.write_raw(&sig);
+                w.write_i32(*prev_tick_hint);
+                w.write_i32(*next_tick_hint);
+            }
+            Message::Cancel {
+                trader,
+                nonce,
+                order_id,
+            } => {
+                w.write_u8(0x02);
+                w.write_addr(trader);
+                w.write_u64(*nonce);
+                w.write_b32(order_id.as_bytes());
+                w.write_raw(&sig);
+            }
+            Message::CollectFees {
+                operator,
+                nonce,
+                asset,
+                amount,
+                to,
+            } => {
+                w.write_u8(0x03);
+                w.write_addr(operator);
+                w.write_u64(*nonce);
+                w.write_b32(asset.as_bytes());
+                w.write_u256(amount);
+                w.write_addr(to);
+                w.write_raw(&sig);
+            }
+            Message::ForceCancel {
+                operator,
+                nonce,
+                order_id,
+            } => {
+                w.write_u8(0x04);
+                w.write_addr(operator);
+                w.write_u64(*nonce);
+                w.write_b32(order_id.as_bytes());
+                w.write_raw(&sig);
+            }
+            Message::FillOrder {
+                trader,
+                nonce,
+                order_id,
+                maker_order_id,
+                max_qty,
+            } => {
+                w.write_u8(0x05);
+                w.write_addr(trader);
+                w.write_u64(*nonce);
+                w.write_b32(order_id.as_bytes());
+                w.write_b32(maker_order_id.as_bytes());
+                w.write_u256(max_qty);
+                w.write_raw(&sig);
+            }
+        }
+        Ok(w.into_bytes())
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, CoreError> {
+        let msg_type = reader.read_u8()?;
+        match msg_type {
+            0x01 => {
+                let trader = reader.read_addr()?;
+                let nonce = reader.read_u64()?;
+                let order_id = OrderId(reader.read_b32()?);
+                let side = Side::from_u8(reader.read_u8()?)?;
+                let tif = TimeInForce::from_u32(reader.read_u32()?)?;
+                let tick_index = reader.read_i32()?;
+                let qty_base = reader.read_u256()?;
+                let reduce_only = decode_flag(reader.read_u8()?, "invalid reduce-only flag")?;
+                let quote_budget = decode_flag(reader.read_u8()?, "invalid quote budget flag")?;
+                let signature = MessageSignature::decode(reader.read_exact(65)?)?;
+                let prev_tick_hint = reader.read_i32()?;
+                let next_tick_hint = reader.read_i32()?;
+                Ok(Self {
+                    message: Message::Place {
+                        trader,
+                        nonce,
+                        order_id,
+                        side,
+                        tif,
+                        tick_index,
+                        qty_base,
+                        prev_tick_hint,
+                        next_tick_hint,
+                        reduce_only,
+                        quote_budget,
+                    },
+                    signature,
+                })
+            }
+            0x02 => {
+                let trader = reader.read_addr()?;
+                let nonce = reader.read_u64()?;
+                let order_id = OrderId(reader.read_b32()?);
+                let signature = MessageSignature::decode(reader.read_exact(65)?)?;
+                Ok(Self {
+                    message: Message::Cancel {
+                        trader,
+                        nonce,
+                        order_id,
+                    },
+                    signature,
+                })
+            }
+            0x03 => {
+                let operator = reader.read_addr()?;
+                let nonce = reader.read_u64()?;
+                let asset = AssetId(reader.read_b32()?);
+                let amount = reader.read_u256()?;
+                let to = reader.read_addr()?;
+                let signature = MessageSignature::decode(reader.read_exact(65)?)?;
+                Ok(Self {
+                    message: Message::CollectFees {
+                        operator,
+                        nonce,
+                        asset,
+                        amount,
+                        to,
+                    },
+                    signature,
+                })
+            }
+            0x04 => {
+                let operator = reader.read_addr()?;
+                let nonce = reader.read_u64()?;
+                let order_id = OrderId(reader.read_b32()?);
+                let signature = MessageSignature::decode(reader.read_exact(65)?)?;
+                Ok(Self {
+                    message: Message::ForceCancel {
+                        operator,
+                        nonce,
+                        order_id,
+                    },
+                    signature,
+                })
+            }
+            0x05 => {
+                let trader = reader.read_addr()?;
+                let nonce = reader.read_u64()?;
+                let order_id = OrderId(reader.read_b32()?);
+                let maker_order_id = OrderId(reader.read_b32()?);
+                let max_qty = reader.read_u256()?;
+                let signature = MessageSignature::decode(reader.read_exact(65)?)?;
+                Ok(Self {
+                    message: Message::FillOrder {
+                        trader,
+                        nonce,
+                        order_id,
+                        maker_order_id,
+                        max_qty,
+                    },
+                    signature,
+                })
+            }
+            _ => Err(CoreError::Decode("unknown message type")),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GuestInput {
     pub public: PublicInputsPartial,
     pub chain_id: u64,
     pub venue_id: [u8; 32],
-    pub market_id: [u8; 32],
+    pub market_id: MarketId,
+    pub instance_salt: [u8; 32],
     pub rules: Rules,
+    pub commit_rules: bool,
+    pub touched_digest: DigestKind,
     pub messages: Vec<SignedMessage>,
 }
 
 impl GuestInput {
-    pub fn encode(&self) -> Vec<u8> {
+    pub fn encode(&self) -> Result<Vec<u8>, CoreError> {
         let mut w = Writer::new();
         w.write_raw(&self.public.encode());
         w.write_u64(self.chain_id);
         w.write_b32(&self.venue_id);
-        w.write_b32(&self.market_id);
+        w.write_b32(self.market_id.as_bytes());
+        w.write_b32(&self.instance_salt);
         w.write_raw(&self.rules.encode());
+        w.write_u8(self.commit_rules as u8);
+        w.write_u8(self.touched_digest.as_u8());
         w.write_u32(self.messages.len() as u32);
         for msg in &self.messages {
-            match &msg.message {
-                Message::Place {
-                    trader,
-                    nonce,
-                    order_id,
-                    side,
-                    tif,
-                    tick_index,
-                    qty_base,
-                    prev_tick_hint,
-                    next_tick_hint,
-                } => {
-                    w.write_u8(0x01);
-                    w.write_addr(trader);
-                    w.write_u64(*nonce);
-                    w.write_b32(order_id);
-                    w.write_u8(side.as_u8());
-                    w.write_u32(tif.as_u32());
-                    w.write_i32(*tick_index);
-                    w.write_u256(qty_base);
-                    let sig = msg.signature.encode();
-                    w.write_raw(&sig);
-                    w.write_i32(*prev_tick_hint);
-                    w.write_i32(*next_tick_hint);
-                }
-                Message::Cancel {
-                    trader,
-                    nonce,
-                    order_id,
-                } => {
-                    w.write_u8(0x02);
-                    w.write_addr(trader);
-                    w.write_u64(*nonce);
-                    w.write_b32(order_id);
-                    let sig = msg.signature.encode();
-                    w.write_raw(&sig);
-                }
-            }
+            w.write_raw(&msg.encode()?);
         }
-        w.into_bytes()
+        Ok(w.into_bytes())
     }
 
     pub fn decode(reader: &mut Reader) -> Result<Self, CoreError> {
         let public = PublicInputsPartial::decode(reader)?;
         let chain_id = reader.read_u64()?;
         let venue_id = reader.read_b32()?;
-        let market_id = reader.read_b32()?;
+        let market_id = MarketId(reader.read_b32()?);
+        let instance_salt = reader.read_b32()?;
         let rules = Rules::decode(reader)?;
+        let commit_rules = decode_flag(reader.read_u8()?, "invalid commit rules flag")?;
+        let touched_digest = DigestKind::from_u8(reader.read_u8()?)?;
         let msg_count = reader.read_u32()? as usize;
+        if msg_count > MAX_MESSAGES_PER_BUNDLE {
+            return Err(CoreError::Decode("too many messages"));
+        }
         let mut messages = Vec::with_capacity(msg_count);
         for _ in 0..msg_count {
-            let msg_type = reader.read_u8()?;
-            match msg_type {
-                0x01 => {
-                    let trader = reader.read_addr()?;
-                    let nonce = reader.read_u64()?;
-                    let order_id = reader.read_b32()?;
-                    let side = Side::from_u8(reader.read_u8()?)?;
-                    let tif = TimeInForce::from_u32(reader.read_u32()?)?;
-                    let tick_index = reader.read_i32()?;
-                    let qty_base = reader.read_u256()?;
-                    let sig_bytes = reader.read_exact(65)?;
-                    let signature = MessageSignature {
-                        r: sig_bytes[..32].try_into().unwrap(),
-                        s: sig_bytes[32..64].try_into().unwrap(),
-                        v: sig_bytes[64],
-                    };
-                    let prev_tick_hint = reader.read_i32()?;
-                    let next_tick_hint = reader.read_i32()?;
-                    messages.push(SignedMessage {
-                        message: Message::Place {
-                            trader,
-                            nonce,
-                            order_id,
-                            side,
-                            tif,
-                            tick_index,
-                            qty_base,
-                            prev_tick_hint,
-                            next_tick_hint,
-                        },
-                        signature,
-                    });
-                }
-                0x02 => {
-                    let trader = reader.read_addr()?;
-                    let nonce = reader.read_u64()?;
-                    let order_id = reader.read_b32()?;
-                    let sig_bytes = reader.read_exact(65)?;
-                    let signature = MessageSignature {
-                        r: sig_bytes[..32].try_into().unwrap(),
-                        s: sig_bytes[32..64].try_into().unwrap(),
-                        v: sig_bytes[64],
-                    };
-                    messages.push(SignedMessage {
-                        message: Message::Cancel {
-                            trader,
-                            nonce,
-                            order_id,
-                        },
-                        signature,
-                    });
-                }
-                _ => return Err(CoreError::Decode("unknown message type")),
-            }
+            messages.push(SignedMessage::decode(reader)?);
         }
         Ok(Self {
             public,
             chain_id,
             venue_id,
             market_id,
+            instance_salt,
             rules,
+            commit_rules,
+            touched_digest,
             messages,
         })
     }
@@ -348,32 +890,43 @@ pub struct GuestBundle {
 }
 
 impl GuestBundle {
-    pub fn encode(&self) -> Vec<u8> {
+    pub fn encode(&self) -> Result<Vec<u8>, CoreError> {
         let mut w = Writer::new();
-        w.write_raw(&self.input.encode());
+        w.write_raw(&self.input.encode()?);
         w.write_u32(self.proofs.len() as u32);
         for proof in &self.proofs {
             w.write_b32(&proof.key);
             w.write_u8(if proof.present { 1 } else { 0 });
             w.write_bytes(&proof.value);
             if proof.siblings.len() != 256 {
-                panic!("proof siblings length");
+                return Err(CoreError::Invalid("proof siblings length"));
             }
             for sibling in &proof.siblings {
                 w.write_b32(sibling);
             }
         }
-        w.into_bytes()
+        Ok(w.into_bytes())
     }
 
     pub fn decode(reader: &mut Reader) -> Result<Self, CoreError> {
+        Self::decode_with_max_value_len(reader, MAX_VALUE_LEN)
+    }
+
+    pub fn decode_with_max_value_len(reader: &mut Reader, max_value_len: usize) -> Result<Self, CoreError> {
+        Self::decode_with_limits(reader, max_value_len, MAX_PROOFS_PER_BUNDLE)
+    }
+
+    pub fn decode_with_limits(reader: &mut Reader, max_value_len: usize, max_proofs: usize) -> Result<Self, CoreError> {
         let input = GuestInput::decode(reader)?;
         let proof_count = reader.read_u32()? as usize;
+        if proof_count > max_proofs {
+            return Err(CoreError::Decode("too many proofs"));
+        }
         let mut proofs = Vec::with_capacity(proof_count);
         for _ in 0..proof_count {
             let key = reader.read_b32()?;
             let present = reader.read_u8()? != 0;
-            let value = reader.read_bytes()?;
+            let value = reader.read_bytes_max(max_value_len)?;
             let mut siblings = Vec::with_capacity(256);
             for _ in 0..256 {
                 siblings.push(reader.read_b32()?);
@@ -2,7 +2,11 @@
 
 extern crate alloc;
 
+#[cfg(feature = "std")]
+pub mod book;
 pub mod constants;
+#[cfg(feature = "std")]
+pub mod db;
 pub mod encoding;
 pub mod errors;
 pub mod hash;
@@ -11,6 +15,9 @@ pub mod math;
 pub mod merkle;
 pub mod engine;
 pub mod outputs;
+#[cfg(feature = "std")]
+pub mod parse;
+pub mod program;
 pub mod state;
 pub mod types;
 pub mod verify;
@@ -1,7 +1,7 @@
 use crate::errors::CoreError;
 use crate::types::{U256, U512};
 
-fn to_u512(value: U256) -> U512 {
+pub fn to_u512(value: U256) -> U512 {
     let mut buf = [0u8; 64];
     let mut tmp = [0u8; 32];
     value.to_big_endian(&mut tmp);
@@ -9,7 +9,8 @@ fn to_u512(value: U256) -> U512 {
     U512::from_big_endian(&buf)
 }
 
-fn to_u256(value: U512) -> Result<U256, CoreError> {
+// Narrows back to U256, failing instead of truncating when any high-half bit is set.
+pub fn to_u256(value: U512) -> Result<U256, CoreError> {
     let mut buf = [0u8; 64];
     value.to_big_endian(&mut buf);
     if buf[..32].iter().any(|b| *b != 0) {
@@ -18,6 +19,12 @@ fn to_u256(value: U512) -> Result<U256, CoreError> {
     Ok(U256::from_big_endian(&buf[32..]))
 }
 
+// Sums in U512 so only a total that truly exceeds U256 fails, never an intermediate.
+pub fn sum_u256(values: impl IntoIterator<Item = U256>) -> Result<U256, CoreError> {
+    let total = values.into_iter().fold(U512::zero(), |acc, value| acc + to_u512(value));
+    to_u256(total).map_err(|_| CoreError::Math("sum overflow"))
+}
+
 pub fn mul_div_down(a: U256, b: U256, denom: U256) -> Result<U256, CoreError> {
     if denom.is_zero() {
         return Err(CoreError::Math("division by zero"));
@@ -41,3 +48,55 @@ pub fn mul_div_up(a: U256, b: U256, denom: U256) -> Result<U256, CoreError> {
     let q = numerator / denom_512;
     to_u256(q)
 }
+
+// Converts a human decimal such as "1.25" into integer units of an asset with `decimals` places,
+// rejecting precision the asset cannot represent.
+pub fn parse_decimal_amount(text: &str, decimals: u8) -> Result<U256, CoreError> {
+    let (whole, frac) = text.split_once('.').unwrap_or((text, ""));
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (whole.is_empty() && frac.is_empty()) || !is_digits(whole) || !is_digits(frac) {
+        return Err(CoreError::Decode("invalid decimal amount"));
+    }
+    let frac = frac.trim_end_matches('0');
+    if frac.len() > decimals as usize {
+        return Err(CoreError::Invalid("too many decimal places"));
+    }
+    let ten = U256::from(10u64);
+    let mut value = U256::zero();
+    for digit in whole.bytes().chain(frac.bytes()) {
+        value = value
+            .checked_mul(ten)
+            .and_then(|v| v.checked_add(U256::from(digit - b'0')))
+            .ok_or(CoreError::Math("amount overflow"))?;
+    }
+    let pad = ten
+        .checked_pow(U256::from(decimals as usize - frac.len()))
+        .ok_or(CoreError::Math("amount overflow"))?;
+    value.checked_mul(pad).ok_or(CoreError::Math("amount overflow"))
+}
+
+// Display only: renders `tick * tick_size / price_scale` as a decimal, trimming trailing zeros.
+#[cfg(feature = "std")]
+pub fn format_price(tick: i32, tick_size: U256, price_scale: U256) -> String {
+    let price = to_u512(tick_size) * U512::from(tick.unsigned_abs());
+    let sign = if tick < 0 && !price.is_zero() { "-" } else { "" };
+    if price_scale.is_zero() {
+        return format!("{sign}{price}");
+    }
+    let scale = to_u512(price_scale);
+    let whole = price / scale;
+    let rem = price % scale;
+    let width = (scale - U512::from(1u8)).to_string().len();
+    let frac = if rem.is_zero() {
+        String::new()
+    } else {
+        let digits = rem * U512::exp10(width) / scale;
+        let padded = format!("{digits:0>width$}");
+        padded.trim_end_matches('0').to_string()
+    };
+    if frac.is_empty() {
+        format!("{sign}{whole}.0")
+    } else {
+        format!("{sign}{whole}.{frac}")
+    }
+}
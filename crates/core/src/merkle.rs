@@ -1,11 +1,12 @@
 use alloc::vec::Vec;
-use core::hash::{Hash, Hasher};
+use core::hash::Hash;
+use core::marker::PhantomData;
 
 use hashbrown::HashMap;
 
 use crate::constants::ZERO32;
 use crate::errors::CoreError;
-use crate::hash::keccak256;
+use crate::hash::{Hasher, Keccak256};
 
 #[derive(Clone, Debug)]
 pub struct Proof {
@@ -27,12 +28,16 @@ impl Proof {
 }
 
 pub fn leaf_hash(key: &[u8; 32], value: &[u8]) -> [u8; 32] {
-    let value_hash = keccak256(value);
+    leaf_hash_with::<Keccak256>(key, value)
+}
+
+pub fn leaf_hash_with<H: Hasher>(key: &[u8; 32], value: &[u8]) -> [u8; 32] {
+    let value_hash = H::hash(value);
     let mut buf = [0u8; 1 + 32 + 32];
     buf[0] = 0x00;
     buf[1..33].copy_from_slice(key);
     buf[33..65].copy_from_slice(&value_hash);
-    keccak256(&buf)
+    H::hash(&buf)
 }
 
 pub fn leaf_hash_absent() -> [u8; 32] {
@@ -40,14 +45,54 @@ pub fn leaf_hash_absent() -> [u8; 32] {
 }
 
 pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    node_hash_with::<Keccak256>(left, right)
+}
+
+pub fn node_hash_with<H: Hasher>(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
     let mut buf = [0u8; 1 + 32 + 32];
     buf[0] = 0x01;
     buf[1..33].copy_from_slice(left);
     buf[33..65].copy_from_slice(right);
-    keccak256(&buf)
+    H::hash(&buf)
+}
+
+/// Hash of an all-empty subtree rooted at `depth`: 256 is an absent leaf, 0 the empty tree's
+/// root. Panics if `depth > 256`.
+pub fn empty_hash(depth: usize) -> [u8; 32] {
+    #[cfg(feature = "std")]
+    {
+        static LADDER: std::sync::OnceLock<Vec<[u8; 32]>> = std::sync::OnceLock::new();
+        LADDER.get_or_init(empty_hash_ladder::<Keccak256>)[depth]
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        empty_hash_with::<Keccak256>(depth)
+    }
+}
+
+pub fn empty_hash_with<H: Hasher>(depth: usize) -> [u8; 32] {
+    assert!(depth <= 256, "empty hash depth out of range");
+    let mut cur = leaf_hash_absent();
+    for _ in depth..256 {
+        cur = node_hash_with::<H>(&cur, &cur);
+    }
+    cur
+}
+
+pub(crate) fn empty_hash_ladder<H: Hasher>() -> Vec<[u8; 32]> {
+    let mut ladder = vec![leaf_hash_absent(); 257];
+    for depth in (0..256).rev() {
+        let child = ladder[depth + 1];
+        ladder[depth] = node_hash_with::<H>(&child, &child);
+    }
+    ladder
 }
 
 pub fn verify_proof(root: &[u8; 32], proof: &Proof) -> Result<[u8; 32], CoreError> {
+    verify_proof_with::<Keccak256>(root, proof)
+}
+
+pub fn verify_proof_with<H: Hasher>(root: &[u8; 32], proof: &Proof) -> Result<[u8; 32], CoreError> {
     if proof.siblings.len() != 256 {
         return Err(CoreError::Invalid("invalid proof length"));
     }
@@ -55,7 +100,7 @@ pub fn verify_proof(root: &[u8; 32], proof: &Proof) -> Result<[u8; 32], CoreErro
         return Err(CoreError::Invalid("absent proof has value bytes"));
     }
     let mut cur = if proof.present {
-        leaf_hash(&proof.key, &proof.value)
+        leaf_hash_with::<H>(&proof.key, &proof.value)
     } else {
         leaf_hash_absent()
     };
@@ -67,7 +112,7 @@ pub fn verify_proof(root: &[u8; 32], proof: &Proof) -> Result<[u8; 32], CoreErro
         } else {
             (sibling, &cur)
         };
-        cur = node_hash(left, right);
+        cur = node_hash_with::<H>(left, right);
     }
     if &cur != root {
         return Err(CoreError::State("merkle proof root mismatch"));
@@ -135,12 +180,20 @@ pub fn verify_proof_debug(root: &[u8; 32], proof: &Proof) -> Result<ProofDebugIn
 }
 
 pub fn apply_proof(root: &[u8; 32], proof: &Proof, new_value: Option<Vec<u8>>) -> Result<[u8; 32], CoreError> {
+    apply_proof_with::<Keccak256>(root, proof, new_value)
+}
+
+pub fn apply_proof_with<H: Hasher>(
+    root: &[u8; 32],
+    proof: &Proof,
+    new_value: Option<Vec<u8>>,
+) -> Result<[u8; 32], CoreError> {
     if proof.siblings.len() != 256 {
         return Err(CoreError::Invalid("invalid proof length"));
     }
-    let old_root = verify_proof(root, proof)?;
+    let old_root = verify_proof_with::<H>(root, proof)?;
     let new_leaf = match new_value.as_ref() {
-        Some(bytes) => leaf_hash(&proof.key, bytes),
+        Some(bytes) => leaf_hash_with::<H>(&proof.key, bytes),
         None => leaf_hash_absent(),
     };
     let mut cur = new_leaf;
@@ -152,7 +205,7 @@ pub fn apply_proof(root: &[u8; 32], proof: &Proof, new_value: Option<Vec<u8>>) -
         } else {
             (sibling, &cur)
         };
-        cur = node_hash(left, right);
+        cur = node_hash_with::<H>(left, right);
     }
     if &old_root != root {
         return Err(CoreError::State("root changed during apply"));
@@ -160,16 +213,39 @@ pub fn apply_proof(root: &[u8; 32], proof: &Proof, new_value: Option<Vec<u8>>) -
     Ok(cur)
 }
 
+// Folds `apply_proof` over a sequence of writes; each proof must be against the root left by the
+// previous write.
+pub fn apply_proofs(root: &[u8; 32], updates: &[(Proof, Option<Vec<u8>>)]) -> Result<[u8; 32], CoreError> {
+    apply_proofs_with::<Keccak256>(root, updates)
+}
+
+pub fn apply_proofs_with<H: Hasher>(
+    root: &[u8; 32],
+    updates: &[(Proof, Option<Vec<u8>>)],
+) -> Result<[u8; 32], CoreError> {
+    let mut cur = *root;
+    for (proof, new_value) in updates {
+        cur = apply_proof_with::<H>(&cur, proof, new_value.clone())?;
+    }
+    Ok(cur)
+}
+
 pub fn get_bit(key: &[u8; 32], depth: u16) -> u8 {
     let byte_index = (depth / 8) as usize;
     let bit_index = 7 - (depth % 8);
     (key[byte_index] >> bit_index) & 1
 }
 
+// `(key, old value, new value)` for one key that differs between two trees.
+pub type ValueChange = ([u8; 32], Option<Vec<u8>>, Option<Vec<u8>>);
+
 #[derive(Clone, Debug)]
-pub struct SparseMerkleTree {
+pub struct SparseMerkleTree<H: Hasher = Keccak256> {
     values: HashMap<[u8; 32], Vec<u8>>,
+    // Hash of every node with a present leaf beneath it; any node missing here is empty.
+    nodes: HashMap<NodeKey, [u8; 32]>,
     empty_hashes: Vec<[u8; 32]>,
+    hasher: PhantomData<H>,
 }
 
 #[derive(Clone, Debug, Eq)]
@@ -185,7 +261,7 @@ impl PartialEq for NodeKey {
 }
 
 impl Hash for NodeKey {
-    fn hash<H: Hasher>(&self, state: &mut H) {
+    fn hash<S: core::hash::Hasher>(&self, state: &mut S) {
         self.depth.hash(state);
         self.prefix.hash(state);
     }
@@ -193,21 +269,28 @@ impl Hash for NodeKey {
 
 impl SparseMerkleTree {
     pub fn new() -> Self {
-        let mut empty_hashes = vec![[0u8; 32]; 257];
-        empty_hashes[256] = ZERO32;
-        for depth in (0..256).rev() {
-            let child = empty_hashes[depth + 1];
-            empty_hashes[depth] = node_hash(&child, &child);
-        }
+        Self::with_hasher()
+    }
+}
+
+impl<H: Hasher> SparseMerkleTree<H> {
+    pub fn with_hasher() -> Self {
         Self {
             values: HashMap::new(),
-            empty_hashes,
+            nodes: HashMap::new(),
+            empty_hashes: empty_hash_ladder::<H>(),
+            hasher: PhantomData,
         }
     }
 
     pub fn root(&self) -> [u8; 32] {
+        self.node_hash([0u8; 32], 0)
+    }
+
+    // Walks the values from scratch without the node cache; `root()` must always agree with it.
+    pub fn recompute_root(&self) -> [u8; 32] {
         let mut memo = HashMap::new();
-        compute_hash(
+        compute_hash::<H>(
             &self.values,
             &self.empty_hashes,
             &mut memo,
@@ -220,6 +303,24 @@ impl SparseMerkleTree {
         self.values.get(&key).cloned()
     }
 
+    // Every stored leaf, in no particular order.
+    pub fn values(&self) -> impl Iterator<Item = (&[u8; 32], &Vec<u8>)> {
+        self.values.iter()
+    }
+
+    // Keys whose values differ between `self` (old) and `other` (new), sorted by key.
+    pub fn diff(&self, other: &Self) -> Vec<ValueChange> {
+        let mut changed: Vec<_> = self
+            .values
+            .keys()
+            .chain(other.values.keys().filter(|key| !self.values.contains_key(*key)))
+            .filter(|key| self.values.get(*key) != other.values.get(*key))
+            .map(|key| (*key, self.get(*key), other.get(*key)))
+            .collect();
+        changed.sort_by_key(|change| change.0);
+        changed
+    }
+
     pub fn update(&mut self, key: [u8; 32], value: Option<Vec<u8>>) {
         match value {
             Some(bytes) => {
@@ -229,23 +330,49 @@ impl SparseMerkleTree {
                 self.values.remove(&key);
             }
         }
+        // Only the nodes on this key's path can change, so rehash them leaf to root.
+        let leaf = self.values.get(&key).map(|bytes| leaf_hash_with::<H>(&key, bytes));
+        self.set_node(key, 256, leaf);
+        for depth in (0..256u16).rev() {
+            let prefix = prefix_with_len(&key, depth);
+            let left = extend_prefix(&prefix, depth, 0);
+            let right = extend_prefix(&prefix, depth, 1);
+            let occupied = self.has_node(left, depth + 1) || self.has_node(right, depth + 1);
+            let hash = occupied
+                .then(|| node_hash_with::<H>(&self.node_hash(left, depth + 1), &self.node_hash(right, depth + 1)));
+            self.set_node(prefix, depth, hash);
+        }
+    }
+
+    fn node_hash(&self, prefix: [u8; 32], depth: u16) -> [u8; 32] {
+        match self.nodes.get(&NodeKey { depth, prefix }) {
+            Some(hash) => *hash,
+            None => self.empty_hashes[depth as usize],
+        }
+    }
+
+    fn has_node(&self, prefix: [u8; 32], depth: u16) -> bool {
+        self.nodes.contains_key(&NodeKey { depth, prefix })
+    }
+
+    fn set_node(&mut self, prefix: [u8; 32], depth: u16, hash: Option<[u8; 32]>) {
+        let key = NodeKey { depth, prefix };
+        match hash {
+            Some(hash) => {
+                self.nodes.insert(key, hash);
+            }
+            None => {
+                self.nodes.remove(&key);
+            }
+        }
     }
 
     pub fn prove(&self, key: [u8; 32]) -> Proof {
-        let mut memo = HashMap::new();
         let mut siblings = Vec::with_capacity(256);
-        for depth in 0..256 {
-            let bit = get_bit(&key, depth as u16);
-            let prefix = prefix_with_len(&key, depth as u16);
-            let sibling_prefix = extend_prefix(&prefix, depth as u16, bit ^ 1);
-            let hash = compute_hash(
-                &self.values,
-                &self.empty_hashes,
-                &mut memo,
-                sibling_prefix,
-                depth as u16 + 1,
-            );
-            siblings.push(hash);
+        for depth in 0..256u16 {
+            let bit = get_bit(&key, depth);
+            let prefix = prefix_with_len(&key, depth);
+            siblings.push(self.node_hash(extend_prefix(&prefix, depth, bit ^ 1), depth + 1));
         }
         let (value, present) = match self.values.get(&key) {
             Some(bytes) => (bytes.clone(), true),
@@ -260,7 +387,8 @@ impl SparseMerkleTree {
     }
 }
 
-fn prefix_with_len(key: &[u8; 32], bits: u16) -> [u8; 32] {
+// Keeps the first `bits` bits of `key` (MSB first, as in `get_bit`) and zeroes the rest.
+pub fn prefix_with_len(key: &[u8; 32], bits: u16) -> [u8; 32] {
     if bits == 0 {
         return [0u8; 32];
     }
@@ -284,7 +412,8 @@ fn prefix_with_len(key: &[u8; 32], bits: u16) -> [u8; 32] {
     out
 }
 
-fn extend_prefix(prefix: &[u8; 32], depth: u16, bit: u8) -> [u8; 32] {
+// Truncates `prefix` to `depth` bits and appends `bit` at position `depth`.
+pub fn extend_prefix(prefix: &[u8; 32], depth: u16, bit: u8) -> [u8; 32] {
     let mut out = prefix_with_len(prefix, depth);
     let byte_index = (depth / 8) as usize;
     let bit_index = 7 - (depth % 8);
@@ -296,7 +425,7 @@ fn extend_prefix(prefix: &[u8; 32], depth: u16, bit: u8) -> [u8; 32] {
     out
 }
 
-fn compute_hash(
+fn compute_hash<H: Hasher>(
     values: &HashMap<[u8; 32], Vec<u8>>,
     empty_hashes: &[[u8; 32]],
     memo: &mut HashMap<NodeKey, [u8; 32]>,
@@ -309,23 +438,23 @@ fn compute_hash(
     }
     let hash = if depth == 256 {
         match values.get(&prefix).map(Vec::as_slice) {
-            Some(bytes) => leaf_hash(&prefix, bytes),
+            Some(bytes) => leaf_hash_with::<H>(&prefix, bytes),
             None => leaf_hash_absent(),
         }
     } else {
         let left_prefix = extend_prefix(&prefix, depth, 0);
         let right_prefix = extend_prefix(&prefix, depth, 1);
         let left = if has_value(values, &left_prefix, depth + 1) {
-            compute_hash(values, empty_hashes, memo, left_prefix, depth + 1)
+            compute_hash::<H>(values, empty_hashes, memo, left_prefix, depth + 1)
         } else {
             empty_hashes[(depth + 1) as usize]
         };
         let right = if has_value(values, &right_prefix, depth + 1) {
-            compute_hash(values, empty_hashes, memo, right_prefix, depth + 1)
+            compute_hash::<H>(values, empty_hashes, memo, right_prefix, depth + 1)
         } else {
             empty_hashes[(depth + 1) as usize]
         };
-        node_hash(&left, &right)
+        node_hash_with::<H>(&left, &right)
     };
     memo.insert(key, hash);
     hash
@@ -1,31 +1,66 @@
 use alloc::vec::Vec;
 
 use crate::constants::ZERO32;
+use crate::errors::CoreError;
 use crate::hash::keccak256;
+use crate::input::PublicInputs;
+use crate::types::{FeeTotal, TradeRecord};
 
+// Leaves are tagged `0x00` and nodes `0x01`, as in the SMT. An odd node is carried up unchanged
+// rather than paired with itself, so `[a, b, c]` and `[a, b, c, c]` commit to different roots.
 pub fn merkle_root(leaves: &[ [u8; 32] ]) -> [u8; 32] {
     if leaves.is_empty() {
         return ZERO32;
     }
-    let mut level = leaves.to_vec();
+    let mut level: Vec<[u8; 32]> = leaves.iter().map(tagged_leaf).collect();
     while level.len() > 1 {
-        let mut next = Vec::with_capacity((level.len() + 1) / 2);
-        let mut i = 0;
-        while i < level.len() {
-            let left = level[i];
-            let right = if i + 1 < level.len() {
-                level[i + 1]
-            } else {
-                level[i]
-            };
-            let mut buf = [0u8; 65];
-            buf[0] = 0x01;
-            buf[1..33].copy_from_slice(&left);
-            buf[33..65].copy_from_slice(&right);
-            next.push(keccak256(&buf));
-            i += 2;
+        let mut next = Vec::with_capacity(level.len().div_ceil(2));
+        for pair in level.chunks(2) {
+            match pair {
+                [left, right] => {
+                    let mut buf = [0u8; 65];
+                    buf[0] = 0x01;
+                    buf[1..33].copy_from_slice(left);
+                    buf[33..65].copy_from_slice(right);
+                    next.push(keccak256(&buf));
+                }
+                [odd] => next.push(*odd),
+                _ => unreachable!(),
+            }
         }
         level = next;
     }
     level[0]
 }
+
+pub fn trades_root(trades: &[TradeRecord]) -> [u8; 32] {
+    let leaves: Vec<[u8; 32]> = trades.iter().map(|t| keccak256(&t.encode())).collect();
+    merkle_root(&leaves)
+}
+
+pub fn fees_root(fee_totals: &[FeeTotal]) -> [u8; 32] {
+    let leaves: Vec<[u8; 32]> = fee_totals.iter().map(|f| keccak256(&f.encode())).collect();
+    merkle_root(&leaves)
+}
+
+/// Checks a batch's trades feed and fee totals against the roots it committed. Rules with
+/// `commit_trade_roots` off commit zero roots, which only an empty batch output matches.
+pub fn verify_output_roots(
+    public: &PublicInputs,
+    trades: &[TradeRecord],
+    fee_totals: &[FeeTotal],
+) -> Result<(), CoreError> {
+    if trades_root(trades) != public.trades_root {
+        return Err(CoreError::Invalid("trades root mismatch"));
+    }
+    if fees_root(fee_totals) != public.fees_root {
+        return Err(CoreError::Invalid("fees root mismatch"));
+    }
+    Ok(())
+}
+
+fn tagged_leaf(leaf: &[u8; 32]) -> [u8; 32] {
+    let mut buf = [0u8; 33];
+    buf[1..].copy_from_slice(leaf);
+    keccak256(&buf)
+}
@@ -0,0 +1,34 @@
+// Text forms of ids, addresses and amounts as the host and daemon read them from JSON. Errors
+// name the offending text, so they are plain strings rather than `CoreError`s.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::types::{Address, U256};
+
+/// Hex bytes with an optional `0x` prefix.
+pub fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    let stripped = s.strip_prefix("0x").unwrap_or(s);
+    hex::decode(stripped).map_err(|e| format!("invalid hex {s:?}: {e}"))
+}
+
+pub fn parse_b32(s: &str) -> Result<[u8; 32], String> {
+    let bytes = parse_hex(s)?;
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .map_err(|_| format!("expected 32 bytes, got {len} in {s:?}"))
+}
+
+pub fn parse_addr(s: &str) -> Result<Address, String> {
+    Address::from_hex(s).map_err(|e| format!("expected 20-byte address in {s:?}: {e}"))
+}
+
+/// `0x`-prefixed hex or plain decimal.
+pub fn parse_u256(s: &str) -> Result<U256, String> {
+    match s.strip_prefix("0x") {
+        Some(digits) => U256::from_str_radix(digits, 16).map_err(|e| format!("invalid hex amount {s:?}: {e:?}")),
+        None => U256::from_dec_str(s).map_err(|e| format!("invalid decimal amount {s:?}: {e:?}")),
+    }
+}
@@ -0,0 +1,100 @@
+use alloc::vec::Vec;
+
+use crate::engine::apply_batch_at_root;
+use crate::constants::ZERO32;
+use crate::errors::CoreError;
+use crate::input::{GuestBundle, PublicInputs};
+use crate::outputs::{fees_root, trades_root};
+use crate::state::ProofState;
+use crate::verify::{batch_digest, check_batch_deadline, domain_separator, message_hash, rules_hash};
+
+#[derive(Debug)]
+pub struct ProgramOutput {
+    pub public: PublicInputs,
+    // `Rules::encode()` when the input asked for the rules to be committed alongside their hash.
+    pub committed_rules: Option<Vec<u8>>,
+    pub touched_digest: [u8; 32],
+}
+
+impl ProgramOutput {
+    // The committed stream: `PublicInputs`, then the rules bytes if committed.
+    pub fn public_values(&self) -> Vec<u8> {
+        let mut out = self.public.encode();
+        if let Some(rules) = &self.committed_rules {
+            out.extend_from_slice(rules);
+        }
+        out
+    }
+}
+
+/// Re-derives the public inputs a proof of `bundle` commits to, without a prover, so an auditor
+/// holding the bundle can compare them against a proof's committed values.
+pub fn recompute_public_inputs(bundle: GuestBundle) -> Result<PublicInputs, CoreError> {
+    run_bundle(bundle).map(|output| output.public)
+}
+
+pub fn run_bundle(bundle: GuestBundle) -> Result<ProgramOutput, CoreError> {
+    let input = bundle.input;
+    let mut proofs = bundle.proofs;
+
+    check_batch_deadline(input.public.batch_timestamp, input.public.batch_deadline)?;
+
+    let expected_domain = domain_separator(input.chain_id, &input.venue_id, &input.market_id, &input.instance_salt);
+    if expected_domain != input.public.domain_separator {
+        return Err(CoreError::Invalid("domain separator mismatch"));
+    }
+    if rules_hash(&input.rules) != input.public.rules_hash {
+        return Err(CoreError::Invalid("rules hash mismatch"));
+    }
+
+    let mut msg_hashes = Vec::with_capacity(input.messages.len());
+    for msg in &input.messages {
+        msg_hashes.push(message_hash(&expected_domain, &msg.message));
+    }
+    if batch_digest(&expected_domain, input.public.batch_seq, &msg_hashes) != input.public.batch_digest {
+        return Err(CoreError::Invalid("batch digest mismatch"));
+    }
+
+    let mut state = ProofState::new(input.public.prev_root, &mut proofs);
+    let output = apply_batch_at_root(
+        &mut state,
+        input.public.prev_root,
+        input.market_id,
+        &input.rules,
+        expected_domain,
+        input.public.batch_seq,
+        &input.messages,
+    )?;
+    state.finish()?;
+
+    let (trades_root, fees_root) = if input.rules.commit_trade_roots {
+        (trades_root(&output.trades), fees_root(&output.fee_totals))
+    } else {
+        (ZERO32, ZERO32)
+    };
+
+    let public = PublicInputs {
+        prev_root: input.public.prev_root,
+        new_root: state.root,
+        batch_digest: input.public.batch_digest,
+        rules_hash: input.public.rules_hash,
+        domain_separator: input.public.domain_separator,
+        batch_seq: input.public.batch_seq,
+        batch_timestamp: input.public.batch_timestamp,
+        batch_deadline: input.public.batch_deadline,
+        da_commitment: input.public.da_commitment,
+        trades_root,
+        fees_root,
+    };
+
+    let mut touched_concat = Vec::with_capacity(state.touched_keys.len() * 32);
+    for key in &state.touched_keys {
+        touched_concat.extend_from_slice(key);
+    }
+
+    Ok(ProgramOutput {
+        public,
+        committed_rules: input.commit_rules.then(|| input.rules.encode()),
+        touched_digest: input.touched_digest.digest(&touched_concat),
+    })
+}
@@ -1,93 +1,251 @@
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use core::marker::PhantomData;
 
 use crate::constants::*;
 use crate::errors::CoreError;
-use crate::hash::keccak256;
-use crate::merkle::{apply_proof, verify_proof, Proof};
-use crate::types::{Balance, FeeVault, MarketBest, Order, OrderNode, TickNode, U256};
+use crate::hash::{Hasher, Keccak256};
+use crate::merkle::{apply_proof_with, verify_proof_with, Proof};
+use crate::types::{Address, AssetId, Balance, FeeVault, MarketBest, MarketId, Order, OrderId, OrderNode, TickNode, U256};
 
 pub trait StateAccess {
+    type Hasher: Hasher;
+    type Checkpoint;
+
+    fn root(&self) -> [u8; 32];
     fn read_value(&mut self, key: [u8; 32]) -> Result<Option<Vec<u8>>, CoreError>;
     fn write_value(&mut self, key: [u8; 32], value: Option<Vec<u8>>) -> Result<(), CoreError>;
+    fn checkpoint(&self) -> Self::Checkpoint;
+    fn rollback(&mut self, checkpoint: Self::Checkpoint) -> Result<(), CoreError>;
+}
+
+pub fn key_balance(account: &Address, asset: &AssetId) -> [u8; 32] {
+    key_balance_with::<Keccak256>(account, asset)
 }
 
-pub fn key_balance(account: &[u8; 20], asset: &[u8; 32]) -> [u8; 32] {
+pub fn key_balance_with<H: Hasher>(account: &Address, asset: &AssetId) -> [u8; 32] {
     let mut buf = Vec::with_capacity(32 + 1 + 20 + 32);
     buf.extend_from_slice(&NS_BAL);
     buf.push(0x1f);
-    buf.extend_from_slice(account);
-    buf.extend_from_slice(asset);
-    keccak256(&buf)
+    buf.extend_from_slice(account.as_bytes());
+    buf.extend_from_slice(asset.as_bytes());
+    H::hash(&buf)
+}
+
+pub fn key_nonce(account: &Address) -> [u8; 32] {
+    key_nonce_with::<Keccak256>(account)
 }
 
-pub fn key_nonce(account: &[u8; 20]) -> [u8; 32] {
+pub fn key_nonce_with<H: Hasher>(account: &Address) -> [u8; 32] {
     let mut buf = Vec::with_capacity(32 + 1 + 20);
     buf.extend_from_slice(&NS_NONCE);
     buf.push(0x1f);
-    buf.extend_from_slice(account);
-    keccak256(&buf)
+    buf.extend_from_slice(account.as_bytes());
+    H::hash(&buf)
 }
 
-pub fn key_order(order_id: &[u8; 32]) -> [u8; 32] {
-    let mut buf = Vec::with_capacity(32 + 1 + 32);
-    buf.extend_from_slice(&NS_ORDER);
-    buf.push(0x1f);
-    buf.extend_from_slice(order_id);
-    keccak256(&buf)
+// `scope` is the market an order id is unique within, or `None` when ids are global
+// (see `Rules::order_scope`).
+pub fn key_order(scope: Option<&MarketId>, order_id: &OrderId) -> [u8; 32] {
+    key_order_with::<Keccak256>(scope, order_id)
 }
 
-pub fn key_order_node(order_id: &[u8; 32]) -> [u8; 32] {
-    let mut buf = Vec::with_capacity(32 + 1 + 32);
-    buf.extend_from_slice(&NS_ORDERNODE);
+pub fn key_order_with<H: Hasher>(scope: Option<&MarketId>, order_id: &OrderId) -> [u8; 32] {
+    scoped_order_key::<H>(&NS_ORDER, scope, order_id)
+}
+
+pub fn key_order_node(scope: Option<&MarketId>, order_id: &OrderId) -> [u8; 32] {
+    key_order_node_with::<Keccak256>(scope, order_id)
+}
+
+pub fn key_order_node_with<H: Hasher>(scope: Option<&MarketId>, order_id: &OrderId) -> [u8; 32] {
+    scoped_order_key::<H>(&NS_ORDERNODE, scope, order_id)
+}
+
+fn scoped_order_key<H: Hasher>(namespace: &[u8; 32], scope: Option<&MarketId>, order_id: &OrderId) -> [u8; 32] {
+    let mut buf = Vec::with_capacity(32 + 1 + 32 + 32);
+    buf.extend_from_slice(namespace);
     buf.push(0x1f);
-    buf.extend_from_slice(order_id);
-    keccak256(&buf)
+    if let Some(market) = scope {
+        buf.extend_from_slice(market.as_bytes());
+    }
+    buf.extend_from_slice(order_id.as_bytes());
+    H::hash(&buf)
+}
+
+pub fn key_tick_node(market: &MarketId, side: u8, tick: i32) -> [u8; 32] {
+    key_tick_node_with::<Keccak256>(market, side, tick)
 }
 
-pub fn key_tick_node(market: &[u8; 32], side: u8, tick: i32) -> [u8; 32] {
+pub fn key_tick_node_with<H: Hasher>(market: &MarketId, side: u8, tick: i32) -> [u8; 32] {
     let mut buf = Vec::with_capacity(32 + 1 + 32 + 1 + 4);
     buf.extend_from_slice(&NS_TICKNODE);
     buf.push(0x1f);
-    buf.extend_from_slice(market);
+    buf.extend_from_slice(market.as_bytes());
     buf.push(side);
     buf.extend_from_slice(&tick.to_be_bytes());
-    keccak256(&buf)
+    H::hash(&buf)
 }
 
-pub fn key_market_best(market: &[u8; 32]) -> [u8; 32] {
+pub fn key_market_best(market: &MarketId) -> [u8; 32] {
+    key_market_best_with::<Keccak256>(market)
+}
+
+pub fn key_market_best_with<H: Hasher>(market: &MarketId) -> [u8; 32] {
     let mut buf = Vec::with_capacity(32 + 1 + 32);
     buf.extend_from_slice(&NS_MARKETBEST);
     buf.push(0x1f);
-    buf.extend_from_slice(market);
-    keccak256(&buf)
+    buf.extend_from_slice(market.as_bytes());
+    H::hash(&buf)
+}
+
+pub fn key_fee_vault(asset: &AssetId) -> [u8; 32] {
+    key_fee_vault_with::<Keccak256>(asset)
 }
 
-pub fn key_fee_vault(asset: &[u8; 32]) -> [u8; 32] {
+pub fn key_fee_vault_with<H: Hasher>(asset: &AssetId) -> [u8; 32] {
     let mut buf = Vec::with_capacity(32 + 1 + 32);
     buf.extend_from_slice(&NS_FEEVAULT);
     buf.push(0x1f);
-    buf.extend_from_slice(asset);
-    keccak256(&buf)
+    buf.extend_from_slice(asset.as_bytes());
+    H::hash(&buf)
+}
+
+pub fn key_trade_seq(market: &MarketId) -> [u8; 32] {
+    key_trade_seq_with::<Keccak256>(market)
+}
+
+pub fn key_trade_seq_with<H: Hasher>(market: &MarketId) -> [u8; 32] {
+    let mut buf = Vec::with_capacity(32 + 1 + 32);
+    buf.extend_from_slice(&NS_TRADESEQ);
+    buf.push(0x1f);
+    buf.extend_from_slice(market.as_bytes());
+    H::hash(&buf)
+}
+
+pub fn key_open_count(account: &Address) -> [u8; 32] {
+    key_open_count_with::<Keccak256>(account)
+}
+
+pub fn key_open_count_with<H: Hasher>(account: &Address) -> [u8; 32] {
+    let mut buf = Vec::with_capacity(32 + 1 + 20);
+    buf.extend_from_slice(&NS_OPENCOUNT);
+    buf.push(0x1f);
+    buf.extend_from_slice(account.as_bytes());
+    H::hash(&buf)
 }
 
-pub struct ProofState<'a> {
+/// Undo record for `ProofState::known`: the key and the entry it replaced, if any.
+type KnownEntry = ([u8; 32], Option<Option<Vec<u8>>>);
+
+pub struct ProofState<'a, H: Hasher = Keccak256> {
     pub root: [u8; 32],
     proofs: &'a mut Vec<Proof>,
+    provided: usize,
     pub touched_keys: Vec<[u8; 32]>,
+    known: BTreeMap<[u8; 32], Option<Vec<u8>>>,
+    journal: Vec<KnownEntry>,
+    #[cfg(feature = "debug_merkle")]
+    trajectory: Option<Vec<RootStep>>,
+    hasher: PhantomData<H>,
+}
+
+/// One applied write: the key and the root before and after it.
+#[cfg(feature = "debug_merkle")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootStep {
+    pub key: [u8; 32],
+    pub old_root: [u8; 32],
+    pub new_root: [u8; 32],
+}
+
+// `U32 count || (key || oldRoot || newRoot)*`; a debugging aid, never committed.
+#[cfg(feature = "debug_merkle")]
+pub fn encode_trajectory(steps: &[RootStep]) -> Vec<u8> {
+    let mut w = crate::encoding::Writer::new();
+    w.write_u32(steps.len() as u32);
+    for step in steps {
+        w.write_b32(&step.key);
+        w.write_b32(&step.old_root);
+        w.write_b32(&step.new_root);
+    }
+    w.into_bytes()
+}
+
+#[cfg(feature = "debug_merkle")]
+pub fn decode_trajectory(bytes: &[u8]) -> Result<Vec<RootStep>, CoreError> {
+    let mut r = crate::encoding::Reader::new(bytes);
+    let count = r.read_u32()? as usize;
+    let mut steps = Vec::new();
+    for _ in 0..count {
+        steps.push(RootStep {
+            key: r.read_b32()?,
+            old_root: r.read_b32()?,
+            new_root: r.read_b32()?,
+        });
+    }
+    r.expect_finished()?;
+    Ok(steps)
+}
+
+/// Index of the first step whose `old_root` does not continue from `start` or the step before.
+#[cfg(feature = "debug_merkle")]
+pub fn first_broken_step(start: [u8; 32], steps: &[RootStep]) -> Option<usize> {
+    let mut root = start;
+    for (index, step) in steps.iter().enumerate() {
+        if step.old_root != root {
+            return Some(index);
+        }
+        root = step.new_root;
+    }
+    None
 }
 
 impl<'a> ProofState<'a> {
     pub fn new(root: [u8; 32], proofs: &'a mut Vec<Proof>) -> Self {
+        Self::with_hasher(root, proofs)
+    }
+}
+
+impl<'a, H: Hasher> ProofState<'a, H> {
+    pub fn with_hasher(root: [u8; 32], proofs: &'a mut Vec<Proof>) -> Self {
         Self {
             root,
+            provided: proofs.len(),
             proofs,
             touched_keys: Vec::new(),
+            known: BTreeMap::new(),
+            journal: Vec::new(),
+            #[cfg(feature = "debug_merkle")]
+            trajectory: None,
+            hasher: PhantomData,
         }
     }
 
-    fn next_proof(&mut self) -> Result<Proof, CoreError> {
+    /// Starts recording every write's root transition; see `take_trajectory`.
+    #[cfg(feature = "debug_merkle")]
+    pub fn record_trajectory(&mut self) {
+        self.trajectory.get_or_insert_with(Vec::new);
+    }
+
+    /// The writes recorded so far, minus any rolled back. Recording stops.
+    #[cfg(feature = "debug_merkle")]
+    pub fn take_trajectory(&mut self) -> Option<Vec<RootStep>> {
+        self.trajectory.take()
+    }
+
+    fn remember(&mut self, key: [u8; 32], value: Option<Vec<u8>>) {
+        let previous = self.known.insert(key, value);
+        self.journal.push((key, previous));
+    }
+
+    fn next_proof(&mut self, key: [u8; 32]) -> Result<Proof, CoreError> {
         if self.proofs.is_empty() {
-            return Err(CoreError::State("missing proof"));
+            // Every supplied proof was consumed, so the shortfall is at index `provided`.
+            return Err(CoreError::MissingProof {
+                index: self.provided,
+                key,
+            });
         }
         Ok(self.proofs.remove(0))
     }
@@ -95,60 +253,203 @@ impl<'a> ProofState<'a> {
     pub fn remaining_proofs(&self) -> usize {
         self.proofs.len()
     }
+
+    pub fn finish(&self) -> Result<(), CoreError> {
+        if !self.proofs.is_empty() {
+            return Err(CoreError::Proof {
+                expected: self.provided - self.proofs.len(),
+                got: self.provided,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProofCheckpoint {
+    root: [u8; 32],
+    touched: usize,
+    journal: usize,
+    #[cfg(feature = "debug_merkle")]
+    trajectory: usize,
 }
 
-impl<'a> StateAccess for ProofState<'a> {
+impl<'a, H: Hasher> StateAccess for ProofState<'a, H> {
+    type Hasher = H;
+    type Checkpoint = ProofCheckpoint;
+
+    fn root(&self) -> [u8; 32] {
+        self.root
+    }
+
     fn read_value(&mut self, key: [u8; 32]) -> Result<Option<Vec<u8>>, CoreError> {
-        let proof = self.next_proof()?;
+        let proof = self.next_proof(key)?;
         if proof.key != key {
             return Err(CoreError::State("proof key mismatch"));
         }
-        verify_proof(&self.root, &proof)?;
+        verify_proof_with::<H>(&self.root, &proof)?;
         self.touched_keys.push(key);
-        if proof.present {
-            Ok(Some(proof.value))
-        } else {
-            Ok(None)
-        }
+        let value = if proof.present { Some(proof.value) } else { None };
+        self.remember(key, value.clone());
+        Ok(value)
     }
 
     fn write_value(&mut self, key: [u8; 32], value: Option<Vec<u8>>) -> Result<(), CoreError> {
-        let proof = self.next_proof()?;
+        let proof = self.next_proof(key)?;
         if proof.key != key {
             return Err(CoreError::State("proof key mismatch"));
         }
-        let new_root = apply_proof(&self.root, &proof, value)?;
+        // The pre-state a write proof claims must match what this key last read or wrote.
+        if let Some(expected) = self.known.get(&key) {
+            let claimed = if proof.present { Some(proof.value.as_slice()) } else { None };
+            if expected.as_deref() != claimed {
+                return Err(CoreError::State("write proof contradicts prior access"));
+            }
+        }
+        let new_root = apply_proof_with::<H>(&self.root, &proof, value.clone())?;
+        #[cfg(feature = "debug_merkle")]
+        if let Some(trajectory) = &mut self.trajectory {
+            trajectory.push(RootStep {
+                key,
+                old_root: self.root,
+                new_root,
+            });
+        }
         self.root = new_root;
         self.touched_keys.push(key);
+        self.remember(key, value);
+        Ok(())
+    }
+
+    // The root commits to every write, so restoring it discards them. Proofs already consumed
+    // stay consumed.
+    fn checkpoint(&self) -> ProofCheckpoint {
+        ProofCheckpoint {
+            root: self.root,
+            touched: self.touched_keys.len(),
+            journal: self.journal.len(),
+            #[cfg(feature = "debug_merkle")]
+            trajectory: self.trajectory.as_ref().map_or(0, Vec::len),
+        }
+    }
+
+    fn rollback(&mut self, checkpoint: ProofCheckpoint) -> Result<(), CoreError> {
+        if checkpoint.touched > self.touched_keys.len() || checkpoint.journal > self.journal.len() {
+            return Err(CoreError::State("stale checkpoint"));
+        }
+        for (key, previous) in self.journal.drain(checkpoint.journal..).rev() {
+            match previous {
+                Some(value) => self.known.insert(key, value),
+                None => self.known.remove(&key),
+            };
+        }
+        self.root = checkpoint.root;
+        self.touched_keys.truncate(checkpoint.touched);
+        #[cfg(feature = "debug_merkle")]
+        if let Some(trajectory) = &mut self.trajectory {
+            trajectory.truncate(checkpoint.trajectory);
+        }
         Ok(())
     }
 }
 
 #[cfg(feature = "std")]
-pub struct RecordingState {
+pub struct RecordingState<H: Hasher = Keccak256> {
     pub root: [u8; 32],
     pub proofs: Vec<Proof>,
-    pub tree: crate::merkle::SparseMerkleTree,
+    pub proof_roots: Vec<[u8; 32]>,
+    pub tree: crate::merkle::SparseMerkleTree<H>,
+    journal: Vec<([u8; 32], Option<Vec<u8>>)>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+pub struct RecordingCheckpoint {
+    root: [u8; 32],
+    proofs: usize,
+    journal: usize,
 }
 
 #[cfg(feature = "std")]
-impl RecordingState {
-    pub fn new(tree: crate::merkle::SparseMerkleTree) -> Self {
+impl<H: Hasher> RecordingState<H> {
+    pub fn new(tree: crate::merkle::SparseMerkleTree<H>) -> Self {
         let root = tree.root();
         Self {
             root,
             proofs: Vec::new(),
+            proof_roots: Vec::new(),
             tree,
+            journal: Vec::new(),
+        }
+    }
+
+    /// Keys read or written since construction, in first-access order.
+    pub fn touched_keys(&self) -> Vec<[u8; 32]> {
+        let mut seen = alloc::collections::BTreeSet::new();
+        self.proofs.iter().map(|proof| proof.key).filter(|key| seen.insert(*key)).collect()
+    }
+
+    /// Debug check that settlement moved value without creating or destroying it: for each asset,
+    /// `available + locked` over the touched balances of `accounts`, plus the fee vault, is the
+    /// same as before the first access. Touched balances of accounts not listed are not counted.
+    pub fn check_conservation(&self, accounts: &[Address], assets: &[AssetId]) -> Result<(), CoreError> {
+        let mut before = BTreeMap::new();
+        for proof in &self.proofs {
+            before
+                .entry(proof.key)
+                .or_insert_with(|| if proof.present { Some(proof.value.clone()) } else { None });
+        }
+        let total = |value: Option<&Vec<u8>>, vault: bool| -> Result<U256, CoreError> {
+            let Some(bytes) = value else {
+                return Ok(U256::zero());
+            };
+            if vault {
+                return Ok(FeeVault::decode(bytes)?.total);
+            }
+            let balance = Balance::decode(bytes)?;
+            balance
+                .available
+                .checked_add(balance.locked)
+                .ok_or(CoreError::Math("balance total overflow"))
+        };
+        for asset in assets {
+            let mut keys = Vec::with_capacity(accounts.len() + 1);
+            keys.push((key_fee_vault_with::<H>(asset), true));
+            keys.extend(accounts.iter().map(|account| (key_balance_with::<H>(account, asset), false)));
+            let (mut sum_before, mut sum_after) = (U256::zero(), U256::zero());
+            for (key, vault) in keys {
+                let Some(prior) = before.get(&key) else {
+                    continue;
+                };
+                sum_before = sum_before
+                    .checked_add(total(prior.as_ref(), vault)?)
+                    .ok_or(CoreError::Math("balance total overflow"))?;
+                sum_after = sum_after
+                    .checked_add(total(self.tree.get(key).as_ref(), vault)?)
+                    .ok_or(CoreError::Math("balance total overflow"))?;
+            }
+            if sum_before != sum_after {
+                return Err(CoreError::State("asset not conserved"));
+            }
         }
+        Ok(())
     }
 }
 
 #[cfg(feature = "std")]
-impl StateAccess for RecordingState {
+impl<H: Hasher> StateAccess for RecordingState<H> {
+    type Hasher = H;
+    type Checkpoint = RecordingCheckpoint;
+
+    fn root(&self) -> [u8; 32] {
+        self.root
+    }
+
     fn read_value(&mut self, key: [u8; 32]) -> Result<Option<Vec<u8>>, CoreError> {
         let proof = self.tree.prove(key);
         self.proofs.push(proof.clone());
-        if let Err(err) = verify_proof(&self.root, &proof) {
+        self.proof_roots.push(self.root);
+        if let Err(err) = verify_proof_with::<H>(&self.root, &proof) {
             #[cfg(feature = "debug_merkle")]
             {
                 use crate::merkle::verify_proof_debug;
@@ -169,62 +470,113 @@ impl StateAccess for RecordingState {
 
     fn write_value(&mut self, key: [u8; 32], value: Option<Vec<u8>>) -> Result<(), CoreError> {
         let proof = self.tree.prove(key);
-        self.proofs.push(proof.clone());
+        let previous = if proof.present { Some(proof.value.clone()) } else { None };
+        self.journal.push((key, previous));
+        self.proofs.push(proof);
+        self.proof_roots.push(self.root);
         self.tree.update(key, value);
         self.root = self.tree.root();
         Ok(())
     }
+
+    fn checkpoint(&self) -> RecordingCheckpoint {
+        RecordingCheckpoint {
+            root: self.root,
+            proofs: self.proofs.len(),
+            journal: self.journal.len(),
+        }
+    }
+
+    // As in `ProofState`, proofs recorded before the rollback stay recorded: the guest consumes them
+    // replaying the message up to its failure.
+    fn rollback(&mut self, checkpoint: RecordingCheckpoint) -> Result<(), CoreError> {
+        if checkpoint.journal > self.journal.len() || checkpoint.proofs > self.proofs.len() {
+            return Err(CoreError::State("stale checkpoint"));
+        }
+        for (key, previous) in self.journal.drain(checkpoint.journal..).rev() {
+            self.tree.update(key, previous);
+        }
+        self.root = self.tree.root();
+        if self.root != checkpoint.root {
+            return Err(CoreError::State("rollback root mismatch"));
+        }
+        Ok(())
+    }
 }
 
-pub fn get_balance<S: StateAccess>(state: &mut S, account: &[u8; 20], asset: &[u8; 32]) -> Result<Balance, CoreError> {
-    let key = key_balance(account, asset);
-    let value = state.read_value(key)?;
+// Reads a leaf and rejects a present value that is not its namespace's record size, so a
+// malformed proof value fails naming the record instead of partway through decoding it.
+fn read_record<S: StateAccess>(
+    state: &mut S,
+    key: [u8; 32],
+    len: usize,
+    err: &'static str,
+) -> Result<Option<Vec<u8>>, CoreError> {
+    match state.read_value(key)? {
+        Some(value) if value.len() != len => Err(CoreError::Decode(err)),
+        value => Ok(value),
+    }
+}
+
+pub fn get_balance<S: StateAccess>(state: &mut S, account: &Address, asset: &AssetId) -> Result<Balance, CoreError> {
+    let key = key_balance_with::<S::Hasher>(account, asset);
+    let value = read_record(state, key, BALANCE_LEN, "invalid balance length")?;
     if value.is_none() {
         return Ok(Balance::empty());
     }
     Balance::decode(value.as_ref().unwrap())
 }
 
-pub fn set_balance<S: StateAccess>(state: &mut S, account: &[u8; 20], asset: &[u8; 32], balance: &Balance) -> Result<(), CoreError> {
-    let key = key_balance(account, asset);
+pub fn set_balance<S: StateAccess>(state: &mut S, account: &Address, asset: &AssetId, balance: &Balance) -> Result<(), CoreError> {
+    let key = key_balance_with::<S::Hasher>(account, asset);
     state.write_value(key, Some(balance.encode().to_vec()))
 }
 
-pub fn get_nonce<S: StateAccess>(state: &mut S, account: &[u8; 20]) -> Result<u64, CoreError> {
-    let key = key_nonce(account);
-    let value = state.read_value(key)?;
+pub fn get_nonce<S: StateAccess>(state: &mut S, account: &Address) -> Result<u64, CoreError> {
+    let key = key_nonce_with::<S::Hasher>(account);
+    let value = read_record(state, key, NONCE_LEN, "invalid nonce length")?;
     if value.is_none() {
         return Ok(0u64);
     }
     let value = value.unwrap();
-    if value.len() != 8 {
-        return Err(CoreError::Decode("invalid nonce length"));
-    }
     Ok(u64::from_be_bytes(value.try_into().unwrap()))
 }
 
-pub fn set_nonce<S: StateAccess>(state: &mut S, account: &[u8; 20], nonce: u64) -> Result<(), CoreError> {
-    let key = key_nonce(account);
+pub fn set_nonce<S: StateAccess>(state: &mut S, account: &Address, nonce: u64) -> Result<(), CoreError> {
+    let key = key_nonce_with::<S::Hasher>(account);
     state.write_value(key, Some(nonce.to_be_bytes().to_vec()))
 }
 
-pub fn get_order<S: StateAccess>(state: &mut S, order_id: &[u8; 32]) -> Result<Option<Order>, CoreError> {
-    let key = key_order(order_id);
-    let value = state.read_value(key)?;
+pub fn get_order<S: StateAccess>(
+    state: &mut S,
+    scope: Option<&MarketId>,
+    order_id: &OrderId,
+) -> Result<Option<Order>, CoreError> {
+    let key = key_order_with::<S::Hasher>(scope, order_id);
+    let value = read_record(state, key, ORDER_LEN, "invalid order length")?;
     if value.is_none() {
         return Ok(None);
     }
     Ok(Some(Order::decode(value.as_ref().unwrap())?))
 }
 
-pub fn set_order<S: StateAccess>(state: &mut S, order_id: &[u8; 32], order: &Order) -> Result<(), CoreError> {
-    let key = key_order(order_id);
+pub fn set_order<S: StateAccess>(
+    state: &mut S,
+    scope: Option<&MarketId>,
+    order_id: &OrderId,
+    order: &Order,
+) -> Result<(), CoreError> {
+    let key = key_order_with::<S::Hasher>(scope, order_id);
     state.write_value(key, Some(order.encode()))
 }
 
-pub fn get_order_node<S: StateAccess>(state: &mut S, order_id: &[u8; 32]) -> Result<OrderNode, CoreError> {
-    let key = key_order_node(order_id);
-    let value = state.read_value(key)?;
+pub fn get_order_node<S: StateAccess>(
+    state: &mut S,
+    scope: Option<&MarketId>,
+    order_id: &OrderId,
+) -> Result<OrderNode, CoreError> {
+    let key = key_order_node_with::<S::Hasher>(scope, order_id);
+    let value = read_record(state, key, ORDER_NODE_LEN, "invalid order node length")?;
     if value.is_none() {
         return Ok(OrderNode {
             prev_order_id: NONE_ORDER_ID,
@@ -234,14 +586,19 @@ pub fn get_order_node<S: StateAccess>(state: &mut S, order_id: &[u8; 32]) -> Res
     OrderNode::decode(value.as_ref().unwrap())
 }
 
-pub fn set_order_node<S: StateAccess>(state: &mut S, order_id: &[u8; 32], node: &OrderNode) -> Result<(), CoreError> {
-    let key = key_order_node(order_id);
+pub fn set_order_node<S: StateAccess>(
+    state: &mut S,
+    scope: Option<&MarketId>,
+    order_id: &OrderId,
+    node: &OrderNode,
+) -> Result<(), CoreError> {
+    let key = key_order_node_with::<S::Hasher>(scope, order_id);
     state.write_value(key, Some(node.encode().to_vec()))
 }
 
-pub fn get_tick_node<S: StateAccess>(state: &mut S, market: &[u8; 32], side: u8, tick: i32) -> Result<TickNode, CoreError> {
-    let key = key_tick_node(market, side, tick);
-    let value = state.read_value(key)?;
+pub fn get_tick_node<S: StateAccess>(state: &mut S, market: &MarketId, side: u8, tick: i32) -> Result<TickNode, CoreError> {
+    let key = key_tick_node_with::<S::Hasher>(market, side, tick);
+    let value = read_record(state, key, TICK_NODE_LEN, "invalid tick node length")?;
     if value.is_none() {
         return Ok(TickNode {
             prev_tick: NONE_TICK,
@@ -253,14 +610,14 @@ pub fn get_tick_node<S: StateAccess>(state: &mut S, market: &[u8; 32], side: u8,
     TickNode::decode(value.as_ref().unwrap())
 }
 
-pub fn set_tick_node<S: StateAccess>(state: &mut S, market: &[u8; 32], side: u8, tick: i32, node: &TickNode) -> Result<(), CoreError> {
-    let key = key_tick_node(market, side, tick);
+pub fn set_tick_node<S: StateAccess>(state: &mut S, market: &MarketId, side: u8, tick: i32, node: &TickNode) -> Result<(), CoreError> {
+    let key = key_tick_node_with::<S::Hasher>(market, side, tick);
     state.write_value(key, Some(node.encode().to_vec()))
 }
 
-pub fn get_market_best<S: StateAccess>(state: &mut S, market: &[u8; 32]) -> Result<MarketBest, CoreError> {
-    let key = key_market_best(market);
-    let value = state.read_value(key)?;
+pub fn get_market_best<S: StateAccess>(state: &mut S, market: &MarketId) -> Result<MarketBest, CoreError> {
+    let key = key_market_best_with::<S::Hasher>(market);
+    let value = read_record(state, key, MARKET_BEST_LEN, "invalid market best length")?;
     if value.is_none() {
         return Ok(MarketBest {
             best_bid: NONE_TICK,
@@ -270,14 +627,14 @@ pub fn get_market_best<S: StateAccess>(state: &mut S, market: &[u8; 32]) -> Resu
     MarketBest::decode(value.as_ref().unwrap())
 }
 
-pub fn set_market_best<S: StateAccess>(state: &mut S, market: &[u8; 32], best: &MarketBest) -> Result<(), CoreError> {
-    let key = key_market_best(market);
+pub fn set_market_best<S: StateAccess>(state: &mut S, market: &MarketId, best: &MarketBest) -> Result<(), CoreError> {
+    let key = key_market_best_with::<S::Hasher>(market);
     state.write_value(key, Some(best.encode().to_vec()))
 }
 
-pub fn get_fee_vault<S: StateAccess>(state: &mut S, asset: &[u8; 32]) -> Result<FeeVault, CoreError> {
-    let key = key_fee_vault(asset);
-    let value = state.read_value(key)?;
+pub fn get_fee_vault<S: StateAccess>(state: &mut S, asset: &AssetId) -> Result<FeeVault, CoreError> {
+    let key = key_fee_vault_with::<S::Hasher>(asset);
+    let value = read_record(state, key, FEE_VAULT_LEN, "invalid fee vault length")?;
     if value.is_none() {
         return Ok(FeeVault {
             total: U256::zero(),
@@ -286,7 +643,37 @@ pub fn get_fee_vault<S: StateAccess>(state: &mut S, asset: &[u8; 32]) -> Result<
     FeeVault::decode(value.as_ref().unwrap())
 }
 
-pub fn set_fee_vault<S: StateAccess>(state: &mut S, asset: &[u8; 32], fee: &FeeVault) -> Result<(), CoreError> {
-    let key = key_fee_vault(asset);
+pub fn set_fee_vault<S: StateAccess>(state: &mut S, asset: &AssetId, fee: &FeeVault) -> Result<(), CoreError> {
+    let key = key_fee_vault_with::<S::Hasher>(asset);
     state.write_value(key, Some(fee.encode().to_vec()))
 }
+
+pub fn get_trade_seq<S: StateAccess>(state: &mut S, market: &MarketId) -> Result<u64, CoreError> {
+    let key = key_trade_seq_with::<S::Hasher>(market);
+    let value = read_record(state, key, TRADE_SEQ_LEN, "invalid trade seq length")?;
+    if value.is_none() {
+        return Ok(0u64);
+    }
+    let value = value.unwrap();
+    Ok(u64::from_be_bytes(value.try_into().unwrap()))
+}
+
+pub fn set_trade_seq<S: StateAccess>(state: &mut S, market: &MarketId, seq: u64) -> Result<(), CoreError> {
+    let key = key_trade_seq_with::<S::Hasher>(market);
+    state.write_value(key, Some(seq.to_be_bytes().to_vec()))
+}
+
+pub fn get_open_count<S: StateAccess>(state: &mut S, account: &Address) -> Result<u32, CoreError> {
+    let key = key_open_count_with::<S::Hasher>(account);
+    let value = read_record(state, key, OPEN_COUNT_LEN, "invalid open count length")?;
+    if value.is_none() {
+        return Ok(0u32);
+    }
+    let value = value.unwrap();
+    Ok(u32::from_be_bytes(value.try_into().unwrap()))
+}
+
+pub fn set_open_count<S: StateAccess>(state: &mut S, account: &Address, count: u32) -> Result<(), CoreError> {
+    let key = key_open_count_with::<S::Hasher>(account);
+    state.write_value(key, Some(count.to_be_bytes().to_vec()))
+}
@@ -1,3 +1,6 @@
+use alloc::string::String;
+use core::fmt;
+
 use crate::encoding::Writer;
 use crate::errors::CoreError;
 
@@ -23,7 +26,248 @@ impl U256 {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address(pub [u8; 20]);
+
+impl Address {
+    pub const ZERO: Address = Address([0u8; 20]);
+
+    pub fn from_hex(value: &str) -> Result<Self, CoreError> {
+        let digits = value.strip_prefix("0x").unwrap_or(value).as_bytes();
+        if digits.len() != 40 {
+            return Err(CoreError::Decode("address must be 20 bytes"));
+        }
+        let mut out = [0u8; 20];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = (hex_nibble(digits[2 * i])? << 4) | hex_nibble(digits[2 * i + 1])?;
+        }
+        Ok(Address(out))
+    }
+
+    pub fn to_hex(&self) -> String {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut out = String::with_capacity(42);
+        out.push_str("0x");
+        for byte in self.0 {
+            out.push(DIGITS[(byte >> 4) as usize] as char);
+            out.push(DIGITS[(byte & 0x0f) as usize] as char);
+        }
+        out
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0u8; 20]
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address(0x")?;
+        write_hex(f, &self.0)?;
+        write!(f, ")")
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl From<[u8; 20]> for Address {
+    fn from(bytes: [u8; 20]) -> Self {
+        Address(bytes)
+    }
+}
+
+// 32-byte identifiers that share a wire form but must not be swapped for one another, e.g. a market
+// id where a state key wants an asset id.
+macro_rules! id32 {
+    ($($(#[$doc:meta])* $name:ident),* $(,)?) => {$(
+        $(#[$doc])*
+        #[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub [u8; 32]);
+
+        impl $name {
+            pub const ZERO: $name = $name([0u8; 32]);
+
+            pub fn is_zero(&self) -> bool {
+                self.0 == [0u8; 32]
+            }
+
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+        }
+
+        impl From<[u8; 32]> for $name {
+            fn from(bytes: [u8; 32]) -> Self {
+                $name(bytes)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, concat!(stringify!($name), "(0x"))?;
+                write_hex(f, &self.0)?;
+                write!(f, ")")
+            }
+        }
+
+        // Truncated to the first four and last two bytes; enough to tell ids apart in a trace.
+        #[cfg(feature = "std")]
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "0x")?;
+                write_hex(f, &self.0[..4])?;
+                write!(f, "..")?;
+                write_hex(f, &self.0[30..])
+            }
+        }
+    )*};
+}
+
+id32! {
+    OrderId,
+    MarketId,
+    /// A market id is not accepted where an asset id is expected:
+    ///
+    /// ```compile_fail
+    /// use clob_core::state::key_fee_vault;
+    /// use clob_core::types::MarketId;
+    ///
+    /// key_fee_vault(&MarketId([1u8; 32]));
+    /// ```
+    ///
+    /// ```
+    /// use clob_core::state::key_fee_vault;
+    /// use clob_core::types::AssetId;
+    ///
+    /// key_fee_vault(&AssetId([1u8; 32]));
+    /// ```
+    AssetId,
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for byte in bytes {
+        write!(f, "{byte:02x}")?;
+    }
+    Ok(())
+}
+
+fn hex_nibble(c: u8) -> Result<u8, CoreError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(CoreError::Decode("invalid address hex")),
+    }
+}
+
+/// A U256 magnitude with a sign, for net balance changes. Zero is always positive, so every value
+/// has exactly one encoding: `U8(negative) || U256(magnitude)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SignedU256 {
+    negative: bool,
+    magnitude: U256,
+}
+
+impl SignedU256 {
+    pub const ZERO: SignedU256 = SignedU256 {
+        negative: false,
+        magnitude: U256([0; 4]),
+    };
+
+    pub fn new(negative: bool, magnitude: U256) -> Self {
+        Self {
+            negative: negative && !magnitude.is_zero(),
+            magnitude,
+        }
+    }
+
+    pub fn positive(magnitude: U256) -> Self {
+        Self::new(false, magnitude)
+    }
+
+    pub fn negative(magnitude: U256) -> Self {
+        Self::new(true, magnitude)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_zero()
+    }
+
+    pub fn magnitude(&self) -> U256 {
+        self.magnitude
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        if self.negative == other.negative {
+            return Some(Self::new(self.negative, self.magnitude.checked_add(other.magnitude)?));
+        }
+        if self.magnitude >= other.magnitude {
+            Some(Self::new(self.negative, self.magnitude - other.magnitude))
+        } else {
+            Some(Self::new(other.negative, other.magnitude - self.magnitude))
+        }
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_add(-other)
+    }
+
+    pub fn encode(&self) -> [u8; 33] {
+        let mut out = [0u8; 33];
+        out[0] = self.negative as u8;
+        out[1..].copy_from_slice(&self.magnitude.to_be_bytes());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, CoreError> {
+        if bytes.len() != 33 {
+            return Err(CoreError::Decode("invalid signed amount length"));
+        }
+        let negative = match bytes[0] {
+            0 => false,
+            1 => true,
+            _ => return Err(CoreError::Decode("invalid signed amount sign")),
+        };
+        let magnitude = U256::from_be_bytes(&bytes[1..]);
+        if negative && magnitude.is_zero() {
+            return Err(CoreError::Decode("negative zero"));
+        }
+        Ok(Self { negative, magnitude })
+    }
+}
+
+impl core::ops::Neg for SignedU256 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(!self.negative, self.magnitude)
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for SignedU256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", self.magnitude)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Side {
     Buy,
     Sell,
@@ -53,6 +297,16 @@ impl Side {
     }
 }
 
+#[cfg(feature = "std")]
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        })
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TimeInForce {
     Gtc,
@@ -76,6 +330,100 @@ impl TimeInForce {
     }
 }
 
+#[cfg(feature = "std")]
+impl fmt::Display for TimeInForce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TimeInForce::Gtc => "gtc",
+            TimeInForce::Ioc => "ioc",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AllocationMode {
+    #[default]
+    Fifo,
+    ProRata,
+}
+
+impl AllocationMode {
+    pub fn from_u8(value: u8) -> Result<Self, CoreError> {
+        match value {
+            0 => Ok(AllocationMode::Fifo),
+            1 => Ok(AllocationMode::ProRata),
+            _ => Err(CoreError::Decode("invalid allocation mode")),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            AllocationMode::Fifo => 0,
+            AllocationMode::ProRata => 1,
+        }
+    }
+}
+
+// Per-market ids fold the market into order and order-node keys; global ids share one keyspace
+// across every market in the tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrderIdScope {
+    #[default]
+    Market,
+    Global,
+}
+
+impl OrderIdScope {
+    pub fn from_u8(value: u8) -> Result<Self, CoreError> {
+        match value {
+            0 => Ok(OrderIdScope::Market),
+            1 => Ok(OrderIdScope::Global),
+            _ => Err(CoreError::Decode("invalid order id scope")),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            OrderIdScope::Market => 0,
+            OrderIdScope::Global => 1,
+        }
+    }
+
+    /// The market folded into order keys under this scope.
+    pub fn market(self, market_id: &MarketId) -> Option<&MarketId> {
+        match self {
+            OrderIdScope::Market => Some(market_id),
+            OrderIdScope::Global => None,
+        }
+    }
+}
+
+// What a batch does with a message that fails: abort the whole batch, or roll the message back,
+// record it as failed and continue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FailureMode {
+    #[default]
+    Abort,
+    Skip,
+}
+
+impl FailureMode {
+    pub fn from_u8(value: u8) -> Result<Self, CoreError> {
+        match value {
+            0 => Ok(FailureMode::Abort),
+            1 => Ok(FailureMode::Skip),
+            _ => Err(CoreError::Decode("invalid failure mode")),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            FailureMode::Abort => 0,
+            FailureMode::Skip => 1,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OrderStatus {
     Open,
@@ -102,6 +450,17 @@ impl OrderStatus {
     }
 }
 
+#[cfg(feature = "std")]
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OrderStatus::Open => "open",
+            OrderStatus::Filled => "filled",
+            OrderStatus::Canceled => "canceled",
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Balance {
     pub available: U256,
@@ -136,7 +495,8 @@ impl Balance {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Order {
-    pub owner: [u8; 20],
+    pub owner: Address,
+    pub market_id: MarketId,
     pub side: Side,
     pub tick: i32,
     pub qty_remaining: U256,
@@ -148,6 +508,7 @@ impl Order {
     pub fn encode(&self) -> Vec<u8> {
         let mut w = Writer::new();
         w.write_addr(&self.owner);
+        w.write_b32(&self.market_id.0);
         w.write_u8(self.side.as_u8());
         w.write_i32(self.tick);
         w.write_u256(&self.qty_remaining);
@@ -159,6 +520,7 @@ impl Order {
     pub fn decode(bytes: &[u8]) -> Result<Self, CoreError> {
         let mut r = crate::encoding::Reader::new(bytes);
         let owner = r.read_addr()?;
+        let market_id = MarketId(r.read_b32()?);
         let side = Side::from_u8(r.read_u8()?)?;
         let tick = r.read_i32()?;
         let qty_remaining = r.read_u256()?;
@@ -167,6 +529,7 @@ impl Order {
         r.expect_finished()?;
         Ok(Self {
             owner,
+            market_id,
             side,
             tick,
             qty_remaining,
@@ -178,15 +541,15 @@ impl Order {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct OrderNode {
-    pub prev_order_id: [u8; 32],
-    pub next_order_id: [u8; 32],
+    pub prev_order_id: OrderId,
+    pub next_order_id: OrderId,
 }
 
 impl OrderNode {
     pub fn encode(&self) -> [u8; 64] {
         let mut out = [0u8; 64];
-        out[..32].copy_from_slice(&self.prev_order_id);
-        out[32..].copy_from_slice(&self.next_order_id);
+        out[..32].copy_from_slice(self.prev_order_id.as_bytes());
+        out[32..].copy_from_slice(self.next_order_id.as_bytes());
         out
     }
 
@@ -195,8 +558,8 @@ impl OrderNode {
             return Err(CoreError::Decode("invalid order node length"));
         }
         Ok(Self {
-            prev_order_id: bytes[..32].try_into().unwrap(),
-            next_order_id: bytes[32..].try_into().unwrap(),
+            prev_order_id: OrderId(bytes[..32].try_into().unwrap()),
+            next_order_id: OrderId(bytes[32..].try_into().unwrap()),
         })
     }
 }
@@ -205,8 +568,8 @@ impl OrderNode {
 pub struct TickNode {
     pub prev_tick: i32,
     pub next_tick: i32,
-    pub head_order_id: [u8; 32],
-    pub tail_order_id: [u8; 32],
+    pub head_order_id: OrderId,
+    pub tail_order_id: OrderId,
 }
 
 impl TickNode {
@@ -214,8 +577,8 @@ impl TickNode {
         let mut out = [0u8; 72];
         out[..4].copy_from_slice(&self.prev_tick.to_be_bytes());
         out[4..8].copy_from_slice(&self.next_tick.to_be_bytes());
-        out[8..40].copy_from_slice(&self.head_order_id);
-        out[40..72].copy_from_slice(&self.tail_order_id);
+        out[8..40].copy_from_slice(self.head_order_id.as_bytes());
+        out[40..72].copy_from_slice(self.tail_order_id.as_bytes());
         out
     }
 
@@ -226,8 +589,8 @@ impl TickNode {
         Ok(Self {
             prev_tick: i32::from_be_bytes(bytes[..4].try_into().unwrap()),
             next_tick: i32::from_be_bytes(bytes[4..8].try_into().unwrap()),
-            head_order_id: bytes[8..40].try_into().unwrap(),
-            tail_order_id: bytes[40..72].try_into().unwrap(),
+            head_order_id: OrderId(bytes[8..40].try_into().unwrap()),
+            tail_order_id: OrderId(bytes[40..72].try_into().unwrap()),
         })
     }
 }
@@ -279,46 +642,71 @@ impl FeeVault {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TradeRecord {
-    pub market_id: [u8; 32],
-    pub maker_order_id: [u8; 32],
-    pub taker_order_id: [u8; 32],
-    pub maker: [u8; 20],
-    pub taker: [u8; 20],
+    pub market_id: MarketId,
+    pub trade_seq: u64,
+    pub maker_order_id: OrderId,
+    pub taker_order_id: OrderId,
+    pub maker: Address,
+    pub taker: Address,
     pub side_taker: Side,
     pub maker_tick: i32,
     pub qty_base: U256,
     pub quote_amt: U256,
-    pub taker_fee_quote: U256,
+    pub taker_fee: U256,
 }
 
 impl TradeRecord {
     pub fn encode(&self) -> Vec<u8> {
         let mut w = Writer::new();
-        w.write_b32(&self.market_id);
-        w.write_b32(&self.maker_order_id);
-        w.write_b32(&self.taker_order_id);
+        w.write_b32(self.market_id.as_bytes());
+        w.write_u64(self.trade_seq);
+        w.write_b32(self.maker_order_id.as_bytes());
+        w.write_b32(self.taker_order_id.as_bytes());
         w.write_addr(&self.maker);
         w.write_addr(&self.taker);
         w.write_u8(self.side_taker.as_u8());
         w.write_i32(self.maker_tick);
         w.write_u256(&self.qty_base);
         w.write_u256(&self.quote_amt);
-        w.write_u256(&self.taker_fee_quote);
+        w.write_u256(&self.taker_fee);
         w.into_bytes()
     }
+
+    pub fn decode(reader: &mut crate::encoding::Reader) -> Result<Self, CoreError> {
+        Ok(Self {
+            market_id: MarketId(reader.read_b32()?),
+            trade_seq: reader.read_u64()?,
+            maker_order_id: OrderId(reader.read_b32()?),
+            taker_order_id: OrderId(reader.read_b32()?),
+            maker: reader.read_addr()?,
+            taker: reader.read_addr()?,
+            side_taker: Side::from_u8(reader.read_u8()?)?,
+            maker_tick: reader.read_i32()?,
+            qty_base: reader.read_u256()?,
+            quote_amt: reader.read_u256()?,
+            taker_fee: reader.read_u256()?,
+        })
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FeeTotal {
-    pub asset_id: [u8; 32],
+    pub asset_id: AssetId,
     pub total_fee: U256,
 }
 
 impl FeeTotal {
     pub fn encode(&self) -> Vec<u8> {
         let mut w = Writer::new();
-        w.write_b32(&self.asset_id);
+        w.write_b32(self.asset_id.as_bytes());
         w.write_u256(&self.total_fee);
         w.into_bytes()
     }
+
+    pub fn decode(reader: &mut crate::encoding::Reader) -> Result<Self, CoreError> {
+        Ok(Self {
+            asset_id: AssetId(reader.read_b32()?),
+            total_fee: reader.read_u256()?,
+        })
+    }
 }
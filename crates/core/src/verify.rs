@@ -1,66 +1,183 @@
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 
 use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
 
-use crate::constants::{BATCH_TAG, DOMAIN_TAG};
+use crate::constants::{BATCH_TAG, DOMAIN_TAG, SIGNING_TAG};
 use crate::errors::CoreError;
-use crate::hash::keccak256;
-use crate::input::{Message, MessageSignature, Rules};
-use crate::types::U256;
+use crate::hash::{keccak256, Hasher, Keccak256};
+use crate::input::{Message, MessageSignature, Rules, SignedMessage};
+use crate::types::{Address, MarketId, OrderId, U256};
 
-pub fn domain_separator(chain_id: u64, venue_id: &[u8; 32], market_id: &[u8; 32]) -> [u8; 32] {
-    let mut buf = Vec::with_capacity(DOMAIN_TAG.len() + 8 + 32 + 32);
+pub fn domain_separator(
+    chain_id: u64,
+    venue_id: &[u8; 32],
+    market_id: &MarketId,
+    instance_salt: &[u8; 32],
+) -> [u8; 32] {
+    domain_separator_with::<Keccak256>(chain_id, venue_id, market_id, instance_salt)
+}
+
+pub fn domain_separator_with<H: Hasher>(
+    chain_id: u64,
+    venue_id: &[u8; 32],
+    market_id: &MarketId,
+    instance_salt: &[u8; 32],
+) -> [u8; 32] {
+    let mut buf = Vec::with_capacity(DOMAIN_TAG.len() + 8 + 32 + 32 + 32);
     buf.extend_from_slice(DOMAIN_TAG);
     buf.extend_from_slice(&chain_id.to_be_bytes());
     buf.extend_from_slice(venue_id);
-    buf.extend_from_slice(market_id);
-    keccak256(&buf)
+    buf.extend_from_slice(market_id.as_bytes());
+    buf.extend_from_slice(instance_salt);
+    H::hash(&buf)
+}
+
+/// A market's signing domain, derived once so every message and batch hashed under it reuses the
+/// separator instead of threading it through by hand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Domain {
+    pub chain_id: u64,
+    pub venue_id: [u8; 32],
+    pub market_id: MarketId,
+    pub instance_salt: [u8; 32],
+    pub separator: [u8; 32],
+}
+
+impl Domain {
+    pub fn new(chain_id: u64, venue_id: [u8; 32], market_id: MarketId, instance_salt: [u8; 32]) -> Self {
+        Self {
+            chain_id,
+            venue_id,
+            market_id,
+            instance_salt,
+            separator: domain_separator(chain_id, &venue_id, &market_id, &instance_salt),
+        }
+    }
+
+    pub fn message_hash(&self, message: &Message) -> [u8; 32] {
+        message_hash(&self.separator, message)
+    }
+
+    pub fn batch_digest(&self, batch_seq: u64, message_hashes: &[[u8; 32]]) -> [u8; 32] {
+        batch_digest(&self.separator, batch_seq, message_hashes)
+    }
 }
 
 pub fn rules_hash(rules: &Rules) -> [u8; 32] {
-    keccak256(&rules.encode())
+    rules_hash_with::<Keccak256>(rules)
+}
+
+pub fn rules_hash_with<H: Hasher>(rules: &Rules) -> [u8; 32] {
+    H::hash(&rules.encode())
 }
 
 pub fn message_hash(domain_separator: &[u8; 32], message: &Message) -> [u8; 32] {
+    message_hash_with::<Keccak256>(domain_separator, message)
+}
+
+pub fn message_hash_with<H: Hasher>(domain_separator: &[u8; 32], message: &Message) -> [u8; 32] {
     let msg_bytes = message.encode_signed();
-    let msg_struct = keccak256(&msg_bytes);
+    let msg_struct = H::hash(&msg_bytes);
     let mut buf = Vec::with_capacity(2 + 32 + 32);
     buf.push(0x19);
     buf.push(0x01);
     buf.extend_from_slice(domain_separator);
     buf.extend_from_slice(&msg_struct);
-    keccak256(&buf)
+    H::hash(&buf)
+}
+
+/// The separator messages in batch `batch_seq` are signed under. With `bind_batch_seq` off it is the
+/// domain separator itself; with it on it also commits to the batch, so a signature verifies in
+/// exactly one batch.
+pub fn signing_separator(domain_separator: &[u8; 32], rules: &Rules, batch_seq: u64) -> [u8; 32] {
+    signing_separator_with::<Keccak256>(domain_separator, rules, batch_seq)
+}
+
+pub fn signing_separator_with<H: Hasher>(domain_separator: &[u8; 32], rules: &Rules, batch_seq: u64) -> [u8; 32] {
+    if !rules.bind_batch_seq {
+        return *domain_separator;
+    }
+    let mut buf = Vec::with_capacity(SIGNING_TAG.len() + 32 + 8);
+    buf.extend_from_slice(SIGNING_TAG);
+    buf.extend_from_slice(domain_separator);
+    buf.extend_from_slice(&batch_seq.to_be_bytes());
+    H::hash(&buf)
+}
+
+pub fn derive_order_id(domain_separator: &[u8; 32], trader: &Address, nonce: u64) -> OrderId {
+    derive_order_id_with::<Keccak256>(domain_separator, trader, nonce)
+}
+
+pub fn derive_order_id_with<H: Hasher>(domain_separator: &[u8; 32], trader: &Address, nonce: u64) -> OrderId {
+    let mut buf = Vec::with_capacity(32 + 20 + 8);
+    buf.extend_from_slice(domain_separator);
+    buf.extend_from_slice(&trader.0);
+    buf.extend_from_slice(&nonce.to_be_bytes());
+    OrderId(H::hash(&buf))
 }
 
 pub fn batch_digest(
     domain_separator: &[u8; 32],
     batch_seq: u64,
     message_hashes: &[ [u8; 32] ],
+) -> [u8; 32] {
+    batch_digest_with::<Keccak256>(domain_separator, batch_seq, message_hashes)
+}
+
+pub fn batch_digest_with<H: Hasher>(
+    domain_separator: &[u8; 32],
+    batch_seq: u64,
+    message_hashes: &[ [u8; 32] ],
 ) -> [u8; 32] {
     let mut msg_concat = Vec::with_capacity(message_hashes.len() * 32);
     for h in message_hashes {
         msg_concat.extend_from_slice(h);
     }
-    let inner = keccak256(&msg_concat);
+    let inner = H::hash(&msg_concat);
     let mut buf = Vec::with_capacity(BATCH_TAG.len() + 32 + 8 + 32);
     buf.extend_from_slice(BATCH_TAG);
     buf.extend_from_slice(domain_separator);
     buf.extend_from_slice(&batch_seq.to_be_bytes());
     buf.extend_from_slice(&inner);
-    keccak256(&buf)
+    H::hash(&buf)
 }
 
-pub fn recover_address(hash: &[u8; 32], sig: &MessageSignature) -> Result<[u8; 20], CoreError> {
+pub fn recover_address(hash: &[u8; 32], sig: &MessageSignature) -> Result<Address, CoreError> {
+    recover_into(&mut [0u8; 64], hash, sig)
+}
+
+// k256 keeps its generator tables static, so the only per-call setup worth sharing across a batch is
+// the compact signature buffer. Results pair with inputs by index.
+pub fn recover_addresses(hashes: &[[u8; 32]], sigs: &[MessageSignature]) -> Vec<Result<Address, CoreError>> {
     let mut sig_bytes = [0u8; 64];
+    hashes
+        .iter()
+        .zip(sigs)
+        .map(|(hash, sig)| recover_into(&mut sig_bytes, hash, sig))
+        .collect()
+}
+
+/// Each message's signer over `msgHash` under `signing_sep`, paired with `messages` by index.
+pub fn recover_signers_with<H: Hasher>(
+    signing_sep: &[u8; 32],
+    messages: &[SignedMessage],
+) -> Vec<Result<Address, CoreError>> {
+    let mut sig_bytes = [0u8; 64];
+    messages
+        .iter()
+        .map(|signed| {
+            let hash = message_hash_with::<H>(signing_sep, &signed.message);
+            recover_into(&mut sig_bytes, &hash, &signed.signature)
+        })
+        .collect()
+}
+
+fn recover_into(sig_bytes: &mut [u8; 64], hash: &[u8; 32], sig: &MessageSignature) -> Result<Address, CoreError> {
     sig_bytes[..32].copy_from_slice(&sig.r);
     sig_bytes[32..].copy_from_slice(&sig.s);
-    let signature = Signature::from_slice(&sig_bytes).map_err(|_| CoreError::Signature("bad signature"))?;
-    let v = match sig.v {
-        0 | 1 => sig.v,
-        27 | 28 => sig.v - 27,
-        _ => return Err(CoreError::Signature("invalid v")),
-    };
-    let recovery_id = RecoveryId::from_byte(v).ok_or(CoreError::Signature("invalid recovery id"))?;
+    let signature = Signature::from_slice(sig_bytes).map_err(|_| CoreError::Signature("bad signature"))?;
+    let recovery_id = RecoveryId::from_byte(sig.y_parity()?).ok_or(CoreError::Signature("invalid recovery id"))?;
     let verify_key = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id)
         .map_err(|_| CoreError::Signature("recover failed"))?;
     let pubkey = verify_key.to_encoded_point(false);
@@ -69,16 +186,25 @@ pub fn recover_address(hash: &[u8; 32], sig: &MessageSignature) -> Result<[u8; 2
         return Err(CoreError::Signature("invalid pubkey"));
     }
     let addr = keccak256(&pubkey[1..]);
-    Ok(addr[12..].try_into().unwrap())
+    Ok(Address(addr[12..].try_into().unwrap()))
 }
 
 pub fn verify_signature(
     domain_separator: &[u8; 32],
     message: &Message,
     sig: &MessageSignature,
-    expected_addr: &[u8; 20],
+    expected_addr: &Address,
 ) -> Result<(), CoreError> {
-    let hash = message_hash(domain_separator, message);
+    verify_signature_with::<Keccak256>(domain_separator, message, sig, expected_addr)
+}
+
+pub fn verify_signature_with<H: Hasher>(
+    domain_separator: &[u8; 32],
+    message: &Message,
+    sig: &MessageSignature,
+    expected_addr: &Address,
+) -> Result<(), CoreError> {
+    let hash = message_hash_with::<H>(domain_separator, message);
     let addr = recover_address(&hash, sig)?;
     if &addr != expected_addr {
         return Err(CoreError::Signature("signer mismatch"));
@@ -86,12 +212,27 @@ pub fn verify_signature(
     Ok(())
 }
 
+// Compares canonical encodings, so 27/28 and 0/1 spellings of one signature collide. A `v` with no
+// canonical encoding cannot verify either, so it is left to fail its own message.
+pub fn check_unique_signatures(messages: &[SignedMessage]) -> Result<(), CoreError> {
+    let mut seen = BTreeSet::new();
+    for signed in messages {
+        let Ok(encoded) = signed.signature.encode() else {
+            continue;
+        };
+        if !seen.insert(encoded) {
+            return Err(CoreError::Invalid("duplicate signature in batch"));
+        }
+    }
+    Ok(())
+}
+
 pub fn price_from_tick(tick_index: i32, tick_size: U256) -> Result<U256, CoreError> {
     if tick_index < 0 {
         return Err(CoreError::Invalid("negative tick"));
     }
     let idx = U256::from(tick_index as u64);
-    Ok(tick_size * idx)
+    tick_size.checked_mul(idx).ok_or(CoreError::Math("price overflow"))
 }
 
 pub fn check_tick_price_multiple(price: U256, tick_size: U256) -> Result<(), CoreError> {
@@ -104,6 +245,14 @@ pub fn check_tick_price_multiple(price: U256, tick_size: U256) -> Result<(), Cor
     Ok(())
 }
 
+/// Rejects a batch stamped after its deadline; a zero deadline never expires.
+pub fn check_batch_deadline(batch_timestamp: u64, batch_deadline: u64) -> Result<(), CoreError> {
+    if batch_deadline != 0 && batch_timestamp > batch_deadline {
+        return Err(CoreError::Invalid("batch past deadline"));
+    }
+    Ok(())
+}
+
 pub fn check_lot_size(qty: U256, lot_size: U256) -> Result<(), CoreError> {
     if lot_size.is_zero() {
         return Err(CoreError::Invalid("lot size zero"));
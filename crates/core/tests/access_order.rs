@@ -0,0 +1,165 @@
+// Golden access sequences: the guest consumes one proof per state access in exactly the order the
+// engine makes them, so any reordering of reads or writes here breaks host/guest sync.
+
+mod common;
+
+use clob_core::engine::apply_batch;
+use clob_core::hash::keccak256;
+use clob_core::input::{Message, RulesBuilder};
+use clob_core::state::{
+    key_balance, key_fee_vault, key_market_best, key_nonce, key_open_count, key_order, key_order_node, key_tick_node,
+    key_trade_seq, RecordingState,
+};
+use clob_core::types::{Address, OrderId, Side, TimeInForce, U256};
+use common::{place, BookBuilder, Trader, BASE, MARKET, QUOTE};
+
+// Names every key the scenarios can touch, so a mismatch reads as a diff of labels.
+fn access_labels(state: &RecordingState, maker: &Address, taker: &Address, orders: &[(&str, OrderId)]) -> Vec<String> {
+    let mut named: Vec<(String, [u8; 32])> = Vec::new();
+    for (who, account) in [("maker", maker), ("taker", taker)] {
+        named.push((format!("nonce {who}"), key_nonce(account)));
+        named.push((format!("open_count {who}"), key_open_count(account)));
+        named.push((format!("balance {who} base"), key_balance(account, &BASE)));
+        named.push((format!("balance {who} quote"), key_balance(account, &QUOTE)));
+    }
+    for (name, order_id) in orders {
+        named.push((format!("order {name}"), key_order(Some(&MARKET), order_id)));
+        named.push((format!("order_node {name}"), key_order_node(Some(&MARKET), order_id)));
+    }
+    for side in [Side::Buy, Side::Sell] {
+        for tick in -1..=4 {
+            named.push((format!("tick_node {side:?} {tick}"), key_tick_node(&MARKET, side.as_u8(), tick)));
+        }
+        named.push((format!("tick_node {side:?} none"), key_tick_node(&MARKET, side.as_u8(), i32::MIN)));
+    }
+    named.push(("market_best".into(), key_market_best(&MARKET)));
+    named.push(("trade_seq".into(), key_trade_seq(&MARKET)));
+    named.push(("fee_vault base".into(), key_fee_vault(&BASE)));
+    named.push(("fee_vault quote".into(), key_fee_vault(&QUOTE)));
+
+    state
+        .proofs
+        .iter()
+        .map(|proof| {
+            let (name, _) = named.iter().find(|(_, key)| *key == proof.key).expect("unnamed key");
+            name.clone()
+        })
+        .collect()
+}
+
+#[test]
+fn place_with_fills_access_order() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (ask_1, ask_2, bid) = (OrderId(keccak256(b"ask-1")), OrderId(keccak256(b"ask-2")), OrderId(keccak256(b"bid")));
+    let rules = RulesBuilder::new(BASE, QUOTE).taker_fee_bps(100).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 1_000, 0)
+        .resting(ask_1, &maker.address, Side::Sell, 1, 10)
+        .resting(ask_2, &maker.address, Side::Sell, 2, 10)
+        .build();
+    // Takes all of ask-1 and half of ask-2.
+    let message = place(&taker, 1, bid, Side::Buy, TimeInForce::Gtc, 2, 15);
+    assert!(apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)]).is_ok());
+
+    let orders = [("ask-1", ask_1), ("ask-2", ask_2), ("bid", bid)];
+    let labels = access_labels(&state, &maker.address, &taker.address, &orders);
+    let expected = [
+        // Nonce, order id check and collateral lock.
+        "nonce taker",
+        "nonce taker",
+        "order bid",
+        "balance taker quote",
+        "balance taker base",
+        "balance taker quote",
+        // Fill ask-1 at tick 1, then unlink it and advance the best ask.
+        "market_best",
+        "tick_node Sell 1",
+        "order ask-1",
+        "balance taker quote",
+        "balance taker base",
+        "balance maker base",
+        "balance maker quote",
+        "balance taker quote",
+        "balance taker base",
+        "balance maker base",
+        "balance maker quote",
+        "fee_vault quote",
+        "fee_vault quote",
+        "order ask-1",
+        "trade_seq",
+        "trade_seq",
+        "order_node ask-1",
+        "order_node ask-1",
+        "tick_node Sell 2",
+        "tick_node Sell 2",
+        "tick_node Sell 1",
+        "market_best",
+        // Fill half of ask-2 at tick 2.
+        "tick_node Sell 2",
+        "order ask-2",
+        "balance taker quote",
+        "balance taker base",
+        "balance maker base",
+        "balance maker quote",
+        "balance taker quote",
+        "balance taker base",
+        "balance maker base",
+        "balance maker quote",
+        "fee_vault quote",
+        "fee_vault quote",
+        "order ask-2",
+        "trade_seq",
+        "trade_seq",
+        "tick_node Sell 2",
+        // Release price improvement and record the filled taker.
+        "balance taker quote",
+        "balance taker quote",
+        "order bid",
+    ];
+    assert_eq!(labels, expected);
+}
+
+#[test]
+fn cancel_access_order() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (ask_1, ask_2, ask_3) = (OrderId(keccak256(b"ask-1")), OrderId(keccak256(b"ask-2")), OrderId(keccak256(b"ask-3")));
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 30)
+        .resting(ask_1, &maker.address, Side::Sell, 1, 10)
+        .resting(ask_2, &maker.address, Side::Sell, 1, 10)
+        .resting(ask_3, &maker.address, Side::Sell, 2, 10)
+        .build();
+    // ask-2 sits behind ask-1 at the best tick and is the level's tail, so the cancel checks both of its
+    // links before relinking around it.
+    let cancel = Message::Cancel {
+        trader: maker.address,
+        nonce: 1,
+        order_id: ask_2,
+    };
+    assert!(apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&maker, cancel)]).is_ok());
+
+    let orders = [("ask-1", ask_1), ("ask-2", ask_2), ("ask-3", ask_3)];
+    let labels = access_labels(&state, &maker.address, &taker.address, &orders);
+    let expected = [
+        "nonce maker",
+        "nonce maker",
+        "order ask-2",
+        "order_node ask-2",
+        "order_node ask-1",
+        "tick_node Sell 1",
+        "balance maker base",
+        "balance maker base",
+        "order ask-2",
+        "tick_node Sell 1",
+        "order_node ask-2",
+        "order_node ask-1",
+        "order_node ask-1",
+        "order_node ask-2",
+        "tick_node Sell 1",
+    ];
+    assert_eq!(labels, expected);
+}
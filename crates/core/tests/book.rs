@@ -0,0 +1,191 @@
+use clob_core::book::{book_quote, queue_position, recompute_market_best, verify_book_links, BookError};
+use clob_core::input::RulesBuilder;
+use clob_core::merkle::SparseMerkleTree;
+use clob_core::state::{key_market_best, key_order, key_order_node, key_tick_node};
+use clob_core::types::{
+    Address, AssetId, MarketBest, MarketId, Order, OrderId, OrderIdScope, OrderNode, OrderStatus, Side, TickNode,
+    TimeInForce, U256,
+};
+
+const MARKET: MarketId = MarketId([3u8; 32]);
+const NONE: i32 = i32::MIN;
+
+fn put_order(tree: &mut SparseMerkleTree, id: OrderId, side: Side, tick: i32, prev: OrderId, next: OrderId) {
+    let order = Order {
+        owner: Address([7u8; 20]),
+        market_id: MARKET,
+        side,
+        tick,
+        qty_remaining: U256::from(5u64),
+        tif: TimeInForce::Gtc,
+        status: OrderStatus::Open,
+    };
+    tree.update(key_order(Some(&MARKET), &id), Some(order.encode()));
+    tree.update(
+        key_order_node(Some(&MARKET), &id),
+        Some(
+            OrderNode {
+                prev_order_id: prev,
+                next_order_id: next,
+            }
+            .encode()
+            .to_vec(),
+        ),
+    );
+}
+
+fn put_tick(tree: &mut SparseMerkleTree, side: Side, tick: i32, prev: i32, next: i32, head: OrderId, tail: OrderId) {
+    tree.update(
+        key_tick_node(&MARKET, side.as_u8(), tick),
+        Some(
+            TickNode {
+                prev_tick: prev,
+                next_tick: next,
+                head_order_id: head,
+                tail_order_id: tail,
+            }
+            .encode()
+            .to_vec(),
+        ),
+    );
+}
+
+fn put_best(tree: &mut SparseMerkleTree, best_bid: i32, best_ask: i32) {
+    tree.update(
+        key_market_best(&MARKET),
+        Some(MarketBest { best_bid, best_ask }.encode().to_vec()),
+    );
+}
+
+fn id(n: u8) -> OrderId {
+    OrderId([n; 32])
+}
+
+// Bids at 5 (orders 1, 2) and 4 (order 3); asks at 6 (order 4) and 8 (order 5).
+fn healthy_book() -> SparseMerkleTree {
+    let z = OrderId::ZERO;
+    let mut tree = SparseMerkleTree::new();
+    put_order(&mut tree, id(1), Side::Buy, 5, z, id(2));
+    put_order(&mut tree, id(2), Side::Buy, 5, id(1), z);
+    put_order(&mut tree, id(3), Side::Buy, 4, z, z);
+    put_order(&mut tree, id(4), Side::Sell, 6, z, z);
+    put_order(&mut tree, id(5), Side::Sell, 8, z, z);
+    put_tick(&mut tree, Side::Buy, 5, NONE, 4, id(1), id(2));
+    put_tick(&mut tree, Side::Buy, 4, 5, NONE, id(3), id(3));
+    put_tick(&mut tree, Side::Sell, 6, NONE, 8, id(4), id(4));
+    put_tick(&mut tree, Side::Sell, 8, 6, NONE, id(5), id(5));
+    put_best(&mut tree, 5, 6);
+    tree
+}
+
+#[test]
+fn healthy_book_passes() {
+    verify_book_links(&healthy_book(), &MARKET, OrderIdScope::Market).expect("healthy book");
+    verify_book_links(&SparseMerkleTree::new(), &MARKET, OrderIdScope::Market).expect("empty book");
+}
+
+#[test]
+fn dangling_next_order_pointer() {
+    let mut tree = healthy_book();
+    put_order(&mut tree, id(2), Side::Buy, 5, id(1), id(9));
+    let err = verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect_err("dangling next");
+    assert!(matches!(err, BookError::OrderMismatch { order_id, .. } if order_id == id(9)));
+}
+
+#[test]
+fn wrong_best_tick() {
+    let mut tree = healthy_book();
+    put_best(&mut tree, 5, 8);
+    let err = verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect_err("wrong best ask");
+    assert!(matches!(err, BookError::BestNotExtreme { side: Side::Sell, tick: 8, prev_tick: 6 }));
+}
+
+#[test]
+fn asymmetric_tick_link() {
+    let mut tree = healthy_book();
+    put_tick(&mut tree, Side::Buy, 4, 7, NONE, id(3), id(3));
+    let err = verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect_err("asymmetric");
+    assert!(matches!(err, BookError::AsymmetricTickLink { side: Side::Buy, tick: 5, next_tick: 4 }));
+}
+
+#[test]
+fn unsorted_ticks() {
+    let mut tree = healthy_book();
+    put_tick(&mut tree, Side::Sell, 6, NONE, 5, id(4), id(4));
+    put_order(&mut tree, id(6), Side::Sell, 5, OrderId::ZERO, OrderId::ZERO);
+    put_tick(&mut tree, Side::Sell, 5, 6, NONE, id(6), id(6));
+    let err = verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect_err("unsorted");
+    assert!(matches!(err, BookError::UnsortedTicks { side: Side::Sell, tick: 6, next_tick: 5 }));
+}
+
+#[test]
+fn order_cycle_and_tail() {
+    let mut tree = healthy_book();
+    put_order(&mut tree, id(2), Side::Buy, 5, id(1), id(1));
+    let err = verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect_err("cycle");
+    assert!(matches!(err, BookError::OrderCycle { side: Side::Buy, tick: 5, order_id } if order_id == id(1)));
+
+    let mut tree = healthy_book();
+    put_tick(&mut tree, Side::Buy, 5, NONE, 4, id(1), id(1));
+    let err = verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect_err("tail");
+    assert!(matches!(err, BookError::TailMismatch { side: Side::Buy, tick: 5 }));
+}
+
+#[test]
+fn queue_position_counts_from_tick_head() {
+    let z = OrderId::ZERO;
+    let mut tree = SparseMerkleTree::new();
+    put_order(&mut tree, id(1), Side::Sell, 7, z, id(2));
+    put_order(&mut tree, id(2), Side::Sell, 7, id(1), id(3));
+    put_order(&mut tree, id(3), Side::Sell, 7, id(2), z);
+    put_tick(&mut tree, Side::Sell, 7, NONE, NONE, id(1), id(3));
+    put_best(&mut tree, NONE, 7);
+
+    assert_eq!(queue_position(&tree, &MARKET, OrderIdScope::Market, &id(1)), Some(0));
+    assert_eq!(queue_position(&tree, &MARKET, OrderIdScope::Market, &id(2)), Some(1));
+    assert_eq!(queue_position(&tree, &MARKET, OrderIdScope::Market, &id(3)), Some(2));
+    assert_eq!(queue_position(&tree, &MARKET, OrderIdScope::Market, &id(9)), None);
+
+    let mut filled = Order::decode(&tree.get(key_order(Some(&MARKET), &id(3))).unwrap()).unwrap();
+    filled.status = OrderStatus::Filled;
+    tree.update(key_order(Some(&MARKET), &id(3)), Some(filled.encode()));
+    assert_eq!(queue_position(&tree, &MARKET, OrderIdScope::Market, &id(3)), None);
+}
+
+#[test]
+fn book_quote_reports_mid_and_spread() {
+    let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32]))
+        .tick_size(U256::from(250u64))
+        .build()
+        .unwrap();
+    // Best bid 5 and best ask 6: prices 1250 and 1500.
+    let quote = book_quote(&healthy_book(), &MARKET, &rules).expect("two-sided book");
+    assert_eq!(quote, (U256::from(1250u64), U256::from(1500u64), U256::from(1375u64), U256::from(250u64)));
+
+    let mut tree = healthy_book();
+    put_best(&mut tree, 5, NONE);
+    assert_eq!(book_quote(&tree, &MARKET, &rules), None);
+    assert_eq!(book_quote(&SparseMerkleTree::new(), &MARKET, &rules), None);
+}
+
+#[test]
+fn stale_market_best_is_detected_and_recomputed() {
+    let healthy = healthy_book();
+    let expected = MarketBest { best_bid: 5, best_ask: 6 };
+    assert_eq!(recompute_market_best(&healthy, &MARKET), expected);
+
+    // The best ask still points at tick 8 after tick 6 was (wrongly) left populated ahead of it.
+    let mut tree = healthy_book();
+    put_best(&mut tree, 5, 8);
+    let err = verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect_err("stale best");
+    assert!(matches!(err, BookError::BestNotExtreme { side: Side::Sell, tick: 8, prev_tick: 6 }));
+    let repaired = recompute_market_best(&tree, &MARKET);
+    assert_eq!(repaired, expected);
+    put_best(&mut tree, repaired.best_bid, repaired.best_ask);
+    verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect("repaired book");
+
+    assert_eq!(
+        recompute_market_best(&SparseMerkleTree::new(), &MARKET),
+        MarketBest { best_bid: NONE, best_ask: NONE }
+    );
+}
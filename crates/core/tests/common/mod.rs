@@ -0,0 +1,186 @@
+// Shared order-book fixtures for the integration tests. Each test binary uses a different subset.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+
+use clob_core::constants::NONE_TICK;
+use clob_core::hash::keccak256;
+use clob_core::input::{Message, MessageSignature, SignedMessage};
+use clob_core::merkle::SparseMerkleTree;
+use clob_core::state::{key_balance, key_market_best, key_nonce, key_order, key_order_node, key_tick_node, RecordingState};
+use clob_core::types::{
+    Address, AssetId, Balance, MarketBest, MarketId, Order, OrderId, OrderNode, OrderStatus, Side, TickNode, TimeInForce,
+    U256,
+};
+use clob_core::verify::{domain_separator, message_hash};
+
+use k256::ecdsa::SigningKey;
+
+pub const BASE: AssetId = AssetId([1u8; 32]);
+pub const QUOTE: AssetId = AssetId([2u8; 32]);
+pub const MARKET: MarketId = MarketId([3u8; 32]);
+
+pub struct Trader {
+    pub key: SigningKey,
+    pub address: Address,
+}
+
+impl Trader {
+    pub fn new(seed: u8) -> Self {
+        let key = SigningKey::from_slice(&[seed; 32]).unwrap();
+        let pubkey = key.verifying_key().to_encoded_point(false);
+        let hash = keccak256(&pubkey.as_bytes()[1..]);
+        let address = Address(hash[12..].try_into().unwrap());
+        Self { key, address }
+    }
+}
+
+// Signs messages under the book's domain.
+pub struct Signer {
+    pub domain: [u8; 32],
+}
+
+impl Signer {
+    pub fn sign(&self, trader: &Trader, message: Message) -> SignedMessage {
+        let hash = message_hash(&self.domain, &message);
+        let (sig, recid) = trader.key.sign_prehash_recoverable(&hash).expect("sign");
+        let sig_bytes = sig.to_bytes();
+        let signature = MessageSignature {
+            r: sig_bytes[..32].try_into().unwrap(),
+            s: sig_bytes[32..].try_into().unwrap(),
+            v: recid.to_byte() + 27,
+        };
+        SignedMessage { message, signature }
+    }
+}
+
+/// Seeds a tree with balances, nonces and resting orders for `MARKET`. Orders at the same tick
+/// queue in the order they are added; tick links and the market best are derived on `build`.
+pub struct BookBuilder {
+    tree: SparseMerkleTree,
+    domain: [u8; 32],
+    orders: Vec<(OrderId, Order)>,
+}
+
+impl Default for BookBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BookBuilder {
+    pub fn new() -> Self {
+        Self {
+            tree: SparseMerkleTree::new(),
+            domain: domain_separator(1, &[9u8; 32], &MARKET, &[0u8; 32]),
+            orders: Vec::new(),
+        }
+    }
+
+    pub fn balance(mut self, account: &Address, asset: &AssetId, available: u64, locked: u64) -> Self {
+        let balance = Balance {
+            available: U256::from(available),
+            locked: U256::from(locked),
+        };
+        self.tree.update(key_balance(account, asset), Some(balance.encode().to_vec()));
+        self
+    }
+
+    pub fn nonce(mut self, account: &Address, nonce: u64) -> Self {
+        self.tree.update(key_nonce(account), Some(nonce.to_be_bytes().to_vec()));
+        self
+    }
+
+    /// Rests an open GTC order; its collateral must be seeded separately with `balance`.
+    pub fn resting(mut self, order_id: OrderId, owner: &Address, side: Side, tick: i32, qty: u64) -> Self {
+        self.orders.push((
+            order_id,
+            Order {
+                owner: *owner,
+                market_id: MARKET,
+                side,
+                tick,
+                qty_remaining: U256::from(qty),
+                tif: TimeInForce::Gtc,
+                status: OrderStatus::Open,
+            },
+        ));
+        self
+    }
+
+    pub fn tree(self) -> SparseMerkleTree {
+        let mut tree = self.tree;
+        let mut levels: BTreeMap<(u8, i32), Vec<OrderId>> = BTreeMap::new();
+        for (order_id, order) in &self.orders {
+            tree.update(key_order(Some(&MARKET), order_id), Some(order.encode()));
+            levels.entry((order.side.as_u8(), order.tick)).or_default().push(*order_id);
+        }
+        for ids in levels.values() {
+            for (i, id) in ids.iter().enumerate() {
+                let node = OrderNode {
+                    prev_order_id: if i == 0 { OrderId::ZERO } else { ids[i - 1] },
+                    next_order_id: ids.get(i + 1).copied().unwrap_or(OrderId::ZERO),
+                };
+                tree.update(key_order_node(Some(&MARKET), id), Some(node.encode().to_vec()));
+            }
+        }
+
+        let mut best = MarketBest {
+            best_bid: NONE_TICK,
+            best_ask: NONE_TICK,
+        };
+        for side in [Side::Buy, Side::Sell] {
+            // Asks link upward from the lowest tick, bids downward from the highest.
+            let mut ticks: Vec<i32> = levels.keys().filter(|(s, _)| *s == side.as_u8()).map(|(_, t)| *t).collect();
+            if side == Side::Buy {
+                ticks.reverse();
+            }
+            for (i, tick) in ticks.iter().enumerate() {
+                let ids = &levels[&(side.as_u8(), *tick)];
+                let node = TickNode {
+                    prev_tick: if i == 0 { NONE_TICK } else { ticks[i - 1] },
+                    next_tick: ticks.get(i + 1).copied().unwrap_or(NONE_TICK),
+                    head_order_id: ids[0],
+                    tail_order_id: ids[ids.len() - 1],
+                };
+                tree.update(key_tick_node(&MARKET, side.as_u8(), *tick), Some(node.encode().to_vec()));
+            }
+            if let Some(first) = ticks.first() {
+                match side {
+                    Side::Buy => best.best_bid = *first,
+                    Side::Sell => best.best_ask = *first,
+                }
+            }
+        }
+        if !self.orders.is_empty() {
+            tree.update(key_market_best(&MARKET), Some(best.encode().to_vec()));
+        }
+        tree
+    }
+
+    pub fn signer(&self) -> Signer {
+        Signer { domain: self.domain }
+    }
+
+    pub fn build(self) -> (RecordingState, Signer) {
+        let signer = self.signer();
+        (RecordingState::new(self.tree()), signer)
+    }
+}
+
+/// A limit Place with no tick hints, flags off.
+pub fn place(trader: &Trader, nonce: u64, order_id: OrderId, side: Side, tif: TimeInForce, tick: i32, qty: u64) -> Message {
+    Message::Place {
+        trader: trader.address,
+        nonce,
+        order_id,
+        side,
+        tif,
+        tick_index: tick,
+        qty_base: U256::from(qty),
+        prev_tick_hint: NONE_TICK,
+        next_tick_hint: NONE_TICK,
+        reduce_only: false,
+        quote_budget: false,
+    }
+}
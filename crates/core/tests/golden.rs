@@ -0,0 +1,344 @@
+// Golden vectors for the wire format shared with the on-chain verifier. A failure here means an
+// encoding changed; update the vector only together with the verifier and the spec.
+
+use clob_core::hash::DigestKind;
+use clob_core::input::{Message, PublicInputs, Rules, RulesBuilder};
+use clob_core::types::{
+    Address, AllocationMode, AssetId, Balance, FeeVault, MarketBest, MarketId, Order, OrderId, OrderNode, OrderStatus,
+    Side, TickNode, TimeInForce, TradeRecord, U256,
+};
+use clob_core::verify::{batch_digest, domain_separator, message_hash, rules_hash};
+
+fn canonical_rules() -> Rules {
+    RulesBuilder::new(AssetId([0x01u8; 32]), AssetId([0x02u8; 32]))
+        .lot_size(U256::from(1_000u64))
+        .taker_fee_bps(25)
+        .allocation_mode(AllocationMode::ProRata)
+        .min_notional(U256::from(5_000u64))
+        .max_balance(U256::from(u128::MAX))
+        .fee_collector(Address([0xfcu8; 20]))
+        .fee_in_received_asset(true)
+        .admin(Address([0xadu8; 20]))
+        .max_price_deviation_bps(500)
+        .base_decimals(8)
+        .quote_decimals(6)
+        .build()
+        .expect("canonical rules")
+}
+
+fn canonical_domain() -> [u8; 32] {
+    domain_separator(8453, &[0x09u8; 32], &MarketId([0x03u8; 32]), &[0x5au8; 32])
+}
+
+fn canonical_messages() -> [Message; 5] {
+    [
+        Message::Place {
+            trader: Address([0x11u8; 20]),
+            nonce: 7,
+            order_id: OrderId([0x44u8; 32]),
+            side: Side::Sell,
+            tif: TimeInForce::Ioc,
+            tick_index: -2,
+            qty_base: U256::from(123_456u64),
+            prev_tick_hint: 1,
+            next_tick_hint: 2,
+            reduce_only: false,
+            quote_budget: false,
+        },
+        Message::Cancel {
+            trader: Address([0x11u8; 20]),
+            nonce: 8,
+            order_id: OrderId([0x44u8; 32]),
+        },
+        Message::CollectFees {
+            operator: Address([0xfcu8; 20]),
+            nonce: 1,
+            asset: AssetId([0x02u8; 32]),
+            amount: U256::from(999u64),
+            to: Address([0x77u8; 20]),
+        },
+        Message::ForceCancel {
+            operator: Address([0xadu8; 20]),
+            nonce: 2,
+            order_id: OrderId([0x44u8; 32]),
+        },
+        Message::FillOrder {
+            trader: Address([0x22u8; 20]),
+            nonce: 3,
+            order_id: OrderId([0x55u8; 32]),
+            maker_order_id: OrderId([0x44u8; 32]),
+            max_qty: U256::from(2_000u64),
+        },
+    ]
+}
+
+fn assert_golden(actual: &[u8], expected: &str) {
+    assert_eq!(hex::encode(actual), expected);
+}
+
+#[test]
+fn rules_layout() {
+    assert_golden(
+        &canonical_rules().encode(),
+        concat!(
+            "0101010101010101010101010101010101010101010101010101010101010101", // baseAssetId
+            "0202020202020202020202020202020202020202020202020202020202020202", // quoteAssetId
+            "0000000000000000000000000000000000000000000000000de0b6b3a7640000", // priceScale
+            "0000000000000000000000000000000000000000000000000de0b6b3a7640000", // tickSize
+            "00000000000000000000000000000000000000000000000000000000000003e8", // lotSize
+            "00000019", // takerFeeBps
+            "00000000", // makerFeeBps
+            "00000080", // maxOrdersPerBatch
+            "00000040", // maxMatchesPerOrder
+            "00000400", // maxFillsPerBatch
+            "01", // allocationMode
+            "0000000000000000000000000000000000000000000000000000000000001388", // minNotional
+            "00000000000000000000000000000000ffffffffffffffffffffffffffffffff", // maxBalance
+            "fcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfc", // feeCollector
+            "01", // feeInReceivedAsset
+            "00", // enforceDerivedOrderId
+            "adadadadadadadadadadadadadadadadadadadad", // admin
+            "000001f4", // maxPriceDeviationBps
+            "08", // baseDecimals
+            "06", // quoteDecimals
+            "01", // commitTradeRoots
+            "00000000", // maxOpenOrdersPerTrader
+            "00000000", // maxBookStepsPerOrder
+            "00000001", // tickSpacing
+            "0000000000000000000000000000000000000000000000000000000000000000", // takerFeeTierNotional
+            "00000000", // takerFeeBpsTier2
+            "00", // orderIdScope
+            "00", // bindBatchSeq
+            "00", // failureMode
+        ),
+    );
+}
+
+#[test]
+fn order_layout() {
+    let order = Order {
+        owner: Address([0x11u8; 20]),
+        market_id: MarketId([0x33u8; 32]),
+        side: Side::Buy,
+        tick: 42,
+        qty_remaining: U256::from(1_000_000u64),
+        tif: TimeInForce::Gtc,
+        status: OrderStatus::Open,
+    };
+    assert_golden(
+        &order.encode(),
+        concat!(
+            "1111111111111111111111111111111111111111", // owner
+            "3333333333333333333333333333333333333333333333333333333333333333", // marketId
+            "00", // side
+            "0000002a", // tick
+            "00000000000000000000000000000000000000000000000000000000000f4240", // qtyRemaining
+            "00000000", // tif
+            "01", // status
+        ),
+    );
+}
+
+#[test]
+fn book_node_layouts() {
+    let tick = TickNode {
+        prev_tick: i32::MIN,
+        next_tick: 43,
+        head_order_id: OrderId([0x44u8; 32]),
+        tail_order_id: OrderId([0x55u8; 32]),
+    };
+    assert_golden(
+        &tick.encode(),
+        concat!(
+            "80000000", // prevTick
+            "0000002b", // nextTick
+            "4444444444444444444444444444444444444444444444444444444444444444", // headOrderId
+            "5555555555555555555555555555555555555555555555555555555555555555", // tailOrderId
+        ),
+    );
+    let node = OrderNode {
+        prev_order_id: OrderId([0x33u8; 32]),
+        next_order_id: OrderId::ZERO,
+    };
+    assert_golden(&node.encode(), &format!("{}{}", "33".repeat(32), "00".repeat(32)));
+    let best = MarketBest {
+        best_bid: 41,
+        best_ask: i32::MIN,
+    };
+    assert_golden(&best.encode(), "0000002980000000");
+}
+
+#[test]
+fn balance_and_vault_layouts() {
+    let balance = Balance {
+        available: U256::from(10u64),
+        locked: U256::from(3u64),
+    };
+    assert_golden(&balance.encode(), &format!("{:0>64}{:0>64}", "0a", "03"));
+    let vault = FeeVault {
+        total: U256::from(77u64),
+    };
+    assert_golden(&vault.encode(), &format!("{:0>64}", "4d"));
+}
+
+#[test]
+fn trade_record_layout() {
+    let trade = TradeRecord {
+        market_id: MarketId([0x03u8; 32]),
+        trade_seq: 9,
+        maker_order_id: OrderId([0x44u8; 32]),
+        taker_order_id: OrderId([0x55u8; 32]),
+        maker: Address([0x11u8; 20]),
+        taker: Address([0x22u8; 20]),
+        side_taker: Side::Sell,
+        maker_tick: 42,
+        qty_base: U256::from(500u64),
+        quote_amt: U256::from(21_000u64),
+        taker_fee: U256::from(53u64),
+    };
+    assert_golden(
+        &trade.encode(),
+        concat!(
+            "0303030303030303030303030303030303030303030303030303030303030303", // marketId
+            "0000000000000009", // tradeSeq
+            "4444444444444444444444444444444444444444444444444444444444444444", // makerOrderId
+            "5555555555555555555555555555555555555555555555555555555555555555", // takerOrderId
+            "1111111111111111111111111111111111111111", // maker
+            "2222222222222222222222222222222222222222", // taker
+            "01", // sideTaker
+            "0000002a", // makerTick
+            "00000000000000000000000000000000000000000000000000000000000001f4", // qtyBase
+            "0000000000000000000000000000000000000000000000000000000000005208", // quoteAmt
+            "0000000000000000000000000000000000000000000000000000000000000035", // takerFee
+        ),
+    );
+}
+
+#[test]
+fn public_inputs_layout() {
+    let public = PublicInputs {
+        prev_root: [0xa0u8; 32],
+        new_root: [0xa1u8; 32],
+        batch_digest: [0xa2u8; 32],
+        rules_hash: [0xa3u8; 32],
+        domain_separator: [0xa4u8; 32],
+        batch_seq: 12,
+        batch_timestamp: 1_700_000_000,
+        batch_deadline: 1_700_003_600,
+        da_commitment: [0xa5u8; 32],
+        trades_root: [0xa6u8; 32],
+        fees_root: [0xa7u8; 32],
+    };
+    assert_golden(
+        &public.encode(),
+        concat!(
+            "a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0", // prevRoot
+            "a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1", // newRoot
+            "a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2", // batchDigest
+            "a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3a3", // rulesHash
+            "a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4a4", // domainSeparator
+            "000000000000000c", // batchSeq
+            "000000006553f100", // batchTimestamp
+            "000000006553ff10", // batchDeadline
+            "a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5", // daCommitment
+            "a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6a6", // tradesRoot
+            "a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7a7", // feesRoot
+        ),
+    );
+}
+
+#[test]
+fn signed_message_layouts() {
+    let [place, cancel, collect, force, fill] = canonical_messages();
+    assert_golden(
+        &place.encode_signed(),
+        concat!(
+            "01", // type
+            "1111111111111111111111111111111111111111", // trader
+            "0000000000000007", // nonce
+            "4444444444444444444444444444444444444444444444444444444444444444", // orderId
+            "01", // side
+            "00000001", // tif
+            "fffffffe", // tickIndex
+            "000000000000000000000000000000000000000000000000000000000001e240", // qtyBase
+            "00", // reduceOnly
+            "00", // quoteBudget
+        ),
+    );
+    assert_golden(
+        &cancel.encode_signed(),
+        concat!(
+            "02", // type
+            "1111111111111111111111111111111111111111", // trader
+            "0000000000000008", // nonce
+            "4444444444444444444444444444444444444444444444444444444444444444", // orderId
+        ),
+    );
+    assert_golden(
+        &collect.encode_signed(),
+        concat!(
+            "03", // type
+            "fcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfcfc", // operator
+            "0000000000000001", // nonce
+            "0202020202020202020202020202020202020202020202020202020202020202", // asset
+            "00000000000000000000000000000000000000000000000000000000000003e7", // amount
+            "7777777777777777777777777777777777777777", // to
+        ),
+    );
+    assert_golden(
+        &force.encode_signed(),
+        concat!(
+            "04", // type
+            "adadadadadadadadadadadadadadadadadadadad", // operator
+            "0000000000000002", // nonce
+            "4444444444444444444444444444444444444444444444444444444444444444", // orderId
+        ),
+    );
+    assert_golden(
+        &fill.encode_signed(),
+        concat!(
+            "05", // type
+            "2222222222222222222222222222222222222222", // trader
+            "0000000000000003", // nonce
+            "5555555555555555555555555555555555555555555555555555555555555555", // orderId
+            "4444444444444444444444444444444444444444444444444444444444444444", // makerOrderId
+            "00000000000000000000000000000000000000000000000000000000000007d0", // maxQty
+        ),
+    );
+}
+
+#[test]
+fn hash_vectors() {
+    let domain = canonical_domain();
+    assert_golden(&domain, "093bf163818203b5445b578144348477bb836e51dd02c28d59300779f2269a12");
+    assert_golden(
+        &rules_hash(&canonical_rules()),
+        "afedfe2d42bcea03de40a7d0a6069a59bd86de8a9ad9e9d3197bd2894d630601",
+    );
+
+    let hashes: Vec<[u8; 32]> = canonical_messages().iter().map(|m| message_hash(&domain, m)).collect();
+    let expected = [
+        "dd79c24ecd931eb85c7c7edcb2fe369fc841f902d4dfbf724cbf6adfc2de2dec",
+        "9be16f6ccd868d3e62def6a2c12b3d6d6498ea422980972af5249ae1c242ea9a",
+        "c0e32152d2973034d869367382b4763692bbf6d5f1bdc8753ad160736a326d1a",
+        "7e79676e23340224a6d300df9bb66ab6c781d9432476f73c0f8cb59bc26cff2f",
+        "bc4a7e82e5da2d99de908eddbf56a190f02e09ebafe01e7d3c0efb01c6cc1943",
+    ];
+    for (hash, expected) in hashes.iter().zip(expected) {
+        assert_golden(hash, expected);
+    }
+    assert_golden(
+        &batch_digest(&domain, 12, &hashes),
+        "72dc80146bc95c60e89fd78977291328412ad8cc1f9f24a8cd4ead16b6f9c135",
+    );
+}
+
+#[test]
+fn touched_digest_vectors() {
+    let keys = [[0x11u8; 32], [0x22u8; 32]].concat();
+    let keccak = DigestKind::Keccak256.digest(&keys);
+    let sha = DigestKind::Sha256.digest(&keys);
+    assert_ne!(keccak, sha);
+    assert_golden(&keccak, "3e92e0db88d6afea9edc4eedf62fffa4d92bcdfc310dccbe943747fe8302e871");
+    assert_golden(&sha, "5189c77d29fe5d546a045ec46986852785fea5c13ac7da9c115ff5fb6edf817c");
+}
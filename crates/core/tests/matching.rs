@@ -1,175 +1,1878 @@
-use clob_core::engine::apply_batch;
-use clob_core::hash::keccak256;
-use clob_core::input::{Message, MessageSignature, Rules, SignedMessage};
+use clob_core::book::verify_book_links;
+use clob_core::constants::{MIN_TICK, NONE_TICK, ZERO32};
+use clob_core::engine::{
+    apply_batch, apply_batch_at_root, apply_recovered_batch_at_root, BatchOutput, MessageOutcome, RecoveredBatch,
+};
+use clob_core::errors::CoreError;
+use clob_core::hash::{keccak256, DigestKind};
+use clob_core::input::{
+    GuestBundle, GuestInput, Message, MessageSignature, PublicInputsPartial, Rules, RulesBuilder, SignedMessage,
+};
 use clob_core::merkle::SparseMerkleTree;
+use clob_core::outputs::{merkle_root, trades_root, verify_output_roots};
+use clob_core::program::{run_bundle, ProgramOutput};
 use clob_core::state::{
-    key_balance, key_market_best, key_nonce, key_order, key_order_node, key_tick_node, RecordingState,
+    key_balance, key_fee_vault, key_market_best, key_nonce, key_open_count, key_order, key_order_node, key_tick_node,
+    key_trade_seq, ProofState, RecordingState, StateAccess,
+};
+use clob_core::types::{
+    Address, AllocationMode, AssetId, Balance, FailureMode, FeeVault, MarketBest, MarketId, Order, OrderId,
+    OrderIdScope, OrderNode, OrderStatus, Side, TickNode, TimeInForce, TradeRecord, U256,
+};
+use clob_core::verify::{
+    batch_digest, derive_order_id, domain_separator, message_hash, price_from_tick, recover_addresses, rules_hash,
+    signing_separator, verify_signature,
 };
-use clob_core::types::{Balance, MarketBest, Order, OrderNode, OrderStatus, Side, TickNode, TimeInForce, U256};
-use clob_core::verify::{domain_separator, message_hash};
 
 use k256::ecdsa::SigningKey;
 
+mod common;
+
+use common::{place, BookBuilder, Signer, Trader, BASE, MARKET, QUOTE};
+
 #[test]
 fn single_fill_ioc_buy() {
-    let base = [1u8; 32];
-    let quote = [2u8; 32];
-    let market = [3u8; 32];
-    let rules = Rules {
-        base_asset_id: base,
-        quote_asset_id: quote,
-        price_scale: U256::from(1_000_000_000_000_000_000u128),
-        tick_size: U256::from(1_000_000_000_000_000_000u128),
-        lot_size: U256::from(1u64),
-        taker_fee_bps: 0,
-        maker_fee_bps: 0,
-        max_orders_per_batch: 128,
-        max_matches_per_order: 64,
-        max_balance: U256::from(1_000_000u64),
-    };
-
-    let maker_key = SigningKey::from_slice(&[0x11u8; 32]).unwrap();
-    let taker_key = SigningKey::from_slice(&[0x22u8; 32]).unwrap();
-    let maker = addr_from_key(&maker_key);
-    let taker = addr_from_key(&taker_key);
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 10)
+        .balance(&maker.address, &QUOTE, 0, 0)
+        .balance(&taker.address, &QUOTE, 10, 0)
+        .nonce(&taker.address, 0)
+        .resting(OrderId(keccak256(b"maker-order")), &maker.address, Side::Sell, 1, 10)
+        .build();
+
+    let message = place(&taker, 1, OrderId(keccak256(b"taker-order")), Side::Buy, TimeInForce::Ioc, 1, 5);
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)]).expect("apply batch");
+
+    let balance = |account: &Address, asset: &AssetId| {
+        let raw = state.tree.get(key_balance(account, asset));
+        raw.map_or_else(Balance::empty, |bytes| Balance::decode(&bytes).unwrap())
+    };
+    assert_eq!(balance(&maker.address, &BASE).locked, U256::from(5u64));
+    assert_eq!(balance(&maker.address, &QUOTE).available, U256::from(5u64));
+    assert_eq!(balance(&taker.address, &QUOTE).available, U256::from(5u64));
+    assert_eq!(balance(&taker.address, &BASE).available, U256::from(5u64));
+}
+
+#[test]
+fn active_tick_rejects_inconsistent_hints() {
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let maker = Trader::new(0x11);
+    let seller = Trader::new(0x33);
+    let maker_order_id = OrderId(keccak256(b"maker-order"));
+    let book = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 10)
+        .balance(&seller.address, &BASE, 10, 0)
+        .resting(maker_order_id, &maker.address, Side::Sell, 1, 10);
+    let signer = book.signer();
+    let tree = book.tree();
+
+    let place = |prev_tick_hint: i32, next_tick_hint: i32| {
+        let message = Message::Place {
+            trader: seller.address,
+            nonce: 1,
+            order_id: OrderId(keccak256(b"seller-order")),
+            side: Side::Sell,
+            tif: TimeInForce::Gtc,
+            tick_index: 1,
+            qty_base: U256::from(5u64),
+            prev_tick_hint,
+            next_tick_hint,
+            reduce_only: false,
+            quote_budget: false,
+        };
+        signer.sign(&seller, message)
+    };
+
+    let mut state = RecordingState::new(tree.clone());
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[place(5, i32::MIN)])
+        .err()
+        .expect("wrong prev hint must be rejected");
+    assert!(matches!(err, CoreError::Invalid("hints inconsistent with active tick")));
+
+    let mut state = RecordingState::new(tree.clone());
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[place(i32::MIN, 7)])
+        .err()
+        .expect("wrong next hint must be rejected");
+    assert!(matches!(err, CoreError::Invalid("hints inconsistent with active tick")));
+
+    let mut state = RecordingState::new(tree);
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[place(i32::MIN, i32::MIN)])
+        .expect("matching hints append to the active tick");
+    let tick = TickNode::decode(&state.tree.get(key_tick_node(&MARKET, Side::Sell.as_u8(), 1)).unwrap()).unwrap();
+    assert_eq!(tick.head_order_id, maker_order_id);
+    assert_eq!(tick.tail_order_id, OrderId(keccak256(b"seller-order")));
+}
+
+#[test]
+fn failed_message_rolls_back_its_writes() {
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let seller = Trader::new(0x33);
+    let book = BookBuilder::new().balance(&seller.address, &BASE, 10, 0);
+    let signer = book.signer();
+    let tree = book.tree();
+
+    let place = |nonce: u64, prev_tick_hint: i32| {
+        let message = Message::Place {
+            trader: seller.address,
+            nonce,
+            order_id: OrderId(keccak256(&nonce.to_be_bytes())),
+            side: Side::Sell,
+            tif: TimeInForce::Gtc,
+            tick_index: 2,
+            qty_base: U256::from(5u64),
+            prev_tick_hint,
+            next_tick_hint: i32::MIN,
+            reduce_only: false,
+            quote_budget: false,
+        };
+        signer.sign(&seller, message)
+    };
+
+    let mut expected = RecordingState::new(tree.clone());
+    apply_batch(&mut expected, MARKET, &rules, signer.domain, &[place(1, i32::MIN)]).expect("first message");
+
+    // The second message locks collateral and bumps the nonce before its hint is rejected.
+    let mut state = RecordingState::new(tree);
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[place(1, i32::MIN), place(2, 9)])
+        .err()
+        .expect("bad hint must be rejected");
+    assert!(matches!(err, CoreError::Invalid("hints inconsistent with active tick")));
+
+    assert_eq!(state.root, expected.root);
+    assert_eq!(state.tree.root(), expected.tree.root());
+    // The rejected message's reads stay recorded; the guest consumes them replaying it to its failure.
+    assert!(state.proofs.len() > expected.proofs.len());
+    let balance = Balance::decode(&state.tree.get(key_balance(&seller.address, &BASE)).unwrap()).unwrap();
+    assert_eq!(balance.available, U256::from(5u64));
+    assert_eq!(balance.locked, U256::from(5u64));
+    assert_eq!(state.tree.get(key_nonce(&seller.address)).unwrap(), 1u64.to_be_bytes().to_vec());
+    assert!(state.tree.get(key_order(Some(&MARKET), &OrderId(keccak256(&2u64.to_be_bytes())))).is_none());
+}
+
+#[test]
+fn min_notional_rejects_dust_orders() {
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .min_notional(U256::from(100u64))
+        .max_balance(U256::from(1_000_000u64))
+        .build()
+        .unwrap();
+    let buyer = Trader::new(0x44);
+    let book = BookBuilder::new().balance(&buyer.address, &QUOTE, 200, 0);
+    let signer = book.signer();
+    let tree = book.tree();
+    let place = |qty: u64| {
+        let message = place(&buyer, 1, OrderId(keccak256(b"notional")), Side::Buy, TimeInForce::Gtc, 1, qty);
+        signer.sign(&buyer, message)
+    };
+
+    let mut state = RecordingState::new(tree.clone());
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[place(5)])
+        .err()
+        .expect("dust order must be rejected");
+    assert!(matches!(err, CoreError::Invalid("below min notional")));
+
+    let mut state = RecordingState::new(tree);
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[place(100)]).expect("order at min notional");
+}
+
+#[test]
+fn gtc_filled_on_last_maker_at_limit_writes_order_once() {
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 10, 0)
+        .balance(&taker.address, &QUOTE, 10, 0)
+        .build();
+
+    let maker_order_id = OrderId(keccak256(b"last-maker"));
+    let taker_order_id = OrderId(keccak256(b"gtc-taker"));
+    let messages = [
+        signer.sign(&maker, place(&maker, 1, maker_order_id, Side::Sell, TimeInForce::Gtc, 2, 5)),
+        signer.sign(&taker, place(&taker, 1, taker_order_id, Side::Buy, TimeInForce::Gtc, 2, 5)),
+    ];
+
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &messages).expect("apply batch");
+    assert_eq!(output.trades.len(), 1);
+    assert_eq!(output.trades[0].qty_base, U256::from(5u64));
+
+    // One existence read and one Filled write; never a resting write.
+    let taker_key_order = key_order(Some(&MARKET), &taker_order_id);
+    assert_eq!(state.proofs.iter().filter(|p| p.key == taker_key_order).count(), 2);
+    let taker_order = Order::decode(&state.tree.get(taker_key_order).unwrap()).unwrap();
+    assert_eq!(taker_order.status, OrderStatus::Filled);
+    assert_eq!(taker_order.qty_remaining, U256::zero());
+    assert!(state.tree.get(key_order_node(Some(&MARKET), &taker_order_id)).is_none());
+    assert!(state.tree.get(key_tick_node(&MARKET, Side::Buy.as_u8(), 2)).is_none());
+
+    let maker_tick = TickNode::decode(&state.tree.get(key_tick_node(&MARKET, Side::Sell.as_u8(), 2)).unwrap()).unwrap();
+    assert_eq!(maker_tick.head_order_id, OrderId::ZERO);
+    assert_eq!(maker_tick.tail_order_id, OrderId::ZERO);
+    let best = MarketBest::decode(&state.tree.get(key_market_best(&MARKET)).unwrap()).unwrap();
+    assert_eq!(best.best_bid, i32::MIN);
+    assert_eq!(best.best_ask, i32::MIN);
+
+    let taker_quote = Balance::decode(&state.tree.get(key_balance(&taker.address, &QUOTE)).unwrap()).unwrap();
+    assert_eq!(taker_quote.available, U256::zero());
+    assert_eq!(taker_quote.locked, U256::zero());
+}
+
+#[test]
+fn skip_mode_applies_messages_around_a_failure() {
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let maker = Trader::new(0x11);
+    let bad = Trader::new(0x33);
+    let taker = Trader::new(0x22);
+    let book = BookBuilder::new()
+        .balance(&maker.address, &BASE, 5, 0)
+        .balance(&bad.address, &BASE, 5, 0)
+        .balance(&taker.address, &QUOTE, 5, 0);
+    let signer = book.signer();
+    let tree = book.tree();
+
+    let place = |trader: &Trader, side: Side, tif: TimeInForce, prev_tick_hint: i32| {
+        let message = Message::Place {
+            trader: trader.address,
+            nonce: 1,
+            order_id: OrderId(keccak256(trader.address.as_bytes())),
+            side,
+            tif,
+            tick_index: 1,
+            qty_base: U256::from(5u64),
+            prev_tick_hint,
+            next_tick_hint: i32::MIN,
+            reduce_only: false,
+            quote_budget: false,
+        };
+        signer.sign(trader, message)
+    };
+    let maker_msg = place(&maker, Side::Sell, TimeInForce::Gtc, i32::MIN);
+    // Locks its base, then trips the active-tick hint check.
+    let bad_msg = place(&bad, Side::Sell, TimeInForce::Gtc, 9);
+    let taker_msg = place(&taker, Side::Buy, TimeInForce::Ioc, i32::MIN);
+    let messages = [maker_msg.clone(), bad_msg, taker_msg.clone()];
+
+    let mut strict = RecordingState::new(tree.clone());
+    assert!(apply_batch(&mut strict, MARKET, &rules, signer.domain, &messages).is_err());
+
+    let mut state = RecordingState::new(tree.clone());
+    let skip_rules = RulesBuilder::new(BASE, QUOTE)
+        .max_balance(U256::from(1_000_000u64))
+        .failure_mode(FailureMode::Skip)
+        .build()
+        .unwrap();
+    let output = apply_batch(&mut state, MARKET, &skip_rules, signer.domain, &messages).expect("skip mode");
+    assert_eq!(output.outcomes.len(), 3);
+    assert!(matches!(output.outcomes[0], MessageOutcome::Applied { .. }));
+    assert!(matches!(
+        output.outcomes[1],
+        MessageOutcome::Failed(CoreError::Invalid("hints inconsistent with active tick"))
+    ));
+    assert!(matches!(output.outcomes[2], MessageOutcome::Applied { .. }));
+    assert_eq!(output.trades.len(), 1);
+    assert_eq!(output.trades[0].maker, maker.address);
+    assert_eq!(output.trades[0].taker, taker.address);
 
+    let bad_balance = Balance::decode(&state.tree.get(key_balance(&bad.address, &BASE)).unwrap()).unwrap();
+    assert_eq!(bad_balance.available, U256::from(5u64));
+    assert_eq!(bad_balance.locked, U256::zero());
+    assert!(state.tree.get(key_nonce(&bad.address)).is_none());
+
+    let mut expected = RecordingState::new(tree);
+    apply_batch(&mut expected, MARKET, &rules, signer.domain, &[maker_msg, taker_msg])
+        .expect("without failing message");
+    assert_eq!(state.tree.root(), expected.tree.root());
+}
+
+fn sweep_two_ticks(tif: TimeInForce, qty: u64, taker_quote: u64) -> (Balance, Balance) {
+    // A third of 1e18 per tick, so every fill rounds.
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .tick_size(U256::from(333_333_333_333_333_333u128))
+        .max_balance(U256::from(1_000_000u64))
+        .build()
+        .unwrap();
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, taker_quote, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+        .build();
+
+    let message = place(&taker, 1, OrderId(keccak256(b"sweep")), Side::Buy, tif, 3, qty);
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)])
+        .expect("apply batch");
+    assert_eq!(output.trades.len(), 2);
+    assert_eq!(output.trades[0].quote_amt, U256::from(3u64));
+    assert_eq!(output.trades[1].quote_amt, U256::from(6u64));
+
+    let taker_quote_after = Balance::decode(&state.tree.get(key_balance(&taker.address, &QUOTE)).unwrap()).unwrap();
+    let taker_base_after = Balance::decode(&state.tree.get(key_balance(&taker.address, &BASE)).unwrap()).unwrap();
+    (taker_quote_after, taker_base_after)
+}
+
+#[test]
+fn ioc_buy_across_ticks_releases_all_collateral() {
+    // lock = ceil(0.999.. * 25) = 25, fills spend 3 + 6, remaining 5 canceled.
+    let (quote, base) = sweep_two_ticks(TimeInForce::Ioc, 25, 25);
+    assert_eq!(quote.locked, U256::zero());
+    assert_eq!(quote.available, U256::from(16u64));
+    assert_eq!(base.available, U256::from(20u64));
+}
+
+#[test]
+fn gtc_buy_fully_filled_releases_price_improvement() {
+    // lock = ceil(0.999.. * 20) = 20, fills spend 3 + 6, nothing rests.
+    let (quote, base) = sweep_two_ticks(TimeInForce::Gtc, 20, 20);
+    assert_eq!(quote.locked, U256::zero());
+    assert_eq!(quote.available, U256::from(11u64));
+    assert_eq!(base.available, U256::from(20u64));
+}
+
+fn crossing_batch(pairs: u64, max_fills_per_batch: u32) -> Result<BatchOutput, CoreError> {
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .max_fills_per_batch(max_fills_per_batch)
+        .max_balance(U256::from(1_000_000u64))
+        .build()
+        .unwrap();
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, pairs, 0)
+        .balance(&taker.address, &QUOTE, pairs, 0)
+        .build();
+
+    let mut messages = Vec::new();
+    for i in 0..pairs {
+        for (trader, side, tif) in [(&maker, Side::Sell, TimeInForce::Gtc), (&taker, Side::Buy, TimeInForce::Ioc)] {
+            let order_id = OrderId(keccak256(&[side.as_u8(), i as u8]));
+            messages.push(signer.sign(trader, place(trader, i + 1, order_id, side, tif, 1, 1)));
+        }
+    }
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &messages)
+}
+
+#[test]
+fn batch_fill_cap_counts_fills_across_takers() {
+    let output = crossing_batch(8, 8).expect("batch at cap");
+    assert_eq!(output.trades.len(), 8);
+
+    let err = crossing_batch(8, 7).err().expect("cap exceeded");
+    assert!(matches!(err, CoreError::Invalid("maxFillsPerBatch exceeded")));
+}
+
+fn same_tick_makers(
+    allocation_mode: AllocationMode,
+    maker_qtys: &[u64],
+    taker_qty: u64,
+) -> Result<(BatchOutput, SparseMerkleTree, Vec<OrderId>), CoreError> {
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .allocation_mode(allocation_mode)
+        .max_balance(U256::from(1_000_000u64))
+        .build()
+        .unwrap();
+    let taker = Trader::new(0x22);
+    let makers: Vec<Trader> = (0..maker_qtys.len()).map(|i| Trader::new(0x31 + i as u8)).collect();
+    let mut book = BookBuilder::new().balance(&taker.address, &QUOTE, taker_qty, 0);
+    for (maker, qty) in makers.iter().zip(maker_qtys) {
+        book = book.balance(&maker.address, &BASE, *qty, 0);
+    }
+    let (mut state, signer) = book.build();
+
+    let mut messages = Vec::new();
+    let mut maker_order_ids = Vec::new();
+    for (i, (maker, qty)) in makers.iter().zip(maker_qtys).enumerate() {
+        let order_id = OrderId(keccak256(&[0xa0, i as u8]));
+        maker_order_ids.push(order_id);
+        messages.push(signer.sign(maker, place(maker, 1, order_id, Side::Sell, TimeInForce::Gtc, 1, *qty)));
+    }
+    let order_id = OrderId(keccak256(b"same-tick-taker"));
+    messages.push(signer.sign(&taker, place(&taker, 1, order_id, Side::Buy, TimeInForce::Ioc, 1, taker_qty)));
+
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &messages)?;
+    Ok((output, state.tree, maker_order_ids))
+}
+
+#[test]
+fn fifo_fills_same_tick_makers_in_insertion_order() {
+    let (output, tree, makers) = same_tick_makers(AllocationMode::Fifo, &[2, 2], 3).expect("apply batch");
+    assert_eq!(output.trades.len(), 2);
+    assert_eq!(output.trades[0].maker_order_id, makers[0]);
+    assert_eq!(output.trades[0].qty_base, U256::from(2u64));
+    assert_eq!(output.trades[1].maker_order_id, makers[1]);
+    assert_eq!(output.trades[1].qty_base, U256::from(1u64));
+
+    let first = Order::decode(&tree.get(key_order(Some(&MARKET), &makers[0])).unwrap()).unwrap();
+    let second = Order::decode(&tree.get(key_order(Some(&MARKET), &makers[1])).unwrap()).unwrap();
+    assert_eq!(first.status, OrderStatus::Filled);
+    assert_eq!(second.status, OrderStatus::Open);
+    assert_eq!(second.qty_remaining, U256::from(1u64));
+}
+
+#[test]
+fn pro_rata_splits_level_and_assigns_dust_to_largest() {
+    // take 3 of 6: floor(3*1/6)=0, floor(3*2/6)=1, floor(3*3/6)=1, dust 1 to the size-3 maker.
+    let (output, tree, makers) = same_tick_makers(AllocationMode::ProRata, &[1, 2, 3], 3).expect("apply batch");
+    assert_eq!(output.trades.len(), 2);
+    assert_eq!(output.trades[0].maker_order_id, makers[1]);
+    assert_eq!(output.trades[0].qty_base, U256::from(1u64));
+    assert_eq!(output.trades[1].maker_order_id, makers[2]);
+    assert_eq!(output.trades[1].qty_base, U256::from(2u64));
+
+    let remaining: Vec<U256> = makers
+        .iter()
+        .map(|id| Order::decode(&tree.get(key_order(Some(&MARKET), id)).unwrap()).unwrap().qty_remaining)
+        .collect();
+    assert_eq!(remaining, vec![U256::from(1u64), U256::from(1u64), U256::from(1u64)]);
+    verify_book_links(&tree, &MarketId([3u8; 32]), OrderIdScope::Market).expect("book links");
+}
+
+#[test]
+fn pro_rata_unlinks_filled_makers_around_survivor() {
+    // take 8 of 9: 3 + 0 + 3, dust 2 fills both size-4 makers and leaves the middle one resting.
+    let (output, tree, makers) = same_tick_makers(AllocationMode::ProRata, &[4, 1, 4], 8).expect("apply batch");
+    assert_eq!(output.trades.len(), 2);
+    assert_eq!(output.trades[0].qty_base, U256::from(4u64));
+    assert_eq!(output.trades[1].qty_base, U256::from(4u64));
+
+    let tick = TickNode::decode(&tree.get(key_tick_node(&MarketId([3u8; 32]), Side::Sell.as_u8(), 1)).unwrap()).unwrap();
+    assert_eq!(tick.head_order_id, makers[1]);
+    assert_eq!(tick.tail_order_id, makers[1]);
+    verify_book_links(&tree, &MarketId([3u8; 32]), OrderIdScope::Market).expect("book links");
+}
+
+#[test]
+fn pro_rata_allocates_within_a_window_of_a_crowded_level() {
+    // 70 one-lot makers overflow the 64-match window; a 2-lot taker still fills, with the dust going
+    // to the first two makers in queue order.
+    let (output, tree, makers) = same_tick_makers(AllocationMode::ProRata, &[1; 70], 2).expect("crowded level");
+    assert_eq!(output.trades.len(), 2);
+    assert_eq!(output.trades[0].maker_order_id, makers[0]);
+    assert_eq!(output.trades[1].maker_order_id, makers[1]);
+
+    let tick = TickNode::decode(&tree.get(key_tick_node(&MARKET, Side::Sell.as_u8(), 1)).unwrap()).unwrap();
+    assert_eq!(tick.head_order_id, makers[2]);
+    assert_eq!(tick.tail_order_id, makers[69]);
+    verify_book_links(&tree, &MARKET, OrderIdScope::Market).expect("book links");
+}
+
+#[test]
+fn instance_salt_separates_signing_domains() {
+    let key = SigningKey::from_slice(&[0x22u8; 32]).unwrap();
+    let trader = addr_from_key(&key);
+    let market = MarketId([3u8; 32]);
+    let old_domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+    let new_domain = domain_separator(1, &[9u8; 32], &market, &[1u8; 32]);
+    assert_ne!(old_domain, new_domain);
+
+    let message = Message::Cancel {
+        trader,
+        nonce: 1,
+        order_id: OrderId([7u8; 32]),
+    };
+    let signature = sign_hash(&key, message_hash(&old_domain, &message));
+    verify_signature(&old_domain, &message, &signature, &trader).expect("signed under old salt");
+    assert!(verify_signature(&new_domain, &message, &signature, &trader).is_err());
+}
+
+#[test]
+fn collect_fees_moves_vault_to_recipient() {
+    let collector = Trader::new(0x44);
+    let intruder = Trader::new(0x55);
+    let treasury = Address([0x66u8; 20]);
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .taker_fee_bps(10)
+        .max_balance(U256::from(1_000_000u64))
+        .fee_collector(collector.address)
+        .build()
+        .unwrap();
+    let book = BookBuilder::new();
+    let signer = book.signer();
+    let mut tree = book.tree();
+    tree.update(key_fee_vault(&QUOTE), Some(FeeVault { total: U256::from(100u64) }.encode().to_vec()));
+
+    let collect = |operator: &Trader, amount: u64| {
+        let message = Message::CollectFees {
+            operator: operator.address,
+            nonce: 1,
+            asset: QUOTE,
+            amount: U256::from(amount),
+            to: treasury,
+        };
+        signer.sign(operator, message)
+    };
+
+    let mut state = RecordingState::new(tree.clone());
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[collect(&collector, 40)]).expect("collect");
+    let vault = FeeVault::decode(&state.tree.get(key_fee_vault(&QUOTE)).unwrap()).unwrap();
+    assert_eq!(vault.total, U256::from(60u64));
+    let credited = Balance::decode(&state.tree.get(key_balance(&treasury, &QUOTE)).unwrap()).unwrap();
+    assert_eq!(credited.available, U256::from(40u64));
+    assert_eq!(credited.locked, U256::zero());
+
+    let mut state = RecordingState::new(tree.clone());
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[collect(&intruder, 40)])
+        .err()
+        .expect("only the fee collector may withdraw");
+    assert!(matches!(err, CoreError::Invalid("fee collector mismatch")));
+
+    let mut state = RecordingState::new(tree);
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[collect(&collector, 101)])
+        .err()
+        .expect("cannot withdraw more than the vault holds");
+    assert!(matches!(err, CoreError::Invalid("fee vault insufficient")));
+}
+
+#[test]
+fn duplicate_signature_rejects_batch() {
+    let market = MarketId([3u8; 32]);
+    let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32]))
+        .failure_mode(FailureMode::Skip)
+        .build()
+        .expect("rules");
+    let key = SigningKey::from_slice(&[0x22u8; 32]).unwrap();
+    let trader = addr_from_key(&key);
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+    let cancel = |nonce: u64, order_id: OrderId| Message::Cancel { trader, nonce, order_id };
+
+    let first = cancel(1, OrderId([7u8; 32]));
+    let signature = sign_hash(&key, message_hash(&domain, &first));
+    let mut parity_form = signature.clone();
+    parity_form.v -= 27;
+    for reused in [signature.clone(), parity_form] {
+        let messages = [
+            SignedMessage { message: first.clone(), signature: signature.clone() },
+            SignedMessage { message: cancel(2, OrderId([8u8; 32])), signature: reused },
+        ];
+        let err = apply_batch(&mut RecordingState::new(SparseMerkleTree::new()), market, &rules, domain, &messages)
+            .err()
+            .expect("shared signature rejects the batch");
+        assert!(matches!(err, CoreError::Invalid("duplicate signature in batch")));
+    }
+}
+
+#[test]
+fn enforced_order_id_must_be_derived() {
+    let quote = AssetId([2u8; 32]);
+    let market = MarketId([3u8; 32]);
+    let rules = RulesBuilder::new(AssetId([1u8; 32]), quote)
+        .enforce_derived_order_id(true)
+        .build()
+        .expect("rules");
+    let key = SigningKey::from_slice(&[0x22u8; 32]).unwrap();
+    let trader = addr_from_key(&key);
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
     let mut tree = SparseMerkleTree::new();
-    let maker_balance = Balance {
-        available: U256::zero(),
-        locked: U256::from(10u64),
+    let funded = Balance { available: U256::from(10u64), locked: U256::zero() };
+    tree.update(key_balance(&trader, &quote), Some(funded.encode().to_vec()));
+    let place = |order_id: OrderId| {
+        let message = Message::Place {
+            trader,
+            nonce: 1,
+            order_id,
+            side: Side::Buy,
+            tif: TimeInForce::Gtc,
+            tick_index: 1,
+            qty_base: U256::from(1u64),
+            prev_tick_hint: i32::MIN,
+            next_tick_hint: i32::MIN,
+            reduce_only: false,
+            quote_budget: false,
+        };
+        let signature = sign_hash(&key, message_hash(&domain, &message));
+        SignedMessage { message, signature }
     };
-    tree.update(key_balance(&maker, &base), Some(maker_balance.encode().to_vec()));
-    tree.update(
-        key_balance(&maker, &quote),
-        Some(
-            Balance {
-                available: U256::zero(),
-                locked: U256::zero(),
-            }
-            .encode()
-            .to_vec(),
-        ),
-    );
-    tree.update(
-        key_balance(&taker, &quote),
-        Some(
-            Balance {
-                available: U256::from(10u64),
-                locked: U256::zero(),
-            }
-            .encode()
-            .to_vec(),
-        ),
-    );
-    tree.update(key_nonce(&taker), Some(0u64.to_be_bytes().to_vec()));
 
-    let maker_order_id = keccak256(b"maker-order");
-    let maker_order = Order {
-        owner: maker,
+    let err = apply_batch(&mut RecordingState::new(tree.clone()), market, &rules, domain, &[place(OrderId([7u8; 32]))])
+        .err()
+        .expect("arbitrary order id is rejected");
+    assert!(matches!(err, CoreError::Invalid("order id not derived")));
+
+    let derived = derive_order_id(&domain, &trader, 1);
+    let mut state = RecordingState::new(tree);
+    apply_batch(&mut state, market, &rules, domain, &[place(derived)]).expect("derived order id");
+    assert!(state.tree.get(key_order(Some(&MARKET), &derived)).is_some());
+}
+
+#[test]
+fn place_rejects_out_of_range_sizes() {
+    let market = MarketId([3u8; 32]);
+    let key = SigningKey::from_slice(&[0x22u8; 32]).unwrap();
+    let trader = addr_from_key(&key);
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+    let place = |rules: &Rules, qty_base: U256| {
+        let message = Message::Place {
+            trader,
+            nonce: 1,
+            order_id: OrderId([7u8; 32]),
+            side: Side::Buy,
+            tif: TimeInForce::Ioc,
+            tick_index: 2,
+            qty_base,
+            prev_tick_hint: i32::MIN,
+            next_tick_hint: i32::MIN,
+            reduce_only: false,
+            quote_budget: false,
+        };
+        let signature = sign_hash(&key, message_hash(&domain, &message));
+        apply_batch(
+            &mut RecordingState::new(SparseMerkleTree::new()),
+            market,
+            rules,
+            domain,
+            &[SignedMessage { message, signature }],
+        )
+        .err()
+        .expect("place is rejected")
+    };
+
+    let capped = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32]))
+        .max_balance(U256::from(1_000u64))
+        .build()
+        .expect("rules");
+    let err = place(&capped, U256::from(1_001u64));
+    assert!(matches!(err, CoreError::Invalid("qtyBase exceeds maxBalance")));
+
+    let uncapped = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).build().expect("rules");
+    let err = place(&uncapped, U256::MAX / U256::from(2u64));
+    assert!(matches!(err, CoreError::Invalid("price * qtyBase overflows")));
+}
+
+#[test]
+fn place_at_sentinel_tick_is_rejected() {
+    let market = MarketId([3u8; 32]);
+    let key = SigningKey::from_slice(&[0x22u8; 32]).unwrap();
+    let trader = addr_from_key(&key);
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+    let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).build().expect("rules");
+    let message = Message::Place {
+        trader,
+        nonce: 1,
+        order_id: OrderId([7u8; 32]),
+        side: Side::Sell,
+        tif: TimeInForce::Gtc,
+        tick_index: NONE_TICK,
+        qty_base: U256::one(),
+        prev_tick_hint: NONE_TICK,
+        next_tick_hint: NONE_TICK,
+        reduce_only: false,
+        quote_budget: false,
+    };
+    let signature = sign_hash(&key, message_hash(&domain, &message));
+    let err = apply_batch(
+        &mut RecordingState::new(SparseMerkleTree::new()),
+        market,
+        &rules,
+        domain,
+        &[SignedMessage { message, signature }],
+    )
+    .err()
+    .expect("sentinel tick is rejected");
+    assert!(matches!(err, CoreError::Invalid("reserved tick")));
+    assert_eq!(MIN_TICK, NONE_TICK + 1);
+}
+
+#[test]
+fn price_band_rejects_buy_far_above_best_ask() {
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .max_price_deviation_bps(5_000)
+        .build()
+        .expect("rules");
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let buy = |tick_index: i32| {
+        let (mut state, signer) = BookBuilder::new()
+            .balance(&maker.address, &BASE, 0, 20)
+            .balance(&taker.address, &QUOTE, 100, 0)
+            .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+            .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+            .build();
+        let message = place(&taker, 1, OrderId(keccak256(b"band")), Side::Buy, TimeInForce::Ioc, tick_index, 5);
+        apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)])
+    };
+
+    // Best ask is tick 1, so a 50% band allows limits up to 1.5 and rejects tick 10.
+    let err = buy(10).err().expect("far limit is rejected");
+    assert!(matches!(err, CoreError::Invalid("price outside band")));
+
+    let output = buy(1).expect("limit at best ask");
+    assert_eq!(output.trades.len(), 1);
+}
+
+#[test]
+fn reduce_only_buy_is_capped_to_locked_base() {
+    let rules = RulesBuilder::new(BASE, QUOTE).build().expect("rules");
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let book = || {
+        BookBuilder::new()
+            .balance(&maker.address, &BASE, 0, 20)
+            .balance(&taker.address, &QUOTE, 100, 0)
+            .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+            .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+    };
+    let signer = book().signer();
+    let message = Message::Place {
+        trader: taker.address,
+        nonce: 1,
+        order_id: OrderId(keccak256(b"reduce-only")),
+        side: Side::Buy,
+        tif: TimeInForce::Gtc,
+        tick_index: 2,
+        qty_base: U256::from(10u64),
+        prev_tick_hint: i32::MIN,
+        next_tick_hint: i32::MIN,
+        reduce_only: true,
+        quote_budget: false,
+    };
+    let signed = [signer.sign(&taker, message)];
+
+    let (mut flat, _) = book().build();
+    let err = apply_batch(&mut flat, MARKET, &rules, signer.domain, &signed)
+        .err()
+        .expect("no exposure to reduce");
+    assert!(matches!(err, CoreError::Invalid("reduce-only would increase exposure")));
+
+    // The taker is short 4 base through a resting ask elsewhere, so only 4 of the 10 may fill.
+    let (mut state, _) = book().balance(&taker.address, &BASE, 0, 4).build();
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &signed).expect("apply batch");
+    assert_eq!(output.trades.len(), 1);
+    assert_eq!(output.trades[0].qty_base, U256::from(4u64));
+
+    let order_key = key_order(Some(&MARKET), &OrderId(keccak256(b"reduce-only")));
+    let order = Order::decode(&state.tree.get(order_key).unwrap()).unwrap();
+    assert_eq!(order.tif, TimeInForce::Ioc);
+    assert_eq!(order.status, OrderStatus::Filled);
+    let taker_quote = Balance::decode(&state.tree.get(key_balance(&taker.address, &QUOTE)).unwrap()).unwrap();
+    assert_eq!(taker_quote.locked, U256::zero());
+    assert_eq!(taker_quote.available, U256::from(96u64));
+}
+
+#[test]
+fn quote_budget_buy_spends_budget_across_ticks() {
+    let rules = RulesBuilder::new(BASE, QUOTE).taker_fee_bps(100).build().expect("rules");
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let book = || {
+        BookBuilder::new()
+            .balance(&maker.address, &BASE, 0, 20)
+            .balance(&taker.address, &QUOTE, 100, 0)
+            .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+            .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+            .build()
+    };
+    let place = |signer: &Signer, side: Side| {
+        let message = Message::Place {
+            trader: taker.address,
+            nonce: 1,
+            order_id: OrderId(keccak256(b"budget")),
+            side,
+            tif: TimeInForce::Gtc,
+            tick_index: 2,
+            qty_base: U256::from(25u64),
+            prev_tick_hint: i32::MIN,
+            next_tick_hint: i32::MIN,
+            reduce_only: false,
+            quote_budget: true,
+        };
+        [signer.sign(&taker, message)]
+    };
+
+    let (mut state, signer) = book();
+    let output =
+        apply_batch(&mut state, MARKET, &rules, signer.domain, &place(&signer, Side::Buy)).expect("apply batch");
+    // Tick 1 takes all 10 for 10 + 1 fee; the 14 left buys 6 at tick 2 for 12 + 1 fee.
+    assert_eq!(output.trades.len(), 2);
+    assert_eq!(output.trades[0].qty_base, U256::from(10u64));
+    assert_eq!(output.trades[0].taker_fee, U256::one());
+    assert_eq!(output.trades[1].qty_base, U256::from(6u64));
+    assert_eq!(output.trades[1].quote_amt, U256::from(12u64));
+    assert_eq!(output.trades[1].taker_fee, U256::one());
+
+    let taker_base = Balance::decode(&state.tree.get(key_balance(&taker.address, &BASE)).unwrap()).unwrap();
+    assert_eq!(taker_base.available, U256::from(16u64));
+    let taker_quote = Balance::decode(&state.tree.get(key_balance(&taker.address, &QUOTE)).unwrap()).unwrap();
+    assert_eq!(taker_quote.locked, U256::zero());
+    assert_eq!(taker_quote.available, U256::from(76u64));
+    let order_key = key_order(Some(&MARKET), &OrderId(keccak256(b"budget")));
+    let order = Order::decode(&state.tree.get(order_key).unwrap()).unwrap();
+    assert_eq!(order.tif, TimeInForce::Ioc);
+
+    let (mut state, signer) = book();
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &place(&signer, Side::Sell))
+        .err()
+        .expect("budget sells are rejected");
+    assert!(matches!(err, CoreError::Invalid("quote budget requires buy")));
+}
+
+#[test]
+fn fill_order_takes_named_maker_and_skips_better_prices() {
+    let rules = RulesBuilder::new(BASE, QUOTE).taker_fee_bps(100).build().expect("rules");
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (better, named) = (OrderId(keccak256(b"ask-1")), OrderId(keccak256(b"ask-2")));
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 100, 0)
+        .resting(better, &maker.address, Side::Sell, 1, 10)
+        .resting(named, &maker.address, Side::Sell, 2, 10)
+        .build();
+    let message = Message::FillOrder {
+        trader: taker.address,
+        nonce: 1,
+        order_id: OrderId(keccak256(b"rfq")),
+        maker_order_id: named,
+        max_qty: U256::from(25u64),
+    };
+
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)])
+        .expect("fill named order");
+
+    assert_eq!(output.trades.len(), 1);
+    let trade = &output.trades[0];
+    assert_eq!(trade.maker_order_id, named);
+    assert_eq!(trade.maker_tick, 2);
+    assert_eq!(trade.side_taker, Side::Buy);
+    assert_eq!(trade.qty_base, U256::from(10u64));
+    assert_eq!(trade.quote_amt, U256::from(20u64));
+    assert_eq!(trade.taker_fee, U256::one());
+
+    let order = |id: &OrderId| Order::decode(&state.tree.get(key_order(Some(&MARKET), id)).unwrap()).unwrap();
+    assert_eq!(order(&named).status, OrderStatus::Filled);
+    assert_eq!(order(&better).status, OrderStatus::Open);
+    assert_eq!(order(&better).qty_remaining, U256::from(10u64));
+    let best = MarketBest::decode(&state.tree.get(key_market_best(&MARKET)).unwrap()).unwrap();
+    assert_eq!(best.best_ask, 1);
+    let level = TickNode::decode(&state.tree.get(key_tick_node(&MARKET, Side::Sell.as_u8(), 1)).unwrap()).unwrap();
+    assert_eq!((level.head_order_id, level.next_tick), (better, NONE_TICK));
+    verify_book_links(&state.tree, &MARKET, OrderIdScope::Market).expect("book links");
+
+    let taker_quote = Balance::decode(&state.tree.get(key_balance(&taker.address, &QUOTE)).unwrap()).unwrap();
+    assert_eq!((taker_quote.available, taker_quote.locked), (U256::from(79u64), U256::zero()));
+    let taker_order = order(&OrderId(keccak256(b"rfq")));
+    assert_eq!((taker_order.status, taker_order.tick), (OrderStatus::Filled, 2));
+}
+
+#[test]
+fn trade_seq_is_consecutive_across_batches() {
+    let rules = RulesBuilder::new(BASE, QUOTE).build().expect("rules");
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 100, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+        .build();
+    let buy = |nonce: u64, qty: u64| {
+        let order_id = OrderId(keccak256(&nonce.to_be_bytes()));
+        [signer.sign(&taker, place(&taker, nonce, order_id, Side::Buy, TimeInForce::Ioc, 2, qty))]
+    };
+
+    let first = apply_batch(&mut state, MARKET, &rules, signer.domain, &buy(1, 12)).expect("first batch");
+    let seqs: Vec<u64> = first.trades.iter().map(|t| t.trade_seq).collect();
+    assert_eq!(seqs, vec![1, 2]);
+
+    let second = apply_batch(&mut state, MARKET, &rules, signer.domain, &buy(2, 3)).expect("second batch");
+    assert_eq!(second.trades.len(), 1);
+    assert_eq!(second.trades[0].trade_seq, 3);
+    assert_eq!(state.tree.get(key_trade_seq(&MARKET)), Some(3u64.to_be_bytes().to_vec()));
+}
+
+#[test]
+fn tree_diff_lists_keys_a_resting_place_writes() {
+    let base = AssetId([1u8; 32]);
+    let quote = AssetId([2u8; 32]);
+    let market = MarketId([3u8; 32]);
+    let rules = RulesBuilder::new(base, quote).build().expect("rules");
+    let key = SigningKey::from_slice(&[0x22u8; 32]).unwrap();
+    let trader = addr_from_key(&key);
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+    let funded = Balance {
+        available: U256::from(100u64),
+        locked: U256::zero(),
+    };
+    let mut tree = SparseMerkleTree::new();
+    tree.update(key_balance(&trader, &quote), Some(funded.encode().to_vec()));
+    let order_id = OrderId(keccak256(b"diff"));
+    let message = Message::Place {
+        trader,
+        nonce: 1,
+        order_id,
+        side: Side::Buy,
+        tif: TimeInForce::Gtc,
+        tick_index: 2,
+        qty_base: U256::from(5u64),
+        prev_tick_hint: i32::MIN,
+        next_tick_hint: i32::MIN,
+        reduce_only: false,
+        quote_budget: false,
+    };
+    let signature = sign_hash(&key, message_hash(&domain, &message));
+
+    let mut state = RecordingState::new(tree.clone());
+    apply_batch(&mut state, market, &rules, domain, &[SignedMessage { message, signature }]).expect("place rests");
+    let diff = tree.diff(&state.tree);
+
+    let mut expected = vec![
+        key_nonce(&trader),
+        key_balance(&trader, &quote),
+        key_order(Some(&MARKET), &order_id),
+        key_order_node(Some(&MARKET), &order_id),
+        key_tick_node(&market, Side::Buy.as_u8(), 2),
+        key_market_best(&market),
+    ];
+    expected.sort();
+    assert_eq!(diff.iter().map(|(key, _, _)| *key).collect::<Vec<_>>(), expected);
+    let (_, old, new) = diff.iter().find(|(key, _, _)| *key == key_balance(&trader, &quote)).unwrap();
+    assert_eq!(old.as_deref(), Some(&funded.encode()[..]));
+    assert!(new.is_some());
+    assert!(diff.iter().filter(|(key, _, _)| *key != key_balance(&trader, &quote)).all(|(_, old, _)| old.is_none()));
+    assert!(state.tree.diff(&state.tree).is_empty());
+}
+
+#[test]
+fn settlement_conserves_touched_assets() {
+    let rules = RulesBuilder::new(BASE, QUOTE).taker_fee_bps(100).build().expect("rules");
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 100, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+        .build();
+    let message = place(&taker, 1, OrderId(keccak256(b"conserve")), Side::Buy, TimeInForce::Gtc, 2, 25);
+
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)])
+        .expect("apply batch");
+    assert_eq!(output.trades.len(), 2);
+    assert!(state.touched_keys().contains(&key_fee_vault(&QUOTE)));
+    let accounts = [maker.address, taker.address];
+    state.check_conservation(&accounts, &[BASE, QUOTE]).expect("batch conserves");
+
+    // Stand-in for a settlement bug: credit the taker one quote unit from nowhere.
+    let key = key_balance(&taker.address, &QUOTE);
+    let mut balance = Balance::decode(&state.tree.get(key).unwrap()).unwrap();
+    balance.available += U256::one();
+    state.write_value(key, Some(balance.encode().to_vec())).unwrap();
+    let err = state.check_conservation(&accounts, &[BASE, QUOTE]).unwrap_err();
+    assert!(matches!(err, CoreError::State("asset not conserved")));
+}
+
+#[test]
+fn admin_force_cancel_returns_funds_to_owner() {
+    let base = AssetId([1u8; 32]);
+    let market = MarketId([3u8; 32]);
+    let owner = Address([0x77u8; 20]);
+    let admin_key = SigningKey::from_slice(&[0x44u8; 32]).unwrap();
+    let intruder_key = SigningKey::from_slice(&[0x55u8; 32]).unwrap();
+    let admin = addr_from_key(&admin_key);
+    let rules = RulesBuilder::new(base, AssetId([2u8; 32])).admin(admin).build().expect("rules");
+
+    let mut tree = SparseMerkleTree::new();
+    let locked = Balance { available: U256::from(5u64), locked: U256::from(10u64) };
+    tree.update(key_balance(&owner, &base), Some(locked.encode().to_vec()));
+    let order_id = OrderId(keccak256(b"owner-order"));
+    let order = Order {
+        owner,
+        market_id: market,
         side: Side::Sell,
         tick: 1,
         qty_remaining: U256::from(10u64),
         tif: TimeInForce::Gtc,
         status: OrderStatus::Open,
     };
-    tree.update(key_order(&maker_order_id), Some(maker_order.encode()));
+    tree.update(key_order(Some(&MARKET), &order_id), Some(order.encode()));
     tree.update(
-        key_order_node(&maker_order_id),
-        Some(
-            OrderNode {
-                prev_order_id: [0u8; 32],
-                next_order_id: [0u8; 32],
-            }
-            .encode()
-            .to_vec(),
-        ),
-    );
-    tree.update(
-        key_tick_node(&market, Side::Sell.as_u8(), 1),
-        Some(
-            TickNode {
-                prev_tick: i32::MIN,
-                next_tick: i32::MIN,
-                head_order_id: maker_order_id,
-                tail_order_id: maker_order_id,
-            }
-            .encode()
-            .to_vec(),
-        ),
-    );
-    tree.update(
-        key_market_best(&market),
-        Some(
-            MarketBest {
-                best_bid: i32::MIN,
-                best_ask: 1,
-            }
-            .encode()
-            .to_vec(),
-        ),
+        key_order_node(Some(&MARKET), &order_id),
+        Some(OrderNode { prev_order_id: OrderId::ZERO, next_order_id: OrderId::ZERO }.encode().to_vec()),
     );
+    let tick_node = TickNode {
+        prev_tick: i32::MIN,
+        next_tick: i32::MIN,
+        head_order_id: order_id,
+        tail_order_id: order_id,
+    };
+    tree.update(key_tick_node(&market, Side::Sell.as_u8(), 1), Some(tick_node.encode().to_vec()));
+    let best = MarketBest { best_bid: i32::MIN, best_ask: 1 };
+    tree.update(key_market_best(&market), Some(best.encode().to_vec()));
+
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+    let force_cancel = |key: &SigningKey| {
+        let message = Message::ForceCancel { operator: addr_from_key(key), nonce: 1, order_id };
+        let signature = sign_hash(key, message_hash(&domain, &message));
+        SignedMessage { message, signature }
+    };
+
+    let err = apply_batch(
+        &mut RecordingState::new(tree.clone()),
+        market,
+        &rules,
+        domain,
+        &[force_cancel(&intruder_key)],
+    )
+    .err()
+    .expect("only the admin may force-cancel");
+    assert!(matches!(err, CoreError::Invalid("admin mismatch")));
+
+    let mut state = RecordingState::new(tree);
+    apply_batch(&mut state, market, &rules, domain, &[force_cancel(&admin_key)]).expect("force cancel");
+    let released = Balance::decode(&state.tree.get(key_balance(&owner, &base)).unwrap()).unwrap();
+    assert_eq!(released.available, U256::from(15u64));
+    assert_eq!(released.locked, U256::zero());
+    assert!(state.tree.get(key_balance(&admin, &base)).is_none());
+    let canceled = Order::decode(&state.tree.get(key_order(Some(&MARKET), &order_id)).unwrap()).unwrap();
+    assert_eq!(canceled.status, OrderStatus::Canceled);
+    let best = MarketBest::decode(&state.tree.get(key_market_best(&market)).unwrap()).unwrap();
+    assert_eq!(best.best_ask, i32::MIN);
+}
+
+#[test]
+fn forged_signer_consumes_no_proofs() {
+    let market = MarketId([3u8; 32]);
+    let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32]))
+        .failure_mode(FailureMode::Skip)
+        .build()
+        .expect("rules");
+    let victim = Address([0x77u8; 20]);
+    let forger_key = SigningKey::from_slice(&[0x55u8; 32]).unwrap();
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+    let message = Message::Cancel { trader: victim, nonce: 1, order_id: OrderId([7u8; 32]) };
+    let signature = sign_hash(&forger_key, message_hash(&domain, &message));
+
+    let tree = SparseMerkleTree::new();
+    let mut proofs = vec![tree.prove(key_nonce(&victim))];
+    let mut state = ProofState::new(tree.root(), &mut proofs);
+    let output = apply_batch(&mut state, market, &rules, domain, &[SignedMessage { message, signature }])
+        .expect("skip mode records the failure");
+    assert!(matches!(output.outcomes[0], MessageOutcome::Failed(CoreError::Signature("signer mismatch"))));
+    assert_eq!(state.remaining_proofs(), 1);
+    assert!(state.touched_keys.is_empty());
+    assert_eq!(state.root, tree.root());
+}
+
+#[test]
+fn apply_at_root_rejects_mismatched_prev_root() {
+    let market = MarketId([3u8; 32]);
+    let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).build().expect("rules");
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+    let mut tree = SparseMerkleTree::new();
+    let claimed_root = tree.root();
+    tree.update(key_nonce(&Address([0x77u8; 20])), Some(4u64.to_be_bytes().to_vec()));
+
+    let mut state = RecordingState::new(tree);
+    let err = apply_batch_at_root(&mut state, claimed_root, market, &rules, domain, 0, &[])
+        .err()
+        .expect("drifted tree is rejected");
+    assert!(matches!(err, CoreError::State("prev root mismatch")));
+
+    let actual_root = state.root;
+    apply_batch_at_root(&mut state, actual_root, market, &rules, domain, 0, &[]).expect("matching root");
+}
+
+#[test]
+fn bound_signature_only_verifies_in_its_batch() {
+    let alice = Trader::new(0x21);
+    let book = BookBuilder::new().balance(&alice.address, &QUOTE, 1_000, 0);
+    let domain = book.signer().domain;
+    let tree = book.tree();
+    let rules = RulesBuilder::new(BASE, QUOTE).bind_batch_seq(true).build().expect("rules");
+    let batch_seq = 7;
+    let signer = Signer {
+        domain: signing_separator(&domain, &rules, batch_seq),
+    };
+    let batch = [signer.sign(&alice, place(&alice, 1, OrderId([0x41u8; 32]), Side::Buy, TimeInForce::Gtc, 10, 5))];
+    let root = tree.root();
+
+    let mut state = RecordingState::new(tree.clone());
+    let err = apply_batch_at_root(&mut state, root, MARKET, &rules, domain, batch_seq + 1, &batch)
+        .err()
+        .expect("signature from the previous batch is rejected");
+    assert!(matches!(err, CoreError::Signature("signer mismatch")));
+
+    let mut state = RecordingState::new(tree.clone());
+    let err = apply_batch(&mut state, MARKET, &rules, domain, &batch)
+        .err()
+        .expect("bound rules need a batch seq");
+    assert!(matches!(err, CoreError::Invalid("batch seq required for bound signatures")));
+
+    let mut state = RecordingState::new(tree);
+    apply_batch_at_root(&mut state, root, MARKET, &rules, domain, batch_seq, &batch).expect("signed batch applies");
+}
+
+#[test]
+fn recovered_signers_apply_like_an_unrecovered_batch() {
+    let alice = Trader::new(0x21);
+    let mallory = Trader::new(0x22);
+    let book = BookBuilder::new().balance(&alice.address, &QUOTE, 1_000, 0);
+    let signer = book.signer();
+    let tree = book.tree();
+    let rules = RulesBuilder::new(BASE, QUOTE).failure_mode(FailureMode::Skip).build().expect("rules");
+    let batch = [
+        signer.sign(&mallory, place(&alice, 1, OrderId([0x41u8; 32]), Side::Buy, TimeInForce::Gtc, 10, 5)),
+        signer.sign(&alice, place(&alice, 1, OrderId([0x42u8; 32]), Side::Buy, TimeInForce::Gtc, 10, 5)),
+    ];
+    let hashes: Vec<[u8; 32]> = batch.iter().map(|signed| message_hash(&signer.domain, &signed.message)).collect();
+    let sigs: Vec<MessageSignature> = batch.iter().map(|signed| signed.signature.clone()).collect();
+    let root = tree.root();
 
-    let domain = domain_separator(1, &[9u8; 32], &market);
-    let taker_order_id = keccak256(b"taker-order");
+    let short = RecoveredBatch::new(&batch, recover_addresses(&hashes[..1], &sigs[..1]));
+    assert!(matches!(short.err(), Some(CoreError::Invalid("signer count mismatch"))));
+
+    let mut recovered_state = RecordingState::new(tree.clone());
+    let recovered_batch = RecoveredBatch::new(&batch, recover_addresses(&hashes, &sigs)).expect("one signer each");
+    let recovered =
+        apply_recovered_batch_at_root(&mut recovered_state, root, MARKET, &rules, signer.domain, 0, recovered_batch)
+            .expect("skip mode records the forged message");
+    assert!(matches!(recovered.outcomes[0], MessageOutcome::Failed(CoreError::Signature("signer mismatch"))));
+    assert!(matches!(recovered.outcomes[1], MessageOutcome::Applied { .. }));
+
+    let mut state = RecordingState::new(tree);
+    let output =
+        apply_batch_at_root(&mut state, root, MARKET, &rules, signer.domain, 0, &batch).expect("batch applies");
+    assert_eq!(recovered.encode(), output.encode());
+    assert_eq!(recovered_state.root, state.root);
+    assert_eq!(recovered_state.proofs.len(), state.proofs.len());
+}
+
+#[test]
+fn zero_tick_size_is_rejected_before_any_state_read() {
+    let market = MarketId([3u8; 32]);
+    let mut rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).build().expect("rules");
+    rules.tick_size = U256::zero();
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
     let message = Message::Place {
-        trader: taker,
+        trader: Address([0x77u8; 20]),
         nonce: 1,
-        order_id: taker_order_id,
+        order_id: OrderId([0x44u8; 32]),
         side: Side::Buy,
-        tif: TimeInForce::Ioc,
+        tif: TimeInForce::Gtc,
         tick_index: 1,
-        qty_base: U256::from(5u64),
+        qty_base: U256::from(1u64),
         prev_tick_hint: i32::MIN,
         next_tick_hint: i32::MIN,
+        reduce_only: false,
+        quote_budget: false,
     };
-    let hash = message_hash(&domain, &message);
-    let signature = sign_hash(&taker_key, hash);
-    let signed = SignedMessage { message, signature };
+    let signature = MessageSignature { r: [1u8; 32], s: [2u8; 32], v: 27 };
+
+    let mut state = RecordingState::new(SparseMerkleTree::new());
+    let err = apply_batch(&mut state, market, &rules, domain, &[SignedMessage { message, signature }])
+        .err()
+        .expect("zero tick size");
+    assert!(matches!(err, CoreError::Invalid("tick size zero")));
+    assert!(state.proofs.is_empty());
+}
+
+#[cfg(feature = "debug_merkle")]
+#[test]
+fn proof_state_trajectory_chains_to_final_root() {
+    use clob_core::state::{decode_trajectory, encode_trajectory, first_broken_step};
+
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (mut recorder, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 100, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+        .build();
+    let prev_root = recorder.root;
+
+    let message = place(&taker, 1, OrderId(keccak256(b"sweep")), Side::Buy, TimeInForce::Gtc, 2, 25);
+    let messages = [signer.sign(&taker, message)];
+    apply_batch(&mut recorder, MARKET, &rules, signer.domain, &messages).expect("record");
+
+    let mut proofs = recorder.proofs;
+    let mut state = ProofState::new(prev_root, &mut proofs);
+    state.record_trajectory();
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &messages).expect("replay");
+    let trajectory = state.take_trajectory().expect("recording");
+
+    assert!(!trajectory.is_empty());
+    assert_eq!(first_broken_step(prev_root, &trajectory), None);
+    assert_eq!(trajectory.last().unwrap().new_root, state.root);
+    assert_eq!(state.root, recorder.root);
+    assert_eq!(decode_trajectory(&encode_trajectory(&trajectory)).expect("decode"), trajectory);
+    assert_eq!(first_broken_step([0xeeu8; 32], &trajectory), Some(0));
+}
+
+#[test]
+fn zero_qty_open_maker_is_rejected() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let book = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 100, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 0)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10);
+    let signer = book.signer();
+    let tree = book.tree();
+
+    let message = place(&taker, 1, OrderId(keccak256(b"sweep")), Side::Buy, TimeInForce::Ioc, 2, 5);
+    let messages = [signer.sign(&taker, message)];
+
+    for mode in [AllocationMode::Fifo, AllocationMode::ProRata] {
+        let rules = RulesBuilder::new(BASE, QUOTE)
+            .max_balance(U256::from(1_000_000u64))
+            .allocation_mode(mode)
+            .build()
+            .unwrap();
+        let mut state = RecordingState::new(tree.clone());
+        let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &messages).err().expect("zero-qty maker");
+        assert!(matches!(err, CoreError::State("zero-qty open maker")), "{mode:?}: {err:?}");
+    }
+}
+
+#[test]
+fn open_order_cap_rejects_extra_resting_order_until_one_is_canceled() {
+    let base = AssetId([1u8; 32]);
+    let quote = AssetId([2u8; 32]);
+    let market = MarketId([3u8; 32]);
+    let rules = RulesBuilder::new(base, quote)
+        .max_balance(U256::from(1_000_000u64))
+        .max_open_orders_per_trader(2)
+        .build()
+        .unwrap();
+    let key = SigningKey::from_slice(&[0x11u8; 32]).unwrap();
+    let trader = addr_from_key(&key);
+    let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
 
+    let mut tree = SparseMerkleTree::new();
+    let funded = Balance { available: U256::from(10u64), locked: U256::zero() };
+    tree.update(key_balance(&trader, &quote), Some(funded.encode().to_vec()));
     let mut state = RecordingState::new(tree);
-    apply_batch(&mut state, market, &rules, domain, &[signed]).expect("apply batch");
-
-    let maker_balance_after = Balance::decode(
-        state
-            .tree
-            .get(key_balance(&maker, &base))
-            .as_ref()
-            .unwrap(),
-    )
-    .unwrap();
-    let maker_quote_after = Balance::decode(
-        state
-            .tree
-            .get(key_balance(&maker, &quote))
-            .as_ref()
-            .unwrap(),
-    )
-    .unwrap();
-    let taker_quote_after = Balance::decode(
-        state
-            .tree
-            .get(key_balance(&taker, &quote))
-            .as_ref()
-            .unwrap(),
-    )
-    .unwrap();
-    let taker_base_raw = state.tree.get(key_balance(&taker, &base));
-    let taker_base_after = if let Some(bytes) = taker_base_raw {
-        Balance::decode(&bytes).unwrap()
-    } else {
-        Balance::empty()
+
+    let place = |nonce: u64| {
+        let message = Message::Place {
+            trader,
+            nonce,
+            order_id: OrderId(keccak256(&nonce.to_be_bytes())),
+            side: Side::Buy,
+            tif: TimeInForce::Gtc,
+            tick_index: 1,
+            qty_base: U256::from(1u64),
+            prev_tick_hint: i32::MIN,
+            next_tick_hint: i32::MIN,
+            reduce_only: false,
+            quote_budget: false,
+        };
+        let signature = sign_hash(&key, message_hash(&domain, &message));
+        SignedMessage { message, signature }
+    };
+
+    apply_batch(&mut state, market, &rules, domain, &[place(1), place(2)]).expect("up to the cap");
+    assert_eq!(state.tree.get(key_open_count(&trader)), Some(2u32.to_be_bytes().to_vec()));
+
+    let err = apply_batch(&mut state, market, &rules, domain, &[place(3)]).err().expect("over the cap");
+    assert!(matches!(err, CoreError::Invalid("max open orders exceeded")));
+
+    let cancel = Message::Cancel {
+        trader,
+        nonce: 3,
+        order_id: OrderId(keccak256(&1u64.to_be_bytes())),
+    };
+    let signature = sign_hash(&key, message_hash(&domain, &cancel));
+    let batch = [SignedMessage { message: cancel, signature }, place(4)];
+    apply_batch(&mut state, market, &rules, domain, &batch).expect("cancel frees a slot");
+    assert_eq!(state.tree.get(key_open_count(&trader)), Some(2u32.to_be_bytes().to_vec()));
+}
+
+#[test]
+fn book_steps_bound_a_sweep_across_many_thin_levels() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let sweep = |max_book_steps: u32| {
+        let rules = RulesBuilder::new(BASE, QUOTE)
+            .max_balance(U256::from(1_000_000u64))
+            .max_book_steps_per_order(max_book_steps)
+            .build()
+            .unwrap();
+        // One lot per tick, so every level costs a tick read, a maker unlink and a neighbour relink.
+        let mut book = BookBuilder::new()
+            .balance(&maker.address, &BASE, 0, 8)
+            .balance(&taker.address, &QUOTE, 1_000, 0);
+        for tick in 1..=8i32 {
+            book = book.resting(OrderId(keccak256(&tick.to_be_bytes())), &maker.address, Side::Sell, tick, 1);
+        }
+        let (mut state, signer) = book.build();
+        let message = place(&taker, 1, OrderId(keccak256(b"sweep")), Side::Buy, TimeInForce::Ioc, 8, 8);
+        apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)])
+    };
+
+    let output = sweep(0).expect("unbounded sweep");
+    assert_eq!(output.trades.len(), 8);
+    sweep(24).expect("eight levels fit in 24 steps");
+    let err = sweep(10).err().expect("walk exceeds the bound");
+    assert!(matches!(err, CoreError::Invalid("book steps exceeded")));
+}
+
+#[test]
+fn fee_collector_taking_keeps_vault_and_balance_separate() {
+    let maker = Trader::new(0x11);
+    let collector = Trader::new(0x22);
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .taker_fee_bps(100)
+        .max_balance(U256::from(1_000_000u64))
+        .fee_collector(collector.address)
+        .build()
+        .unwrap();
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 10)
+        .balance(&collector.address, &QUOTE, 100, 0)
+        .resting(OrderId(keccak256(b"ask")), &maker.address, Side::Sell, 1, 10)
+        .build();
+    let quote_of = |state: &RecordingState, account: &Address| {
+        Balance::decode(&state.tree.get(key_balance(account, &QUOTE)).unwrap()).unwrap()
+    };
+    let vault_of = |state: &RecordingState| FeeVault::decode(&state.tree.get(key_fee_vault(&QUOTE)).unwrap()).unwrap();
+
+    let buy = place(&collector, 1, OrderId(keccak256(b"buy")), Side::Buy, TimeInForce::Ioc, 2, 10);
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&collector, buy)]).expect("take as collector");
+    // The fee leaves the collector's balance like any taker's and lands only in the vault.
+    assert_eq!(quote_of(&state, &collector.address).available, U256::from(89u64));
+    assert_eq!(quote_of(&state, &maker.address).available, U256::from(10u64));
+    assert_eq!(vault_of(&state).total, U256::from(1u64));
+
+    let collect = Message::CollectFees {
+        operator: collector.address,
+        nonce: 2,
+        asset: QUOTE,
+        amount: U256::from(1u64),
+        to: collector.address,
     };
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&collector, collect)]).expect("collect to self");
+    assert_eq!(quote_of(&state, &collector.address).available, U256::from(90u64));
+    assert_eq!(vault_of(&state).total, U256::zero());
+}
+
+#[test]
+fn tick_spacing_rejects_unaligned_ticks() {
+    let trader = Trader::new(0x11);
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .max_balance(U256::from(1_000_000u64))
+        .tick_spacing(5)
+        .build()
+        .unwrap();
+    let (mut state, signer) = BookBuilder::new().balance(&trader.address, &QUOTE, 100, 0).build();
+
+    let unaligned = place(&trader, 1, OrderId(keccak256(b"bid-7")), Side::Buy, TimeInForce::Gtc, 7, 1);
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&trader, unaligned)])
+        .err()
+        .expect("tick 7 is off the grid");
+    assert!(matches!(err, CoreError::Invalid("tick not aligned")));
+
+    let aligned = place(&trader, 1, OrderId(keccak256(b"bid-10")), Side::Buy, TimeInForce::Gtc, 10, 1);
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&trader, aligned)]).expect("tick 10 rests");
+    let best = MarketBest::decode(&state.tree.get(key_market_best(&MARKET)).unwrap()).unwrap();
+    assert_eq!(best.best_bid, 10);
+}
+
+#[test]
+fn taker_fee_tier_blends_rate_across_the_boundary() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .taker_fee_bps(100)
+        .taker_fee_tier(U256::from(4_000u64), 50)
+        .max_balance(U256::from(1_000_000u64))
+        .build()
+        .unwrap();
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 10_000)
+        .balance(&taker.address, &QUOTE, 100_000, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 5_000)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 1, 5_000)
+        .build();
+
+    let buy = place(&taker, 1, OrderId(keccak256(b"large")), Side::Buy, TimeInForce::Ioc, 2, 10_000);
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, buy)]).expect("apply batch");
+    // 4_000 quote at 1% and 1_000 at 0.5% on the first fill, then 5_000 at 0.5%.
+    assert_eq!(output.trades.len(), 2);
+    assert_eq!(output.trades[0].taker_fee, U256::from(45u64));
+    assert_eq!(output.trades[1].taker_fee, U256::from(25u64));
+
+    let vault = FeeVault::decode(&state.tree.get(key_fee_vault(&QUOTE)).unwrap()).unwrap();
+    assert_eq!(vault.total, U256::from(70u64));
+    let quote = Balance::decode(&state.tree.get(key_balance(&taker.address, &QUOTE)).unwrap()).unwrap();
+    assert_eq!(quote.available, U256::from(100_000u64 - 10_000 - 70));
+}
+
+#[test]
+fn stale_market_best_rejects_matching() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let book = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 100, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10);
+    let signer = book.signer();
+    let mut tree = book.tree();
+    // Skips the populated tick 1, so a buy would otherwise trade through it at tick 2.
+    let stale = MarketBest { best_bid: NONE_TICK, best_ask: 2 };
+    tree.update(key_market_best(&MARKET), Some(stale.encode().to_vec()));
+    let mut state = RecordingState::new(tree);
+
+    let buy = place(&taker, 1, OrderId(keccak256(b"buy")), Side::Buy, TimeInForce::Ioc, 2, 5);
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, buy)])
+        .err()
+        .expect("stale best");
+    assert!(matches!(err, CoreError::State("stale market best")));
+}
+
+#[test]
+fn cancel_rejects_order_whose_stored_tick_disagrees_with_the_book() {
+    let maker = Trader::new(0x11);
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let ask_2 = OrderId(keccak256(b"ask-2"));
+    let book = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(ask_2, &maker.address, Side::Sell, 2, 10);
+    let signer = book.signer();
+    let mut tree = book.tree();
+    // ask-2 rests at tick 2, but its record now claims tick 1.
+    let mut order = Order::decode(&tree.get(key_order(Some(&MARKET), &ask_2)).unwrap()).unwrap();
+    order.tick = 1;
+    tree.update(key_order(Some(&MARKET), &ask_2), Some(order.encode()));
+    let mut state = RecordingState::new(tree);
+
+    let cancel = Message::Cancel {
+        trader: maker.address,
+        nonce: 1,
+        order_id: ask_2,
+    };
+    let err = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&maker, cancel)])
+        .err()
+        .expect("corrupt order tick");
+    assert!(matches!(err, CoreError::State("order not queued at its tick")));
+}
+
+#[test]
+fn deep_order_stays_cancelable_under_a_small_book_step_cap() {
+    let maker = Trader::new(0x11);
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .max_balance(U256::from(1_000_000u64))
+        .max_book_steps_per_order(2)
+        .build()
+        .unwrap();
+    let ids: Vec<OrderId> = (0..4u8).map(|i| OrderId(keccak256(&[0xc0, i]))).collect();
+    let mut book = BookBuilder::new().balance(&maker.address, &BASE, 0, 40);
+    for id in &ids {
+        book = book.resting(*id, &maker.address, Side::Sell, 1, 10);
+    }
+    let (mut state, signer) = book.build();
+
+    // The fourth order sits three links behind the head, past a walk bounded at two steps.
+    let cancels: Vec<_> = [ids[3], ids[1]]
+        .into_iter()
+        .enumerate()
+        .map(|(i, order_id)| {
+            let cancel = Message::Cancel { trader: maker.address, nonce: i as u64 + 1, order_id };
+            signer.sign(&maker, cancel)
+        })
+        .collect();
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &cancels).expect("cancel deep orders");
+
+    let level = TickNode::decode(&state.tree.get(key_tick_node(&MARKET, Side::Sell.as_u8(), 1)).unwrap()).unwrap();
+    assert_eq!((level.head_order_id, level.tail_order_id), (ids[0], ids[2]));
+    verify_book_links(&state.tree, &MARKET, OrderIdScope::Market).expect("book links");
+    let maker_base = Balance::decode(&state.tree.get(key_balance(&maker.address, &BASE)).unwrap()).unwrap();
+    assert_eq!((maker_base.available, maker_base.locked), (U256::from(20u64), U256::from(20u64)));
+}
+
+#[test]
+fn applied_outcome_reports_volume_weighted_price_across_ticks() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 1_000, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+        .build();
+    // Takes all of tick 1 and half of tick 2; the second order rests without filling.
+    let sweep = place(&taker, 1, OrderId(keccak256(b"sweep")), Side::Buy, TimeInForce::Ioc, 2, 15);
+    let rest = place(&taker, 2, OrderId(keccak256(b"rest")), Side::Buy, TimeInForce::Gtc, 1, 5);
+    let messages = [signer.sign(&taker, sweep), signer.sign(&taker, rest)];
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &messages).expect("apply batch");
+
+    let (low, high) = (price_from_tick(1, rules.tick_size).unwrap(), price_from_tick(2, rules.tick_size).unwrap());
+    let MessageOutcome::Applied { avg_price } = output.outcomes[0] else {
+        panic!("sweep not applied");
+    };
+    assert!(low < avg_price && avg_price < high);
+    let quote: U256 = output.trades.iter().map(|trade| trade.quote_amt).fold(U256::zero(), |acc, q| acc + q);
+    assert_eq!(avg_price, quote * rules.price_scale / U256::from(15u64));
+    assert!(matches!(output.outcomes[1], MessageOutcome::Applied { avg_price } if avg_price.is_zero()));
+}
+
+#[test]
+fn non_crossing_place_reads_no_opposite_tick_node() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let book = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 10)
+        .balance(&maker.address, &QUOTE, 0, 20)
+        .balance(&taker.address, &BASE, 10, 0)
+        .balance(&taker.address, &QUOTE, 1_000, 0)
+        .resting(OrderId(keccak256(b"bid")), &maker.address, Side::Buy, 2, 10)
+        .resting(OrderId(keccak256(b"ask")), &maker.address, Side::Sell, 5, 10);
+    let signer = book.signer();
+    let tree = book.tree();
+
+    // Each order joins its own best level and stops at the price check against MarketBest, before
+    // the opposite best tick is read.
+    for (side, tick, opposite) in [(Side::Buy, 2, 5), (Side::Sell, 5, 2)] {
+        let mut state = RecordingState::new(tree.clone());
+        let order_id = OrderId(keccak256(&[side.as_u8()]));
+        let message = place(&taker, 1, order_id, side, TimeInForce::Gtc, tick, 10);
+        assert!(apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)]).is_ok());
+        let opposite_key = key_tick_node(&MARKET, side.opposite().as_u8(), opposite);
+        assert!(state.proofs.iter().all(|proof| proof.key != opposite_key));
+        assert!(state.tree.get(key_order(Some(&MARKET), &order_id)).is_some());
+    }
+}
+
+#[test]
+fn self_crossing_gtc_fills_own_ask_before_resting() {
+    let trader = Trader::new(0x11);
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let ask_id = OrderId(keccak256(b"own-ask"));
+    let bid_id = OrderId(keccak256(b"own-bid"));
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&trader.address, &BASE, 0, 5)
+        .balance(&trader.address, &QUOTE, 1_000, 0)
+        .resting(ask_id, &trader.address, Side::Sell, 1, 5)
+        .build();
+    let message = place(&trader, 1, bid_id, Side::Buy, TimeInForce::Gtc, 1, 8);
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&trader, message)])
+        .expect("apply batch");
+
+    // No STP mode exists: the own ask is the maker, and only the remainder rests.
+    assert_eq!(output.trades.len(), 1);
+    let trade = &output.trades[0];
+    assert_eq!((trade.maker_order_id, trade.maker, trade.taker), (ask_id, trader.address, trader.address));
+    assert_eq!(trade.qty_base, U256::from(5u64));
+    let ask = Order::decode(&state.tree.get(key_order(Some(&MARKET), &ask_id)).expect("ask record")).unwrap();
+    assert_eq!(ask.status, OrderStatus::Filled);
+    let bid = Order::decode(&state.tree.get(key_order(Some(&MARKET), &bid_id)).expect("remainder rests")).unwrap();
+    assert_eq!(bid.qty_remaining, U256::from(3u64));
+    let best = MarketBest::decode(&state.tree.get(key_market_best(&MARKET)).expect("market best")).unwrap();
+    assert_eq!((best.best_bid, best.best_ask), (1, NONE_TICK));
+    verify_book_links(&state.tree, &MARKET, OrderIdScope::Market).expect("book links");
+}
+
+#[test]
+fn order_id_scope_decides_whether_markets_share_order_ids() {
+    let trader = Trader::new(0x11);
+    let other_market = MarketId([4u8; 32]);
+    let order_id = OrderId(keccak256(b"shared-id"));
+    let run = |scope: OrderIdScope| {
+        let rules = RulesBuilder::new(BASE, QUOTE)
+            .max_balance(U256::from(1_000_000u64))
+            .order_id_scope(scope)
+            .build()
+            .unwrap();
+        let book = BookBuilder::new().balance(&trader.address, &QUOTE, 1_000, 0);
+        let signer = book.signer();
+        let mut state = RecordingState::new(book.tree());
+        let bid = place(&trader, 1, order_id, Side::Buy, TimeInForce::Gtc, 1, 10);
+        assert!(apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&trader, bid)]).is_ok());
+
+        let other = Signer {
+            domain: domain_separator(1, &[9u8; 32], &other_market, &[0u8; 32]),
+        };
+        let bid = place(&trader, 2, order_id, Side::Buy, TimeInForce::Gtc, 1, 10);
+        let second = apply_batch(&mut state, other_market, &rules, other.domain, &[other.sign(&trader, bid)]).err();
+        (second, state)
+    };
+
+    // Per-market ids key each record under its market, so both orders rest independently.
+    let (second, state) = run(OrderIdScope::Market);
+    assert!(second.is_none());
+    assert!(state.tree.get(key_order(Some(&MARKET), &order_id)).is_some());
+    assert!(state.tree.get(key_order(Some(&other_market), &order_id)).is_some());
+    assert!(state.tree.get(key_order(None, &order_id)).is_none());
+
+    // Global ids share one record, so the second market sees the id as taken.
+    let (second, state) = run(OrderIdScope::Global);
+    assert!(matches!(second, Some(CoreError::Invalid("order id already exists"))));
+    assert!(state.tree.get(key_order(None, &order_id)).is_some());
+    assert!(state.tree.get(key_order(Some(&other_market), &order_id)).is_none());
+}
+
+#[test]
+fn global_scope_rejects_messages_naming_another_markets_order() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let admin = Trader::new(0x44);
+    let other_market = MarketId([4u8; 32]);
+    let (other_base, other_quote) = (AssetId([5u8; 32]), AssetId([6u8; 32]));
+    let order_id = OrderId(keccak256(b"market-a-ask"));
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .max_balance(U256::from(1_000_000u64))
+        .order_id_scope(OrderIdScope::Global)
+        .admin(admin.address)
+        .build()
+        .unwrap();
+    let other_rules = RulesBuilder::new(other_base, other_quote)
+        .max_balance(U256::from(1_000_000u64))
+        .order_id_scope(OrderIdScope::Global)
+        .admin(admin.address)
+        .build()
+        .unwrap();
+    let book = BookBuilder::new()
+        .balance(&maker.address, &BASE, 10, 0)
+        .balance(&taker.address, &other_quote, 100, 0);
+    let signer = book.signer();
+    let mut state = RecordingState::new(book.tree());
+    let ask = place(&maker, 1, order_id, Side::Sell, TimeInForce::Gtc, 1, 10);
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&maker, ask)]).expect("ask rests");
+    let rested = state.tree.root();
+
+    // The ask's record sits under the shared global key, but its collateral is in the first market's base.
+    let other = Signer {
+        domain: domain_separator(1, &[9u8; 32], &other_market, &[0u8; 32]),
+    };
+    let messages = [
+        other.sign(
+            &taker,
+            Message::FillOrder {
+                trader: taker.address,
+                nonce: 1,
+                order_id: OrderId(keccak256(b"cross-market-rfq")),
+                maker_order_id: order_id,
+                max_qty: U256::from(10u64),
+            },
+        ),
+        other.sign(&maker, Message::Cancel { trader: maker.address, nonce: 2, order_id }),
+        other.sign(&admin, Message::ForceCancel { operator: admin.address, nonce: 1, order_id }),
+    ];
+    for message in messages {
+        let err = apply_batch(&mut state, other_market, &other_rules, other.domain, &[message])
+            .err()
+            .expect("order belongs to another market");
+        assert!(matches!(err, CoreError::Invalid("order in another market")));
+        assert_eq!(state.tree.root(), rested);
+    }
+
+    let order = Order::decode(&state.tree.get(key_order(None, &order_id)).unwrap()).unwrap();
+    assert_eq!((order.market_id, order.status), (MARKET, OrderStatus::Open));
+    let cancel = Message::Cancel { trader: maker.address, nonce: 2, order_id };
+    apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&maker, cancel)]).expect("cancel at home");
+    let maker_base = Balance::decode(&state.tree.get(key_balance(&maker.address, &BASE)).unwrap()).unwrap();
+    assert_eq!((maker_base.available, maker_base.locked), (U256::from(10u64), U256::zero()));
+}
+
+fn fee_in_received_asset_fill(taker_side: Side) -> (BatchOutput, RecordingState, Trader) {
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .taker_fee_bps(100)
+        .max_balance(U256::from(1_000_000u64))
+        .fee_in_received_asset(true)
+        .build()
+        .unwrap();
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (maker_asset, taker_asset) = match taker_side {
+        Side::Buy => (BASE, QUOTE),
+        Side::Sell => (QUOTE, BASE),
+    };
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &maker_asset, 0, 1_000)
+        .balance(&taker.address, &taker_asset, 1_000, 0)
+        .resting(OrderId(keccak256(b"maker-order")), &maker.address, taker_side.opposite(), 1, 1_000)
+        .build();
+
+    let message = place(&taker, 1, OrderId(keccak256(b"taker-order")), taker_side, TimeInForce::Ioc, 1, 1_000);
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)])
+        .expect("apply batch");
+    (output, state, taker)
+}
+
+#[test]
+fn fee_in_received_asset_charges_buy_in_base() {
+    let (output, state, taker) = fee_in_received_asset_fill(Side::Buy);
+
+    assert_eq!(output.trades.len(), 1);
+    assert_eq!(output.trades[0].taker_fee, U256::from(10u64));
+    assert_eq!(output.fee_totals.len(), 1);
+    assert_eq!(output.fee_totals[0].asset_id, BASE);
+    assert_eq!(output.fee_totals[0].total_fee, U256::from(10u64));
+
+    let taker_base = Balance::decode(&state.tree.get(key_balance(&taker.address, &BASE)).unwrap()).unwrap();
+    let taker_quote = Balance::decode(&state.tree.get(key_balance(&taker.address, &QUOTE)).unwrap()).unwrap();
+    assert_eq!(taker_base.available, U256::from(990u64));
+    assert_eq!(taker_quote.available, U256::zero());
+    assert_eq!(taker_quote.locked, U256::zero());
+    let vault = FeeVault::decode(&state.tree.get(key_fee_vault(&BASE)).unwrap()).unwrap();
+    assert_eq!(vault.total, U256::from(10u64));
+    assert!(state.tree.get(key_fee_vault(&QUOTE)).is_none());
+}
+
+#[test]
+fn fee_in_received_asset_charges_sell_in_quote() {
+    let (output, state, taker) = fee_in_received_asset_fill(Side::Sell);
+
+    assert_eq!(output.trades.len(), 1);
+    assert_eq!(output.trades[0].taker_fee, U256::from(10u64));
+    assert_eq!(output.fee_totals.len(), 1);
+    assert_eq!(output.fee_totals[0].asset_id, QUOTE);
+    assert_eq!(output.fee_totals[0].total_fee, U256::from(10u64));
+
+    let taker_quote = Balance::decode(&state.tree.get(key_balance(&taker.address, &QUOTE)).unwrap()).unwrap();
+    assert_eq!(taker_quote.available, U256::from(990u64));
+    let vault = FeeVault::decode(&state.tree.get(key_fee_vault(&QUOTE)).unwrap()).unwrap();
+    assert_eq!(vault.total, U256::from(10u64));
+    assert!(state.tree.get(key_fee_vault(&BASE)).is_none());
+}
+
+fn prove_two_tick_sweep(commit_trade_roots: bool) -> (BatchOutput, ProgramOutput) {
+    let rules = RulesBuilder::new(BASE, QUOTE)
+        .taker_fee_bps(100)
+        .max_balance(U256::from(1_000_000u64))
+        .commit_trade_roots(commit_trade_roots)
+        .build()
+        .unwrap();
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 20)
+        .balance(&taker.address, &QUOTE, 100, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 2, 10)
+        .build();
+    let prev_root = state.root;
+
+    let message = place(&taker, 1, OrderId(keccak256(b"sweep")), Side::Buy, TimeInForce::Ioc, 2, 20);
+    let messages = vec![signer.sign(&taker, message)];
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &messages).expect("apply batch");
+
+    let domain = signer.domain;
+    let hashes: Vec<[u8; 32]> = messages.iter().map(|m| message_hash(&domain, &m.message)).collect();
+    let bundle = GuestBundle {
+        input: GuestInput {
+            public: PublicInputsPartial {
+                prev_root,
+                batch_digest: batch_digest(&domain, 1, &hashes),
+                rules_hash: rules_hash(&rules),
+                domain_separator: domain,
+                batch_seq: 1,
+                batch_timestamp: 0,
+                batch_deadline: 0,
+                da_commitment: [0u8; 32],
+            },
+            chain_id: 1,
+            venue_id: [9u8; 32],
+            market_id: MARKET,
+            instance_salt: [0u8; 32],
+            rules,
+            commit_rules: false,
+            touched_digest: DigestKind::Keccak256,
+            messages,
+        },
+        proofs: state.proofs,
+    };
+    (output, run_bundle(bundle).expect("prove sweep"))
+}
+
+#[test]
+fn trades_feed_rebuilds_the_committed_trades_root() {
+    let (output, committed) = prove_two_tick_sweep(true);
+    let decoded = BatchOutput::decode_trades_feed(&output.encode_trades_feed()).expect("decode feed");
+    assert_eq!(decoded, output.trades);
+    assert_eq!(decoded.len(), 2);
+    assert_eq!(trades_root(&decoded), committed.public.trades_root);
+}
+
+#[test]
+fn trade_roots_are_zero_when_not_committed() {
+    let (_, committed) = prove_two_tick_sweep(true);
+    assert_ne!(committed.public.trades_root, ZERO32);
+    assert_ne!(committed.public.fees_root, ZERO32);
+
+    let (_, root_only) = prove_two_tick_sweep(false);
+    assert_eq!(root_only.public.trades_root, ZERO32);
+    assert_eq!(root_only.public.fees_root, ZERO32);
+    assert_eq!(root_only.public.prev_root, committed.public.prev_root);
+    assert_eq!(root_only.public.new_root, committed.public.new_root);
+}
+
+#[test]
+fn batch_output_round_trips_and_reproduces_committed_roots() {
+    let (output, proved) = prove_two_tick_sweep(true);
+    let decoded = BatchOutput::decode(&output.encode()).expect("decode output");
+    assert_eq!(decoded.trades, output.trades);
+    assert_eq!(decoded.fee_totals, output.fee_totals);
+    assert_eq!(decoded.fee_totals.len(), 1);
+
+    let trade_leaves: Vec<[u8; 32]> = decoded.trades.iter().map(|t| keccak256(&t.encode())).collect();
+    let fee_leaves: Vec<[u8; 32]> = decoded.fee_totals.iter().map(|f| keccak256(&f.encode())).collect();
+    assert_eq!(merkle_root(&trade_leaves), proved.public.trades_root);
+    assert_eq!(merkle_root(&fee_leaves), proved.public.fees_root);
+
+    let mut truncated = output.encode();
+    truncated.pop();
+    assert!(BatchOutput::decode(&truncated).is_err());
+}
+
+#[test]
+fn aggregate_by_tick_sums_fills_per_maker_tick() {
+    let maker = Trader::new(0x11);
+    let taker = Trader::new(0x22);
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let (mut state, signer) = BookBuilder::new()
+        .balance(&maker.address, &BASE, 0, 25)
+        .balance(&taker.address, &QUOTE, 1_000, 0)
+        .resting(OrderId(keccak256(b"ask-1")), &maker.address, Side::Sell, 1, 10)
+        .resting(OrderId(keccak256(b"ask-2")), &maker.address, Side::Sell, 1, 5)
+        .resting(OrderId(keccak256(b"ask-3")), &maker.address, Side::Sell, 2, 10)
+        .build();
+    let message = place(&taker, 1, OrderId(keccak256(b"sweep")), Side::Buy, TimeInForce::Ioc, 2, 20);
+    let output = apply_batch(&mut state, MARKET, &rules, signer.domain, &[signer.sign(&taker, message)])
+        .expect("apply batch");
+    assert_eq!(output.trades.len(), 3);
+
+    let quote = |trades: &[TradeRecord]| trades.iter().fold(U256::zero(), |acc, trade| acc + trade.quote_amt);
+    assert_eq!(
+        output.aggregate_by_tick().expect("aggregate"),
+        vec![(1, U256::from(15u64), quote(&output.trades[..2])), (2, U256::from(5u64), quote(&output.trades[2..]))]
+    );
+
+    let mut overflowing = output;
+    overflowing.trades[1].quote_amt = U256::MAX;
+    let err = overflowing.aggregate_by_tick().unwrap_err();
+    assert!(matches!(err, CoreError::Math("tick quote overflow")));
+}
+
+#[test]
+fn tampered_trade_fails_output_root_check() {
+    let (output, proved) = prove_two_tick_sweep(true);
+    verify_output_roots(&proved.public, &output.trades, &output.fee_totals).expect("untampered output");
+
+    let mut trades = output.trades.clone();
+    trades[1].quote_amt += U256::one();
+    let err = verify_output_roots(&proved.public, &trades, &output.fee_totals).unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("trades root mismatch")));
 
-    assert_eq!(maker_balance_after.locked, U256::from(5u64));
-    assert_eq!(maker_quote_after.available, U256::from(5u64));
-    assert_eq!(taker_quote_after.available, U256::from(5u64));
-    assert_eq!(taker_base_after.available, U256::from(5u64));
+    let err = verify_output_roots(&proved.public, &output.trades, &[]).unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("fees root mismatch")));
 }
 
-fn addr_from_key(key: &SigningKey) -> [u8; 20] {
+fn addr_from_key(key: &SigningKey) -> Address {
     let pubkey = key.verifying_key().to_encoded_point(false);
     let hash = keccak256(&pubkey.as_bytes()[1..]);
-    hash[12..].try_into().unwrap()
+    Address(hash[12..].try_into().unwrap())
 }
 
 fn sign_hash(key: &SigningKey, hash: [u8; 32]) -> MessageSignature {
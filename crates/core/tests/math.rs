@@ -1,5 +1,7 @@
-use clob_core::math::{mul_div_down, mul_div_up};
-use clob_core::types::U256;
+use clob_core::errors::CoreError;
+use clob_core::math::{format_price, mul_div_down, mul_div_up, parse_decimal_amount, sum_u256};
+use clob_core::parse::parse_u256;
+use clob_core::types::{SignedU256, U256};
 
 #[test]
 fn mul_div_down_basic() {
@@ -18,3 +20,100 @@ fn mul_div_up_basic() {
     let out = mul_div_up(a, b, d).expect("mul_div_up");
     assert_eq!(out, U256::from(34u64));
 }
+
+#[test]
+fn mul_div_at_u256_max_overflows_cleanly() {
+    let err = mul_div_down(U256::MAX, U256::MAX, U256::one()).unwrap_err();
+    assert!(matches!(err, CoreError::Math("mul_div overflow")));
+    let err = mul_div_up(U256::MAX, U256::MAX, U256::one()).unwrap_err();
+    assert!(matches!(err, CoreError::Math("mul_div overflow")));
+    // The full 512-bit product survives, so dividing it back down stays exact.
+    assert_eq!(mul_div_down(U256::MAX, U256::MAX, U256::MAX).unwrap(), U256::MAX);
+    assert_eq!(mul_div_up(U256::MAX, U256::MAX, U256::MAX).unwrap(), U256::MAX);
+}
+
+#[test]
+fn format_price_renders_decimal() {
+    let e18 = U256::from(1_000_000_000_000_000_000u128);
+    assert_eq!(format_price(3, e18, e18), "3.0");
+    assert_eq!(format_price(0, e18, e18), "0.0");
+    assert_eq!(format_price(125, U256::from(10_000_000_000_000_000u128), e18), "1.25");
+    assert_eq!(format_price(7, U256::from(1u64), e18), "0.000000000000000007");
+    assert_eq!(format_price(-2, U256::from(5u64), U256::from(100u64)), "-0.1");
+    assert_eq!(format_price(1, U256::from(1u64), U256::from(3u64)), "0.3");
+    assert_eq!(format_price(4, U256::from(6u64), U256::zero()), "24");
+}
+
+#[test]
+fn parse_decimal_amount_scales_to_decimals() {
+    assert_eq!(parse_decimal_amount("1.25", 6).unwrap(), U256::from(1_250_000u64));
+    assert_eq!(parse_decimal_amount("42", 0).unwrap(), U256::from(42u64));
+    assert_eq!(parse_decimal_amount(".5", 1).unwrap(), U256::from(5u64));
+    assert_eq!(parse_decimal_amount("0.100", 1).unwrap(), U256::one());
+    assert!(parse_decimal_amount("0.1234567", 6).is_err());
+    assert!(parse_decimal_amount("1.5", 0).is_err());
+    assert!(parse_decimal_amount("", 6).is_err());
+    assert!(parse_decimal_amount("1.2.3", 6).is_err());
+    assert!(parse_decimal_amount("-1", 6).is_err());
+    assert!(parse_decimal_amount(&"9".repeat(80), 0).is_err());
+}
+
+#[test]
+fn sum_u256_fails_cleanly_past_u256() {
+    let large = U256::MAX / U256::from(4u64);
+    assert_eq!(sum_u256(vec![large; 4]).unwrap(), large * U256::from(4u64));
+    let err = sum_u256(vec![large; 1_000]).unwrap_err();
+    assert!(matches!(err, CoreError::Math("sum overflow")));
+}
+
+#[test]
+fn signed_u256_crosses_zero_and_stays_canonical() {
+    let five = SignedU256::positive(U256::from(5u64));
+    let three = SignedU256::positive(U256::from(3u64));
+
+    let minus_two = three.checked_sub(five).expect("sub");
+    assert!(minus_two.is_negative());
+    assert_eq!(minus_two.magnitude(), U256::from(2u64));
+    assert_eq!(minus_two.checked_add(SignedU256::positive(U256::from(2u64))), Some(SignedU256::ZERO));
+    assert_eq!(minus_two.checked_add(five), Some(three));
+    assert_eq!(SignedU256::negative(U256::zero()), SignedU256::ZERO);
+    assert!(!five.checked_sub(five).unwrap().is_negative());
+
+    let max = SignedU256::positive(U256::MAX);
+    assert_eq!(max.checked_add(SignedU256::positive(U256::one())), None);
+    assert_eq!((-max).checked_sub(SignedU256::positive(U256::one())), None);
+    assert_eq!(max.checked_add(-max), Some(SignedU256::ZERO));
+}
+
+#[test]
+fn signed_u256_encoding_round_trips() {
+    for value in [
+        SignedU256::ZERO,
+        SignedU256::positive(U256::from(7u64)),
+        SignedU256::negative(U256::from(7u64)),
+        SignedU256::negative(U256::MAX),
+    ] {
+        let bytes = value.encode();
+        assert_eq!(bytes[0], value.is_negative() as u8);
+        assert_eq!(SignedU256::decode(&bytes).expect("decode"), value);
+    }
+    assert_eq!(SignedU256::ZERO.encode(), [0u8; 33]);
+
+    let mut negative_zero = [0u8; 33];
+    negative_zero[0] = 1;
+    assert!(matches!(SignedU256::decode(&negative_zero), Err(CoreError::Decode("negative zero"))));
+    negative_zero[0] = 2;
+    assert!(matches!(SignedU256::decode(&negative_zero), Err(CoreError::Decode("invalid signed amount sign"))));
+    assert!(SignedU256::decode(&[0u8; 32]).is_err());
+}
+
+#[test]
+fn parse_u256_accepts_hex_and_decimal() {
+    let from_hex = parse_u256("0xde0b6b3a7640000").unwrap();
+    let from_dec = parse_u256("1000000000000000000").unwrap();
+    assert_eq!(from_hex, from_dec);
+    assert_eq!(from_dec, U256::from(1_000_000_000_000_000_000u128));
+    assert_eq!(parse_u256("0").unwrap(), U256::zero());
+    assert!(parse_u256("12ab").is_err());
+    assert!(parse_u256("-1").is_err());
+}
@@ -1,5 +1,29 @@
-use clob_core::hash::keccak256;
-use clob_core::merkle::{apply_proof, leaf_hash, leaf_hash_absent, verify_proof, SparseMerkleTree};
+use std::collections::BTreeMap;
+
+use clob_core::db::DbState;
+use clob_core::errors::CoreError;
+use clob_core::hash::{keccak256, Hasher};
+use clob_core::merkle::{
+    apply_proof, apply_proof_with, apply_proofs, empty_hash, empty_hash_with, extend_prefix, get_bit, leaf_hash, leaf_hash_absent,
+    prefix_with_len, verify_proof, verify_proof_with, SparseMerkleTree,
+};
+use clob_core::constants::{BALANCE_LEN, EMPTY_ROOT, ORDER_LEN};
+use clob_core::state::{
+    get_balance, get_nonce, get_order, key_balance, key_nonce, key_nonce_with, key_order, set_nonce, ProofState,
+    StateAccess,
+};
+use clob_core::types::{Address, AssetId, Balance, MarketId, Order, OrderId, OrderStatus, Side, TimeInForce, U256};
+
+struct SaltedHasher;
+
+impl Hasher for SaltedHasher {
+    fn hash(data: &[u8]) -> [u8; 32] {
+        let mut buf = Vec::with_capacity(data.len() + 1);
+        buf.push(0xa5);
+        buf.extend_from_slice(data);
+        keccak256(&buf)
+    }
+}
 
 #[test]
 fn merkle_roundtrip() {
@@ -18,6 +42,30 @@ fn merkle_roundtrip() {
     assert_ne!(root, new_root);
 }
 
+#[test]
+fn apply_proofs_threads_root_through_updates() {
+    let mut tree = SparseMerkleTree::new();
+    tree.update(keccak256(b"key-a"), Some(b"a0".to_vec()));
+    let root = tree.root();
+
+    let writes = [
+        (keccak256(b"key-a"), Some(b"a1".to_vec())),
+        (keccak256(b"key-b"), Some(b"b1".to_vec())),
+        (keccak256(b"key-a"), None),
+    ];
+    let mut updates = Vec::new();
+    for (key, value) in writes {
+        updates.push((tree.prove(key), value.clone()));
+        tree.update(key, value);
+    }
+    assert_eq!(apply_proofs(&root, &updates).expect("apply proofs"), tree.root());
+    assert_eq!(apply_proofs(&root, &[]).unwrap(), root);
+
+    // A proof taken before the first write does not match the root it leaves behind.
+    let stale = [updates[0].clone(), updates[0].clone()];
+    assert!(apply_proofs(&root, &stale).is_err());
+}
+
 #[test]
 fn proof_fails_on_wrong_root() {
     let mut tree = SparseMerkleTree::new();
@@ -60,3 +108,304 @@ fn leaf_hash_empty_is_keyed() {
     let absent = leaf_hash_absent();
     assert_ne!(empty_value, absent);
 }
+
+#[test]
+fn custom_hasher_tree_roundtrip() {
+    let key = keccak256(b"key-custom");
+    let value = b"value".to_vec();
+    let mut keccak_tree = SparseMerkleTree::new();
+    keccak_tree.update(key, Some(value.clone()));
+    let mut tree = SparseMerkleTree::<SaltedHasher>::with_hasher();
+    tree.update(key, Some(value));
+
+    let root = tree.root();
+    assert_ne!(root, keccak_tree.root());
+    let proof = tree.prove(key);
+    verify_proof_with::<SaltedHasher>(&root, &proof).expect("verify proof");
+    assert!(verify_proof(&root, &proof).is_err());
+
+    let new_root = apply_proof_with::<SaltedHasher>(&root, &proof, Some(b"next".to_vec())).expect("apply proof");
+    tree.update(key, Some(b"next".to_vec()));
+    assert_eq!(new_root, tree.root());
+}
+
+#[test]
+fn proof_state_uses_custom_hasher_keys() {
+    let account = Address([7u8; 20]);
+    let key = key_nonce_with::<SaltedHasher>(&account);
+    let mut tree = SparseMerkleTree::<SaltedHasher>::with_hasher();
+    let prev_root = tree.root();
+    let mut write_proofs = vec![tree.prove(key)];
+    tree.update(key, Some(5u64.to_be_bytes().to_vec()));
+
+    let mut writer = ProofState::<SaltedHasher>::with_hasher(prev_root, &mut write_proofs);
+    set_nonce(&mut writer, &account, 5).expect("write nonce");
+    assert_eq!(writer.root, tree.root());
+
+    let mut read_proofs = vec![tree.prove(key)];
+    let mut reader = ProofState::<SaltedHasher>::with_hasher(tree.root(), &mut read_proofs);
+    assert_eq!(get_nonce(&mut reader, &account).expect("read nonce"), 5);
+}
+
+#[test]
+fn proof_state_reports_proof_count_mismatch() {
+    let account = Address([7u8; 20]);
+    let tree = SparseMerkleTree::new();
+    let key = key_nonce(&account);
+    let mut proofs = vec![tree.prove(key), tree.prove(key)];
+    let mut state = ProofState::new(tree.root(), &mut proofs);
+    assert_eq!(get_nonce(&mut state, &account).expect("read nonce"), 0);
+    assert!(matches!(state.finish(), Err(CoreError::Proof { expected: 1, got: 2 })));
+
+    assert_eq!(get_nonce(&mut state, &account).expect("read nonce"), 0);
+    state.finish().expect("all proofs consumed");
+    let err = get_nonce(&mut state, &account).unwrap_err();
+    assert!(matches!(err, CoreError::MissingProof { index: 2, key: k } if k == key));
+}
+
+#[test]
+fn proof_state_rejects_write_proof_contradicting_prior_read() {
+    let account = Address([7u8; 20]);
+    let tree = SparseMerkleTree::new();
+    let key = key_nonce(&account);
+    let mut forged = tree.prove(key);
+    forged.present = true;
+    forged.value = 5u64.to_be_bytes().to_vec();
+    let mut proofs = vec![tree.prove(key), forged, tree.prove(key)];
+    let mut state = ProofState::new(tree.root(), &mut proofs);
+
+    assert_eq!(get_nonce(&mut state, &account).expect("read nonce"), 0);
+    let err = set_nonce(&mut state, &account, 1).unwrap_err();
+    assert!(matches!(err, CoreError::State("write proof contradicts prior access")));
+
+    set_nonce(&mut state, &account, 1).expect("honest write proof");
+    state.finish().expect("all proofs consumed");
+}
+
+#[test]
+fn typed_getters_reject_wrong_length_leaves_by_record() {
+    let account = Address([7u8; 20]);
+    let asset = AssetId([2u8; 32]);
+    let order_id = OrderId([4u8; 32]);
+    let mut tree = SparseMerkleTree::new();
+    tree.update(key_balance(&account, &asset), Some(vec![1u8; 32]));
+    // One byte short of an order; without the length check this would fail inside the decoder.
+    tree.update(key_order(None, &order_id), Some(vec![0u8; ORDER_LEN - 1]));
+    let mut proofs = vec![tree.prove(key_balance(&account, &asset)), tree.prove(key_order(None, &order_id))];
+    let mut state = ProofState::new(tree.root(), &mut proofs);
+
+    let err = get_balance(&mut state, &account, &asset).unwrap_err();
+    assert!(matches!(err, CoreError::Decode("invalid balance length")));
+    let err = get_order(&mut state, None, &order_id).unwrap_err();
+    assert!(matches!(err, CoreError::Decode("invalid order length")));
+
+    let order = Order {
+        owner: account,
+        market_id: MarketId([3u8; 32]),
+        side: Side::Buy,
+        tick: 1,
+        qty_remaining: U256::one(),
+        tif: TimeInForce::Gtc,
+        status: OrderStatus::Open,
+    };
+    assert_eq!(order.encode().len(), ORDER_LEN);
+    assert_eq!(Balance::empty().encode().len(), BALANCE_LEN);
+}
+
+#[test]
+fn empty_hash_ladder_matches_fresh_tree() {
+    let tree = SparseMerkleTree::new();
+    let proof = tree.prove(keccak256(b"any-key"));
+    assert_eq!(empty_hash(256), leaf_hash_absent());
+    for depth in (0..256).rev() {
+        assert_eq!(empty_hash(depth + 1), proof.siblings[depth], "depth {}", depth + 1);
+    }
+    assert_eq!(empty_hash(0), tree.root());
+
+    let salted = SparseMerkleTree::<SaltedHasher>::with_hasher();
+    let proof = salted.prove(keccak256(b"any-key"));
+    assert_eq!(empty_hash_with::<SaltedHasher>(0), salted.root());
+    assert_eq!(empty_hash_with::<SaltedHasher>(128), proof.siblings[127]);
+    assert_ne!(empty_hash_with::<SaltedHasher>(0), empty_hash(0));
+}
+
+#[test]
+fn empty_root_constant_matches_fresh_tree() {
+    assert_eq!(EMPTY_ROOT, SparseMerkleTree::new().root());
+    assert_eq!(EMPTY_ROOT, empty_hash(0));
+}
+
+fn prefix_test_keys() -> Vec<[u8; 32]> {
+    vec![[0x00u8; 32], [0xffu8; 32], [0xaau8; 32], [0x55u8; 32], keccak256(b"prefix-1"), keccak256(b"prefix-2")]
+}
+
+fn flip_bit(key: &[u8; 32], depth: u16) -> [u8; 32] {
+    let mut out = *key;
+    out[(depth / 8) as usize] ^= 0x80 >> (depth % 8);
+    out
+}
+
+#[test]
+fn prefix_helpers_mask_exactly_at_every_depth() {
+    for key in prefix_test_keys() {
+        for bits in 0..=256u16 {
+            let prefix = prefix_with_len(&key, bits);
+            for depth in 0..256u16 {
+                let expected = if depth < bits { get_bit(&key, depth) } else { 0 };
+                assert_eq!(get_bit(&prefix, depth), expected, "bits {bits} depth {depth}");
+            }
+        }
+        for depth in 0..256u16 {
+            for bit in [0u8, 1] {
+                let extended = extend_prefix(&key, depth, bit);
+                for d in 0..256u16 {
+                    let expected = match d.cmp(&depth) {
+                        core::cmp::Ordering::Less => get_bit(&key, d),
+                        core::cmp::Ordering::Equal => bit,
+                        core::cmp::Ordering::Greater => 0,
+                    };
+                    assert_eq!(get_bit(&extended, d), expected, "depth {depth} bit {bit} at {d}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn proofs_round_trip_for_keys_diverging_at_every_depth() {
+    for key in [[0xffu8; 32], keccak256(b"prefix-1")] {
+        let mut tree = SparseMerkleTree::new();
+        tree.update(key, Some(b"base".to_vec()));
+        let stored = |depth: u16| depth % 37 == 0 || depth % 8 == 7;
+        for depth in (0..256u16).filter(|d| stored(*d)) {
+            tree.update(flip_bit(&key, depth), Some(depth.to_be_bytes().to_vec()));
+        }
+        let root = tree.root();
+        for depth in 0..256u16 {
+            let neighbor = flip_bit(&key, depth);
+            let proof = tree.prove(neighbor);
+            assert_eq!(proof.present, stored(depth), "depth {depth}");
+            verify_proof(&root, &proof).unwrap_or_else(|e| panic!("depth {depth}: {e}"));
+        }
+        verify_proof(&root, &tree.prove(key)).expect("base key");
+    }
+}
+
+// Pairs of keys that agree on every bit except one next to a byte boundary, so the subtree
+// scan in `recompute_root` has to mask exactly at full and partial bytes.
+#[test]
+fn keys_diverging_at_byte_boundaries_prove_and_recompute() {
+    for base in [[0x00u8; 32], [0xffu8; 32], keccak256(b"boundary")] {
+        for depth in [7u16, 8, 9, 15, 16, 17] {
+            let twin = flip_bit(&base, depth);
+            let mut tree = SparseMerkleTree::new();
+            tree.update(base, Some(b"base".to_vec()));
+            tree.update(twin, Some(b"twin".to_vec()));
+            let root = tree.root();
+            assert_eq!(root, tree.recompute_root(), "depth {depth}");
+
+            for (key, value) in [(base, b"base"), (twin, b"twin")] {
+                let proof = tree.prove(key);
+                assert!(proof.present, "depth {depth}");
+                assert_eq!(proof.value, value.to_vec(), "depth {depth}");
+                verify_proof(&root, &proof).unwrap_or_else(|e| panic!("depth {depth}: {e}"));
+            }
+            // The sibling one bit further down is empty, so its absence proof must also hold.
+            let absent = flip_bit(&base, depth + 1);
+            verify_proof(&root, &tree.prove(absent)).unwrap_or_else(|e| panic!("depth {depth}: {e}"));
+
+            let removed = apply_proof(&root, &tree.prove(twin), None).expect("remove twin");
+            let mut alone = SparseMerkleTree::new();
+            alone.update(base, Some(b"base".to_vec()));
+            assert_eq!(removed, alone.root(), "depth {depth}");
+        }
+    }
+}
+
+#[test]
+fn cached_root_matches_recompute_over_random_updates() {
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+    let base = keccak256(b"fuzz-base");
+    let pool: Vec<[u8; 32]> = (0..24u64)
+        .map(|i| match i % 3 {
+            0 => keccak256(&i.to_be_bytes()),
+            _ => flip_bit(&base, (next() % 256) as u16),
+        })
+        .collect();
+
+    let mut tree = SparseMerkleTree::new();
+    for step in 0..300 {
+        let key = pool[(next() % pool.len() as u64) as usize];
+        let value = match next() % 4 {
+            0 => None,
+            1 => Some(Vec::new()),
+            _ => Some(next().to_be_bytes().to_vec()),
+        };
+        tree.update(key, value);
+        assert_eq!(tree.root(), tree.recompute_root(), "step {step}");
+    }
+    let root = tree.root();
+    for key in &pool {
+        verify_proof(&root, &tree.prove(*key)).expect("cached proof");
+    }
+
+    for key in &pool {
+        tree.update(*key, None);
+    }
+    assert_eq!(tree.root(), empty_hash(0));
+    assert_eq!(tree.recompute_root(), empty_hash(0));
+}
+
+#[test]
+fn db_state_roots_match_in_memory_tree() {
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+    let base = keccak256(b"db-base");
+    let pool: Vec<[u8; 32]> = (0..16u64)
+        .map(|i| match i % 2 {
+            0 => keccak256(&i.to_be_bytes()),
+            _ => flip_bit(&base, (next() % 256) as u16),
+        })
+        .collect();
+
+    let mut tree = SparseMerkleTree::new();
+    let mut db = DbState::open(BTreeMap::new()).expect("open empty store");
+    assert_eq!(db.root(), tree.root());
+    for step in 0..120 {
+        let key = pool[(next() % pool.len() as u64) as usize];
+        let value = match next() % 4 {
+            0 => None,
+            1 => Some(Vec::new()),
+            _ => Some(next().to_be_bytes().to_vec()),
+        };
+        tree.update(key, value.clone());
+        db.write_value(key, value).expect("write");
+        assert_eq!(db.root(), tree.root(), "step {step}");
+    }
+    for key in &pool {
+        let proof = db.prove(*key).expect("prove");
+        assert_eq!(proof.siblings, tree.prove(*key).siblings);
+        verify_proof(&tree.root(), &proof).expect("db proof verifies against the tree root");
+    }
+
+    // A rolled-back write leaves the store as it was, and a reopened store keeps its root.
+    let checkpoint = db.checkpoint();
+    db.write_value(pool[0], Some(vec![0xee])).expect("write");
+    db.rollback(checkpoint).expect("rollback");
+    assert_eq!(db.root(), tree.root());
+    db.commit();
+    let reopened = DbState::open(db.into_store()).expect("reopen");
+    assert_eq!(reopened.root(), tree.root());
+    assert_eq!(reopened.get(pool[1]).expect("get"), tree.get(pool[1]));
+}
@@ -0,0 +1,44 @@
+use clob_core::errors::CoreError;
+use clob_core::input::RulesBuilder;
+use clob_core::types::{AllocationMode, AssetId, U256};
+
+#[test]
+fn builder_defaults_produce_valid_rules() {
+    let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).build().expect("default rules");
+    rules.validate().expect("defaults pass validation");
+    assert_eq!(rules.price_scale, U256::from(1_000_000_000_000_000_000u128));
+    assert_eq!(rules.lot_size, U256::one());
+    assert_eq!(rules.maker_fee_bps, 0);
+    assert_eq!(rules.max_orders_per_batch, 128);
+    assert_eq!(rules.max_matches_per_order, 64);
+    assert_eq!(rules.max_fills_per_batch, 1024);
+    assert_eq!(rules.allocation_mode, AllocationMode::Fifo);
+    assert_eq!(rules.base_decimals, 18);
+    assert_eq!(rules.quote_decimals, 18);
+}
+
+#[test]
+fn builder_rejects_invalid_rules() {
+    let builder = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32]));
+
+    let err = builder.clone().price_scale(U256::from(1_000_000u64)).build().unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("priceScale must be 1e18")));
+
+    let err = builder.clone().maker_fee_bps(5).build().unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("makerFeeBps must be zero")));
+
+    let err = builder.clone().quote_decimals(78).build().unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("decimals exceed 77")));
+
+    let err = builder.clone().max_balance(U256::zero()).build().unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("max balance zero")));
+
+    let err = builder.clone().taker_fee_bps(10).taker_fee_tier(U256::from(100u64), 20).build().unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("takerFeeBpsTier2 exceeds takerFeeBps")));
+
+    let err = builder.clone().tick_spacing(0).build().unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("tick spacing not positive")));
+
+    let err = builder.lot_size(U256::zero()).build().unwrap_err();
+    assert!(matches!(err, CoreError::Invalid("lot size zero")));
+}
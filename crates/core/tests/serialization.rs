@@ -1,12 +1,29 @@
-use clob_core::input::{Message, Rules};
-use clob_core::types::{Side, TimeInForce, U256};
-use clob_core::verify::{batch_digest, domain_separator, message_hash, rules_hash};
+use clob_core::constants::{MAX_MESSAGES_PER_BUNDLE, MAX_VALUE_LEN, ZERO32};
+use clob_core::encoding::{Reader, Writer};
+use clob_core::errors::CoreError;
+use clob_core::hash::{keccak256, DigestKind};
+use clob_core::input::{
+    GuestBundle, GuestInput, Message, MessageSignature, PublicInputsPartial, Rules, SignedMessage,
+};
+use clob_core::engine::BatchOutput;
+use clob_core::merkle::SparseMerkleTree;
+use clob_core::outputs::merkle_root;
+use clob_core::program::run_bundle;
+use clob_core::types::{
+    Address, AllocationMode, AssetId, FailureMode, MarketId, OrderId, OrderIdScope, Side, TimeInForce, TradeRecord,
+    U256,
+};
+use clob_core::verify::{
+    batch_digest, domain_separator, message_hash, recover_address, recover_addresses, rules_hash, Domain,
+};
+
+use k256::ecdsa::SigningKey;
 
 #[test]
 fn rules_hash_stable() {
     let rules = Rules {
-        base_asset_id: [1u8; 32],
-        quote_asset_id: [2u8; 32],
+        base_asset_id: AssetId([1u8; 32]),
+        quote_asset_id: AssetId([2u8; 32]),
         price_scale: U256::from(1_000_000_000_000_000_000u128),
         tick_size: U256::from(1_000_000_000_000_000_000u128),
         lot_size: U256::from(1u64),
@@ -14,7 +31,26 @@ fn rules_hash_stable() {
         maker_fee_bps: 0,
         max_orders_per_batch: 128,
         max_matches_per_order: 64,
+        max_fills_per_batch: 1024,
+        allocation_mode: AllocationMode::Fifo,
+        min_notional: U256::zero(),
         max_balance: U256::from(1_000_000u64),
+        fee_collector: Address::ZERO,
+        fee_in_received_asset: false,
+        enforce_derived_order_id: false,
+        admin: Address::ZERO,
+        max_price_deviation_bps: 0,
+        base_decimals: 18,
+        quote_decimals: 18,
+        commit_trade_roots: true,
+        max_open_orders_per_trader: 0,
+        max_book_steps_per_order: 0,
+        tick_spacing: 1,
+        taker_fee_tier_notional: U256::zero(),
+        taker_fee_bps_tier2: 0,
+        order_id_scope: OrderIdScope::Market,
+        bind_batch_seq: false,
+        failure_mode: FailureMode::Abort,
     };
     let h1 = rules_hash(&rules);
     let h2 = rules_hash(&rules);
@@ -23,16 +59,16 @@ fn rules_hash_stable() {
 
 #[test]
 fn batch_digest_changes_with_order() {
-    let domain = domain_separator(1, &[3u8; 32], &[4u8; 32]);
+    let domain = domain_separator(1, &[3u8; 32], &MarketId([4u8; 32]), &[0u8; 32]);
     let msg1 = Message::Cancel {
-        trader: [9u8; 20],
+        trader: Address([9u8; 20]),
         nonce: 1,
-        order_id: [7u8; 32],
+        order_id: OrderId([7u8; 32]),
     };
     let msg2 = Message::Cancel {
-        trader: [8u8; 20],
+        trader: Address([8u8; 20]),
         nonce: 2,
-        order_id: [6u8; 32],
+        order_id: OrderId([6u8; 32]),
     };
     let h1 = message_hash(&domain, &msg1);
     let h2 = message_hash(&domain, &msg2);
@@ -41,32 +77,471 @@ fn batch_digest_changes_with_order() {
     assert_ne!(a, b);
 }
 
+#[test]
+fn domain_hashing_matches_free_functions() {
+    let domain = Domain::new(8453, [3u8; 32], MarketId([4u8; 32]), [5u8; 32]);
+    let separator = domain_separator(8453, &[3u8; 32], &MarketId([4u8; 32]), &[5u8; 32]);
+    assert_eq!(domain.separator, separator);
+
+    let msg = Message::Cancel {
+        trader: Address([9u8; 20]),
+        nonce: 1,
+        order_id: OrderId([7u8; 32]),
+    };
+    let hash = domain.message_hash(&msg);
+    assert_eq!(hash, message_hash(&separator, &msg));
+    assert_eq!(domain.batch_digest(2, &[hash]), batch_digest(&separator, 2, &[hash]));
+}
+
 #[test]
 fn message_hash_distinct() {
-    let domain = domain_separator(1, &[3u8; 32], &[4u8; 32]);
+    let domain = domain_separator(1, &[3u8; 32], &MarketId([4u8; 32]), &[0u8; 32]);
     let msg1 = Message::Place {
-        trader: [9u8; 20],
+        trader: Address([9u8; 20]),
         nonce: 1,
-        order_id: [7u8; 32],
+        order_id: OrderId([7u8; 32]),
         side: Side::Buy,
         tif: TimeInForce::Gtc,
         tick_index: 1,
         qty_base: U256::from(1u64),
         prev_tick_hint: 0,
         next_tick_hint: 0,
+        reduce_only: false,
+        quote_budget: false,
     };
     let msg2 = Message::Place {
-        trader: [9u8; 20],
+        trader: Address([9u8; 20]),
         nonce: 1,
-        order_id: [7u8; 32],
+        order_id: OrderId([7u8; 32]),
         side: Side::Sell,
         tif: TimeInForce::Gtc,
         tick_index: 1,
         qty_base: U256::from(1u64),
         prev_tick_hint: 0,
         next_tick_hint: 0,
+        reduce_only: false,
+        quote_budget: false,
     };
     let h1 = message_hash(&domain, &msg1);
     let h2 = message_hash(&domain, &msg2);
     assert_ne!(h1, h2);
 }
+
+fn empty_bundle() -> GuestBundle {
+    GuestBundle {
+        input: GuestInput {
+            public: PublicInputsPartial {
+                prev_root: [0u8; 32],
+                batch_digest: [0u8; 32],
+                rules_hash: [0u8; 32],
+                domain_separator: [0u8; 32],
+                batch_seq: 1,
+                batch_timestamp: 0,
+                batch_deadline: 0,
+                da_commitment: [0u8; 32],
+            },
+            chain_id: 1,
+            venue_id: [3u8; 32],
+            market_id: MarketId([4u8; 32]),
+            instance_salt: [0u8; 32],
+            rules: Rules {
+                base_asset_id: AssetId([1u8; 32]),
+                quote_asset_id: AssetId([2u8; 32]),
+                price_scale: U256::from(1_000_000_000_000_000_000u128),
+                tick_size: U256::from(1_000_000_000_000_000_000u128),
+                lot_size: U256::from(1u64),
+                taker_fee_bps: 10,
+                maker_fee_bps: 0,
+                max_orders_per_batch: 128,
+                max_matches_per_order: 64,
+                max_fills_per_batch: 1024,
+                allocation_mode: AllocationMode::Fifo,
+                min_notional: U256::zero(),
+                max_balance: U256::from(1_000_000u64),
+                fee_collector: Address::ZERO,
+                fee_in_received_asset: false,
+                enforce_derived_order_id: false,
+                admin: Address::ZERO,
+                max_price_deviation_bps: 0,
+                base_decimals: 18,
+                quote_decimals: 18,
+                commit_trade_roots: true,
+                max_open_orders_per_trader: 0,
+                max_book_steps_per_order: 0,
+                tick_spacing: 1,
+                taker_fee_tier_notional: U256::zero(),
+                taker_fee_bps_tier2: 0,
+                order_id_scope: OrderIdScope::Market,
+                bind_batch_seq: false,
+                failure_mode: FailureMode::Abort,
+            },
+            commit_rules: false,
+            touched_digest: DigestKind::Keccak256,
+            messages: Vec::new(),
+        },
+        proofs: Vec::new(),
+    }
+}
+
+#[test]
+fn bundle_rejects_oversized_proof_value() {
+    let bundle = empty_bundle();
+    let mut w = Writer::new();
+    w.write_raw(&bundle.input.encode().unwrap());
+    w.write_u32(1);
+    w.write_b32(&[5u8; 32]);
+    w.write_u8(1);
+    w.write_u32(1_000_000);
+    let bytes = w.into_bytes();
+
+    let err = GuestBundle::decode(&mut Reader::new(&bytes)).expect_err("oversized value");
+    assert!(matches!(err, CoreError::Decode("value too large")));
+}
+
+#[test]
+fn bundle_rejects_proof_count_over_limit() {
+    let bundle = empty_bundle();
+    let mut w = Writer::new();
+    w.write_raw(&bundle.input.encode().unwrap());
+    w.write_u32(u32::MAX);
+    let bytes = w.into_bytes();
+
+    let err = GuestBundle::decode(&mut Reader::new(&bytes)).expect_err("oversized proof count");
+    assert!(matches!(err, CoreError::Decode("too many proofs")));
+
+    let mut bundle = empty_bundle();
+    bundle.proofs = vec![clob_core::merkle::Proof::new([5u8; 32], Vec::new(), false, vec![[0u8; 32]; 256]); 2];
+    let bytes = bundle.encode().unwrap();
+    GuestBundle::decode_with_limits(&mut Reader::new(&bytes), MAX_VALUE_LEN, 2).expect("count at limit");
+    let err = GuestBundle::decode_with_limits(&mut Reader::new(&bytes), MAX_VALUE_LEN, 1)
+        .expect_err("limit is configurable");
+    assert!(matches!(err, CoreError::Decode("too many proofs")));
+}
+
+#[test]
+fn input_rejects_message_count_over_limit() {
+    let mut bytes = empty_bundle().input.encode().unwrap();
+    let count_at = bytes.len() - 4;
+    assert_eq!(bytes[count_at..], [0u8; 4]);
+
+    bytes[count_at..].copy_from_slice(&u32::MAX.to_be_bytes());
+    let err = GuestInput::decode(&mut Reader::new(&bytes)).expect_err("oversized message count");
+    assert!(matches!(err, CoreError::Decode("too many messages")));
+
+    bytes[count_at..].copy_from_slice(&(MAX_MESSAGES_PER_BUNDLE as u32).to_be_bytes());
+    let err = GuestInput::decode(&mut Reader::new(&bytes)).expect_err("count at limit reads messages");
+    assert!(matches!(err, CoreError::Decode(msg) if msg != "too many messages"));
+}
+
+#[test]
+fn bundle_accepts_value_at_limit() {
+    let mut bundle = empty_bundle();
+    bundle.proofs.push(clob_core::merkle::Proof::new(
+        [5u8; 32],
+        vec![7u8; MAX_VALUE_LEN],
+        true,
+        vec![[0u8; 32]; 256],
+    ));
+    let bytes = bundle.encode().unwrap();
+    let decoded = GuestBundle::decode(&mut Reader::new(&bytes)).expect("decode");
+    assert_eq!(decoded.proofs[0].value.len(), MAX_VALUE_LEN);
+
+    let err = GuestBundle::decode_with_max_value_len(&mut Reader::new(&bytes), MAX_VALUE_LEN - 1)
+        .expect_err("limit is configurable");
+    assert!(matches!(err, CoreError::Decode("value too large")));
+}
+
+#[test]
+fn bundle_round_trips_force_cancel() {
+    let mut bundle = empty_bundle();
+    bundle.input.rules.admin = Address([0x44u8; 20]);
+    bundle.input.messages.push(SignedMessage {
+        message: Message::ForceCancel {
+            operator: Address([0x44u8; 20]),
+            nonce: 3,
+            order_id: OrderId([7u8; 32]),
+        },
+        signature: MessageSignature { r: [1u8; 32], s: [2u8; 32], v: 27 },
+    });
+    let bytes = bundle.encode().unwrap();
+    let decoded = GuestBundle::decode(&mut Reader::new(&bytes)).expect("decode");
+    assert_eq!(decoded.input.rules.admin, Address([0x44u8; 20]));
+    match &decoded.input.messages[0].message {
+        Message::ForceCancel { operator, nonce, order_id } => {
+            assert_eq!(*operator, Address([0x44u8; 20]));
+            assert_eq!(*nonce, 3);
+            assert_eq!(*order_id, OrderId([7u8; 32]));
+        }
+        other => panic!("unexpected message {other:?}"),
+    }
+    assert_eq!(decoded.encode().unwrap(), bytes);
+}
+
+#[test]
+fn empty_batch_commits_a_no_op() {
+    let mut tree = SparseMerkleTree::new();
+    tree.update([5u8; 32], Some(vec![1u8; 32]));
+    let mut bundle = empty_bundle();
+    let input = &mut bundle.input;
+    let domain = domain_separator(input.chain_id, &input.venue_id, &input.market_id, &input.instance_salt);
+    input.public.prev_root = tree.root();
+    input.public.domain_separator = domain;
+    input.public.rules_hash = rules_hash(&input.rules);
+    input.public.batch_digest = batch_digest(&domain, input.public.batch_seq, &[]);
+
+    let output = run_bundle(bundle.clone()).expect("empty batch");
+    assert_eq!(output.public.new_root, output.public.prev_root);
+    assert_eq!(output.public.trades_root, ZERO32);
+    assert_eq!(output.public.fees_root, ZERO32);
+
+    bundle.proofs.push(tree.prove([5u8; 32]));
+    let err = run_bundle(bundle).err().expect("an empty batch consumes no proofs");
+    assert!(matches!(err, CoreError::Proof { expected: 0, got: 1 }));
+}
+
+#[test]
+fn batch_past_deadline_is_rejected() {
+    let mut bundle = empty_bundle();
+    let input = &mut bundle.input;
+    let domain = domain_separator(input.chain_id, &input.venue_id, &input.market_id, &input.instance_salt);
+    input.public.prev_root = SparseMerkleTree::new().root();
+    input.public.domain_separator = domain;
+    input.public.rules_hash = rules_hash(&input.rules);
+    input.public.batch_digest = batch_digest(&domain, input.public.batch_seq, &[]);
+    input.public.batch_timestamp = 1_000;
+    input.public.batch_deadline = 1_000;
+
+    let bytes = bundle.encode().unwrap();
+    let decoded = GuestBundle::decode(&mut Reader::new(&bytes)).expect("decode");
+    assert_eq!(decoded.input.public.batch_deadline, 1_000);
+    let output = run_bundle(decoded).expect("timestamp at the deadline");
+    // The deadline is committed right after the timestamp, so a verifier sees the bound it was proven under.
+    assert_eq!(output.public.batch_deadline, 1_000);
+    let committed = output.public_values();
+    assert_eq!(committed[5 * 32 + 16..5 * 32 + 24], 1_000u64.to_be_bytes());
+
+    bundle.input.public.batch_timestamp = 1_001;
+    let err = run_bundle(bundle.clone()).expect_err("stale batch");
+    assert!(matches!(err, CoreError::Invalid("batch past deadline")));
+
+    bundle.input.public.batch_deadline = 0;
+    run_bundle(bundle).expect("zero deadline never expires");
+}
+
+#[test]
+fn committed_rules_decode_to_input_rules() {
+    let mut bundle = empty_bundle();
+    let input = &mut bundle.input;
+    let domain = domain_separator(input.chain_id, &input.venue_id, &input.market_id, &input.instance_salt);
+    input.public.domain_separator = domain;
+    input.public.rules_hash = rules_hash(&input.rules);
+    input.public.batch_digest = batch_digest(&domain, input.public.batch_seq, &[]);
+    input.public.prev_root = SparseMerkleTree::new().root();
+
+    let plain = run_bundle(bundle.clone()).expect("rules not committed");
+    assert!(plain.committed_rules.is_none());
+    assert_eq!(plain.public_values(), plain.public.encode());
+
+    bundle.input.commit_rules = true;
+    let bytes = bundle.encode().unwrap();
+    let decoded = GuestBundle::decode(&mut Reader::new(&bytes)).expect("decode");
+    assert!(decoded.input.commit_rules);
+    let output = run_bundle(decoded).expect("rules committed");
+    let values = output.public_values();
+    let public_len = output.public.encode().len();
+    assert_eq!(values[..public_len], output.public.encode()[..]);
+
+    let mut reader = Reader::new(&values[public_len..]);
+    let rules = Rules::decode(&mut reader).expect("committed rules decode");
+    reader.expect_finished().expect("rules end the stream");
+    assert_eq!(rules.encode(), bundle.input.rules.encode());
+    assert_eq!(rules.taker_fee_bps, 10);
+    assert_eq!(rules.tick_size, bundle.input.rules.tick_size);
+    assert_eq!(rules_hash(&rules), output.public.rules_hash);
+}
+
+#[test]
+fn trades_feed_roundtrip() {
+    let trade = |n: u8| TradeRecord {
+        market_id: MarketId([n; 32]),
+        trade_seq: n as u64,
+        maker_order_id: OrderId([n + 1; 32]),
+        taker_order_id: OrderId([n + 2; 32]),
+        maker: Address([n + 3; 20]),
+        taker: Address([n + 4; 20]),
+        side_taker: if n == 2 { Side::Sell } else { Side::Buy },
+        maker_tick: -(n as i32),
+        qty_base: U256::from(n as u64 * 10),
+        quote_amt: U256::from(n as u64 * 100),
+        taker_fee: U256::from(n as u64),
+    };
+    let output = BatchOutput {
+        trades: vec![trade(1), trade(2), trade(3)],
+        fee_totals: Vec::new(),
+        outcomes: Vec::new(),
+    };
+    let feed = output.encode_trades_feed();
+    assert_eq!(&feed[..4], &3u32.to_be_bytes());
+    let decoded = BatchOutput::decode_trades_feed(&feed).expect("decode feed");
+    assert_eq!(decoded, output.trades);
+
+    let empty = BatchOutput {
+        trades: Vec::new(),
+        fee_totals: Vec::new(),
+        outcomes: Vec::new(),
+    };
+    assert!(BatchOutput::decode_trades_feed(&empty.encode_trades_feed()).unwrap().is_empty());
+
+    let mut truncated = feed.clone();
+    truncated.pop();
+    assert!(BatchOutput::decode_trades_feed(&truncated).is_err());
+}
+
+#[test]
+fn compact_signature_recovers_same_address() {
+    let key = SigningKey::from_slice(&[0x11u8; 32]).unwrap();
+    let mut parities = [false; 2];
+    for i in 0u8..16 {
+        let hash = keccak256(&[i]);
+        let (sig, recid) = key.sign_prehash_recoverable(&hash).expect("sign");
+        let sig_bytes = sig.to_bytes();
+        let standard = MessageSignature {
+            r: sig_bytes[..32].try_into().unwrap(),
+            s: sig_bytes[32..].try_into().unwrap(),
+            v: recid.to_byte() + 27,
+        };
+        parities[recid.to_byte() as usize] = true;
+
+        let compact = standard.encode_compact().expect("low-s signature");
+        let expanded = MessageSignature::decode(&compact).expect("decode compact");
+        assert_eq!(expanded.encode().unwrap(), standard.encode().unwrap());
+        assert_eq!(
+            recover_address(&hash, &expanded).unwrap(),
+            recover_address(&hash, &standard).unwrap()
+        );
+    }
+    assert_eq!(parities, [true, true]);
+
+    assert!(MessageSignature::decode(&[0u8; 63]).is_err());
+}
+
+#[test]
+fn signature_v_has_one_wire_form() {
+    let key = SigningKey::from_slice(&[0x11u8; 32]).unwrap();
+    let hash = keccak256(b"canonical-v");
+    let (sig, recid) = key.sign_prehash_recoverable(&hash).expect("sign");
+    let sig_bytes = sig.to_bytes();
+    let legacy = MessageSignature {
+        r: sig_bytes[..32].try_into().unwrap(),
+        s: sig_bytes[32..].try_into().unwrap(),
+        v: recid.to_byte() + 27,
+    };
+    let canonical = MessageSignature {
+        v: recid.to_byte(),
+        ..legacy.clone()
+    };
+    assert_eq!(legacy.encode().unwrap(), canonical.encode().unwrap());
+    assert_eq!(legacy.encode().unwrap()[64], recid.to_byte());
+    assert_eq!(
+        recover_address(&hash, &legacy).unwrap(),
+        recover_address(&hash, &canonical).unwrap()
+    );
+
+    let wire = canonical.encode().unwrap();
+    assert_eq!(MessageSignature::decode(&wire).unwrap().v, recid.to_byte());
+    let mut legacy_wire = wire;
+    legacy_wire[64] += 27;
+    assert!(matches!(
+        MessageSignature::decode(&legacy_wire),
+        Err(CoreError::Decode("non-canonical v"))
+    ));
+    assert_eq!(MessageSignature::decode_relaxed(&legacy_wire).unwrap().encode().unwrap(), wire);
+    legacy_wire[64] = 29;
+    assert!(MessageSignature::decode_relaxed(&legacy_wire).is_err());
+
+    // A `v` with no parity has no wire form, so encoding fails instead of writing it through.
+    let unencodable = MessageSignature { v: 29, ..canonical };
+    assert!(matches!(unencodable.encode(), Err(CoreError::Signature("invalid v"))));
+}
+
+#[test]
+fn address_hex_round_trip() {
+    let addr = Address::from_hex("0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf").expect("parse");
+    assert_eq!(addr.to_hex(), "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+    assert_eq!(Address::from_hex(&addr.to_hex()[2..]).unwrap(), addr);
+
+    let mut w = Writer::new();
+    w.write_addr(&addr);
+    let bytes = w.into_bytes();
+    assert_eq!(bytes, addr.as_bytes().to_vec());
+    assert_eq!(Reader::new(&bytes).read_addr().unwrap(), addr);
+
+    assert!(Address::from_hex("0x1234").is_err());
+    assert!(Address::from_hex("0x7e5f4552091a69125d5dfcb7b8c2659029395bdz").is_err());
+}
+
+#[test]
+fn reader_rejects_oversized_lengths() {
+    let bytes = [0xffu8; 8];
+    let mut reader = Reader::new(&bytes);
+    reader.read_u8().unwrap();
+    assert!(matches!(reader.read_exact(usize::MAX), Err(CoreError::Decode("length overflow"))));
+
+    let mut reader = Reader::new(&bytes);
+    assert!(matches!(reader.read_bytes(), Err(CoreError::Decode("unexpected EOF"))));
+}
+
+#[test]
+fn address_zero_detection() {
+    assert!(Address::ZERO.is_zero());
+    assert!(Address::default().is_zero());
+    assert!(Address::from_hex("0x0000000000000000000000000000000000000000").unwrap().is_zero());
+    assert!(!Address([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]).is_zero());
+}
+
+#[test]
+fn merkle_root_does_not_collide_on_duplicated_last_leaf() {
+    let leaves: Vec<[u8; 32]> = (0u8..5).map(|i| keccak256(&[i])).collect();
+    for n in 1..leaves.len() {
+        let odd = &leaves[..n];
+        let mut padded = odd.to_vec();
+        padded.push(odd[n - 1]);
+        assert_ne!(merkle_root(odd), merkle_root(&padded), "{n} leaves");
+    }
+    assert_ne!(merkle_root(&leaves[..1]), leaves[0]);
+    assert_eq!(merkle_root(&[]), ZERO32);
+}
+
+#[test]
+fn batch_recovery_matches_per_message_recovery() {
+    let key = SigningKey::from_slice(&[0x11u8; 32]).unwrap();
+    let mut hashes = Vec::new();
+    let mut sigs = Vec::new();
+    for i in 0u8..6 {
+        let hash = keccak256(&[i]);
+        let (sig, recid) = key.sign_prehash_recoverable(&hash).expect("sign");
+        let sig_bytes = sig.to_bytes();
+        let mut sig = MessageSignature {
+            r: sig_bytes[..32].try_into().unwrap(),
+            s: sig_bytes[32..].try_into().unwrap(),
+            v: recid.to_byte(),
+        };
+        match i {
+            1 => sig.v = 5,
+            3 => sig.r = [0u8; 32],
+            4 => sig.s[31] ^= 1,
+            _ => {}
+        }
+        hashes.push(hash);
+        sigs.push(sig);
+    }
+
+    let batch = recover_addresses(&hashes, &sigs);
+    assert_eq!(batch.len(), hashes.len());
+    for ((hash, sig), batched) in hashes.iter().zip(&sigs).zip(&batch) {
+        let single = recover_address(hash, sig);
+        assert_eq!(format!("{batched:?}"), format!("{single:?}"));
+    }
+    assert!(batch[0].is_ok() && batch[2].is_ok() && batch[5].is_ok());
+    assert!(batch[1].is_err() && batch[3].is_err());
+}
@@ -0,0 +1,313 @@
+// Operator daemon: keeps one market's tree in memory and serves it over line-delimited JSON.
+// Each request line is `{"id": .., "method": .., "params": {..}}` and gets exactly one response
+// line echoing `id` with either `result` or `error`.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use clob_core::encoding::Reader;
+use clob_core::engine::apply_batch_at_root;
+use clob_core::hash::DigestKind;
+use clob_core::input::{GuestBundle, GuestInput, PublicInputsPartial, Rules, SignedMessage};
+use clob_core::merkle::SparseMerkleTree;
+use clob_core::parse::{parse_addr, parse_b32, parse_hex, parse_u256};
+use clob_core::program::run_bundle;
+use clob_core::state::{key_balance, RecordingState};
+use clob_core::types::{AssetId, Balance, MarketId, U256};
+use clob_core::verify::{check_batch_deadline, rules_hash, Domain};
+
+pub struct MarketConfig {
+    pub domain: Domain,
+    pub rules: Rules,
+}
+
+pub struct Daemon {
+    config: MarketConfig,
+    tree: SparseMerkleTree,
+    // One bundle per accepted batch; a batch's `batch_seq` is its index here.
+    bundles: Vec<GuestBundle>,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Response {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SubmitBatchParams {
+    // Each entry is a hex `SignedMessage::encode()`, the same bytes a `GuestInput` carries.
+    messages: Vec<String>,
+    #[serde(default)]
+    batch_timestamp: u64,
+    #[serde(default)]
+    batch_deadline: u64,
+    da_commitment: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GetBalanceParams {
+    account: String,
+    asset: String,
+}
+
+#[derive(Deserialize)]
+struct ExecuteBatchParams {
+    batch_seq: u64,
+}
+
+#[derive(Deserialize)]
+pub struct ConfigFile {
+    pub chain_id: u64,
+    pub venue_id: String,
+    pub market_id: String,
+    pub instance_salt: Option<String>,
+    // Hex `Rules::encode()`.
+    pub rules: String,
+    #[serde(default)]
+    pub balances: Vec<BalanceJson>,
+}
+
+#[derive(Deserialize)]
+pub struct BalanceJson {
+    pub account: String,
+    pub asset: String,
+    pub available: String,
+    pub locked: String,
+}
+
+impl ConfigFile {
+    // Builds the daemon over a genesis tree holding only the listed balances.
+    pub fn into_daemon(self) -> Result<Daemon, String> {
+        let rules_bytes = parse_hex(&self.rules).map_err(|e| format!("rules: {e}"))?;
+        let mut reader = Reader::new(&rules_bytes);
+        let rules = Rules::decode(&mut reader).map_err(|e| format!("rules: {e}"))?;
+        if reader.remaining() != 0 {
+            return Err("rules: trailing bytes".into());
+        }
+        rules.validate().map_err(|e| format!("rules: {e}"))?;
+        let instance_salt = match &self.instance_salt {
+            Some(salt) => parse_b32(salt).map_err(|e| format!("instance_salt: {e}"))?,
+            None => [0u8; 32],
+        };
+        let domain = Domain::new(
+            self.chain_id,
+            parse_b32(&self.venue_id).map_err(|e| format!("venue_id: {e}"))?,
+            parse_b32(&self.market_id).map(MarketId).map_err(|e| format!("market_id: {e}"))?,
+            instance_salt,
+        );
+        let config = MarketConfig { domain, rules };
+        let mut tree = SparseMerkleTree::new();
+        for bal in &self.balances {
+            let balance = Balance {
+                available: parse_u256(&bal.available)?,
+                locked: parse_u256(&bal.locked)?,
+            };
+            let key = key_balance(&parse_addr(&bal.account)?, &parse_b32(&bal.asset).map(AssetId)?);
+            tree.update(key, Some(balance.encode().to_vec()));
+        }
+        Ok(Daemon::new(config, tree))
+    }
+}
+
+impl Daemon {
+    pub fn new(config: MarketConfig, tree: SparseMerkleTree) -> Self {
+        Self {
+            config,
+            tree,
+            bundles: Vec::new(),
+        }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    // Answers one request line; malformed requests still get an error response.
+    pub fn handle_line(&mut self, line: &str) -> String {
+        let response = match serde_json::from_str::<Request>(line) {
+            Ok(request) => {
+                let outcome = self.dispatch(&request.method, request.params);
+                Response {
+                    id: request.id,
+                    result: outcome.as_ref().ok().cloned(),
+                    error: outcome.err(),
+                }
+            }
+            Err(err) => Response {
+                id: Value::Null,
+                result: None,
+                error: Some(format!("parse request: {err}")),
+            },
+        };
+        serde_json::to_string(&response).expect("response serializes")
+    }
+
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, String> {
+        match method {
+            "submit_batch" => self.submit_batch(parse_params(params)?),
+            "get_root" => Ok(json!({ "root": hex32(&self.root()) })),
+            "get_balance" => self.get_balance(parse_params(params)?),
+            // `prove_batch` is the original name and stays as an alias for existing clients.
+            "execute_batch" | "prove_batch" => self.execute_batch(parse_params(params)?),
+            other => Err(format!("unknown method {other:?}")),
+        }
+    }
+
+    // Applies the batch to a copy of the tree and keeps it only if every message succeeds, so a
+    // rejected batch leaves the root and the batch sequence untouched.
+    fn submit_batch(&mut self, params: SubmitBatchParams) -> Result<Value, String> {
+        let messages = params
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(index, hex)| decode_message(hex).map_err(|e| format!("messages[{index}]: {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        check_batch_deadline(params.batch_timestamp, params.batch_deadline)
+            .map_err(|e| format!("batch_timestamp: {e}"))?;
+        let da_commitment = match &params.da_commitment {
+            Some(commitment) => parse_b32(commitment).map_err(|e| format!("da_commitment: {e}"))?,
+            None => [0u8; 32],
+        };
+
+        let (domain, rules) = (&self.config.domain, &self.config.rules);
+        let prev_root = self.tree.root();
+        let mut state = RecordingState::new(self.tree.clone());
+        // Numbered from 1: `ClobVerifier` accepts `state.batchSeq + 1`, starting from zero.
+        let batch_seq = self.bundles.len() as u64 + 1;
+        let output =
+            apply_batch_at_root(&mut state, prev_root, domain.market_id, rules, domain.separator, batch_seq, &messages)
+                .map_err(|e| format!("apply batch: {e}"))?;
+
+        let msg_hashes: Vec<[u8; 32]> = messages.iter().map(|m| domain.message_hash(&m.message)).collect();
+        let bundle = GuestBundle {
+            input: GuestInput {
+                public: PublicInputsPartial {
+                    prev_root,
+                    batch_digest: domain.batch_digest(batch_seq, &msg_hashes),
+                    rules_hash: rules_hash(rules),
+                    domain_separator: domain.separator,
+                    batch_seq,
+                    batch_timestamp: params.batch_timestamp,
+                    batch_deadline: params.batch_deadline,
+                    da_commitment,
+                },
+                chain_id: domain.chain_id,
+                venue_id: domain.venue_id,
+                market_id: domain.market_id,
+                instance_salt: domain.instance_salt,
+                rules: rules.clone(),
+                commit_rules: false,
+                touched_digest: DigestKind::default(),
+                messages,
+            },
+            proofs: state.proofs,
+        };
+        self.tree = state.tree;
+        self.bundles.push(bundle);
+        Ok(json!({
+            "batch_seq": batch_seq,
+            "prev_root": hex32(&prev_root),
+            "new_root": hex32(&self.tree.root()),
+            "trades": output.trades.len(),
+        }))
+    }
+
+    fn get_balance(&self, params: GetBalanceParams) -> Result<Value, String> {
+        let account = parse_addr(&params.account).map_err(|e| format!("account: {e}"))?;
+        let asset = parse_b32(&params.asset).map(AssetId).map_err(|e| format!("asset: {e}"))?;
+        let balance = match self.tree.get(key_balance(&account, &asset)) {
+            Some(bytes) => Balance::decode(&bytes).map_err(|e| format!("balance: {e}"))?,
+            None => Balance::empty(),
+        };
+        Ok(json!({
+            "available": u256_hex(balance.available),
+            "locked": u256_hex(balance.locked),
+        }))
+    }
+
+    // Runs the guest program natively over the stored bundle; no SP1 proof is produced. The bundle
+    // is exactly what the prover takes as stdin, and the public values are what its proof will commit.
+    fn execute_batch(&self, params: ExecuteBatchParams) -> Result<Value, String> {
+        let bundle = params
+            .batch_seq
+            .checked_sub(1)
+            .and_then(|index| self.bundles.get(index as usize))
+            .ok_or_else(|| format!("unknown batch {}", params.batch_seq))?;
+        let output = run_bundle(bundle.clone()).map_err(|e| format!("execute bundle: {e}"))?;
+        let bytes = bundle.encode().map_err(|e| format!("encode bundle: {e}"))?;
+        Ok(json!({
+            "bundle": format!("0x{}", hex::encode(bytes)),
+            "public_values": format!("0x{}", hex::encode(output.public_values())),
+            "new_root": hex32(&output.public.new_root),
+        }))
+    }
+}
+
+// Accepts connections until the listener fails, one thread per connection. Requests from all
+// connections are serialized through the daemon lock, so batches apply in arrival order.
+pub fn serve(listener: TcpListener, daemon: Arc<Mutex<Daemon>>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let daemon = Arc::clone(&daemon);
+        thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, &daemon) {
+                eprintln!("connection: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, daemon: &Mutex<Daemon>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = daemon.lock().expect("daemon lock poisoned").handle_line(&line);
+        writer.write_all(response.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, String> {
+    serde_json::from_value(params).map_err(|e| format!("invalid params: {e}"))
+}
+
+fn decode_message(s: &str) -> Result<SignedMessage, String> {
+    let bytes = parse_hex(s)?;
+    let mut reader = Reader::new(&bytes);
+    let message = SignedMessage::decode(&mut reader).map_err(|e| e.to_string())?;
+    if reader.remaining() != 0 {
+        return Err("trailing bytes".into());
+    }
+    Ok(message)
+}
+
+fn hex32(bytes: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn u256_hex(value: U256) -> String {
+    format!("{value:#x}")
+}
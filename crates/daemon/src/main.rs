@@ -0,0 +1,37 @@
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use clap::Parser;
+
+use clob_daemon::{serve, ConfigFile};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Market, rules and genesis balances as JSON.
+    #[arg(long, value_name = "FILE")]
+    config: PathBuf,
+
+    #[arg(long, default_value = "127.0.0.1:7070")]
+    listen: String,
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(err) = run(&args) {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let text = fs::read_to_string(&args.config)
+        .map_err(|e| format!("read config file {}: {e}", args.config.display()))?;
+    let config: ConfigFile = serde_json::from_str(&text).map_err(|e| format!("parse config json: {e}"))?;
+    let daemon = config.into_daemon()?;
+    let listener = TcpListener::bind(&args.listen).map_err(|e| format!("bind {}: {e}", args.listen))?;
+    eprintln!("listening on {} at root 0x{}", args.listen, hex::encode(daemon.root()));
+    serve(listener, Arc::new(Mutex::new(daemon))).map_err(|e| format!("serve: {e}"))
+}
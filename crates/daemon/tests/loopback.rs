@@ -0,0 +1,127 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use clob_core::constants::NONE_TICK;
+use clob_core::hash::keccak256;
+use clob_core::input::{Message, MessageSignature, RulesBuilder, SignedMessage};
+use clob_core::merkle::SparseMerkleTree;
+use clob_core::state::key_balance;
+use clob_core::types::{Address, AssetId, Balance, MarketId, OrderId, Side, TimeInForce, U256};
+use clob_core::verify::Domain;
+use clob_daemon::{serve, Daemon, MarketConfig};
+
+use k256::ecdsa::SigningKey;
+
+const BASE: AssetId = AssetId([1u8; 32]);
+const QUOTE: AssetId = AssetId([2u8; 32]);
+const MARKET: MarketId = MarketId([3u8; 32]);
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    next_id: u64,
+}
+
+impl Client {
+    fn call(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let request = json!({ "id": self.next_id, "method": method, "params": params });
+        writeln!(self.writer, "{request}").expect("send request");
+        let mut line = String::new();
+        self.reader.read_line(&mut line).expect("read response");
+        let response: Value = serde_json::from_str(&line).expect("response json");
+        assert_eq!(response["id"], self.next_id);
+        response
+    }
+}
+
+fn start_daemon(trader: &Address) -> Client {
+    let rules = RulesBuilder::new(BASE, QUOTE).max_balance(U256::from(1_000_000u64)).build().unwrap();
+    let config = MarketConfig {
+        domain: Domain::new(1, [9u8; 32], MARKET, [0u8; 32]),
+        rules,
+    };
+    let mut tree = SparseMerkleTree::new();
+    let balance = Balance {
+        available: U256::from(1_000u64),
+        locked: U256::zero(),
+    };
+    tree.update(key_balance(trader, &QUOTE), Some(balance.encode().to_vec()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind loopback");
+    let addr = listener.local_addr().unwrap();
+    let daemon = Arc::new(Mutex::new(Daemon::new(config, tree)));
+    thread::spawn(move || serve(listener, daemon));
+
+    let stream = TcpStream::connect(addr).expect("connect");
+    Client {
+        reader: BufReader::new(stream.try_clone().unwrap()),
+        writer: stream,
+        next_id: 0,
+    }
+}
+
+fn signed_bid(key: &SigningKey, trader: Address, nonce: u64, qty: u64) -> String {
+    let message = Message::Place {
+        trader,
+        nonce,
+        order_id: OrderId(keccak256(&nonce.to_be_bytes())),
+        side: Side::Buy,
+        tif: TimeInForce::Gtc,
+        tick_index: 1,
+        qty_base: U256::from(qty),
+        prev_tick_hint: NONE_TICK,
+        next_tick_hint: NONE_TICK,
+        reduce_only: false,
+        quote_budget: false,
+    };
+    let domain = Domain::new(1, [9u8; 32], MARKET, [0u8; 32]);
+    let (sig, recid) = key.sign_prehash_recoverable(&domain.message_hash(&message)).expect("sign");
+    let sig_bytes = sig.to_bytes();
+    let signature = MessageSignature {
+        r: sig_bytes[..32].try_into().unwrap(),
+        s: sig_bytes[32..].try_into().unwrap(),
+        v: recid.to_byte() + 27,
+    };
+    format!("0x{}", hex::encode(SignedMessage { message, signature }.encode().unwrap()))
+}
+
+#[test]
+fn submit_then_get_balance_over_loopback() {
+    let key = SigningKey::from_slice(&[0x11u8; 32]).unwrap();
+    let pubkey = key.verifying_key().to_encoded_point(false);
+    let trader = Address(keccak256(&pubkey.as_bytes()[1..])[12..].try_into().unwrap());
+    let mut client = start_daemon(&trader);
+    let balance_params = json!({ "account": trader.to_hex(), "asset": format!("0x{}", hex::encode(QUOTE.as_bytes())) });
+
+    let genesis = client.call("get_root", Value::Null)["result"]["root"].clone();
+    let before = client.call("get_balance", balance_params.clone());
+    assert_eq!(before["result"], json!({ "available": "0x3e8", "locked": "0x0" }));
+
+    let submitted = client.call("submit_batch", json!({ "messages": [signed_bid(&key, trader, 1, 10)] }));
+    let result = &submitted["result"];
+    assert_eq!(result["batch_seq"], 1);
+    assert_eq!(result["prev_root"], genesis);
+    assert_eq!(client.call("get_root", Value::Null)["result"]["root"], result["new_root"]);
+
+    // The resting bid locks its quote.
+    let after = client.call("get_balance", balance_params.clone());
+    assert_eq!(after["result"], json!({ "available": "0x3de", "locked": "0xa" }));
+
+    let executed = client.call("execute_batch", json!({ "batch_seq": 1 }));
+    assert_eq!(executed["result"]["new_root"], result["new_root"]);
+    let aliased = client.call("prove_batch", json!({ "batch_seq": 1 }));
+    assert_eq!(aliased["result"], executed["result"]);
+
+    // A replayed nonce is rejected and leaves the root where it was.
+    let rejected = client.call("submit_batch", json!({ "messages": [signed_bid(&key, trader, 1, 10)] }));
+    assert!(rejected["error"].as_str().unwrap().starts_with("apply batch"), "{rejected}");
+    assert_eq!(client.call("get_root", Value::Null)["result"]["root"], result["new_root"]);
+    assert!(client.call("execute_batch", json!({ "batch_seq": 0 }))["error"].is_string());
+    assert!(client.call("execute_batch", json!({ "batch_seq": 2 }))["error"].is_string());
+    assert!(client.call("no_such_method", Value::Null)["error"].is_string());
+}
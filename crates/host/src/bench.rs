@@ -0,0 +1,96 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    // Seeding the tree from the input state, including the prev root.
+    PopulateState,
+    // `apply_recovered_batch_at_root` over a `RecordingState`, which also generates every proof.
+    ApplyBatch,
+    // Trades and fees roots over the batch output.
+    OutputRoots,
+    Execute,
+    Prove,
+}
+
+impl Phase {
+    pub fn label(self) -> &'static str {
+        match self {
+            Phase::PopulateState => "populate_state",
+            Phase::ApplyBatch => "apply_batch",
+            Phase::OutputRoots => "output_roots",
+            Phase::Execute => "execute",
+            Phase::Prove => "prove",
+        }
+    }
+}
+
+// Wall-clock time per host phase in the order the phases ran. Disabled timings still run each
+// phase but record nothing, so the pipeline reads the same with and without `--benchmark`.
+pub struct Timings {
+    enabled: bool,
+    phases: Vec<(Phase, Duration)>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            phases: Vec::new(),
+        }
+    }
+
+    pub fn time<T>(&mut self, phase: Phase, run: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let value = run();
+        if self.enabled {
+            self.phases.push((phase, started.elapsed()));
+        }
+        value
+    }
+
+    pub fn report(&self, out: &mut impl Write) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let mut total = Duration::ZERO;
+        for (phase, elapsed) in &self.phases {
+            writeln!(out, "{:<16}{:>12.3} ms", phase.label(), millis(*elapsed))?;
+            total += *elapsed;
+        }
+        writeln!(out, "{:<16}{:>12.3} ms", "total", millis(total))
+    }
+}
+
+fn millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Phase; 5] = [Phase::PopulateState, Phase::ApplyBatch, Phase::OutputRoots, Phase::Execute, Phase::Prove];
+
+    #[test]
+    fn report_lists_every_timed_phase() {
+        let mut timings = Timings::new(true);
+        for phase in ALL {
+            assert_eq!(timings.time(phase, || phase.label().len()), phase.label().len());
+        }
+        let mut out = Vec::new();
+        timings.report(&mut out).expect("write report");
+        let text = String::from_utf8(out).expect("utf8 report");
+        let labels: Vec<&str> = text.lines().map(|line| line.split_whitespace().next().unwrap()).collect();
+        let mut expected: Vec<&str> = ALL.iter().map(|phase| phase.label()).collect();
+        expected.push("total");
+        assert_eq!(labels, expected);
+        assert!(text.lines().all(|line| line.ends_with(" ms")));
+
+        let mut disabled = Timings::new(false);
+        disabled.time(Phase::ApplyBatch, || ());
+        let mut out = Vec::new();
+        disabled.report(&mut out).expect("write report");
+        assert!(out.is_empty());
+    }
+}
@@ -1,18 +1,40 @@
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
 
-use clob_core::engine::apply_batch;
-use clob_core::hash::keccak256;
+use clob_core::engine::{apply_recovered_batch_at_root, RecoveredBatch};
+use clob_core::hash::DigestKind;
 use clob_core::input::{GuestBundle, GuestInput, Message, MessageSignature, PublicInputsPartial, Rules, SignedMessage};
+use clob_core::math::parse_decimal_amount;
 use clob_core::merkle::SparseMerkleTree;
-use clob_core::outputs::merkle_root;
+use clob_core::outputs;
+use clob_core::parse::{parse_addr, parse_b32, parse_hex, parse_u256};
 use clob_core::state::RecordingState;
-use clob_core::types::{FeeTotal, Side, TimeInForce, U256};
-use clob_core::verify::{batch_digest, domain_separator, message_hash, rules_hash};
+use clob_core::types::{
+    Address, AllocationMode, AssetId, FailureMode, MarketId, OrderId, OrderIdScope, Side, TimeInForce, U256,
+};
+use clob_core::verify::{
+    batch_digest, check_batch_deadline, check_lot_size, derive_order_id, domain_separator, message_hash, rules_hash,
+    signing_separator,
+};
+
+use bench::{Phase, Timings};
+use signer::{LocalSigner, Signer, SignerSet};
+
+mod bench;
+mod preflight;
+// Point reads for callers embedding the host; not exposed as a CLI mode.
+#[allow(dead_code)]
+mod query;
+mod replay;
+mod retry;
+mod signer;
+mod summary;
+mod trace;
 
 pub const CLOB_ELF: &[u8] = include_elf!("clob-guest");
 
@@ -25,11 +47,48 @@ struct Args {
     #[arg(long)]
     prove: bool,
 
+    #[arg(long, value_name = "FILE", required_unless_present = "replay")]
+    input: Option<PathBuf>,
+
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
     #[arg(long, value_name = "FILE")]
-    input: PathBuf,
+    trace: Option<PathBuf>,
 
     #[arg(long, value_name = "FILE")]
-    output: PathBuf,
+    save_bundle: Option<PathBuf>,
+
+    #[arg(long, value_name = "BUNDLE", conflicts_with_all = ["input", "execute", "prove"])]
+    replay: Option<PathBuf>,
+
+    /// Retries after a failed prove request before giving up.
+    #[arg(long, default_value_t = 3)]
+    max_retries: u32,
+
+    /// Seconds after the first prove attempt past which no retry is started.
+    #[arg(long, value_name = "SECS", default_value_t = 3600)]
+    prover_timeout: u64,
+
+    /// Commit the full encoded rules after the public inputs, not just their hash.
+    #[arg(long)]
+    commit_rules: bool,
+
+    /// Hash for the uncommitted touched-keys digest: keccak256 or sha256.
+    #[arg(long, value_name = "HASH", default_value = "keccak256", value_parser = parse_digest_kind)]
+    touched_digest: DigestKind,
+
+    /// Print how long each host phase took to stdout.
+    #[arg(long)]
+    benchmark: bool,
+}
+
+fn parse_digest_kind(value: &str) -> Result<DigestKind, String> {
+    match value {
+        "keccak256" => Ok(DigestKind::Keccak256),
+        "sha256" => Ok(DigestKind::Sha256),
+        other => Err(format!("unknown digest {other:?}")),
+    }
 }
 
 #[derive(Deserialize)]
@@ -37,11 +96,15 @@ struct InputFile {
     chain_id: u64,
     venue_id: String,
     market_id: String,
+    #[serde(default)]
+    instance_salt: Option<String>,
     rules: RulesJson,
     state: StateJson,
     batch: Vec<MessageJson>,
     batch_seq: u64,
     batch_timestamp: u64,
+    #[serde(default)]
+    batch_deadline: u64,
     da_commitment: String,
 }
 
@@ -56,7 +119,44 @@ struct RulesJson {
     maker_fee_bps: u32,
     max_orders_per_batch: u32,
     max_matches_per_order: u32,
+    max_fills_per_batch: u32,
+    #[serde(default)]
+    allocation_mode: u8,
+    #[serde(default)]
+    min_notional: Option<String>,
     max_balance: String,
+    #[serde(default)]
+    fee_collector: Option<String>,
+    #[serde(default)]
+    fee_in_received_asset: bool,
+    #[serde(default)]
+    enforce_derived_order_id: bool,
+    #[serde(default)]
+    admin: Option<String>,
+    #[serde(default)]
+    max_price_deviation_bps: u32,
+    #[serde(default)]
+    base_decimals: Option<u8>,
+    #[serde(default)]
+    quote_decimals: Option<u8>,
+    #[serde(default)]
+    commit_trade_roots: Option<bool>,
+    #[serde(default)]
+    max_open_orders_per_trader: u32,
+    #[serde(default)]
+    max_book_steps_per_order: u32,
+    #[serde(default)]
+    tick_spacing: Option<i32>,
+    #[serde(default)]
+    taker_fee_tier_notional: Option<String>,
+    #[serde(default)]
+    taker_fee_bps_tier2: u32,
+    #[serde(default)]
+    order_id_scope: u8,
+    #[serde(default)]
+    bind_batch_seq: bool,
+    #[serde(default)]
+    failure_mode: u8,
 }
 
 #[derive(Deserialize)]
@@ -68,6 +168,7 @@ struct StateJson {
     tick_nodes: Vec<TickNodeJson>,
     market_best: Option<MarketBestJson>,
     fee_vaults: Vec<FeeVaultJson>,
+    trade_seq: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -129,13 +230,24 @@ struct MessageJson {
     kind: String,
     trader: String,
     nonce: u64,
+    #[serde(default)]
     order_id: String,
     side: Option<u8>,
     tif: Option<u32>,
     tick_index: Option<i32>,
     qty_base: Option<String>,
+    qty: Option<String>,
     prev_tick_hint: Option<i32>,
     next_tick_hint: Option<i32>,
+    #[serde(default)]
+    reduce_only: bool,
+    #[serde(default)]
+    quote_budget: bool,
+    asset: Option<String>,
+    amount: Option<String>,
+    to: Option<String>,
+    maker_order_id: Option<String>,
+    max_qty: Option<String>,
     signature: String,
     private_key: Option<String>,
 }
@@ -151,6 +263,7 @@ struct OutputFile {
     fees_root: String,
     public_values: String,
     proof: Option<String>,
+    summary: summary::BatchSummary,
 }
 
 fn main() {
@@ -158,50 +271,99 @@ fn main() {
     sp1_sdk::utils::setup_logger();
     let args = Args::parse();
 
-    if args.execute == args.prove {
-        eprintln!("Specify exactly one of --execute or --prove.");
+    if let Some(bundle_path) = &args.replay {
+        match replay::replay_file(bundle_path) {
+            Ok(output) => println!("{}", replay::public_inputs_json(&output)),
+            Err(err) => {
+                eprintln!("error: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let dry_run = !args.execute && !args.prove && args.trace.is_some();
+    if args.execute && args.prove || (!args.execute && !args.prove && !dry_run) {
+        eprintln!("Specify exactly one of --execute or --prove, or only --trace for a dry run.");
+        std::process::exit(1);
+    }
+
+    if let Err(err) = run(&args) {
+        eprintln!("error: {err}");
         std::process::exit(1);
     }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let mut timings = Timings::new(args.benchmark);
+    run_timed(args, &mut timings)?;
+    timings.report(&mut std::io::stdout()).map_err(|e| format!("write benchmark: {e}"))
+}
 
-    let input_text = fs::read_to_string(&args.input).expect("read input file");
-    let input: InputFile = serde_json::from_str(&input_text).expect("parse input json");
-
-    let rules = Rules {
-        base_asset_id: parse_b32(&input.rules.base_asset_id),
-        quote_asset_id: parse_b32(&input.rules.quote_asset_id),
-        price_scale: parse_u256(&input.rules.price_scale),
-        tick_size: parse_u256(&input.rules.tick_size),
-        lot_size: parse_u256(&input.rules.lot_size),
-        taker_fee_bps: input.rules.taker_fee_bps,
-        maker_fee_bps: input.rules.maker_fee_bps,
-        max_orders_per_batch: input.rules.max_orders_per_batch,
-        max_matches_per_order: input.rules.max_matches_per_order,
-        max_balance: parse_u256(&input.rules.max_balance),
+fn run_timed(args: &Args, timings: &mut Timings) -> Result<(), String> {
+    let input_path = args.input.as_ref().ok_or("--input is required")?;
+    let input_text = fs::read_to_string(input_path)
+        .map_err(|e| format!("read input file {}: {e}", input_path.display()))?;
+    let input: InputFile = serde_json::from_str(&input_text).map_err(|e| format!("parse input json: {e}"))?;
+
+    check_batch_deadline(input.batch_timestamp, input.batch_deadline).map_err(|e| format!("batch_timestamp: {e}"))?;
+    let rules = parse_rules(&input.rules)?;
+    let venue_id = parse_b32(&input.venue_id).map_err(|e| format!("venue_id: {e}"))?;
+    let market_id = parse_b32(&input.market_id).map(MarketId).map_err(|e| format!("market_id: {e}"))?;
+    let instance_salt = match &input.instance_salt {
+        Some(salt) => parse_b32(salt).map_err(|e| format!("instance_salt: {e}"))?,
+        None => [0u8; 32],
     };
+    let da_commitment = parse_b32(&input.da_commitment).map_err(|e| format!("da_commitment: {e}"))?;
 
     let mut tree = SparseMerkleTree::new();
-    populate_state(&mut tree, &input.state, &rules, parse_b32(&input.market_id));
-    let prev_root = tree.root();
+    let prev_root = timings.time(Phase::PopulateState, || {
+        populate_state(&mut tree, &input.state, &rules, market_id).map(|()| tree.root())
+    })?;
 
     let mut state = RecordingState::new(tree);
-    let domain_sep = domain_separator(input.chain_id, &parse_b32(&input.venue_id), &parse_b32(&input.market_id));
-
-    let messages = build_messages(&input.batch, &domain_sep);
-    let output = apply_batch(&mut state, parse_b32(&input.market_id), &rules, domain_sep, &messages)
-        .expect("apply batch");
-
-    let trade_leaves: Vec<[u8; 32]> = output
-        .trades
-        .iter()
-        .map(|t| keccak256(&t.encode()))
-        .collect();
-    let trades_root = merkle_root(&trade_leaves);
-    let fee_leaves: Vec<[u8; 32]> = output
-        .fee_totals
-        .iter()
-        .map(|f: &FeeTotal| keccak256(&f.encode()))
-        .collect();
-    let fees_root = merkle_root(&fee_leaves);
+    let domain_sep = domain_separator(input.chain_id, &venue_id, &market_id, &instance_salt);
+
+    let signers = env_signers()?;
+    let messages = build_messages(&input.batch, &rules, &domain_sep, input.batch_seq, &signers)?;
+    let signing_sep = signing_separator(&domain_sep, &rules, input.batch_seq);
+    let recovered = preflight::recover_batch_signers(&messages, &signing_sep);
+    if rules.failure_mode == FailureMode::Abort {
+        preflight::check_batch_signers(&messages, &recovered)?;
+    }
+    let batch = RecoveredBatch::new(&messages, recovered).map_err(|e| format!("apply batch: {e}"))?;
+    let output = timings
+        .time(Phase::ApplyBatch, || {
+            apply_recovered_batch_at_root(&mut state, prev_root, market_id, &rules, domain_sep, input.batch_seq, batch)
+        })
+        .map_err(|e| format!("apply batch: {e}"))?;
+    if cfg!(debug_assertions) {
+        let accounts = conservation_accounts(&messages, &input.state)?;
+        state
+            .check_conservation(&accounts, &[rules.base_asset_id, rules.quote_asset_id])
+            .map_err(|e| format!("conservation check: {e}"))?;
+    }
+    let batch_summary = summary::summarize(&output, &rules)?;
+
+    let (trades_root, fees_root) = timings.time(Phase::OutputRoots, || {
+        if rules.commit_trade_roots {
+            (outputs::trades_root(&output.trades), outputs::fees_root(&output.fee_totals))
+        } else {
+            ([0u8; 32], [0u8; 32])
+        }
+    });
+
+    if let Some(trace_path) = &args.trace {
+        let labels = trace_labels(&input.state, &rules, market_id, &messages)?;
+        let trace = trace::build_trace(&state.proofs, &state.tree, &labels);
+        let trace_text = serde_json::to_string_pretty(&trace).map_err(|e| format!("encode trace json: {e}"))?;
+        fs::write(trace_path, trace_text).map_err(|e| format!("write trace {}: {e}", trace_path.display()))?;
+        if !args.execute && !args.prove {
+            return Ok(());
+        }
+    }
+    let output_path = args.output.as_ref().ok_or("--output is required with --execute or --prove")?;
+    preflight::verify_recorded_proofs(&state.proofs, &state.proof_roots)?;
 
     let rules_h = rules_hash(&rules);
     let mut msg_hashes = Vec::with_capacity(messages.len());
@@ -218,34 +380,54 @@ fn main() {
             domain_separator: domain_sep,
             batch_seq: input.batch_seq,
             batch_timestamp: input.batch_timestamp,
-            da_commitment: parse_b32(&input.da_commitment),
+            batch_deadline: input.batch_deadline,
+            da_commitment,
         },
         chain_id: input.chain_id,
-        venue_id: parse_b32(&input.venue_id),
-        market_id: parse_b32(&input.market_id),
+        venue_id,
+        market_id,
+        instance_salt,
         rules,
+        commit_rules: args.commit_rules,
+        touched_digest: args.touched_digest,
         messages: messages.clone(),
     };
     let bundle = GuestBundle {
         input: guest_input,
         proofs: state.proofs.clone(),
     };
+    let bundle_bytes = bundle.encode().map_err(|e| format!("encode bundle: {e}"))?;
+    if let Some(bundle_path) = &args.save_bundle {
+        fs::write(bundle_path, &bundle_bytes)
+            .map_err(|e| format!("write bundle {}: {e}", bundle_path.display()))?;
+    }
 
     let mut stdin = SP1Stdin::new();
-    stdin.write(&bundle.encode());
+    stdin.write(&bundle_bytes);
     let client = ProverClient::from_env();
 
     let public_values;
     let proof_hex;
 
     if args.execute {
-        let (output, _) = client.execute(CLOB_ELF, &stdin).run().expect("execute");
+        let (output, _) = timings
+            .time(Phase::Execute, || client.execute(CLOB_ELF, &stdin).run())
+            .map_err(|e| format!("execute: {e}"))?;
         public_values = hex::encode(output.as_slice());
         proof_hex = None;
     } else {
         let (pk, vk) = client.setup(CLOB_ELF);
-        let proof = client.prove(&pk, &stdin).run().expect("prove");
-        client.verify(&proof, &vk).expect("verify");
+        let policy = retry::RetryPolicy {
+            max_retries: args.max_retries,
+            initial_backoff: Duration::from_secs(5),
+            timeout: Duration::from_secs(args.prover_timeout),
+        };
+        let proof = timings
+            .time(Phase::Prove, || {
+                retry::retry_with_backoff(&policy, || client.prove(&pk, &stdin).run(), std::thread::sleep)
+            })
+            .map_err(|e| format!("prove: {e}"))?;
+        client.verify(&proof, &vk).map_err(|e| format!("verify: {e}"))?;
         public_values = hex::encode(proof.public_values.as_slice());
         proof_hex = Some(hex::encode(proof.proof.as_slice()));
     }
@@ -260,88 +442,288 @@ fn main() {
         fees_root: format!("0x{}", hex::encode(fees_root)),
         public_values: format!("0x{}", public_values),
         proof: proof_hex.map(|p| format!("0x{}", p)),
+        summary: batch_summary,
     };
 
-    fs::write(&args.output, serde_json::to_string_pretty(&output_json).unwrap())
-        .expect("write output");
+    let output_text = serde_json::to_string_pretty(&output_json).map_err(|e| format!("encode output json: {e}"))?;
+    fs::write(output_path, output_text).map_err(|e| format!("write output {}: {e}", output_path.display()))?;
+    Ok(())
+}
+
+fn trace_labels(
+    state: &StateJson,
+    rules: &Rules,
+    market_id: MarketId,
+    messages: &[SignedMessage],
+) -> Result<trace::KeyLabels, String> {
+    let mut labels = trace::KeyLabels::new(rules, market_id);
+    labels.add_messages(messages);
+    for bal in &state.balances {
+        labels.add_account(&parse_addr(&bal.account)?);
+    }
+    for nonce in &state.nonces {
+        labels.add_account(&parse_addr(&nonce.account)?);
+    }
+    for ord in &state.orders {
+        labels.add_account(&parse_addr(&ord.owner)?);
+        labels.add_order(&parse_b32(&ord.order_id).map(OrderId)?);
+    }
+    for node in &state.order_nodes {
+        labels.add_order(&parse_b32(&node.order_id).map(OrderId)?);
+    }
+    for tick in &state.tick_nodes {
+        labels.add_tick(tick.side, tick.tick);
+        labels.add_tick(tick.side, tick.prev);
+        labels.add_tick(tick.side, tick.next);
+    }
+    Ok(labels)
 }
 
-fn build_messages(batch: &[MessageJson], domain_sep: &[u8; 32]) -> Vec<SignedMessage> {
+fn parse_rules(rules: &RulesJson) -> Result<Rules, String> {
+    Ok(Rules {
+        base_asset_id: parse_b32(&rules.base_asset_id).map(AssetId).map_err(|e| format!("rules.base_asset_id: {e}"))?,
+        quote_asset_id: parse_b32(&rules.quote_asset_id).map(AssetId).map_err(|e| format!("rules.quote_asset_id: {e}"))?,
+        price_scale: parse_u256(&rules.price_scale).map_err(|e| format!("rules.price_scale: {e}"))?,
+        tick_size: parse_u256(&rules.tick_size).map_err(|e| format!("rules.tick_size: {e}"))?,
+        lot_size: parse_u256(&rules.lot_size).map_err(|e| format!("rules.lot_size: {e}"))?,
+        taker_fee_bps: rules.taker_fee_bps,
+        maker_fee_bps: rules.maker_fee_bps,
+        max_orders_per_batch: rules.max_orders_per_batch,
+        max_matches_per_order: rules.max_matches_per_order,
+        max_fills_per_batch: rules.max_fills_per_batch,
+        allocation_mode: AllocationMode::from_u8(rules.allocation_mode).map_err(|e| format!("rules.allocation_mode: {e}"))?,
+        min_notional: match &rules.min_notional {
+            Some(value) => parse_u256(value).map_err(|e| format!("rules.min_notional: {e}"))?,
+            None => U256::zero(),
+        },
+        max_balance: parse_u256(&rules.max_balance).map_err(|e| format!("rules.max_balance: {e}"))?,
+        fee_collector: match &rules.fee_collector {
+            Some(value) => parse_addr(value).map_err(|e| format!("rules.fee_collector: {e}"))?,
+            None => Address::ZERO,
+        },
+        fee_in_received_asset: rules.fee_in_received_asset,
+        enforce_derived_order_id: rules.enforce_derived_order_id,
+        admin: match &rules.admin {
+            Some(value) => parse_addr(value).map_err(|e| format!("rules.admin: {e}"))?,
+            None => Address::ZERO,
+        },
+        max_price_deviation_bps: rules.max_price_deviation_bps,
+        base_decimals: rules.base_decimals.unwrap_or(18),
+        quote_decimals: rules.quote_decimals.unwrap_or(18),
+        commit_trade_roots: rules.commit_trade_roots.unwrap_or(true),
+        max_open_orders_per_trader: rules.max_open_orders_per_trader,
+        max_book_steps_per_order: rules.max_book_steps_per_order,
+        tick_spacing: rules.tick_spacing.unwrap_or(1),
+        taker_fee_tier_notional: match &rules.taker_fee_tier_notional {
+            Some(value) => parse_u256(value).map_err(|e| format!("rules.taker_fee_tier_notional: {e}"))?,
+            None => U256::zero(),
+        },
+        taker_fee_bps_tier2: rules.taker_fee_bps_tier2,
+        order_id_scope: OrderIdScope::from_u8(rules.order_id_scope).map_err(|e| format!("rules.order_id_scope: {e}"))?,
+        bind_batch_seq: rules.bind_batch_seq,
+        failure_mode: FailureMode::from_u8(rules.failure_mode).map_err(|e| format!("rules.failure_mode: {e}"))?,
+    })
+}
+
+fn build_messages(
+    batch: &[MessageJson],
+    rules: &Rules,
+    domain_sep: &[u8; 32],
+    batch_seq: u64,
+    signers: &SignerSet,
+) -> Result<Vec<SignedMessage>, String> {
+    let signing_sep = signing_separator(domain_sep, rules, batch_seq);
     batch
         .iter()
-        .map(|msg| {
+        .enumerate()
+        .map(|(i, msg)| {
+            let message = parse_message(msg, rules, domain_sep).map_err(|e| format!("message {i}: {e}"))?;
             let signature = if msg.signature == "auto" {
-                let priv_key = msg.private_key.as_ref().expect("private_key");
-                sign_message(priv_key, msg, domain_sep)
+                let signature = match &msg.private_key {
+                    Some(priv_key) => {
+                        let key_bytes = parse_hex(priv_key).map_err(|e| format!("message {i}: private_key: {e}"))?;
+                        let local = LocalSigner::from_slice(&key_bytes)
+                            .map_err(|e| format!("message {i}: private_key: {e}"))?;
+                        sign_message(&local, &message, &signing_sep)
+                    }
+                    None => {
+                        let signer = signers.find(message_trader(&message)).ok_or_else(|| {
+                            format!("message {i}: signature is \"auto\" but no private_key or signer for trader")
+                        })?;
+                        sign_message(signer, &message, &signing_sep)
+                    }
+                };
+                signature.map_err(|e| format!("message {i}: {e}"))?
             } else {
-                parse_sig(&msg.signature)
+                parse_sig(&msg.signature).map_err(|e| format!("message {i}: signature: {e}"))?
             };
-            let trader = parse_addr(&msg.trader);
-            match msg.kind.as_str() {
-                "place" => SignedMessage {
-                    message: Message::Place {
-                        trader,
-                        nonce: msg.nonce,
-                        order_id: parse_b32(&msg.order_id),
-                        side: Side::from_u8(msg.side.expect("side")).expect("side"),
-                        tif: TimeInForce::from_u32(msg.tif.expect("tif")).expect("tif"),
-                        tick_index: msg.tick_index.expect("tick_index"),
-                        qty_base: parse_u256(msg.qty_base.as_ref().expect("qty_base")),
-                        prev_tick_hint: msg.prev_tick_hint.unwrap_or(i32::MIN),
-                        next_tick_hint: msg.next_tick_hint.unwrap_or(i32::MIN),
-                    },
-                    signature,
-                },
-                "cancel" => SignedMessage {
-                    message: Message::Cancel {
-                        trader,
-                        nonce: msg.nonce,
-                        order_id: parse_b32(&msg.order_id),
-                    },
-                    signature,
-                },
-                _ => panic!("unknown message kind"),
-            }
+            Ok(SignedMessage { message, signature })
         })
         .collect()
 }
 
-fn populate_state(tree: &mut SparseMerkleTree, state: &StateJson, rules: &Rules, market_id: [u8; 32]) {
+fn env_signers() -> Result<SignerSet, String> {
+    let mut signers = SignerSet::new();
+    let Ok(keys) = std::env::var("CLOB_SIGNER_KEYS") else {
+        return Ok(signers);
+    };
+    for (i, key) in keys.split(',').map(str::trim).filter(|k| !k.is_empty()).enumerate() {
+        let key_bytes = parse_hex(key).map_err(|e| format!("CLOB_SIGNER_KEYS[{i}]: {e}"))?;
+        let local = LocalSigner::from_slice(&key_bytes).map_err(|e| format!("CLOB_SIGNER_KEYS[{i}]: {e}"))?;
+        signers.add(Box::new(local));
+    }
+    Ok(signers)
+}
+
+fn message_trader(message: &Message) -> &Address {
+    match message {
+        Message::Place { trader, .. } => trader,
+        Message::Cancel { trader, .. } => trader,
+        Message::CollectFees { operator, .. } => operator,
+        Message::ForceCancel { operator, .. } => operator,
+        Message::FillOrder { trader, .. } => trader,
+    }
+}
+
+// Every account whose balances the batch can move: signers, fee recipients and resting makers.
+fn conservation_accounts(messages: &[SignedMessage], state: &StateJson) -> Result<Vec<Address>, String> {
+    let mut accounts: Vec<Address> = messages.iter().map(|signed| *message_trader(&signed.message)).collect();
+    for signed in messages {
+        if let Message::CollectFees { to, .. } = &signed.message {
+            accounts.push(*to);
+        }
+    }
+    for order in &state.orders {
+        accounts.push(parse_addr(&order.owner)?);
+    }
+    accounts.sort();
+    accounts.dedup();
+    Ok(accounts)
+}
+
+// A place sizes by raw `qty_base` or by a human decimal `qty` in the asset it spends from: quote for
+// a quote budget, base otherwise. Human base amounts must also land on the lot grid.
+fn place_qty(msg: &MessageJson, rules: &Rules) -> Result<U256, String> {
+    match (&msg.qty_base, &msg.qty) {
+        (Some(raw), None) => parse_u256(raw).map_err(|e| format!("qty_base: {e}")),
+        (None, Some(human)) if msg.quote_budget => {
+            parse_decimal_amount(human, rules.quote_decimals).map_err(|e| format!("qty: {e}"))
+        }
+        (None, Some(human)) => {
+            let qty = parse_decimal_amount(human, rules.base_decimals).map_err(|e| format!("qty: {e}"))?;
+            check_lot_size(qty, rules.lot_size).map_err(|e| format!("qty: {e}"))?;
+            Ok(qty)
+        }
+        (Some(_), Some(_)) => Err("place takes qty_base or qty, not both".to_string()),
+        (None, None) => Err("place requires qty_base or qty".to_string()),
+    }
+}
+
+fn parse_message(msg: &MessageJson, rules: &Rules, domain_sep: &[u8; 32]) -> Result<Message, String> {
+    let trader = parse_addr(&msg.trader).map_err(|e| format!("trader: {e}"))?;
+    match msg.kind.as_str() {
+        "place" => {
+            let order_id = if msg.order_id == "derive" {
+                derive_order_id(domain_sep, &trader, msg.nonce)
+            } else {
+                parse_b32(&msg.order_id).map(OrderId).map_err(|e| format!("order_id: {e}"))?
+            };
+            let side = msg.side.ok_or("place requires side")?;
+            let tif = msg.tif.ok_or("place requires tif")?;
+            Ok(Message::Place {
+                trader,
+                nonce: msg.nonce,
+                order_id,
+                side: Side::from_u8(side).map_err(|e| format!("side: {e}"))?,
+                tif: TimeInForce::from_u32(tif).map_err(|e| format!("tif: {e}"))?,
+                tick_index: msg.tick_index.ok_or("place requires tick_index")?,
+                qty_base: place_qty(msg, rules)?,
+                prev_tick_hint: msg.prev_tick_hint.unwrap_or(i32::MIN),
+                next_tick_hint: msg.next_tick_hint.unwrap_or(i32::MIN),
+                reduce_only: msg.reduce_only,
+                quote_budget: msg.quote_budget,
+            })
+        }
+        "cancel" => Ok(Message::Cancel {
+            trader,
+            nonce: msg.nonce,
+            order_id: parse_b32(&msg.order_id).map(OrderId).map_err(|e| format!("order_id: {e}"))?,
+        }),
+        "force_cancel" => Ok(Message::ForceCancel {
+            operator: trader,
+            nonce: msg.nonce,
+            order_id: parse_b32(&msg.order_id).map(OrderId).map_err(|e| format!("order_id: {e}"))?,
+        }),
+        "collect_fees" => {
+            let asset = msg.asset.as_ref().ok_or("collect_fees requires asset")?;
+            let amount = msg.amount.as_ref().ok_or("collect_fees requires amount")?;
+            let to = msg.to.as_ref().ok_or("collect_fees requires to")?;
+            Ok(Message::CollectFees {
+                operator: trader,
+                nonce: msg.nonce,
+                asset: parse_b32(asset).map(AssetId).map_err(|e| format!("asset: {e}"))?,
+                amount: parse_u256(amount).map_err(|e| format!("amount: {e}"))?,
+                to: parse_addr(to).map_err(|e| format!("to: {e}"))?,
+            })
+        }
+        "fill_order" => {
+            let order_id = if msg.order_id == "derive" {
+                derive_order_id(domain_sep, &trader, msg.nonce)
+            } else {
+                parse_b32(&msg.order_id).map(OrderId).map_err(|e| format!("order_id: {e}"))?
+            };
+            let maker_order_id = msg.maker_order_id.as_ref().ok_or("fill_order requires maker_order_id")?;
+            let max_qty = msg.max_qty.as_ref().ok_or("fill_order requires max_qty")?;
+            Ok(Message::FillOrder {
+                trader,
+                nonce: msg.nonce,
+                order_id,
+                maker_order_id: parse_b32(maker_order_id).map(OrderId).map_err(|e| format!("maker_order_id: {e}"))?,
+                max_qty: parse_u256(max_qty).map_err(|e| format!("max_qty: {e}"))?,
+            })
+        }
+        other => Err(format!("unknown message kind {other:?}")),
+    }
+}
+
+fn populate_state(tree: &mut SparseMerkleTree, state: &StateJson, rules: &Rules, market_id: MarketId) -> Result<(), String> {
     use clob_core::state::{
         key_balance, key_fee_vault, key_market_best, key_nonce, key_order, key_order_node,
-        key_tick_node,
+        key_tick_node, key_trade_seq,
     };
     use clob_core::types::{Balance, FeeVault, MarketBest, Order, OrderNode, OrderStatus, TickNode};
 
     for bal in &state.balances {
-        let key = key_balance(&parse_addr(&bal.account), &parse_b32(&bal.asset));
+        let key = key_balance(&parse_addr(&bal.account)?, &parse_b32(&bal.asset).map(AssetId)?);
         let balance = Balance {
-            available: parse_u256(&bal.available),
-            locked: parse_u256(&bal.locked),
+            available: parse_u256(&bal.available)?,
+            locked: parse_u256(&bal.locked)?,
         };
         tree.update(key, Some(balance.encode().to_vec()));
     }
     for nonce in &state.nonces {
-        let key = key_nonce(&parse_addr(&nonce.account));
+        let key = key_nonce(&parse_addr(&nonce.account)?);
         tree.update(key, Some(nonce.nonce.to_be_bytes().to_vec()));
     }
+    let scope = rules.order_scope(&market_id);
     for ord in &state.orders {
         let order = Order {
-            owner: parse_addr(&ord.owner),
-            side: Side::from_u8(ord.side).expect("side"),
+            owner: parse_addr(&ord.owner)?,
+            market_id,
+            side: Side::from_u8(ord.side).map_err(|e| e.to_string())?,
             tick: ord.tick,
-            qty_remaining: parse_u256(&ord.qty_remaining),
-            tif: TimeInForce::from_u32(ord.tif).expect("tif"),
-            status: OrderStatus::from_u8(ord.status).expect("status"),
+            qty_remaining: parse_u256(&ord.qty_remaining)?,
+            tif: TimeInForce::from_u32(ord.tif).map_err(|e| e.to_string())?,
+            status: OrderStatus::from_u8(ord.status).map_err(|e| e.to_string())?,
         };
-        let key = key_order(&parse_b32(&ord.order_id));
+        let key = key_order(scope, &parse_b32(&ord.order_id).map(OrderId)?);
         tree.update(key, Some(order.encode()));
     }
     for node in &state.order_nodes {
-        let key = key_order_node(&parse_b32(&node.order_id));
+        let key = key_order_node(scope, &parse_b32(&node.order_id).map(OrderId)?);
         let on = OrderNode {
-            prev_order_id: parse_b32(&node.prev),
-            next_order_id: parse_b32(&node.next),
+            prev_order_id: parse_b32(&node.prev).map(OrderId)?,
+            next_order_id: parse_b32(&node.next).map(OrderId)?,
         };
         tree.update(key, Some(on.encode().to_vec()));
     }
@@ -350,8 +732,8 @@ fn populate_state(tree: &mut SparseMerkleTree, state: &StateJson, rules: &Rules,
         let tn = TickNode {
             prev_tick: tick.prev,
             next_tick: tick.next,
-            head_order_id: parse_b32(&tick.head),
-            tail_order_id: parse_b32(&tick.tail),
+            head_order_id: parse_b32(&tick.head).map(OrderId)?,
+            tail_order_id: parse_b32(&tick.tail).map(OrderId)?,
         };
         tree.update(key, Some(tn.encode().to_vec()));
     }
@@ -364,90 +746,216 @@ fn populate_state(tree: &mut SparseMerkleTree, state: &StateJson, rules: &Rules,
         tree.update(key, Some(mb.encode().to_vec()));
     }
     for fee in &state.fee_vaults {
-        let key = key_fee_vault(&parse_b32(&fee.asset));
+        let key = key_fee_vault(&parse_b32(&fee.asset).map(AssetId)?);
         let fv = FeeVault {
-            total: parse_u256(&fee.total),
+            total: parse_u256(&fee.total)?,
         };
         tree.update(key, Some(fv.encode().to_vec()));
     }
+    if let Some(seq) = state.trade_seq {
+        tree.update(key_trade_seq(&market_id), Some(seq.to_be_bytes().to_vec()));
+    }
     let _ = rules;
+    Ok(())
 }
 
-fn parse_b32(s: &str) -> [u8; 32] {
-    let bytes = parse_hex(s);
-    bytes.try_into().expect("b32 length")
+fn parse_sig(s: &str) -> Result<MessageSignature, String> {
+    let bytes = parse_hex(s)?;
+    if bytes.len() != 65 && bytes.len() != 64 {
+        return Err(format!(
+            "expected 65-byte signature or 64-byte compact signature, got {} bytes",
+            bytes.len()
+        ));
+    }
+    MessageSignature::decode_relaxed(&bytes).map_err(|e| e.to_string())
 }
 
-fn parse_addr(s: &str) -> [u8; 20] {
-    let bytes = parse_hex(s);
-    bytes.try_into().expect("addr length")
+fn sign_message(signer: &dyn Signer, message: &Message, domain_sep: &[u8; 32]) -> Result<MessageSignature, String> {
+    if &signer.address() != message_trader(message) {
+        return Err("signer address does not match trader".to_string());
+    }
+    let hash = message_hash(domain_sep, message);
+    signer.sign_prehash(&hash)
 }
 
-fn parse_sig(s: &str) -> MessageSignature {
-    let bytes = parse_hex(s);
-    if bytes.len() != 65 {
-        panic!("signature length");
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use clob_core::input::RulesBuilder;
+
+    use super::*;
+
+    fn test_rules() -> Rules {
+        RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).build().unwrap()
     }
-    MessageSignature {
-        r: bytes[..32].try_into().unwrap(),
-        s: bytes[32..64].try_into().unwrap(),
-        v: bytes[64],
+
+    fn message_json(value: serde_json::Value) -> MessageJson {
+        serde_json::from_value(value).unwrap()
     }
-}
 
-fn sign_message(priv_key_hex: &str, msg: &MessageJson, domain_sep: &[u8; 32]) -> MessageSignature {
-    use k256::ecdsa::SigningKey;
-    use k256::ecdsa::signature::hazmat::PrehashSigner;
-    let key_bytes = parse_hex(priv_key_hex);
-    let signing_key = SigningKey::from_bytes(&key_bytes).expect("signing key");
-    let trader = parse_addr(&msg.trader);
-    let message = match msg.kind.as_str() {
-        "place" => Message::Place {
-            trader,
-            nonce: msg.nonce,
-            order_id: parse_b32(&msg.order_id),
-            side: Side::from_u8(msg.side.expect("side")).expect("side"),
-            tif: TimeInForce::from_u32(msg.tif.expect("tif")).expect("tif"),
-            tick_index: msg.tick_index.expect("tick_index"),
-            qty_base: parse_u256(msg.qty_base.as_ref().expect("qty_base")),
-            prev_tick_hint: msg.prev_tick_hint.unwrap_or(i32::MIN),
-            next_tick_hint: msg.next_tick_hint.unwrap_or(i32::MIN),
-        },
-        "cancel" => Message::Cancel {
-            trader,
-            nonce: msg.nonce,
-            order_id: parse_b32(&msg.order_id),
-        },
-        _ => panic!("unknown message kind"),
-    };
-    let hash = message_hash(domain_sep, &message);
-    let (sig, recid) = signing_key.sign_prehash_recoverable(&hash).expect("sign");
-    let sig_bytes = sig.to_bytes();
-    MessageSignature {
-        r: sig_bytes[..32].try_into().unwrap(),
-        s: sig_bytes[32..].try_into().unwrap(),
-        v: recid.to_byte() + 27,
+    fn cancel_json(trader: &str, signature: &str) -> MessageJson {
+        message_json(serde_json::json!({
+            "kind": "cancel",
+            "trader": trader,
+            "nonce": 1,
+            "order_id": format!("0x{}", "11".repeat(32)),
+            "signature": signature,
+        }))
     }
-}
 
-fn parse_hex(s: &str) -> Vec<u8> {
-    let s = s.strip_prefix("0x").unwrap_or(s);
-    hex::decode(s).expect("hex decode")
-}
+    #[test]
+    fn build_messages_rejects_malformed_signature() {
+        let msg = cancel_json(&format!("0x{}", "22".repeat(20)), "0xdeadbeef");
+        let err = build_messages(&[msg], &test_rules(), &[0u8; 32], 0, &SignerSet::new())
+            .err()
+            .expect("expected error");
+        assert!(err.contains("message 0"), "{err}");
+        assert!(err.contains("65-byte signature"), "{err}");
+    }
 
-fn parse_u256(s: &str) -> U256 {
-    let bytes = parse_hex(s);
-    U256::from_be_bytes(&pad32(bytes))
-}
+    #[test]
+    fn build_messages_rejects_bad_address() {
+        let sig = format!("0x{}", "00".repeat(65));
+        let msg = cancel_json("0x1234", &sig);
+        let err = build_messages(&[msg], &test_rules(), &[0u8; 32], 0, &SignerSet::new())
+            .err()
+            .expect("expected error");
+        assert!(err.contains("trader"), "{err}");
+        assert!(err.contains("20-byte address"), "{err}");
+    }
+
+    #[test]
+    fn build_messages_auto_signs() {
+        let mut msg = cancel_json("0x7e5f4552091a69125d5dfcb7b8c2659029395bdf", "auto");
+        msg.private_key = Some(format!("0x{}{}", "00".repeat(31), "01"));
+        let messages = build_messages(&[msg], &test_rules(), &[0u8; 32], 0, &SignerSet::new()).expect("build messages");
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].signature.v == 27 || messages[0].signature.v == 28);
+    }
+
+    fn human_place_json(qty: &str) -> MessageJson {
+        message_json(serde_json::json!({
+            "kind": "place",
+            "trader": format!("0x{}", "22".repeat(20)),
+            "nonce": 1,
+            "order_id": format!("0x{}", "11".repeat(32)),
+            "side": 0,
+            "tif": 0,
+            "tick_index": 1,
+            "qty": qty,
+            "signature": format!("0x{}", "00".repeat(65)),
+        }))
+    }
+
+    #[test]
+    fn build_messages_scales_human_qty_by_base_decimals() {
+        let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32]))
+            .lot_size(U256::from(1_000u64))
+            .base_decimals(6)
+            .build()
+            .unwrap();
+        let messages = build_messages(&[human_place_json("1.5")], &rules, &[0u8; 32], 0, &SignerSet::new())
+            .expect("build messages");
+        let Message::Place { qty_base, .. } = &messages[0].message else {
+            panic!("expected place");
+        };
+        assert_eq!(*qty_base, U256::from(1_500_000u64));
+    }
 
-fn pad32(mut bytes: Vec<u8>) -> [u8; 32] {
-    if bytes.len() > 32 {
-        panic!("u256 too long");
+    #[test]
+    fn build_messages_rejects_over_precise_human_qty() {
+        let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).base_decimals(6).build().unwrap();
+        let err = build_messages(&[human_place_json("1.2345678")], &rules, &[0u8; 32], 0, &SignerSet::new())
+            .err()
+            .expect("expected error");
+        assert!(err.contains("qty"), "{err}");
+        assert!(err.contains("too many decimal places"), "{err}");
     }
-    if bytes.len() < 32 {
-        let mut padded = vec![0u8; 32 - bytes.len()];
-        padded.append(&mut bytes);
-        bytes = padded;
+
+    #[test]
+    fn build_messages_derives_order_id() {
+        let trader = Address([0x22u8; 20]);
+        let msg = message_json(serde_json::json!({
+            "kind": "place",
+            "trader": format!("0x{}", "22".repeat(20)),
+            "nonce": 3,
+            "order_id": "derive",
+            "side": 0,
+            "tif": 0,
+            "tick_index": 1,
+            "qty_base": "1",
+            "signature": format!("0x{}", "00".repeat(65)),
+        }));
+        let domain = [5u8; 32];
+        let messages = build_messages(&[msg], &test_rules(), &domain, 0, &SignerSet::new()).expect("build messages");
+        let Message::Place { order_id, .. } = &messages[0].message else {
+            panic!("expected place");
+        };
+        assert_eq!(*order_id, derive_order_id(&domain, &trader, 3));
+    }
+
+    struct MockSigner {
+        address: Address,
+        calls: Cell<usize>,
+        last_hash: Cell<[u8; 32]>,
+    }
+
+    impl Signer for MockSigner {
+        fn address(&self) -> Address {
+            self.address
+        }
+
+        fn sign_prehash(&self, hash: &[u8; 32]) -> Result<MessageSignature, String> {
+            self.calls.set(self.calls.get() + 1);
+            self.last_hash.set(*hash);
+            Ok(MessageSignature {
+                r: [0xaa; 32],
+                s: [0xbb; 32],
+                v: 27,
+            })
+        }
+    }
+
+    #[test]
+    fn build_messages_uses_external_signer() {
+        let address = Address([0x22u8; 20]);
+        let msg = cancel_json(&format!("0x{}", "22".repeat(20)), "auto");
+        let domain = [5u8; 32];
+        let mock = Rc::new(MockSigner {
+            address,
+            calls: Cell::new(0),
+            last_hash: Cell::new([0u8; 32]),
+        });
+        let mut signers = SignerSet::new();
+        signers.add(Box::new(SharedSigner(mock.clone())));
+
+        let messages = build_messages(&[msg], &test_rules(), &domain, 0, &signers).expect("build messages");
+        assert_eq!(mock.calls.get(), 1);
+        assert_eq!(mock.last_hash.get(), message_hash(&domain, &messages[0].message));
+        assert_eq!(messages[0].signature.r, [0xaa; 32]);
+        assert_eq!(messages[0].signature.s, [0xbb; 32]);
+    }
+
+    #[test]
+    fn build_messages_requires_signer_for_auto() {
+        let msg = cancel_json(&format!("0x{}", "22".repeat(20)), "auto");
+        let err = build_messages(&[msg], &test_rules(), &[0u8; 32], 0, &SignerSet::new())
+            .err()
+            .expect("expected error");
+        assert!(err.contains("no private_key or signer"), "{err}");
+    }
+
+    struct SharedSigner(Rc<MockSigner>);
+
+    impl Signer for SharedSigner {
+        fn address(&self) -> Address {
+            self.0.address()
+        }
+
+        fn sign_prehash(&self, hash: &[u8; 32]) -> Result<MessageSignature, String> {
+            self.0.sign_prehash(hash)
+        }
     }
-    bytes.try_into().unwrap()
 }
@@ -0,0 +1,140 @@
+use rayon::prelude::*;
+
+use clob_core::errors::CoreError;
+use clob_core::input::{MessageSignature, SignedMessage};
+use clob_core::merkle::{verify_proof, Proof};
+use clob_core::types::Address;
+use clob_core::verify::{message_hash, recover_addresses};
+
+use crate::message_trader;
+
+const SIGNATURE_CHUNK: usize = 64;
+
+// Each proof is checked against the root it was generated under, so a bad sibling surfaces here
+// instead of as a failed guest run after an expensive prove.
+pub fn verify_recorded_proofs(proofs: &[Proof], roots: &[[u8; 32]]) -> Result<(), String> {
+    if proofs.len() != roots.len() {
+        return Err(format!("{} proofs but {} root snapshots", proofs.len(), roots.len()));
+    }
+    let failure = proofs
+        .par_iter()
+        .zip(roots.par_iter())
+        .enumerate()
+        .find_map_first(|(index, (proof, root))| verify_proof(root, proof).err().map(|err| (index, err)));
+    match failure {
+        Some((index, err)) => Err(format!(
+            "proof {index} for key 0x{}: {err}",
+            hex::encode(proofs[index].key)
+        )),
+        None => Ok(()),
+    }
+}
+
+// Recovers every signer across threads before the batch runs, in chunks that share
+// `recover_addresses`' buffer. Results pair with `messages` by index and are handed to the engine,
+// so no signature is recovered twice.
+pub fn recover_batch_signers(messages: &[SignedMessage], signing_sep: &[u8; 32]) -> Vec<Result<Address, CoreError>> {
+    let hashes: Vec<[u8; 32]> = messages
+        .par_iter()
+        .map(|signed| message_hash(signing_sep, &signed.message))
+        .collect();
+    let sigs: Vec<MessageSignature> = messages.iter().map(|signed| signed.signature.clone()).collect();
+    hashes
+        .par_chunks(SIGNATURE_CHUNK)
+        .zip(sigs.par_chunks(SIGNATURE_CHUNK))
+        .flat_map_iter(|(hashes, sigs)| recover_addresses(hashes, sigs))
+        .collect()
+}
+
+// Under `FailureMode::Abort` a forged message fails the whole batch anyway, so it is reported by
+// index before paying for the proofs recorded ahead of it. Skip mode leaves it to the engine.
+pub fn check_batch_signers(messages: &[SignedMessage], signers: &[Result<Address, CoreError>]) -> Result<(), String> {
+    for (index, (signed, signer)) in messages.iter().zip(signers).enumerate() {
+        match signer {
+            Ok(signer) if signer == message_trader(&signed.message) => {}
+            Ok(_) => return Err(format!("message {index}: signer mismatch")),
+            Err(err) => return Err(format!("message {index}: {err}")),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clob_core::input::Message;
+    use clob_core::types::OrderId;
+
+    use crate::signer::{LocalSigner, Signer};
+
+    use clob_core::hash::keccak256;
+    use clob_core::merkle::SparseMerkleTree;
+    use clob_core::state::{RecordingState, StateAccess};
+
+    fn recorded_state() -> RecordingState {
+        let mut tree = SparseMerkleTree::new();
+        for i in 0u8..4 {
+            tree.update(keccak256(&[i]), Some(vec![i; 8]));
+        }
+        let mut state = RecordingState::new(tree);
+        for i in 0u8..8 {
+            let key = keccak256(&[i % 4]);
+            state.read_value(key).unwrap();
+            state.write_value(key, Some(vec![i; 16])).unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn recorded_proofs_verify_against_snapshots() {
+        let state = recorded_state();
+        assert_eq!(state.proofs.len(), 16);
+        verify_recorded_proofs(&state.proofs, &state.proof_roots).expect("all proofs valid");
+        assert!(verify_recorded_proofs(&state.proofs, &state.proof_roots[1..]).is_err());
+    }
+
+    #[test]
+    fn corrupted_sibling_is_caught() {
+        let mut state = recorded_state();
+        state.proofs[9].siblings[255][0] ^= 1;
+        let err = verify_recorded_proofs(&state.proofs, &state.proof_roots).unwrap_err();
+        assert!(err.starts_with("proof 9 "), "{err}");
+    }
+
+    fn signed_cancels(count: u8, domain_sep: &[u8; 32]) -> Vec<SignedMessage> {
+        (1..=count)
+            .map(|i| {
+                let signer = LocalSigner::from_slice(&[i; 32]).unwrap();
+                let message = Message::Cancel {
+                    trader: signer.address(),
+                    nonce: i as u64,
+                    order_id: OrderId([i; 32]),
+                };
+                let signature = signer.sign_prehash(&message_hash(domain_sep, &message)).unwrap();
+                SignedMessage { message, signature }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batch_signatures_are_checked_by_index() {
+        let domain = [7u8; 32];
+        let mut messages = signed_cancels(130, &domain);
+        let signers = recover_batch_signers(&messages, &domain);
+        assert_eq!(signers.len(), messages.len());
+        check_batch_signers(&messages, &signers).expect("all signatures valid");
+
+        if let Message::Cancel { trader, .. } = &mut messages[100].message {
+            *trader = Address([0x99u8; 20]);
+        }
+        let err = check_batch_signers(&messages, &recover_batch_signers(&messages, &domain)).unwrap_err();
+        assert_eq!(err, "message 100: signer mismatch");
+
+        messages[70].signature.v = 9;
+        let signers = recover_batch_signers(&messages, &domain);
+        assert!(signers[70].is_err() && signers[100].is_ok());
+        let err = check_batch_signers(&messages, &signers).unwrap_err();
+        assert!(err.starts_with("message 70: "), "{err}");
+    }
+}
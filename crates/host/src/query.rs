@@ -0,0 +1,111 @@
+use clob_core::merkle::Proof;
+use clob_core::state::{
+    get_balance, get_fee_vault, get_nonce, get_order, key_balance, key_fee_vault, key_nonce, key_order, ProofState,
+};
+use clob_core::types::{Address, AssetId, Balance, FeeVault, MarketId, Order, OrderId};
+
+#[derive(Clone, Debug)]
+pub enum QueryKey {
+    Balance { account: Address, asset: AssetId },
+    Nonce(Address),
+    // `scope` is the order's market under per-market ids and `None` under global ids.
+    Order { scope: Option<MarketId>, order_id: OrderId },
+    FeeVault(AssetId),
+}
+
+impl QueryKey {
+    pub fn key(&self) -> [u8; 32] {
+        match self {
+            QueryKey::Balance { account, asset } => key_balance(account, asset),
+            QueryKey::Nonce(account) => key_nonce(account),
+            QueryKey::Order { scope, order_id } => key_order(scope.as_ref(), order_id),
+            QueryKey::FeeVault(asset) => key_fee_vault(asset),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryValue {
+    Balance(Balance),
+    Nonce(u64),
+    Order(Option<Order>),
+    FeeVault(FeeVault),
+}
+
+// Answers point reads at `prev_root` from one proof per key, in order. Nothing is signed or
+// written; a proof that does not match its key or the root fails the whole query.
+pub fn read_only_query(prev_root: &[u8; 32], proofs: &[Proof], keys: &[QueryKey]) -> Result<Vec<QueryValue>, String> {
+    if proofs.len() != keys.len() {
+        return Err(format!("{} proofs for {} keys", proofs.len(), keys.len()));
+    }
+    let mut proofs = proofs.to_vec();
+    let mut state = ProofState::new(*prev_root, &mut proofs);
+    let mut values = Vec::with_capacity(keys.len());
+    for (index, key) in keys.iter().enumerate() {
+        let value = match key {
+            QueryKey::Balance { account, asset } => get_balance(&mut state, account, asset).map(QueryValue::Balance),
+            QueryKey::Nonce(account) => get_nonce(&mut state, account).map(QueryValue::Nonce),
+            QueryKey::Order { scope, order_id } => {
+                get_order(&mut state, scope.as_ref(), order_id).map(QueryValue::Order)
+            }
+            QueryKey::FeeVault(asset) => get_fee_vault(&mut state, asset).map(QueryValue::FeeVault),
+        };
+        values.push(value.map_err(|e| format!("query {index}: {e}"))?);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clob_core::merkle::SparseMerkleTree;
+    use clob_core::types::{OrderStatus, Side, TimeInForce, U256};
+
+    #[test]
+    fn query_reads_balance_and_order_at_root() {
+        let trader = Address([0x22u8; 20]);
+        let asset = AssetId([2u8; 32]);
+        let market = MarketId([3u8; 32]);
+        let order_id = OrderId([0x44u8; 32]);
+        let balance = Balance {
+            available: U256::from(7u64),
+            locked: U256::from(3u64),
+        };
+        let order = Order {
+            owner: trader,
+            market_id: market,
+            side: Side::Buy,
+            tick: 2,
+            qty_remaining: U256::from(3u64),
+            tif: TimeInForce::Gtc,
+            status: OrderStatus::Open,
+        };
+        let mut tree = SparseMerkleTree::new();
+        tree.update(key_balance(&trader, &asset), Some(balance.encode().to_vec()));
+        tree.update(key_order(Some(&market), &order_id), Some(order.encode()));
+        let root = tree.root();
+
+        let keys = [
+            QueryKey::Balance { account: trader, asset },
+            QueryKey::Order {
+                scope: Some(market),
+                order_id,
+            },
+            QueryKey::Order {
+                scope: Some(market),
+                order_id: OrderId([0x55u8; 32]),
+            },
+        ];
+        let proofs: Vec<Proof> = keys.iter().map(|key| tree.prove(key.key())).collect();
+        let values = read_only_query(&root, &proofs, &keys).expect("query");
+        assert_eq!(
+            values,
+            vec![QueryValue::Balance(balance), QueryValue::Order(Some(order)), QueryValue::Order(None)]
+        );
+
+        tree.update(key_balance(&trader, &asset), None);
+        let err = read_only_query(&tree.root(), &proofs[..1], &keys[..1]).unwrap_err();
+        assert!(err.contains("query 0"), "{err}");
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use clob_core::encoding::Reader;
+use clob_core::input::GuestBundle;
+use clob_core::program::{run_bundle, ProgramOutput};
+
+pub fn replay_file(path: &Path) -> Result<ProgramOutput, String> {
+    let bytes = fs::read(path).map_err(|e| format!("read bundle {}: {e}", path.display()))?;
+    replay_bundle(&bytes)
+}
+
+pub fn replay_bundle(bytes: &[u8]) -> Result<ProgramOutput, String> {
+    let mut reader = Reader::new(bytes);
+    let bundle = GuestBundle::decode(&mut reader).map_err(|e| format!("decode bundle: {e}"))?;
+    reader.expect_finished().map_err(|e| format!("decode bundle: {e}"))?;
+    run_bundle(bundle).map_err(|e| format!("replay: {e}"))
+}
+
+pub fn public_inputs_json(output: &ProgramOutput) -> Value {
+    let public = &output.public;
+    let hex = |bytes: &[u8; 32]| format!("0x{}", hex::encode(bytes));
+    json!({
+        "prev_root": hex(&public.prev_root),
+        "new_root": hex(&public.new_root),
+        "batch_digest": hex(&public.batch_digest),
+        "rules_hash": hex(&public.rules_hash),
+        "domain_separator": hex(&public.domain_separator),
+        "batch_seq": public.batch_seq,
+        "batch_timestamp": public.batch_timestamp,
+        "batch_deadline": public.batch_deadline,
+        "da_commitment": hex(&public.da_commitment),
+        "trades_root": hex(&public.trades_root),
+        "fees_root": hex(&public.fees_root),
+        "public_values": format!("0x{}", hex::encode(output.public_values())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clob_core::engine::{apply_batch, BatchOutput, MessageOutcome};
+    use clob_core::hash::DigestKind;
+    use clob_core::input::{GuestInput, Message, PublicInputs, PublicInputsPartial, Rules, SignedMessage};
+    use clob_core::merkle::SparseMerkleTree;
+    use clob_core::outputs::merkle_root;
+    use clob_core::program::recompute_public_inputs;
+    use clob_core::state::{key_balance, RecordingState};
+    use clob_core::types::{
+        Address, AllocationMode, AssetId, Balance, FailureMode, MarketId, OrderId, OrderIdScope, Side, TimeInForce,
+        U256,
+    };
+    use clob_core::verify::{batch_digest, domain_separator, message_hash, rules_hash};
+
+    use crate::sign_message;
+    use crate::signer::LocalSigner;
+
+    fn recorded_bundle() -> (GuestBundle, [u8; 32]) {
+        let (bundle, new_root, _) = record_buys(FailureMode::Abort, &[(1, 3)]);
+        (bundle, new_root)
+    }
+
+    // Records a batch of GTC buys at tick 2, one per `(nonce, qty_base)`, from a trader holding 10
+    // quote; each costs twice its quantity.
+    fn record_buys(failure_mode: FailureMode, buys: &[(u64, u64)]) -> (GuestBundle, [u8; 32], BatchOutput) {
+        let market = MarketId([3u8; 32]);
+        let venue = [9u8; 32];
+        let rules = Rules {
+            base_asset_id: AssetId([1u8; 32]),
+            quote_asset_id: AssetId([2u8; 32]),
+            price_scale: U256::from(1_000_000_000_000_000_000u128),
+            tick_size: U256::from(1_000_000_000_000_000_000u128),
+            lot_size: U256::from(1u64),
+            taker_fee_bps: 0,
+            maker_fee_bps: 0,
+            max_orders_per_batch: 128,
+            max_matches_per_order: 64,
+            max_fills_per_batch: 1024,
+            allocation_mode: AllocationMode::Fifo,
+            min_notional: U256::zero(),
+            max_balance: U256::from(1_000_000u64),
+            fee_collector: Address::ZERO,
+            fee_in_received_asset: false,
+            enforce_derived_order_id: false,
+            admin: Address::ZERO,
+            max_price_deviation_bps: 0,
+            base_decimals: 18,
+            quote_decimals: 18,
+            commit_trade_roots: true,
+            max_open_orders_per_trader: 0,
+            max_book_steps_per_order: 0,
+            tick_spacing: 1,
+            taker_fee_tier_notional: U256::zero(),
+            taker_fee_bps_tier2: 0,
+            order_id_scope: OrderIdScope::Market,
+            bind_batch_seq: false,
+            failure_mode,
+        };
+        let mut key_bytes = [0u8; 32];
+        key_bytes[31] = 1;
+        let signer = LocalSigner::from_slice(&key_bytes).unwrap();
+        let trader = Address::from_hex("7e5f4552091a69125d5dfcb7b8c2659029395bdf").unwrap();
+
+        let mut tree = SparseMerkleTree::new();
+        let funded = Balance {
+            available: U256::from(10u64),
+            locked: U256::zero(),
+        };
+        tree.update(key_balance(&trader, &rules.quote_asset_id), Some(funded.encode().to_vec()));
+        let prev_root = tree.root();
+
+        let domain = domain_separator(1, &venue, &market, &[0u8; 32]);
+        let messages: Vec<SignedMessage> = buys
+            .iter()
+            .enumerate()
+            .map(|(i, &(nonce, qty))| {
+                let message = Message::Place {
+                    trader,
+                    nonce,
+                    order_id: OrderId([0x44u8 + i as u8; 32]),
+                    side: Side::Buy,
+                    tif: TimeInForce::Gtc,
+                    tick_index: 2,
+                    qty_base: U256::from(qty),
+                    prev_tick_hint: i32::MIN,
+                    next_tick_hint: i32::MIN,
+                    reduce_only: false,
+                    quote_budget: false,
+                };
+                let signature = sign_message(&signer, &message, &domain).unwrap();
+                SignedMessage { message, signature }
+            })
+            .collect();
+
+        let mut state = RecordingState::new(tree);
+        let output = apply_batch(&mut state, market, &rules, domain, &messages).expect("apply batch");
+
+        let hashes: Vec<[u8; 32]> = messages.iter().map(|m| message_hash(&domain, &m.message)).collect();
+        let bundle = GuestBundle {
+            input: GuestInput {
+                public: PublicInputsPartial {
+                    prev_root,
+                    batch_digest: batch_digest(&domain, 7, &hashes),
+                    rules_hash: rules_hash(&rules),
+                    domain_separator: domain,
+                    batch_seq: 7,
+                    batch_timestamp: 0,
+                    batch_deadline: 0,
+                    da_commitment: [0u8; 32],
+                },
+                chain_id: 1,
+                venue_id: venue,
+                market_id: market,
+                instance_salt: [0u8; 32],
+                rules,
+                commit_rules: false,
+                touched_digest: DigestKind::Keccak256,
+                messages,
+            },
+            proofs: state.proofs,
+        };
+        (bundle, state.root, output)
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_root() {
+        let (bundle, new_root) = recorded_bundle();
+        let output = replay_bundle(&bundle.encode().unwrap()).expect("replay");
+        assert_eq!(output.public.new_root, new_root);
+        assert_eq!(output.public.batch_seq, 7);
+        assert_eq!(public_inputs_json(&output)["new_root"], format!("0x{}", hex::encode(new_root)));
+    }
+
+    #[test]
+    fn replay_skips_a_failing_message_like_the_host() {
+        // The middle buy needs 6 quote with 4 left, so it reads its proofs and then fails; the last
+        // reuses its nonce and spends the 4.
+        let (bundle, new_root, output) = record_buys(FailureMode::Skip, &[(1, 3), (2, 3), (2, 2)]);
+        assert!(matches!(
+            output.outcomes[..],
+            [MessageOutcome::Applied { .. }, MessageOutcome::Failed(_), MessageOutcome::Applied { .. }]
+        ));
+        let replayed = replay_bundle(&bundle.encode().unwrap()).expect("replay");
+        assert_eq!(replayed.public.new_root, new_root);
+
+        // The mode is committed through rulesHash, so the guest cannot be run under the other one.
+        let mut aborting = bundle;
+        aborting.input.rules.failure_mode = FailureMode::Abort;
+        let err = replay_bundle(&aborting.encode().unwrap()).unwrap_err();
+        assert!(err.contains("rules hash mismatch"), "{err}");
+        aborting.input.public.rules_hash = rules_hash(&aborting.input.rules);
+        assert!(replay_bundle(&aborting.encode().unwrap()).is_err());
+    }
+
+    #[test]
+    fn recomputed_public_inputs_match_recorded_run() {
+        let (bundle, new_root) = recorded_bundle();
+        let partial = bundle.input.public.clone();
+        let recomputed = recompute_public_inputs(bundle.clone()).expect("recompute");
+        // The sample batch only rests an order, so both record sets are empty.
+        let expected = PublicInputs {
+            prev_root: partial.prev_root,
+            new_root,
+            batch_digest: partial.batch_digest,
+            rules_hash: partial.rules_hash,
+            domain_separator: partial.domain_separator,
+            batch_seq: partial.batch_seq,
+            batch_timestamp: partial.batch_timestamp,
+            batch_deadline: partial.batch_deadline,
+            da_commitment: partial.da_commitment,
+            trades_root: merkle_root(&[]),
+            fees_root: merkle_root(&[]),
+        };
+        assert_eq!(recomputed, expected);
+        assert_eq!(recomputed, replay_bundle(&bundle.encode().unwrap()).expect("replay").public);
+    }
+
+    #[test]
+    fn replay_reports_missing_proofs() {
+        let (mut bundle, _) = recorded_bundle();
+        let dropped = bundle.proofs.pop().expect("recorded proofs");
+        let index = bundle.proofs.len();
+        let err = replay_bundle(&bundle.encode().unwrap()).unwrap_err();
+        assert!(err.contains("proof count mismatch"), "{err}");
+        assert!(err.contains(&format!("ran out at proof {index} ")), "{err}");
+        assert!(err.contains(&hex::encode(dropped.key)), "{err}");
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub timeout: Duration,
+}
+
+// Retries `attempt` with doubling backoff. A blocking attempt cannot be interrupted, so `timeout`
+// bounds when the last retry may start rather than how long an attempt may run.
+pub fn retry_with_backoff<T, E: Display>(
+    policy: &RetryPolicy,
+    mut attempt: impl FnMut() -> Result<T, E>,
+    mut sleep: impl FnMut(Duration),
+) -> Result<T, String> {
+    let started = Instant::now();
+    let mut backoff = policy.initial_backoff;
+    let mut attempts = 0u32;
+    loop {
+        attempts += 1;
+        let err = match attempt() {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        if attempts > policy.max_retries {
+            return Err(format!("failed after {attempts} attempts: {err}"));
+        }
+        if started.elapsed() + backoff >= policy.timeout {
+            return Err(format!("timed out after {attempts} attempts: {err}"));
+        }
+        eprintln!("attempt {attempts} failed: {err}; retrying in {}s", backoff.as_secs_f64());
+        sleep(backoff);
+        backoff = backoff.saturating_mul(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn retries_until_mock_prover_succeeds() {
+        let mut calls = 0;
+        let mut sleeps = Vec::new();
+        let mock_prove = || {
+            calls += 1;
+            if calls <= 2 {
+                Err(format!("network error {calls}"))
+            } else {
+                Ok("proof")
+            }
+        };
+        let proof = retry_with_backoff(&policy(3), mock_prove, |d| sleeps.push(d)).expect("third attempt succeeds");
+        assert_eq!(proof, "proof");
+        assert_eq!(calls, 3);
+        assert_eq!(sleeps, vec![Duration::from_secs(1), Duration::from_secs(2)]);
+
+        let err = retry_with_backoff(&policy(1), || Err::<(), _>("network error"), |_| {}).unwrap_err();
+        assert_eq!(err, "failed after 2 attempts: network error");
+
+        let tight = RetryPolicy {
+            timeout: Duration::from_millis(500),
+            ..policy(3)
+        };
+        let err = retry_with_backoff(&tight, || Err::<(), _>("network error"), |_| {}).unwrap_err();
+        assert!(err.starts_with("timed out after 1 attempts"), "{err}");
+    }
+}
@@ -0,0 +1,64 @@
+use k256::ecdsa::SigningKey;
+
+use clob_core::hash::keccak256;
+use clob_core::input::MessageSignature;
+use clob_core::types::Address;
+
+pub trait Signer {
+    fn address(&self) -> Address;
+    fn sign_prehash(&self, hash: &[u8; 32]) -> Result<MessageSignature, String>;
+}
+
+pub struct LocalSigner {
+    key: SigningKey,
+}
+
+impl LocalSigner {
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, String> {
+        let key = SigningKey::from_slice(bytes).map_err(|_| "not a valid secp256k1 scalar".to_string())?;
+        Ok(Self { key })
+    }
+}
+
+impl Signer for LocalSigner {
+    fn address(&self) -> Address {
+        let pubkey = self.key.verifying_key().to_encoded_point(false);
+        let hash = keccak256(&pubkey.as_bytes()[1..]);
+        Address(hash[12..].try_into().unwrap())
+    }
+
+    fn sign_prehash(&self, hash: &[u8; 32]) -> Result<MessageSignature, String> {
+        let (sig, recid) = self
+            .key
+            .sign_prehash_recoverable(hash)
+            .map_err(|e| format!("sign: {e}"))?;
+        let sig_bytes = sig.to_bytes();
+        Ok(MessageSignature {
+            r: sig_bytes[..32].try_into().unwrap(),
+            s: sig_bytes[32..].try_into().unwrap(),
+            v: recid.to_byte() + 27,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct SignerSet {
+    signers: Vec<Box<dyn Signer>>,
+}
+
+impl SignerSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, signer: Box<dyn Signer>) {
+        self.signers.push(signer);
+    }
+
+    pub fn find(&self, address: &Address) -> Option<&dyn Signer> {
+        self.signers
+            .iter()
+            .find(|signer| &signer.address() == address)
+            .map(|signer| signer.as_ref())
+    }
+}
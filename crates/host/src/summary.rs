@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use clob_core::engine::BatchOutput;
+use clob_core::input::Rules;
+use clob_core::math::sum_u256;
+use clob_core::types::{Address, Side, SignedU256, U256};
+
+#[derive(Serialize)]
+pub struct BatchSummary {
+    pub trade_count: usize,
+    pub quote_volume: String,
+    pub fees: Vec<FeeSummary>,
+    pub positions: Vec<PositionChange>,
+}
+
+#[derive(Serialize)]
+pub struct FeeSummary {
+    pub asset: String,
+    pub total: String,
+}
+
+// Signed decimal deltas; a leading `-` marks a net outflow.
+#[derive(Serialize)]
+pub struct PositionChange {
+    pub trader: String,
+    pub base: String,
+    pub quote: String,
+}
+
+#[derive(Default)]
+struct Position {
+    base: SignedU256,
+    quote: SignedU256,
+}
+
+fn credit(delta: &mut SignedU256, amount: U256) -> Result<(), String> {
+    *delta = delta
+        .checked_add(SignedU256::positive(amount))
+        .ok_or("position delta overflow")?;
+    Ok(())
+}
+
+fn debit(delta: &mut SignedU256, amount: U256) -> Result<(), String> {
+    *delta = delta
+        .checked_sub(SignedU256::positive(amount))
+        .ok_or("position delta overflow")?;
+    Ok(())
+}
+
+pub fn summarize(output: &BatchOutput, rules: &Rules) -> Result<BatchSummary, String> {
+    let quote_volume =
+        sum_u256(output.trades.iter().map(|trade| trade.quote_amt)).map_err(|e| format!("quote volume: {e}"))?;
+    let mut positions: BTreeMap<Address, Position> = BTreeMap::new();
+    for trade in &output.trades {
+        let fee_in_base = rules.fee_in_received_asset && trade.side_taker == Side::Buy;
+        let taker = positions.entry(trade.taker).or_default();
+        match trade.side_taker {
+            Side::Buy => {
+                credit(&mut taker.base, trade.qty_base)?;
+                debit(&mut taker.quote, trade.quote_amt)?;
+                if fee_in_base {
+                    debit(&mut taker.base, trade.taker_fee)?;
+                } else {
+                    debit(&mut taker.quote, trade.taker_fee)?;
+                }
+            }
+            Side::Sell => {
+                debit(&mut taker.base, trade.qty_base)?;
+                credit(&mut taker.quote, trade.quote_amt)?;
+                debit(&mut taker.quote, trade.taker_fee)?;
+            }
+        }
+        let maker = positions.entry(trade.maker).or_default();
+        match trade.side_taker {
+            Side::Buy => {
+                debit(&mut maker.base, trade.qty_base)?;
+                credit(&mut maker.quote, trade.quote_amt)?;
+            }
+            Side::Sell => {
+                credit(&mut maker.base, trade.qty_base)?;
+                debit(&mut maker.quote, trade.quote_amt)?;
+            }
+        }
+    }
+
+    Ok(BatchSummary {
+        trade_count: output.trades.len(),
+        quote_volume: quote_volume.to_string(),
+        fees: output
+            .fee_totals
+            .iter()
+            .map(|fee| FeeSummary {
+                asset: format!("0x{}", hex::encode(fee.asset_id.as_bytes())),
+                total: fee.total_fee.to_string(),
+            })
+            .collect(),
+        positions: positions
+            .iter()
+            .map(|(trader, position)| PositionChange {
+                trader: trader.to_hex(),
+                base: position.base.to_string(),
+                quote: position.quote.to_string(),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clob_core::input::RulesBuilder;
+    use clob_core::types::{AssetId, FeeTotal, MarketId, OrderId, TradeRecord};
+
+    fn trade(maker: Address, taker: Address, side_taker: Side, qty: u64, quote: u64, fee: u64) -> TradeRecord {
+        TradeRecord {
+            market_id: MarketId([3u8; 32]),
+            trade_seq: 1,
+            maker_order_id: OrderId([4u8; 32]),
+            taker_order_id: OrderId([5u8; 32]),
+            maker,
+            taker,
+            side_taker,
+            maker_tick: 1,
+            qty_base: U256::from(qty),
+            quote_amt: U256::from(quote),
+            taker_fee: U256::from(fee),
+        }
+    }
+
+    #[test]
+    fn summarize_counts_trades_fees_and_positions() {
+        let maker = Address([0x11u8; 20]);
+        let taker = Address([0x22u8; 20]);
+        let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).taker_fee_bps(10).build().unwrap();
+        let output = BatchOutput {
+            trades: vec![
+                trade(maker, taker, Side::Buy, 5, 500, 1),
+                trade(maker, taker, Side::Sell, 2, 200, 1),
+            ],
+            fee_totals: vec![FeeTotal { asset_id: AssetId([2u8; 32]), total_fee: U256::from(2u64) }],
+            outcomes: Vec::new(),
+        };
+
+        let summary = summarize(&output, &rules).unwrap();
+        assert_eq!(summary.trade_count, 2);
+        assert_eq!(summary.quote_volume, "700");
+        assert_eq!(summary.fees.len(), 1);
+        assert_eq!(summary.fees[0].asset, format!("0x{}", "02".repeat(32)));
+        assert_eq!(summary.fees[0].total, "2");
+
+        assert_eq!(summary.positions.len(), 2);
+        let maker_change = &summary.positions[0];
+        assert_eq!(maker_change.trader, maker.to_hex());
+        assert_eq!((maker_change.base.as_str(), maker_change.quote.as_str()), ("-3", "300"));
+        let taker_change = &summary.positions[1];
+        assert_eq!(taker_change.trader, taker.to_hex());
+        assert_eq!((taker_change.base.as_str(), taker_change.quote.as_str()), ("3", "-302"));
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use clob_core::constants::NONE_TICK;
+use clob_core::input::{Message, Rules, SignedMessage};
+use clob_core::math::format_price;
+use clob_core::merkle::{Proof, SparseMerkleTree};
+use clob_core::state::{
+    key_balance, key_fee_vault, key_market_best, key_nonce, key_open_count, key_order, key_order_node, key_tick_node,
+    key_trade_seq,
+};
+use clob_core::types::{
+    Address, AssetId, Balance, FeeVault, MarketBest, MarketId, Order, OrderId, OrderIdScope, OrderNode, TickNode, U256,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyKind {
+    Balance,
+    Nonce,
+    Order,
+    OrderNode,
+    TickNode,
+    MarketBest,
+    FeeVault,
+    TradeSeq,
+    OpenCount,
+}
+
+impl KeyKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            KeyKind::Balance => "balance",
+            KeyKind::Nonce => "nonce",
+            KeyKind::Order => "order",
+            KeyKind::OrderNode => "order_node",
+            KeyKind::TickNode => "tick_node",
+            KeyKind::MarketBest => "market_best",
+            KeyKind::FeeVault => "fee_vault",
+            KeyKind::TradeSeq => "trade_seq",
+            KeyKind::OpenCount => "open_count",
+        }
+    }
+}
+
+pub struct KeyLabels {
+    market_id: MarketId,
+    order_id_scope: OrderIdScope,
+    tick_size: U256,
+    price_scale: U256,
+    assets: Vec<AssetId>,
+    labels: HashMap<[u8; 32], (KeyKind, String)>,
+}
+
+impl KeyLabels {
+    pub fn new(rules: &Rules, market_id: MarketId) -> Self {
+        let mut labels = Self {
+            market_id,
+            order_id_scope: rules.order_id_scope,
+            tick_size: rules.tick_size,
+            price_scale: rules.price_scale,
+            assets: vec![rules.base_asset_id, rules.quote_asset_id],
+            labels: HashMap::new(),
+        };
+        labels.insert(key_market_best(&market_id), KeyKind::MarketBest, format!("market={}", hex32(market_id.as_bytes())));
+        labels.insert(key_trade_seq(&market_id), KeyKind::TradeSeq, format!("market={}", hex32(market_id.as_bytes())));
+        for asset in labels.assets.clone() {
+            labels.insert(key_fee_vault(&asset), KeyKind::FeeVault, format!("asset={}", hex32(asset.as_bytes())));
+        }
+        labels
+    }
+
+    fn insert(&mut self, key: [u8; 32], kind: KeyKind, label: String) {
+        self.labels.entry(key).or_insert((kind, label));
+    }
+
+    pub fn add_account(&mut self, account: &Address) {
+        let account_hex = account.to_hex();
+        self.insert(key_nonce(account), KeyKind::Nonce, format!("account={account_hex}"));
+        self.insert(key_open_count(account), KeyKind::OpenCount, format!("account={account_hex}"));
+        for asset in self.assets.clone() {
+            self.insert(
+                key_balance(account, &asset),
+                KeyKind::Balance,
+                format!("account={account_hex} asset={}", hex32(asset.as_bytes())),
+            );
+        }
+    }
+
+    pub fn add_order(&mut self, order_id: &OrderId) {
+        let label = format!("order={}", hex32(order_id.as_bytes()));
+        let scope = self.order_id_scope.market(&self.market_id);
+        let (order_key, node_key) = (key_order(scope, order_id), key_order_node(scope, order_id));
+        self.insert(order_key, KeyKind::Order, label.clone());
+        self.insert(node_key, KeyKind::OrderNode, label);
+    }
+
+    pub fn add_tick(&mut self, side: u8, tick: i32) {
+        let key = key_tick_node(&self.market_id, side, tick);
+        let label = if tick == NONE_TICK {
+            format!("side={side} tick={tick}")
+        } else {
+            format!("side={side} tick={tick} price={}", format_price(tick, self.tick_size, self.price_scale))
+        };
+        self.insert(key, KeyKind::TickNode, label);
+    }
+
+    pub fn add_messages(&mut self, messages: &[SignedMessage]) {
+        for signed in messages {
+            match &signed.message {
+                Message::Place {
+                    trader,
+                    order_id,
+                    side,
+                    tick_index,
+                    prev_tick_hint,
+                    next_tick_hint,
+                    ..
+                } => {
+                    self.add_account(trader);
+                    self.add_order(order_id);
+                    for tick in [*tick_index, *prev_tick_hint, *next_tick_hint] {
+                        self.add_tick(side.as_u8(), tick);
+                        self.add_tick(side.opposite().as_u8(), tick);
+                    }
+                }
+                Message::Cancel { trader, order_id, .. } => {
+                    self.add_account(trader);
+                    self.add_order(order_id);
+                }
+                Message::ForceCancel { operator, order_id, .. } => {
+                    self.add_account(operator);
+                    self.add_order(order_id);
+                }
+                Message::FillOrder {
+                    trader,
+                    order_id,
+                    maker_order_id,
+                    ..
+                } => {
+                    self.add_account(trader);
+                    self.add_order(order_id);
+                    self.add_order(maker_order_id);
+                }
+                Message::CollectFees { operator, asset, to, .. } => {
+                    self.add_account(operator);
+                    self.add_account(to);
+                    self.insert(key_fee_vault(asset), KeyKind::FeeVault, format!("asset={}", hex32(asset.as_bytes())));
+                }
+            }
+        }
+    }
+
+    fn get(&self, key: &[u8; 32]) -> Option<&(KeyKind, String)> {
+        self.labels.get(key)
+    }
+}
+
+#[derive(Serialize)]
+pub struct TraceEntry {
+    pub kind: String,
+    pub label: Option<String>,
+    pub accesses: usize,
+    pub present_before: bool,
+    pub before: Value,
+    pub after: Value,
+}
+
+pub fn build_trace(proofs: &[Proof], tree: &SparseMerkleTree, labels: &KeyLabels) -> BTreeMap<String, TraceEntry> {
+    let mut trace: BTreeMap<String, TraceEntry> = BTreeMap::new();
+    for proof in proofs {
+        let key_hex = hex32(&proof.key);
+        if let Some(entry) = trace.get_mut(&key_hex) {
+            entry.accesses += 1;
+            continue;
+        }
+        let kind = labels.get(&proof.key).map(|(kind, _)| *kind);
+        let before = if proof.present {
+            decode_value(kind, &proof.value, labels)
+        } else {
+            Value::Null
+        };
+        let after = match tree.get(proof.key) {
+            Some(bytes) => decode_value(kind, &bytes, labels),
+            None => Value::Null,
+        };
+        trace.insert(
+            key_hex,
+            TraceEntry {
+                kind: kind.map(KeyKind::as_str).unwrap_or("unknown").to_string(),
+                label: labels.get(&proof.key).map(|(_, label)| label.clone()),
+                accesses: 1,
+                present_before: proof.present,
+                before,
+                after,
+            },
+        );
+    }
+    trace
+}
+
+// Records render for operators: words for enums, prices next to ticks, and ids truncated.
+fn decode_value(kind: Option<KeyKind>, bytes: &[u8], labels: &KeyLabels) -> Value {
+    let decoded = match kind {
+        Some(KeyKind::Balance) => Balance::decode(bytes).ok().map(|b| {
+            json!({
+                "available": u256_hex(&b.available.to_be_bytes()),
+                "locked": u256_hex(&b.locked.to_be_bytes()),
+            })
+        }),
+        Some(KeyKind::Nonce | KeyKind::TradeSeq) => <[u8; 8]>::try_from(bytes).ok().map(|b| json!(u64::from_be_bytes(b))),
+        Some(KeyKind::OpenCount) => <[u8; 4]>::try_from(bytes).ok().map(|b| json!(u32::from_be_bytes(b))),
+        Some(KeyKind::Order) => Order::decode(bytes).ok().map(|o| {
+            json!({
+                "owner": o.owner.to_string(),
+                "side": o.side.to_string(),
+                "tick": o.tick,
+                "price": format_price(o.tick, labels.tick_size, labels.price_scale),
+                "qty_remaining": u256_hex(&o.qty_remaining.to_be_bytes()),
+                "tif": o.tif.to_string(),
+                "status": o.status.to_string(),
+            })
+        }),
+        Some(KeyKind::OrderNode) => OrderNode::decode(bytes).ok().map(|n| {
+            json!({
+                "prev": n.prev_order_id.to_string(),
+                "next": n.next_order_id.to_string(),
+            })
+        }),
+        Some(KeyKind::TickNode) => TickNode::decode(bytes).ok().map(|n| {
+            json!({
+                "prev": n.prev_tick,
+                "next": n.next_tick,
+                "head": n.head_order_id.to_string(),
+                "tail": n.tail_order_id.to_string(),
+            })
+        }),
+        Some(KeyKind::MarketBest) => MarketBest::decode(bytes).ok().map(|b| {
+            json!({
+                "best_bid": b.best_bid,
+                "best_ask": b.best_ask,
+            })
+        }),
+        Some(KeyKind::FeeVault) => FeeVault::decode(bytes)
+            .ok()
+            .map(|f| json!({ "total": u256_hex(&f.total.to_be_bytes()) })),
+        None => None,
+    };
+    decoded.unwrap_or_else(|| json!({ "raw": format!("0x{}", hex::encode(bytes)) }))
+}
+
+fn hex32(bytes: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn u256_hex(bytes: &[u8; 32]) -> String {
+    let trimmed = hex::encode(bytes).trim_start_matches('0').to_string();
+    if trimmed.is_empty() {
+        "0x0".to_string()
+    } else {
+        format!("0x{trimmed}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clob_core::engine::apply_batch;
+    use clob_core::state::RecordingState;
+    use clob_core::input::RulesBuilder;
+    use clob_core::types::{AllocationMode, FailureMode, OrderStatus, Side, TimeInForce, U256};
+    use clob_core::verify::domain_separator;
+
+    use crate::sign_message;
+    use crate::signer::LocalSigner;
+
+    #[test]
+    fn trace_contains_balance_and_order_keys() {
+        let market = MarketId([3u8; 32]);
+        let rules = Rules {
+            base_asset_id: AssetId([1u8; 32]),
+            quote_asset_id: AssetId([2u8; 32]),
+            price_scale: U256::from(1_000_000_000_000_000_000u128),
+            tick_size: U256::from(1_000_000_000_000_000_000u128),
+            lot_size: U256::from(1u64),
+            taker_fee_bps: 0,
+            maker_fee_bps: 0,
+            max_orders_per_batch: 128,
+            max_matches_per_order: 64,
+            max_fills_per_batch: 1024,
+            allocation_mode: AllocationMode::Fifo,
+            min_notional: U256::zero(),
+            max_balance: U256::from(1_000_000u64),
+            fee_collector: Address::ZERO,
+            fee_in_received_asset: false,
+            enforce_derived_order_id: false,
+            admin: Address::ZERO,
+            max_price_deviation_bps: 0,
+            base_decimals: 18,
+            quote_decimals: 18,
+            commit_trade_roots: true,
+            max_open_orders_per_trader: 0,
+            max_book_steps_per_order: 0,
+            tick_spacing: 1,
+            taker_fee_tier_notional: U256::zero(),
+            taker_fee_bps_tier2: 0,
+            order_id_scope: OrderIdScope::Market,
+            bind_batch_seq: false,
+            failure_mode: FailureMode::Abort,
+        };
+        let mut key_bytes = [0u8; 32];
+        key_bytes[31] = 1;
+        let signer = LocalSigner::from_slice(&key_bytes).unwrap();
+        let trader = Address::from_hex("7e5f4552091a69125d5dfcb7b8c2659029395bdf").unwrap();
+
+        let mut tree = SparseMerkleTree::new();
+        let funded = Balance {
+            available: U256::from(10u64),
+            locked: U256::zero(),
+        };
+        tree.update(key_balance(&trader, &rules.quote_asset_id), Some(funded.encode().to_vec()));
+
+        let domain = domain_separator(1, &[9u8; 32], &market, &[0u8; 32]);
+        let order_id = OrderId([0x44u8; 32]);
+        let message = Message::Place {
+            trader,
+            nonce: 1,
+            order_id,
+            side: Side::Buy,
+            tif: TimeInForce::Gtc,
+            tick_index: 2,
+            qty_base: U256::from(3u64),
+            prev_tick_hint: i32::MIN,
+            next_tick_hint: i32::MIN,
+            reduce_only: false,
+            quote_budget: false,
+        };
+        let signature = sign_message(&signer, &message, &domain).unwrap();
+        let messages = vec![SignedMessage { message, signature }];
+
+        let mut state = RecordingState::new(tree);
+        apply_batch(&mut state, market, &rules, domain, &messages).expect("apply batch");
+
+        let mut labels = KeyLabels::new(&rules, market);
+        labels.add_messages(&messages);
+        let trace = build_trace(&state.proofs, &state.tree, &labels);
+
+        let balance_entry = &trace[&hex32(&key_balance(&trader, &rules.quote_asset_id))];
+        assert_eq!(balance_entry.kind, "balance");
+        assert!(balance_entry.present_before);
+        assert_eq!(balance_entry.before["available"], "0xa");
+        assert_eq!(balance_entry.after["available"], "0x4");
+        assert_eq!(balance_entry.after["locked"], "0x6");
+
+        let order_entry = &trace[&hex32(&key_order(Some(&market), &order_id))];
+        assert_eq!(order_entry.kind, "order");
+        assert!(!order_entry.present_before);
+        assert_eq!(order_entry.before, Value::Null);
+        assert_eq!(order_entry.after["status"], "open");
+        assert_eq!(order_entry.after["price"], "2.0");
+
+        assert!(trace.contains_key(&hex32(&key_nonce(&trader))));
+        let tick_entry = &trace[&hex32(&key_tick_node(&market, Side::Buy.as_u8(), 2))];
+        assert_eq!(tick_entry.label.as_deref(), Some("side=0 tick=2 price=2.0"));
+        assert!(trace.values().all(|entry| entry.kind != "unknown"));
+    }
+
+    #[test]
+    fn order_renders_owner_hex_and_status_word() {
+        let rules = RulesBuilder::new(AssetId([1u8; 32]), AssetId([2u8; 32])).build().unwrap();
+        let labels = KeyLabels::new(&rules, MarketId([3u8; 32]));
+        let order = Order {
+            owner: Address([0xabu8; 20]),
+            market_id: MarketId([3u8; 32]),
+            side: Side::Sell,
+            tick: 7,
+            qty_remaining: U256::from(5u64),
+            tif: TimeInForce::Gtc,
+            status: OrderStatus::Canceled,
+        };
+        let rendered = decode_value(Some(KeyKind::Order), &order.encode(), &labels);
+        assert_eq!(rendered["owner"], format!("0x{}", "ab".repeat(20)));
+        assert_eq!(rendered["status"], "canceled");
+        assert_eq!(rendered["side"], "sell");
+        assert_eq!(rendered["price"], "7.0");
+
+        let node = OrderNode {
+            prev_order_id: OrderId([0x12u8; 32]),
+            next_order_id: OrderId::ZERO,
+        };
+        let rendered = decode_value(Some(KeyKind::OrderNode), &node.encode(), &labels);
+        assert_eq!(rendered["prev"], "0x12121212..1212");
+    }
+}